@@ -0,0 +1,484 @@
+//! Performance baseline for common grammars
+//!
+//! Every grammar is defined twice, with a peg text and with the
+//! macros, to compare both paths (they should be equivalent)
+//!
+//! The same grammars are written with pest (it builds a tree of pairs,
+//! compared with ```parse```) and with nom combinators (they just
+//! recognize the input, compared with ```matches```)
+//!
+//! ```ignore
+//!     cargo bench
+//! ```
+
+#[macro_use]
+extern crate dynparser;
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, Criterion};
+use dynparser::parser::expression::SetOfRules;
+use dynparser::parser::release_scratch_buffers;
+use dynparser::{matches, parse, rules_from_peg};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till, take_while};
+use nom::character::complete::{anychar, char, digit1, multispace0, none_of, one_of};
+use nom::combinator::{all_consuming, opt, recognize};
+use nom::multi::{many0_count, separated_list0};
+use nom::sequence::{delimited, pair, preceded, tuple};
+use nom::IResult;
+
+//-----------------------------------------------------------------------
+//  JSON
+
+const JSON_PEG: &str = r#"
+    main        =   _  value  _
+
+    value       =   object  /  array  /  string  /  number
+                /   'true'  /  'false'  /  'null'
+
+    object      =   '{'  _  ( pair  ( _ ','  _  pair )* )?  _  '}'
+    pair        =   string  _  ':'  _  value
+    array       =   '['  _  ( value  ( _ ','  _  value )* )?  _  ']'
+
+    string      =   '"'  ( "\\" .  /  !'"' . )*  '"'
+    number      =   '-'?  [0-9]+  ( '.' [0-9]+ )?
+
+    _           =   ( ' '  /  "\t"  /  "\r"  /  "\n" )*
+"#;
+
+fn json_macro_rules() -> SetOfRules {
+    let digits = || rep!(ematch!(chlist "", from '0', to '9'), 1);
+    let list = |item: &str, open: &str, close: &str| {
+        and!(
+            lit!(open),
+            ref_rule!("_"),
+            rep!(
+                and!(
+                    ref_rule!(item),
+                    rep!(
+                        and!(ref_rule!("_"), lit!(","), ref_rule!("_"), ref_rule!(item)),
+                        0
+                    )
+                ),
+                0,
+                1
+            ),
+            ref_rule!("_"),
+            lit!(close)
+        )
+    };
+
+    rules! {
+        "main"      =>  and!(ref_rule!("_"), ref_rule!("value"), ref_rule!("_")),
+        "value"     =>  or!(
+                            ref_rule!("object"),
+                            ref_rule!("array"),
+                            ref_rule!("string"),
+                            ref_rule!("number"),
+                            lit!("true"),
+                            lit!("false"),
+                            lit!("null")
+                        ),
+        "object"    =>  list("pair", "{", "}"),
+        "pair"      =>  and!(
+                            ref_rule!("string"),
+                            ref_rule!("_"),
+                            lit!(":"),
+                            ref_rule!("_"),
+                            ref_rule!("value")
+                        ),
+        "array"     =>  list("value", "[", "]"),
+        "string"    =>  and!(
+                            lit!("\""),
+                            rep!(or!(and!(lit!("\\"), dot!()), and!(not!(lit!("\"")), dot!())), 0),
+                            lit!("\"")
+                        ),
+        "number"    =>  and!(
+                            rep!(lit!("-"), 0, 1),
+                            digits(),
+                            rep!(and!(lit!("."), digits()), 0, 1)
+                        ),
+        "_"         =>  rep!(or!(lit!(" "), lit!("\t"), lit!("\r"), lit!("\n")), 0)
+    }
+}
+
+mod json_pest {
+    use pest::Parser;
+
+    #[derive(pest_derive::Parser)]
+    #[grammar_inline = r#"
+        main    =   { SOI ~ ws ~ value ~ ws ~ EOI }
+
+        value   =   { object | array | string | number | "true" | "false" | "null" }
+
+        object  =   { "{" ~ ws ~ (pair ~ (ws ~ "," ~ ws ~ pair)*)? ~ ws ~ "}" }
+        pair    =   { string ~ ws ~ ":" ~ ws ~ value }
+        array   =   { "[" ~ ws ~ (value ~ (ws ~ "," ~ ws ~ value)*)? ~ ws ~ "]" }
+
+        string  =   { "\"" ~ ("\\" ~ ANY | !"\"" ~ ANY)* ~ "\"" }
+        number  =   { "-"? ~ ASCII_DIGIT+ ~ ("." ~ ASCII_DIGIT+)? }
+
+        ws      =   { (" " | "\t" | "\r" | "\n")* }
+    "#]
+    struct Json;
+
+    pub fn parse(input: &str) -> bool {
+        Json::parse(Rule::main, input).is_ok()
+    }
+}
+
+fn json_nom(input: &str) -> bool {
+    fn value(i: &str) -> IResult<&str, &str> {
+        alt((
+            object,
+            array,
+            string,
+            number,
+            tag("true"),
+            tag("false"),
+            tag("null"),
+        ))(i)
+    }
+    fn list<'a>(
+        item: fn(&'a str) -> IResult<&'a str, &'a str>,
+        open: char,
+        close: char,
+    ) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+        recognize(tuple((
+            char(open),
+            multispace0,
+            separated_list0(tuple((multispace0, char(','), multispace0)), item),
+            multispace0,
+            char(close),
+        )))
+    }
+    fn object(i: &str) -> IResult<&str, &str> {
+        list(pair_, '{', '}')(i)
+    }
+    fn pair_(i: &str) -> IResult<&str, &str> {
+        recognize(tuple((string, multispace0, char(':'), multispace0, value)))(i)
+    }
+    fn array(i: &str) -> IResult<&str, &str> {
+        list(value, '[', ']')(i)
+    }
+    fn string(i: &str) -> IResult<&str, &str> {
+        let chars = many0_count(alt((preceded(char('\\'), anychar), none_of("\""))));
+        recognize(delimited(char('"'), chars, char('"')))(i)
+    }
+    fn number(i: &str) -> IResult<&str, &str> {
+        recognize(tuple((
+            opt(char('-')),
+            digit1,
+            opt(pair(char('.'), digit1)),
+        )))(i)
+    }
+
+    all_consuming(delimited(multispace0, value, multispace0))(input).is_ok()
+}
+
+fn json_input(records: usize) -> String {
+    let items: Vec<String> = (0..records)
+        .map(|i| {
+            format!(
+                r#"{{"id": {}, "name": "item \"{}\"", "price": {}.25, "tags": ["a", "b"], "active": true}}"#,
+                i, i, i
+            )
+        })
+        .collect();
+    format!("[\n{}\n]", items.join(",\n"))
+}
+
+//-----------------------------------------------------------------------
+//  ARITHMETIC EXPRESSIONS
+
+const EXPR_PEG: &str = r#"
+    main        =   _  expr  _
+
+    expr        =   term    ( _  ('+' / '-')  _  term )*
+    term        =   factor  ( _  [*/]  _  factor )*
+    factor      =   num  /  '('  _  expr  _  ')'
+
+    num         =   [0-9]+
+
+    _           =   ' '*
+"#;
+
+fn expr_macro_rules() -> SetOfRules {
+    let op = |chars: &str| ematch!(chlist chars, from2 vec![]);
+    rules! {
+        "main"      =>  and!(ref_rule!("_"), ref_rule!("expr"), ref_rule!("_")),
+        "expr"      =>  and!(
+                            ref_rule!("term"),
+                            rep!(and!(ref_rule!("_"), op("+-"), ref_rule!("_"), ref_rule!("term")), 0)
+                        ),
+        "term"      =>  and!(
+                            ref_rule!("factor"),
+                            rep!(and!(ref_rule!("_"), op("*/"), ref_rule!("_"), ref_rule!("factor")), 0)
+                        ),
+        "factor"    =>  or!(
+                            ref_rule!("num"),
+                            and!(lit!("("), ref_rule!("_"), ref_rule!("expr"), ref_rule!("_"), lit!(")"))
+                        ),
+        "num"       =>  rep!(ematch!(chlist "", from '0', to '9'), 1),
+        "_"         =>  rep!(lit!(" "), 0)
+    }
+}
+
+mod expr_pest {
+    use pest::Parser;
+
+    #[derive(pest_derive::Parser)]
+    #[grammar_inline = r#"
+        main    =   { SOI ~ ws ~ expr ~ ws ~ EOI }
+
+        expr    =   { term ~ (ws ~ ("+" | "-") ~ ws ~ term)* }
+        term    =   { factor ~ (ws ~ ("*" | "/") ~ ws ~ factor)* }
+        factor  =   { num | "(" ~ ws ~ expr ~ ws ~ ")" }
+
+        num     =   { ASCII_DIGIT+ }
+
+        ws      =   { " "* }
+    "#]
+    struct Expr;
+
+    pub fn parse(input: &str) -> bool {
+        Expr::parse(Rule::main, input).is_ok()
+    }
+}
+
+fn expr_nom(input: &str) -> bool {
+    fn spaces(i: &str) -> IResult<&str, &str> {
+        take_while(|c| c == ' ')(i)
+    }
+    fn expr(i: &str) -> IResult<&str, &str> {
+        let rest = many0_count(tuple((spaces, one_of("+-"), spaces, term)));
+        recognize(pair(term, rest))(i)
+    }
+    fn term(i: &str) -> IResult<&str, &str> {
+        let rest = many0_count(tuple((spaces, one_of("*/"), spaces, factor)));
+        recognize(pair(factor, rest))(i)
+    }
+    fn factor(i: &str) -> IResult<&str, &str> {
+        let parens = tuple((char('('), spaces, expr, spaces, char(')')));
+        alt((digit1, recognize(parens)))(i)
+    }
+
+    all_consuming(delimited(spaces, expr, spaces))(input).is_ok()
+}
+
+fn expr_input(terms: usize) -> String {
+    (0..terms).fold("1".to_string(), |acc, i| match i % 3 {
+        0 => format!("{} + {} * 3", acc, i),
+        1 => format!("({} - {}) / 2", acc, i),
+        _ => format!("{} * ({} + 7)", acc, i),
+    })
+}
+
+//-----------------------------------------------------------------------
+//  LOG LINES
+
+const LOG_PEG: &str = r#"
+    main        =   line*
+
+    line        =   date  ' '  time  ' '  level  ' '  message  "\n"
+
+    date        =   num '-' num '-' num
+    time        =   num ':' num ':' num ('.' num)?
+    level       =   'DEBUG'  /  'INFO'  /  'WARN'  /  'ERROR'
+    message     =   (!"\n" .)*
+
+    num         =   [0-9]+
+"#;
+
+fn log_macro_rules() -> SetOfRules {
+    let sep = |s: &str| {
+        and!(
+            ref_rule!("num"),
+            lit!(s),
+            ref_rule!("num"),
+            lit!(s),
+            ref_rule!("num")
+        )
+    };
+    rules! {
+        "main"      =>  rep!(ref_rule!("line"), 0),
+        "line"      =>  and!(
+                            ref_rule!("date"),
+                            lit!(" "),
+                            ref_rule!("time"),
+                            lit!(" "),
+                            ref_rule!("level"),
+                            lit!(" "),
+                            ref_rule!("message"),
+                            lit!("\n")
+                        ),
+        "date"      =>  sep("-"),
+        "time"      =>  and!(sep(":"), rep!(and!(lit!("."), ref_rule!("num")), 0, 1)),
+        "level"     =>  or!(lit!("DEBUG"), lit!("INFO"), lit!("WARN"), lit!("ERROR")),
+        "message"   =>  rep!(and!(not!(lit!("\n")), dot!()), 0),
+        "num"       =>  rep!(ematch!(chlist "", from '0', to '9'), 1)
+    }
+}
+
+mod log_pest {
+    use pest::Parser;
+
+    #[derive(pest_derive::Parser)]
+    #[grammar_inline = r#"
+        main    =   { SOI ~ line* ~ EOI }
+
+        line    =   { date ~ " " ~ time ~ " " ~ level ~ " " ~ message ~ "\n" }
+
+        date    =   { num ~ "-" ~ num ~ "-" ~ num }
+        time    =   { num ~ ":" ~ num ~ ":" ~ num ~ ("." ~ num)? }
+        level   =   { "DEBUG" | "INFO" | "WARN" | "ERROR" }
+        message =   { (!"\n" ~ ANY)* }
+
+        num     =   { ASCII_DIGIT+ }
+    "#]
+    struct Log;
+
+    pub fn parse(input: &str) -> bool {
+        Log::parse(Rule::main, input).is_ok()
+    }
+}
+
+fn log_nom(input: &str) -> bool {
+    fn sep<'a>(s: char) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+        recognize(tuple((digit1, char(s), digit1, char(s), digit1)))
+    }
+    fn line(i: &str) -> IResult<&str, &str> {
+        let time = pair(sep(':'), opt(pair(char('.'), digit1)));
+        let level = alt((tag("DEBUG"), tag("INFO"), tag("WARN"), tag("ERROR")));
+        recognize(tuple((
+            sep('-'),
+            char(' '),
+            time,
+            char(' '),
+            level,
+            char(' '),
+            take_till(|c| c == '\n'),
+            char('\n'),
+        )))(i)
+    }
+
+    all_consuming(many0_count(line))(input).is_ok()
+}
+
+fn log_input(lines: usize) -> String {
+    let levels = ["DEBUG", "INFO", "WARN", "ERROR"];
+    (0..lines)
+        .map(|i| {
+            format!(
+                "2019-03-{:02} 10:{:02}:{:02}.{:03} {} request {} processed in {} ms\n",
+                i % 28 + 1,
+                i % 60,
+                (i * 7) % 60,
+                i % 1000,
+                levels[i % levels.len()],
+                i,
+                i * 3 % 97
+            )
+        })
+        .collect()
+}
+
+//-----------------------------------------------------------------------
+//  BENCHES
+
+//  the same grammar on other libraries
+struct Others {
+    pest: fn(&str) -> bool,
+    nom: fn(&str) -> bool,
+}
+
+fn bench_grammar(
+    c: &mut Criterion,
+    name: &str,
+    peg: &str,
+    macro_rules: SetOfRules,
+    others: Others,
+    input: &str,
+) {
+    let peg_rules = rules_from_peg(peg).unwrap();
+    assert!(parse(input, &peg_rules).is_ok());
+    assert!(parse(input, &macro_rules).is_ok());
+    assert!((others.pest)(input));
+    assert!((others.nom)(input));
+
+    let mut group = c.benchmark_group(name);
+    group.bench_function("compile_peg", |b| {
+        b.iter(|| rules_from_peg(black_box(peg)).unwrap())
+    });
+    group.bench_function("parse_peg_rules", |b| {
+        b.iter(|| parse(black_box(input), &peg_rules).unwrap())
+    });
+    group.bench_function("parse_macro_rules", |b| {
+        b.iter(|| parse(black_box(input), &macro_rules).unwrap())
+    });
+    //  the node vectors allocated again on every parsing
+    group.bench_function("parse_peg_rules_no_scratch", |b| {
+        b.iter(|| {
+            release_scratch_buffers();
+            parse(black_box(input), &peg_rules).unwrap()
+        })
+    });
+    group.bench_function("parse_pest", |b| b.iter(|| (others.pest)(black_box(input))));
+    //  without building the AST
+    group.bench_function("matches_peg_rules", |b| {
+        b.iter(|| matches(black_box(input), &peg_rules))
+    });
+    group.bench_function("recognize_nom", |b| {
+        b.iter(|| (others.nom)(black_box(input)))
+    });
+    group.finish();
+}
+
+fn json(c: &mut Criterion) {
+    let others = Others {
+        pest: json_pest::parse,
+        nom: json_nom,
+    };
+    bench_grammar(
+        c,
+        "json",
+        JSON_PEG,
+        json_macro_rules(),
+        others,
+        &json_input(200),
+    );
+}
+
+fn arithmetic(c: &mut Criterion) {
+    let others = Others {
+        pest: expr_pest::parse,
+        nom: expr_nom,
+    };
+    bench_grammar(
+        c,
+        "arithmetic",
+        EXPR_PEG,
+        expr_macro_rules(),
+        others,
+        &expr_input(150),
+    );
+}
+
+fn log_lines(c: &mut Criterion) {
+    let others = Others {
+        pest: log_pest::parse,
+        nom: log_nom,
+    };
+    bench_grammar(
+        c,
+        "log_lines",
+        LOG_PEG,
+        log_macro_rules(),
+        others,
+        &log_input(500),
+    );
+}
+
+criterion_group!(benches, json, arithmetic, log_lines);
+criterion_main!(benches);
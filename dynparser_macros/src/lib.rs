@@ -0,0 +1,181 @@
+#![warn(missing_docs)]
+//! Compile time validation of peg grammars for
+//! [dynparser](https://github.com/jleahred/dynparser)
+//!
+//! A grammar embedded on the source code, will be checked while
+//! compiling. A typo on it, will fail the build
+//!
+//! The expansion uses the ```dynparser``` macros, it has to be a
+//! dependency too
+
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Compile a peg grammar at build time
+///
+/// It expands to the equivalent ```rules!{}``` construction, with the
+/// annotations of the rules (```@raw```, ```@sync```...) and their access
+/// policy
+///
+/// ```
+/// use dynparser::parse;
+/// use dynparser_macros::peg_rules;
+///
+/// fn main() {
+///     let rules = peg_rules!(
+///         r#"
+///         main    =   'hello'  ' '+  ('world' / name)
+///         name    =   [A-Z] [a-z]*
+///         "#
+///     );
+///
+///     assert!(parse("hello  John", &rules).is_ok());
+/// }
+/// ```
+///
+/// An invalid grammar will not compile
+///
+/// ```compile_fail
+/// use dynparser_macros::peg_rules;
+///
+/// fn main() {
+///     let rules = peg_rules!("main = 'hello' /");
+/// }
+/// ```
+#[proc_macro]
+pub fn peg_rules(input: TokenStream) -> TokenStream {
+    let code = grammar_text(input)
+        .and_then(|peg| rules_code(&peg))
+        .unwrap_or_else(|err| format!("compile_error!({:?})", err));
+
+    code.parse().expect("invalid generated code")
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  I N T E R N A L
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+//  macros are imported explicitly, to avoid collisions with the names
+//  of custom matchers
+fn rules_code(peg: &str) -> Result<String, String> {
+    let rules = dynparser::rules_from_peg(peg).map_err(|e| match e {
+        dynparser::peg::Error::Parser(e) => format!("invalid grammar: {}", e),
+        e => format!("invalid grammar: {}", e),
+    })?;
+
+    let unicode = if cfg!(feature = "unicode") {
+        "xid_continue, xid_start,"
+    } else {
+        ""
+    };
+    let graphemes = if cfg!(feature = "graphemes") {
+        "grapheme,"
+    } else {
+        ""
+    };
+
+    Ok(format!(
+        "{{
+            #[allow(unused_imports)]
+            use ::dynparser::{{
+                and, balanced, bof, bol, custom, deprecated, dot, dot_no_newline, ematch, eof, eol,
+                error, keywords, label, lit, lit_char, no_case, not, not_keyword, or, pos, ref_rule,
+                rep, rules, until, warning,
+                {} {}
+            }};
+            #[allow(unused_imports)]
+            use ::dynparser::parser::expression::RuleAccess;
+            let rules = rules!{{ {} }};
+            rules{}
+        }}",
+        unicode,
+        graphemes,
+        dynparser::peg::gcode::rust_from_rules(&rules),
+        dynparser::peg::gcode::rust_meta_from_rules(&rules)
+    ))
+}
+
+fn grammar_text(input: TokenStream) -> Result<String, String> {
+    let mut tokens = input.into_iter();
+    match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(lit)), None) => unquote(&lit.to_string()),
+        //  from a macro_rules expansion
+        (Some(TokenTree::Group(group)), None) if group.delimiter() == Delimiter::None => {
+            grammar_text(group.stream())
+        }
+        _ => Err("expected a string literal with the grammar".to_string()),
+    }
+}
+
+//  value of a string literal from its source code
+fn unquote(lit: &str) -> Result<String, String> {
+    if let Some(raw) = lit.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        raw.get(hashes + 1..raw.len() - hashes - 1)
+            .map(|s| s.to_string())
+            .ok_or_else(|| format!("invalid raw string {}", lit))
+    } else if lit.len() >= 2 && lit.starts_with('"') && lit.ends_with('"') {
+        unescape(&lit[1..lit.len() - 1])
+    } else {
+        Err(format!("expected a string literal, found {}", lit))
+    }
+}
+
+fn unescape(s: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            result.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('0') => result.push('\0'),
+            Some('\\') => result.push('\\'),
+            Some('\'') => result.push('\''),
+            Some('"') => result.push('"'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                result.push(code2char(&hex)?);
+            }
+            Some('u') => {
+                let hex: String = chars.by_ref().take_while(|&ch| ch != '}').collect();
+                result.push(code2char(hex.trim_start_matches('{'))?);
+            }
+            //  line continuation
+            Some('\n') | Some('\r') => {
+                while chars.peek().is_some_and(|ch| ch.is_whitespace()) {
+                    chars.next();
+                }
+            }
+            other => return Err(format!("unknown escape {:?}", other)),
+        }
+    }
+    Ok(result)
+}
+
+fn code2char(hex: &str) -> Result<char, String> {
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(std::char::from_u32)
+        .ok_or_else(|| format!("invalid escaped char {}", hex))
+}
@@ -0,0 +1,40 @@
+//-----------------------------------------------------------------------
+//
+//  mod dynparser_macros  TEST
+//
+//-----------------------------------------------------------------------
+
+use super::{rules_code, unquote};
+
+#[test]
+fn unquote_literals() {
+    assert_eq!(
+        unquote(r####"r#"main = "a""#"####).unwrap(),
+        r#"main = "a""#
+    );
+    assert_eq!(unquote(r#"r"main = 'a'""#).unwrap(), "main = 'a'");
+    assert_eq!(
+        unquote(r#""main = \"a\"\n\t'\x41' '\u{f1}'""#).unwrap(),
+        "main = \"a\"\n\t'A' 'ñ'"
+    );
+    assert_eq!(unquote("\"main = \\\n     'a'\"").unwrap(), "main = 'a'");
+
+    assert!(unquote("'a'").is_err());
+    assert!(unquote(r#""\q""#).is_err());
+}
+
+#[test]
+fn generated_rules_code() {
+    let code = rules_code("main = 'a' [bc] [d-f]").unwrap();
+    assert!(code.contains(r##"ematch!(chlist r#"bc"#, from2 vec![])"##));
+    assert!(code.contains("from 'd', to 'f'"));
+    assert!(code.contains("lit_char!('a')"));
+
+    let code = rules_code("main = a \n @internal @raw \n a = 'a'").unwrap();
+    assert!(code.contains(r##".set_meta(r#"a"#, "raw", None)"##));
+    assert!(code.contains(r##".set_access(r#"a"#, RuleAccess::Internal)"##));
+
+    let err = rules_code("main = 'a' /").unwrap_err();
+    assert!(err.starts_with("invalid grammar: "));
+    assert!(err.contains("(row 0, col"));
+}
@@ -0,0 +1,112 @@
+//-----------------------------------------------------------------------
+//
+//  peg_rules!  TEST
+//
+//  the expansion has to give the same rules as rules_from_peg
+//
+//-----------------------------------------------------------------------
+
+use dynparser::{parse, parse_recovering, rules_from_peg};
+use dynparser_macros::peg_rules;
+
+macro_rules! same_rules {
+    ($peg:expr) => {
+        (peg_rules!($peg), rules_from_peg($peg).unwrap())
+    };
+}
+
+#[test]
+fn raw_rules() {
+    let (rules, expected) = same_rules!(
+        r#"
+        main    =   call  ';'
+        @raw
+        call    =   name  _  '('  _  name  _  ')'
+        name    =   [a-z]+
+        _       =   ' '*
+        "#
+    );
+
+    let input = "f ( x );";
+    let ast = parse(input, &rules).unwrap();
+    assert_eq!(ast, parse(input, &expected).unwrap());
+    assert_eq!(rules.rule_meta("call"), expected.rule_meta("call"));
+}
+
+#[test]
+fn passthrough_rules() {
+    let (rules, expected) = same_rules!(
+        r#"
+        main    =   sum
+        @passthrough
+        sum     =   product  ('+'  product)*
+        @passthrough
+        product =   num  ('*'  num)*
+        num     =   [0-9]+
+        "#
+    );
+
+    for input in &["2", "2*3", "2+3*4"] {
+        assert_eq!(
+            parse(input, &rules).unwrap(),
+            parse(input, &expected).unwrap()
+        );
+    }
+}
+
+#[test]
+fn recovering_rules() {
+    let (rules, expected) = same_rules!(
+        r#"
+        %recover sync
+
+        main    =   '{'  stmt*  '}'
+
+        @sync(";")
+        stmt    =   name  '='  value  ';'
+        name    =   [a-z]+
+        value   =   [0-9]+
+        "#
+    );
+
+    let input = "{a=1;b=x;c=3;d 4;}";
+    let recovered = parse_recovering(input, &rules);
+    assert_eq!(recovered.errors.len(), 2);
+    assert_eq!(
+        format!("{:?}", recovered),
+        format!("{:?}", parse_recovering(input, &expected))
+    );
+
+    let (rules, expected) = same_rules!(
+        r#"
+        %recover lines
+
+        main    =   (line  "\n")*
+        line    =   name  ' = '  value
+        name    =   [a-z]+
+        value   =   [0-9]+
+        "#
+    );
+
+    let recovered = parse_recovering("a = 1\nb = x\nc = 3\n", &rules);
+    assert_eq!(recovered.skipped, vec![1]);
+    assert_eq!(
+        recovered.ast,
+        parse_recovering("a = 1\nb = x\nc = 3\n", &expected).ast
+    );
+}
+
+#[cfg(feature = "graphemes")]
+#[test]
+fn grapheme_rules() {
+    let (rules, expected) = same_rules!("main = grapheme grapheme '!'");
+
+    //  e + COMBINING ACUTE ACCENT
+    let input = "e\u{301}a!";
+    assert_eq!(
+        parse(input, &rules).unwrap(),
+        parse(input, &expected).unwrap()
+    );
+    assert!(parse("ab!", &rules).is_ok());
+    assert!(parse("abc!", &rules).is_err());
+}
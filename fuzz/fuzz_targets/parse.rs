@@ -0,0 +1,43 @@
+//! Any grammar with any input has to return Ok or Err, never panic
+//!
+//! The data is the peg grammar, a zero byte and the input. The first
+//! byte selects the parse options
+//!
+//! ```text
+//! cargo +nightly fuzz run parse
+//! ```
+
+#![no_main]
+
+use dynparser::{parse_with, rules_from_peg, ParseOptions};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (flags, data) = match data.split_first() {
+        Some((flags, data)) => (*flags, data),
+        None => return,
+    };
+    let text = String::from_utf8_lossy(data);
+    let (grammar, input) = text.split_once('\0').unwrap_or((&text, ""));
+
+    let rules = match rules_from_peg(grammar) {
+        Ok(rules) => rules,
+        Err(e) => {
+            let _ = e.to_string();
+            return;
+        }
+    };
+    let options = ParseOptions::default()
+        .memoize(flags & 1 != 0)
+        .trim_trailing_whitespace(flags & 2 != 0)
+        .require_eof(flags & 4 == 0)
+        .skip_bom(flags & 8 != 0)
+        .skip_shebang(flags & 16 != 0)
+        .max_repetitions(10_000);
+    if let Err(e) = parse_with(input, &rules, &options) {
+        let _ = e.render(input, 2);
+    }
+    let _ = dynparser::parse_iter(input, &rules, "main")
+        .take(10)
+        .count();
+});
@@ -0,0 +1,233 @@
+#![warn(missing_docs)]
+//! Semantic actions. Build your values while parsing
+//!
+//! An action is called when its rule matches, with the captures of the
+//! rule, and it returns a value of the application. The value is a
+//! capture of the rule that called it. Then, there is no need to walk
+//! an ```ast::Node``` after parsing
+//!
+//! The captures of a rule are the values of its inner actions and the
+//! text of the tokens, in order. Rules without action are transparent,
+//! their captures are added to the rule calling them
+//!
+//! Actions are called only for the accepted parsing path (not on
+//! discarded alternatives)
+//!
+//! ```
+//! use dynparser::rules_from_peg;
+//!
+//! let rules = rules_from_peg(
+//!     r#"
+//! main    =   num  ('+'  num)*
+//! num     =   [0-9]+
+//!     "#,
+//! ).unwrap();
+//!
+//! let sum = rules
+//!     .with_action("num", |c| c.text().parse::<i64>().unwrap())
+//!     .with_action("main", |c| c.into_values().into_iter().sum());
+//!
+//! assert_eq!(sum.parse("1+20+300").unwrap(), Some(321));
+//! assert!(sum.parse("1+").is_err());
+//! ```
+
+use crate::ast::builder::TreeBuilder;
+use crate::ast::kind::NodeKind;
+use crate::parser::{self, expression};
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// A function building a value from the captures of a rule
+pub type Action<T> = Box<dyn Fn(Captures<T>) -> T>;
+
+/// Rules with actions, created with ```SetOfRules::with_action```
+///
+/// Look at the module documentation
+pub struct Actions<'a, T> {
+    rules: &'a expression::SetOfRules,
+    actions: HashMap<String, Action<T>>,
+}
+
+/// Something matched by a rule
+#[derive(Debug, Clone, PartialEq)]
+pub enum Capture<T> {
+    /// Value returned by the action of an inner rule
+    Value(T),
+    /// Text of a token (literal, match, dot...)
+    Text(String),
+}
+
+/// What a rule matched, received by its action
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captures<T>(Vec<Capture<T>>);
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl expression::SetOfRules {
+    /// Call the action when the rule matches
+    ///
+    /// A label (```name:expr``` on peg) can have an action too. Look at
+    /// the ```actions``` module
+    pub fn with_action<T, F>(&self, name: &str, action: F) -> Actions<'_, T>
+    where
+        F: Fn(Captures<T>) -> T + 'static,
+    {
+        Actions {
+            rules: self,
+            actions: HashMap::new(),
+        }
+        .with_action(name, action)
+    }
+}
+
+impl<'a, T> Actions<'a, T> {
+    /// Add the action of another rule (replacing the previous one, if
+    /// any)
+    pub fn with_action<F>(mut self, name: &str, action: F) -> Self
+    where
+        F: Fn(Captures<T>) -> T + 'static,
+    {
+        self.actions.insert(name.to_string(), Box::new(action));
+        self
+    }
+
+    /// The rule has an action
+    pub fn has_action(&self, name: &str) -> bool {
+        self.actions.contains_key(name)
+    }
+
+    /// Parse the input, calling the actions
+    ///
+    /// It returns the value of the ```main``` action. If ```main```
+    /// has no action, the last value of its captures (```None``` if
+    /// there isn't any)
+    pub fn parse(&self, text: &str) -> Result<Option<T>, parser::Error> {
+        let mut builder = ActionBuilder {
+            actions: &self.actions,
+            open: vec![],
+            root: vec![],
+        };
+        crate::parse_with_builder(
+            text,
+            self.rules,
+            &crate::ParseOptions::default(),
+            &mut builder,
+        )?;
+        Ok(builder.root.into_iter().rev().find_map(|c| match c {
+            Capture::Value(v) => Some(v),
+            Capture::Text(_) => None,
+        }))
+    }
+}
+
+impl<T> Captures<T> {
+    /// Values and texts, in order
+    pub fn items(&self) -> &[Capture<T>] {
+        &self.0
+    }
+
+    /// The values, without the texts
+    pub fn into_values(self) -> Vec<T> {
+        self.0
+            .into_iter()
+            .filter_map(|c| match c {
+                Capture::Value(v) => Some(v),
+                Capture::Text(_) => None,
+            })
+            .collect()
+    }
+
+    /// The texts together, without the values
+    pub fn text(&self) -> String {
+        self.0
+            .iter()
+            .filter_map(|c| match c {
+                Capture::Value(_) => None,
+                Capture::Text(t) => Some(t.as_str()),
+            })
+            .collect()
+    }
+
+    /// Number of captures
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The rule captured nothing
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<T> IntoIterator for Captures<T> {
+    type Item = Capture<T>;
+    type IntoIter = std::vec::IntoIter<Capture<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  I N T E R N A L
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+struct ActionBuilder<'a, T> {
+    actions: &'a HashMap<String, Action<T>>,
+    //  open nodes, with their captures
+    open: Vec<(String, Vec<Capture<T>>)>,
+    root: Vec<Capture<T>>,
+}
+
+impl<'a, T> ActionBuilder<'a, T> {
+    fn captures(&mut self) -> &mut Vec<Capture<T>> {
+        match self.open.last_mut() {
+            Some((_, captures)) => captures,
+            None => &mut self.root,
+        }
+    }
+}
+
+impl<'a, T> TreeBuilder for ActionBuilder<'a, T> {
+    fn open_node(&mut self, name: &str) {
+        self.open.push((name.to_string(), vec![]));
+    }
+
+    fn token(&mut self, kind: &NodeKind, text: &str) {
+        if *kind != NodeKind::EOF {
+            self.captures().push(Capture::Text(text.to_string()));
+        }
+    }
+
+    fn close_node(&mut self) {
+        if let Some((name, captures)) = self.open.pop() {
+            match self.actions.get(&name) {
+                Some(action) => {
+                    let value = action(Captures(captures));
+                    self.captures().push(Capture::Value(value));
+                }
+                None => self.captures().extend(captures),
+            }
+        }
+    }
+}
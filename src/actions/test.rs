@@ -0,0 +1,103 @@
+//-----------------------------------------------------------------------
+//
+//  mod actions  TEST
+//
+//-----------------------------------------------------------------------
+use super::Capture;
+use crate::rules_from_peg;
+
+#[derive(Debug, PartialEq)]
+enum Expr {
+    Num(i64),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+}
+
+#[test]
+fn test_typed_ast() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   sum
+        sum     =   prod  ('+'  prod)*
+        prod    =   num  ('*'  num)*
+        num     =   [0-9]+
+        "#,
+    )
+    .unwrap();
+
+    let fold = |op: fn(Box<Expr>, Box<Expr>) -> Expr| {
+        move |c: super::Captures<Expr>| {
+            let mut values = c.into_values().into_iter();
+            let first = values.next().unwrap();
+            values.fold(first, |acc, e| op(Box::new(acc), Box::new(e)))
+        }
+    };
+    let actions = rules
+        .with_action("num", |c| Expr::Num(c.text().parse().unwrap()))
+        .with_action("prod", fold(Expr::Mul))
+        .with_action("sum", fold(Expr::Add));
+
+    assert!(!actions.has_action("main"));
+    assert_eq!(
+        actions.parse("1+2*3").unwrap(),
+        Some(Expr::Add(
+            Box::new(Expr::Num(1)),
+            Box::new(Expr::Mul(Box::new(Expr::Num(2)), Box::new(Expr::Num(3))))
+        ))
+    );
+    assert!(actions.parse("1+*3").is_err());
+}
+
+#[test]
+fn test_captures_of_transparent_rules() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   item  (','  item)*
+        item    =   key  '='  val
+        key     =   [a-z]+
+        val     =   [0-9]+
+        "#,
+    )
+    .unwrap();
+
+    let actions = rules
+        .with_action("val", |c| c.text())
+        .with_action("main", |c| {
+            c.into_iter()
+                .map(|item| match item {
+                    Capture::Value(v) => format!("<{}>", v),
+                    Capture::Text(t) => t,
+                })
+                .collect()
+        });
+
+    assert_eq!(
+        actions.parse("a=1,bc=23").unwrap(),
+        Some("a=<1>,bc=<23>".to_string())
+    );
+}
+
+#[test]
+fn test_discarded_alternatives() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   long  /  short
+        long    =   num  'x'
+        short   =   num
+        num     =   [0-9]+
+        "#,
+    )
+    .unwrap();
+
+    let actions = rules.with_action("num", |c| c.text());
+    assert_eq!(actions.parse("12").unwrap(), Some("12".to_string()));
+
+    let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+    let counter = calls.clone();
+    let actions = rules.with_action("num", move |c| {
+        counter.set(counter.get() + 1);
+        c.text()
+    });
+    actions.parse("12").unwrap();
+    assert_eq!(calls.get(), 1);
+}
@@ -0,0 +1,169 @@
+//! Build your own tree while parsing
+//!
+//! ```parse_with_builder``` calls a ```TreeBuilder``` with the nodes
+//! and values of the input, in order. The builder can create any tree
+//! type (a typed AST, a green tree...), or just collect some data,
+//! without creating an ```ast::Node``` first
+//!
+//! Only the accepted parsing path is received. Nodes and values on
+//! discarded alternatives (backtracking) are not
+//!
+//! ```
+//! use dynparser::ast::builder::TreeBuilder;
+//! use dynparser::ast::kind::NodeKind;
+//! use dynparser::{parse_with_builder, rules_from_peg, ParseOptions};
+//!
+//! //  s-expression with the rules and the numbers
+//! #[derive(Default)]
+//! struct SExpr(String);
+//!
+//! impl TreeBuilder for SExpr {
+//!     fn open_node(&mut self, name: &str) {
+//!         self.0.push_str(&format!("({}", name));
+//!     }
+//!     fn token(&mut self, kind: &NodeKind, text: &str) {
+//!         if *kind == NodeKind::Class {
+//!             self.0.push_str(&format!(" {}", text));
+//!         }
+//!     }
+//!     fn close_node(&mut self) {
+//!         self.0.push(')');
+//!     }
+//! }
+//!
+//! let rules = rules_from_peg(
+//!     r#"
+//! main    =   num  ('+'  num)*
+//! num     =   [0-9]
+//!     "#,
+//! ).unwrap();
+//!
+//! let mut builder = SExpr::default();
+//! parse_with_builder("1+2", &rules, &ParseOptions::default(), &mut builder).unwrap();
+//! assert_eq!(builder.0, "(main(num 1)(num 2))");
+//! ```
+
+use crate::ast::kind::NodeKind;
+use crate::ast::Node;
+use crate::parser;
+use std::ops::Range;
+
+#[cfg(feature = "rowan")]
+pub mod rowan;
+
+/// Receives the tree while parsing
+///
+/// Every ```open_node``` has its ```close_node```, and the values
+/// received between them are children of the node
+pub trait TreeBuilder {
+    /// A rule (or a label) starts
+    fn open_node(&mut self, name: &str);
+    /// A value, with the kind of expression that produced it
+    fn token(&mut self, kind: &NodeKind, text: &str);
+    /// Same as ```token```, with the bytes of the input consumed. The
+    /// text can be different (```until```, ```balanced```, custom
+    /// values...). By default, it calls ```token```
+    fn token_at(&mut self, kind: &NodeKind, text: &str, _consumed: Range<usize>) {
+        self.token(kind, text)
+    }
+    /// Last open node finished
+    fn close_node(&mut self);
+    /// The input is not valid. Nothing else will be received
+    fn error(&mut self, _error: &parser::Error) {}
+}
+
+/// Builder of ```ast::Node```, the tree returned by ```parse```
+///
+/// ```
+/// use dynparser::ast::builder::AstBuilder;
+/// use dynparser::{parse, parse_with_builder, rules_from_peg, ParseOptions};
+///
+/// let rules = rules_from_peg("main = 'a'  b\nb = 'b'+").unwrap();
+///
+/// let mut builder = AstBuilder::default();
+/// parse_with_builder("abb", &rules, &ParseOptions::default(), &mut builder).unwrap();
+/// assert_eq!(builder.finish(), parse("abb", &rules).ok());
+/// ```
+#[derive(Debug, Default)]
+pub struct AstBuilder {
+    //  open nodes, the root first
+    open: Vec<(String, Vec<Node>)>,
+    root: Option<Node>,
+}
+
+impl AstBuilder {
+    /// The tree, if all the nodes were closed
+    pub fn finish(self) -> Option<Node> {
+        if self.open.is_empty() {
+            self.root
+        } else {
+            None
+        }
+    }
+
+    fn add(&mut self, node: Node) {
+        match self.open.last_mut() {
+            Some((_, children)) => children.push(node),
+            None => self.root = Some(node),
+        }
+    }
+}
+
+impl TreeBuilder for AstBuilder {
+    fn open_node(&mut self, name: &str) {
+        self.open.push((name.to_owned(), vec![]));
+    }
+
+    fn token(&mut self, _kind: &NodeKind, text: &str) {
+        self.add(Node::Val(text.to_owned()));
+    }
+
+    fn close_node(&mut self) {
+        if let Some(node) = self.open.pop() {
+            self.add(Node::Rule(node));
+        }
+    }
+}
+
+/// Nothing is built. To validate an input, with the error information
+impl TreeBuilder for () {
+    fn open_node(&mut self, _name: &str) {}
+    fn token(&mut self, _kind: &NodeKind, _text: &str) {}
+    fn close_node(&mut self) {}
+}
+
+//  registered while parsing, sent to the builder on success
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TreeEvent {
+    Open(String),
+    Token(NodeKind, String, Range<usize>),
+    Close,
+}
+
+impl TreeEvent {
+    pub(crate) fn send(&self, builder: &mut impl TreeBuilder) {
+        match self {
+            TreeEvent::Open(name) => builder.open_node(name),
+            TreeEvent::Token(kind, text, consumed) => {
+                builder.token_at(kind, text, consumed.clone())
+            }
+            TreeEvent::Close => builder.close_node(),
+        }
+    }
+}
+
+//  the events after an open are a single complete node
+pub(crate) fn is_single_node(events: &[TreeEvent]) -> bool {
+    let mut depth = 0;
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            TreeEvent::Open(_) => depth += 1,
+            TreeEvent::Close => depth -= 1,
+            TreeEvent::Token(..) => (),
+        }
+        if depth <= 0 {
+            return i + 1 == events.len() && depth == 0 && i > 0;
+        }
+    }
+    false
+}
@@ -0,0 +1,135 @@
+//! Lossless syntax trees with ```rowan```
+//!
+//! Available with the ```rowan``` feature. ```GreenBuilder``` creates a
+//! rowan ```GreenNode``` while parsing, with the rowan kinds given by
+//! a function
+//!
+//! The tokens have the source text (not the values of ```until```,
+//! ```balanced``` or custom matchers). Spaces and comments are on the
+//! tree, as the tokens of the rules matching them. The text of the
+//! tree is the full input
+//!
+//! ```
+//! use dynparser::ast::builder::rowan::{Element, GreenBuilder};
+//! use dynparser::ast::kind::NodeKind;
+//! use dynparser::{parse_with_builder, rules_from_peg, ParseOptions};
+//! use rowan::SyntaxKind;
+//!
+//! const ROOT: SyntaxKind = SyntaxKind(0);
+//! const NUM: SyntaxKind = SyntaxKind(1);
+//! const TRIVIA: SyntaxKind = SyntaxKind(2);
+//! const TOKEN: SyntaxKind = SyntaxKind(3);
+//!
+//! let rules = rules_from_peg(
+//!     r#"
+//! main    =   _  num  (_  ','  _  num)*  _
+//! num     =   [0-9]+
+//! _       =   (' '  /  '/*'  (!'*/'  .)*  '*/')*
+//!     "#,
+//! ).unwrap();
+//!
+//! let kinds = |element: Element| match element {
+//!     Element::Node("num") => NUM,
+//!     Element::Node("_") => TRIVIA,
+//!     Element::Node(_) => ROOT,
+//!     Element::Token(_) => TOKEN,
+//! };
+//!
+//! let input = "1, /* two */ 2 ";
+//! let mut builder = GreenBuilder::new(input, kinds);
+//! parse_with_builder(input, &rules, &ParseOptions::default(), &mut builder).unwrap();
+//! let green = builder.finish().unwrap();
+//!
+//! assert_eq!(green.kind(), ROOT);
+//! assert_eq!(green.to_string(), input);
+//! ```
+
+use super::TreeBuilder;
+use crate::ast::kind::NodeKind;
+use std::ops::Range;
+
+/// Element of the tree, to get its rowan kind
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Element<'a> {
+    /// A rule or a label, with its name
+    Node(&'a str),
+    /// A token, with the kind of expression that produced it. Input
+    /// not matched by any token (if any) is ```NodeKind::Other```
+    Token(&'a NodeKind),
+}
+
+/// Builder of a rowan ```GreenNode```
+///
+/// The input has to be the parsed one
+pub struct GreenBuilder<'i, F> {
+    input: &'i str,
+    kinds: F,
+    //  bytes of the input already on the tree
+    pos: usize,
+    open_nodes: usize,
+    finished: bool,
+    builder: ::rowan::GreenNodeBuilder<'static>,
+}
+
+impl<'i, F> GreenBuilder<'i, F>
+where
+    F: Fn(Element) -> ::rowan::SyntaxKind,
+{
+    /// Builder for the input, with the kinds given by the function
+    pub fn new(input: &'i str, kinds: F) -> Self {
+        GreenBuilder {
+            input,
+            kinds,
+            pos: 0,
+            open_nodes: 0,
+            finished: false,
+            builder: ::rowan::GreenNodeBuilder::new(),
+        }
+    }
+
+    /// The tree, if the input was parsed
+    pub fn finish(self) -> Option<::rowan::GreenNode> {
+        if self.finished {
+            Some(self.builder.finish())
+        } else {
+            None
+        }
+    }
+
+    //  text up to end, as a token
+    fn add_token(&mut self, kind: &NodeKind, end: usize) {
+        if let Some(text) = self.input.get(self.pos..end).filter(|t| !t.is_empty()) {
+            self.builder.token((self.kinds)(Element::Token(kind)), text);
+            self.pos = end;
+        }
+    }
+}
+
+impl<'i, F> TreeBuilder for GreenBuilder<'i, F>
+where
+    F: Fn(Element) -> ::rowan::SyntaxKind,
+{
+    fn open_node(&mut self, name: &str) {
+        self.builder.start_node((self.kinds)(Element::Node(name)));
+        self.open_nodes += 1;
+    }
+
+    fn token(&mut self, kind: &NodeKind, text: &str) {
+        let end = self.pos + text.len();
+        self.add_token(kind, end);
+    }
+
+    fn token_at(&mut self, kind: &NodeKind, _text: &str, consumed: Range<usize>) {
+        self.add_token(&NodeKind::Other, consumed.start);
+        self.add_token(kind, consumed.end);
+    }
+
+    fn close_node(&mut self) {
+        self.open_nodes -= 1;
+        if self.open_nodes == 0 {
+            self.add_token(&NodeKind::Other, self.input.len());
+            self.finished = true;
+        }
+        self.builder.finish_node();
+    }
+}
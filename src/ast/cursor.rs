@@ -0,0 +1,221 @@
+//! Read the children of a node in order, checking their shape
+//!
+//! It's the usual way to process an AST. Every rule is consumed
+//! with a cursor over its children, and a wrong shape is an error
+//! instead of a panic
+//!
+//! ```
+//! use dynparser::ast::{cursor::NodeCursor, Error};
+//! use dynparser::{parse, rules_from_peg};
+//!
+//! //  (name, value)
+//! fn assignment(mut c: NodeCursor) -> Result<(String, i64), Error> {
+//!     let name = c.expect_rule("name")?.expect_value()?.to_string();
+//!     c.expect_this_value(" = ")?;
+//!     let value = c.expect_rule("num")?.expect_value()?;
+//!     c.expect_end()?;
+//!     Ok((name, value.parse().unwrap()))
+//! }
+//!
+//! fn main() {
+//!     let rules = rules_from_peg(
+//!         r#"
+//!         main        =   assignment  (';' assignment)*
+//!         assignment  =   name  ' = '  num
+//!         name        =   [a-z]+
+//!         num         =   [0-9]+
+//!         "#,
+//!     ).unwrap();
+//!
+//!     let ast = parse("a = 1;bc = 23", &rules).unwrap().compact();
+//!
+//!     let mut main = NodeCursor::children(&ast);
+//!     let mut result = vec![main.expect_rule("assignment").and_then(assignment).unwrap()];
+//!     while main.optional_this_value(";") {
+//!         result.push(main.expect_rule("assignment").and_then(assignment).unwrap());
+//!     }
+//!     main.expect_end().unwrap();
+//!
+//!     assert_eq!(result, vec![("a".to_string(), 1), ("bc".to_string(), 23)]);
+//! }
+//! ```
+
+use crate::ast::{error, Error, Node};
+use std::fmt::Debug;
+use std::result::Result;
+
+/// Sequential reader over a slice of nodes
+///
+/// Usually, the children of a rule
+#[derive(Debug, Clone, Copy)]
+pub struct NodeCursor<'a, V = String> {
+    nodes: &'a [Node<V>],
+}
+
+impl<'a, V: Debug> NodeCursor<'a, V> {
+    /// Cursor at the beginning of the nodes
+    pub fn new(nodes: &'a [Node<V>]) -> Self {
+        NodeCursor { nodes }
+    }
+
+    /// Cursor over the children of the node
+    ///
+    /// ```Val``` and ```EOF``` nodes have no children
+    pub fn children(node: &'a Node<V>) -> Self {
+        Self::new(node.rule_parts().1)
+    }
+
+    /// Nodes not consumed yet
+    pub fn remaining(&self) -> &'a [Node<V>] {
+        self.nodes
+    }
+
+    /// True when all nodes have been consumed
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Next node, without consuming it
+    pub fn peek(&self) -> Option<&'a Node<V>> {
+        self.nodes.first()
+    }
+
+    /// Name of the next node, if it's a rule
+    pub fn peek_rule(&self) -> Option<&'a str> {
+        self.peek().and_then(|n| n.rule_parts().0)
+    }
+
+    /// Consume the next node, whatever it is
+    pub fn next_node(&mut self) -> Result<&'a Node<V>, Error> {
+        let (first, rest) = self
+            .nodes
+            .split_first()
+            .ok_or_else(|| error("expected node, found end of nodes", None))?;
+        self.nodes = rest;
+        Ok(first)
+    }
+
+    /// Consume the next node if it's the rule ```name```
+    ///
+    /// It returns a cursor over the children of the rule
+    pub fn expect_rule(&mut self, name: &str) -> Result<NodeCursor<'a, V>, Error> {
+        match self.peek() {
+            Some(Node::Rule((n, children))) if n == name => {
+                self.nodes = &self.nodes[1..];
+                Ok(NodeCursor::new(children))
+            }
+            other => Err(self.unexpected(&format!("expected rule {}", name), other)),
+        }
+    }
+
+    /// Consume the next node if it's a value
+    pub fn expect_value(&mut self) -> Result<&'a V, Error> {
+        match self.peek() {
+            Some(Node::Val(v)) => {
+                self.nodes = &self.nodes[1..];
+                Ok(v)
+            }
+            other => Err(self.unexpected("expected value", other)),
+        }
+    }
+
+    /// Consume the next node if it's the value ```v```
+    pub fn expect_this_value(&mut self, v: &str) -> Result<(), Error>
+    where
+        V: AsRef<str>,
+    {
+        match self.peek() {
+            Some(Node::Val(nv)) if nv.as_ref() == v => {
+                self.nodes = &self.nodes[1..];
+                Ok(())
+            }
+            other => Err(self.unexpected(&format!("expected value <{}>", v), other)),
+        }
+    }
+
+    /// Consume the next node if it's the rule ```name```
+    ///
+    /// If it's not, nothing is consumed
+    pub fn optional_rule(&mut self, name: &str) -> Option<NodeCursor<'a, V>> {
+        self.expect_rule(name).ok()
+    }
+
+    /// Consume the next node if it's the value ```v```
+    ///
+    /// If it's not, nothing is consumed and it returns false
+    pub fn optional_this_value(&mut self, v: &str) -> bool
+    where
+        V: AsRef<str>,
+    {
+        self.expect_this_value(v).is_ok()
+    }
+
+    /// Consume the nodes while the condition is true
+    ///
+    /// ```
+    /// use dynparser::ast::{cursor::NodeCursor, Node};
+    ///
+    /// let nodes: Vec<Node> = vec![
+    ///     Node::Val("a".to_string()),
+    ///     Node::Val("b".to_string()),
+    ///     Node::Rule(("c".to_string(), vec![])),
+    /// ];
+    ///
+    /// let mut cursor = NodeCursor::new(&nodes);
+    /// let vals = cursor.take_while(|n| n.rule_parts().0.is_none());
+    /// assert_eq!(vals.len(), 2);
+    /// assert_eq!(cursor.peek_rule(), Some("c"));
+    /// ```
+    pub fn take_while<F>(&mut self, mut f: F) -> &'a [Node<V>]
+    where
+        F: FnMut(&Node<V>) -> bool,
+    {
+        let n = self.nodes.iter().take_while(|n| f(n)).count();
+        let (taken, rest) = self.nodes.split_at(n);
+        self.nodes = rest;
+        taken
+    }
+
+    /// Consume the consecutive ```name``` rules, processing every one
+    ///
+    /// It stops at the first error
+    ///
+    /// ```
+    /// use dynparser::ast::{cursor::NodeCursor, Node};
+    ///
+    /// let ast = Node::from_sexpr(r#"(main (num "1") (num "2") ";")"#).unwrap();
+    ///
+    /// let mut cursor = NodeCursor::children(&ast);
+    /// let nums = cursor
+    ///     .collect_rules("num", |mut c| c.expect_value().map(|v| v.parse::<u32>().unwrap()))
+    ///     .unwrap();
+    /// assert_eq!(nums, vec![1, 2]);
+    /// assert!(cursor.expect_this_value(";").is_ok());
+    /// assert!(cursor.expect_end().is_ok());
+    /// ```
+    pub fn collect_rules<T, F>(&mut self, name: &str, mut f: F) -> Result<Vec<T>, Error>
+    where
+        F: FnMut(NodeCursor<'a, V>) -> Result<T, Error>,
+    {
+        let mut result = vec![];
+        while let Some(children) = self.optional_rule(name) {
+            result.push(f(children)?);
+        }
+        Ok(result)
+    }
+
+    /// Error if there are nodes not consumed
+    pub fn expect_end(&self) -> Result<(), Error> {
+        match self.peek() {
+            None => Ok(()),
+            other => Err(self.unexpected("expected end of nodes", other)),
+        }
+    }
+
+    fn unexpected(&self, descr: &str, found: Option<&Node<V>>) -> Error {
+        match found {
+            Some(node) => error(descr, Some(&format!("{:?}", node))),
+            None => error(&format!("{}, found end of nodes", descr), None),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Kind of expression that produced every node
+//!
+//! On a ```Node<String>``` a leaf is just text. ```parse_with_kinds```
+//! produces a ```Node<Leaf>```, where every value knows if it came
+//! from a literal, a character class, a dot or a custom matcher
+//!
+//! ```
+//! use dynparser::ast::kind::NodeKind;
+//! use dynparser::{parse_with_kinds, rules_from_peg, ParseOptions};
+//!
+//! let rules = rules_from_peg(
+//!     r#"
+//! main    =   num  '+'  .
+//! num     =   [0-9]+
+//!     "#,
+//! ).unwrap();
+//!
+//! let ast = parse_with_kinds("1+x", &rules, &ParseOptions::default()).unwrap();
+//! assert_eq!(ast.kind(), NodeKind::Rule("main".to_string()));
+//!
+//! let (_, nodes) = ast.rule_parts();
+//! let kinds: Vec<NodeKind> = nodes.iter().map(|n| n.kind()).collect();
+//! assert_eq!(
+//!     kinds,
+//!     vec![NodeKind::Rule("num".to_string()), NodeKind::Literal, NodeKind::Dot]
+//! );
+//!
+//! //  for the code expecting text values
+//! let ast = ast.without_kinds();
+//! assert_eq!(ast, dynparser::parse("1+x", &rules).unwrap());
+//! ```
+
+use crate::ast::Node;
+
+/// Expression that produced a node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    /// A rule, with its name
+    Rule(String),
+    /// A literal (```'abc'```, ```'abc'i```)
+    Literal,
+    /// A character class (```[a-z]```, ```xid_start```...)
+    Class,
+    /// Any char (```.```)
+    Dot,
+    /// A custom matcher, with its name
+    Custom(String),
+    /// Source text matched by a rule annotated with ```@raw```
+    Raw,
+    /// Text consumed by other atoms (```until```, ```balanced```) and
+    /// zero width assertions (```bol```, ```warning```...)
+    Other,
+    /// End of file (```eof```, the value is ```"EOF"```)
+    EOF,
+}
+
+/// Value of a node, with the kind of expression that produced it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leaf {
+    /// The expression that produced the value
+    pub kind: NodeKind,
+    /// Consumed text
+    pub text: String,
+}
+
+impl AsRef<str> for Leaf {
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Node<Leaf> {
+    /// Kind of expression that produced the node
+    pub fn kind(&self) -> NodeKind {
+        match self {
+            Node::Rule((name, _)) => NodeKind::Rule(name.clone()),
+            Node::Val(leaf) => leaf.kind.clone(),
+            Node::EOF => NodeKind::EOF,
+        }
+    }
+
+    /// The same tree, with the text of the values
+    pub fn without_kinds(&self) -> Node {
+        self.map_vals(&|leaf: &Leaf| leaf.text.clone())
+    }
+}
+
+impl From<Node<Leaf>> for Node {
+    fn from(node: Node<Leaf>) -> Self {
+        node.without_kinds()
+    }
+}
+
+impl Node {
+    //  kinds of the values, in the order of the tree
+    pub(crate) fn with_kinds(self, kinds: &mut impl Iterator<Item = NodeKind>) -> Node<Leaf> {
+        match self {
+            Node::Rule((name, nodes)) => Node::Rule((
+                name,
+                nodes.into_iter().map(|n| n.with_kinds(kinds)).collect(),
+            )),
+            Node::Val(text) => Node::Val(Leaf {
+                kind: kinds.next().unwrap_or(NodeKind::Other),
+                text,
+            }),
+            Node::EOF => Node::EOF,
+        }
+    }
+}
@@ -2,10 +2,17 @@
 //! And some functions to work with AST
 //!
 
+use crate::parser::expression::{RuleId, RuleNames};
 use idata::cont::IVec;
 use std::result::Result;
 
+pub mod builder;
+pub mod cursor;
 pub mod flat;
+pub mod kind;
+mod sexpr;
+pub mod trivia;
+pub mod value;
 
 // -------------------------------------------------------------------------------------
 //  T Y P E S
@@ -33,18 +40,27 @@ pub fn error(desc: &str, ast_context: Option<&str>) -> Error {
 }
 
 /// Information of a node
-#[derive(Debug, PartialEq)]
-pub enum Node {
+///
+/// Parsing produces ```Node<String>```, where values are the
+/// consumed text. Once the tree is built, it's possible to
+/// replace the values with typed data (numbers, enums...)
+/// with ```map_vals``` or ```try_map_vals```, keeping the
+/// shape of the tree
+///
+/// In the same way, rule names can be replaced by ```RuleId```
+/// with ```with_rule_ids```
+#[derive(Debug, PartialEq, Clone)]
+pub enum Node<V = String, K = String> {
     /// The node is terminal (atom) with a name
-    Val(String),
+    Val(V),
     /// The node is not terminal (rule)
     /// with a name and a vec of nodes
-    Rule((String, Vec<Node>)),
+    Rule((K, Vec<Node<V, K>>)),
     /// Reached end of file
     EOF,
 }
 
-impl Node {
+impl<V: Clone> Node<V> {
     /// Remove nodes with one of the names in the list.
     /// It will remove the childs
     /// ```
@@ -71,11 +87,11 @@ impl Node {
 
     pub fn prune(&self, nodes2prune: &[&str]) -> Self {
         let nname2prune = |nname: &str| nodes2prune.iter().find(|n| *n == &nname);
-        let node2prune = |node: &Node| match node {
+        let node2prune = |node: &Node<V>| match node {
             Node::Rule((nname, _)) => nname2prune(nname).is_some(),
             _ => false,
         };
-        let prune_vn = |vnodes: &[Node]| {
+        let prune_vn = |vnodes: &[Node<V>]| {
             vnodes.iter().fold(vec![], |acc, n| {
                 if !node2prune(n) {
                     acc.ipush(n.prune(nodes2prune))
@@ -120,9 +136,9 @@ impl Node {
     /// ```
 
     pub fn pass_through_except(&self, nodes2keep: &[&str]) -> Self {
-        fn pthr_vn(vnodes: &[Node], nodes2keep: &[&str]) -> Vec<Node> {
+        fn pthr_vn<V: Clone>(vnodes: &[Node<V>], nodes2keep: &[&str]) -> Vec<Node<V>> {
             let nname2keep = |nname: &str| nodes2keep.iter().find(|n| *n == &nname);
-            let node2keep = |node: &Node| match node {
+            let node2keep = |node: &Node<V>| match node {
                 Node::Rule((nname, _)) => nname2keep(nname).is_some(),
                 _ => true,
             };
@@ -143,7 +159,194 @@ impl Node {
             Node::Rule((n, vn)) => Node::Rule((n.clone(), pthr_vn(vn, nodes2keep))),
         }
     }
+}
+
+impl<V, K: Clone> Node<V, K> {
+    /// Replace the values of the tree, keeping the shape
+    /// ```
+    ///    use dynparser::ast;
+    ///
+    ///    let ast = ast::Node::Rule((
+    ///        "sum".to_string(),
+    ///        vec![
+    ///            ast::Node::Val("1".to_string()),
+    ///            ast::Node::Val("22".to_string()),
+    ///        ],
+    ///    ));
+    ///
+    ///    let lengths: ast::Node<usize> = ast.map_vals(&|v| v.len());
+    ///
+    ///    assert!(
+    ///        lengths
+    ///            == ast::Node::Rule((
+    ///                "sum".to_string(),
+    ///                vec![ast::Node::Val(1), ast::Node::Val(2)]
+    ///            ))
+    ///    )
+    /// ```
+    pub fn map_vals<W, F>(&self, f: &F) -> Node<W, K>
+    where
+        F: Fn(&V) -> W,
+    {
+        match self {
+            Node::EOF => Node::EOF,
+            Node::Val(v) => Node::Val(f(v)),
+            Node::Rule((n, vn)) => {
+                Node::Rule((n.clone(), vn.iter().map(|n| n.map_vals(f)).collect()))
+            }
+        }
+    }
+
+    /// Replace the values of the tree, keeping the shape
+    /// The conversion could fail. It will stop on first error
+    /// ```
+    ///    use dynparser::ast;
+    ///
+    ///    let ast = ast::Node::Rule((
+    ///        "sum".to_string(),
+    ///        vec![
+    ///            ast::Node::Val("1".to_string()),
+    ///            ast::Node::Val("22".to_string()),
+    ///        ],
+    ///    ));
+    ///
+    ///    let numbers = ast.try_map_vals(&|v| v.parse::<i64>()).unwrap();
+    ///
+    ///    assert!(
+    ///        numbers
+    ///            == ast::Node::Rule((
+    ///                "sum".to_string(),
+    ///                vec![ast::Node::Val(1), ast::Node::Val(22)]
+    ///            ))
+    ///    );
+    ///
+    ///    let wrong: ast::Node = ast::Node::Val("a".to_string());
+    ///    assert!(wrong.try_map_vals(&|v| v.parse::<i64>()).is_err());
+    /// ```
+    pub fn try_map_vals<W, E, F>(&self, f: &F) -> Result<Node<W, K>, E>
+    where
+        F: Fn(&V) -> Result<W, E>,
+    {
+        match self {
+            Node::EOF => Ok(Node::EOF),
+            Node::Val(v) => Ok(Node::Val(f(v)?)),
+            Node::Rule((n, vn)) => Ok(Node::Rule((
+                n.clone(),
+                vn.iter()
+                    .map(|n| n.try_map_vals(f))
+                    .collect::<Result<_, _>>()?,
+            ))),
+        }
+    }
+}
+
+impl<V: Clone, K> Node<V, K> {
+    /// Replace the rule names of the tree, keeping the shape
+    /// ```
+    ///    use dynparser::ast;
+    ///
+    ///    let ast = ast::Node::Rule((
+    ///        "main".to_string(),
+    ///        vec![ast::Node::Rule(("num".to_string(), vec![]))],
+    ///    ));
+    ///
+    ///    let lengths: ast::Node<String, usize> = ast.map_rules(&|name| name.len());
+    ///
+    ///    assert!(lengths == ast::Node::Rule((4, vec![ast::Node::Rule((3, vec![]))])))
+    /// ```
+    pub fn map_rules<K2, F>(&self, f: &F) -> Node<V, K2>
+    where
+        F: Fn(&K) -> K2,
+    {
+        match self {
+            Node::EOF => Node::EOF,
+            Node::Val(v) => Node::Val(v.clone()),
+            Node::Rule((n, vn)) => Node::Rule((f(n), vn.iter().map(|n| n.map_rules(f)).collect())),
+        }
+    }
 
+    /// Replace the rule names of the tree, keeping the shape
+    /// The conversion could fail. It will stop on first error
+    pub fn try_map_rules<K2, E, F>(&self, f: &F) -> Result<Node<V, K2>, E>
+    where
+        F: Fn(&K) -> Result<K2, E>,
+    {
+        match self {
+            Node::EOF => Ok(Node::EOF),
+            Node::Val(v) => Ok(Node::Val(v.clone())),
+            Node::Rule((n, vn)) => Ok(Node::Rule((
+                f(n)?,
+                vn.iter()
+                    .map(|n| n.try_map_rules(f))
+                    .collect::<Result<_, _>>()?,
+            ))),
+        }
+    }
+}
+
+impl<V: Clone> Node<V> {
+    /// Replace the rule names with ```RuleId```
+    ///
+    /// Names not registered on the table (i.e. nodes added by hand)
+    /// will produce an error
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::{ast, parse};
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  and!(ref_rule!("a"), ref_rule!("a")),
+    ///        "a"      =>  lit!("a")
+    ///     };
+    ///     let names = rules.rule_names();
+    ///     let id_a = names.id("a").unwrap();
+    ///     let id_main = names.id("main").unwrap();
+    ///
+    ///     let ast = parse("aa", &rules).unwrap().with_rule_ids(&names).unwrap();
+    ///
+    ///     match ast {
+    ///         ast::Node::Rule((id, nodes)) => {
+    ///             assert!(id == id_main);
+    ///             assert!(nodes[0] == ast::Node::Rule((id_a, vec![ast::Node::Val("a".to_string())])));
+    ///         }
+    ///         _ => panic!("expected rule"),
+    ///     }
+    /// }
+    /// ```
+    pub fn with_rule_ids(&self, names: &RuleNames) -> Result<Node<V, RuleId>, Error> {
+        self.try_map_rules(&|name: &String| {
+            names
+                .id(name)
+                .ok_or_else(|| error(&format!("unknown rule name {}", name), None))
+        })
+    }
+}
+
+impl<V> Node<V> {
+    /// Rule name and children of the node
+    ///
+    /// For ```Val``` and ```EOF``` nodes, there is no name nor children.
+    /// It's used by ```node_match!```
+    /// ```
+    ///    use dynparser::ast::Node;
+    ///
+    ///    let node: Node = Node::Rule(("num".to_string(), vec![Node::Val("12".to_string())]));
+    ///
+    ///    match node.rule_parts() {
+    ///        (Some("num"), [Node::Val(v)]) => assert_eq!(v, "12"),
+    ///        _ => panic!("expected num"),
+    ///    }
+    ///    assert!(Node::<String>::EOF.rule_parts() == (None, &[][..]));
+    /// ```
+    pub fn rule_parts(&self) -> (Option<&str>, &[Node<V>]) {
+        match self {
+            Node::Rule((name, nodes)) => (Some(name.as_str()), nodes.as_slice()),
+            Node::Val(_) | Node::EOF => (None, &[]),
+        }
+    }
+}
+
+impl Node {
     /// Concat consecutive Val nodes
     /// ```
     ///    use dynparser::ast;
@@ -222,7 +425,7 @@ impl Node {
 ///    assert!(node_name == "root");
 ///    assert!(nodes[0] == ast::Node::Val("hello".to_string()),)
 /// ```
-pub fn get_nodename_and_nodes(node: &Node) -> Result<(&str, &[Node]), Error> {
+pub fn get_nodename_and_nodes<V>(node: &Node<V>) -> Result<(&str, &[Node<V>]), Error> {
     match node {
         Node::Rule((nname, nodes)) => Ok((nname, nodes)),
         _ => Err(error("expected node::Rule", None)),
@@ -328,7 +531,7 @@ pub fn consume_val(nodes: &[Node]) -> Result<(&str, &[Node]), Error> {
 ///     assert!(nodes.len() == 0);
 ///```
 ///
-pub fn split_first_nodes(nodes: &[Node]) -> Result<(&Node, &[Node]), Error> {
+pub fn split_first_nodes<V>(nodes: &[Node<V>]) -> Result<(&Node<V>, &[Node<V>]), Error> {
     nodes
         .split_first()
         .ok_or_else(|| error("trying get first element from nodes on empty slice", None))
@@ -363,6 +566,9 @@ pub fn consume_this_value<'a>(v: &str, nodes: &'a [Node]) -> Result<&'a [Node],
     }
 }
 
+//  rest of nodes, and sub nodes of the consumed one
+type RestAndSubnodes<'a, V> = (&'a [Node<V>], &'a [Node<V>]);
+
 /// Consume a node if it's a Rule kind with a specific value
 /// and return the rest of nodes and the sub_nodes for the consumed node
 ///
@@ -377,10 +583,10 @@ pub fn consume_this_value<'a>(v: &str, nodes: &'a [Node]) -> Result<&'a [Node],
 ///     let nodes = ast::consume_this_value("world", &sub_nodes).unwrap();
 ///```
 ///
-pub fn consume_node_get_subnodes_for_rule_name_is<'a>(
+pub fn consume_node_get_subnodes_for_rule_name_is<'a, V: std::fmt::Debug>(
     name: &str,
-    nodes: &'a [Node],
-) -> Result<(&'a [Node], &'a [Node]), Error> {
+    nodes: &'a [Node<V>],
+) -> Result<RestAndSubnodes<'a, V>, Error> {
     let (node, nodes) = split_first_nodes(nodes)?;
     match node {
         Node::Rule((n, sub_nodes)) => {
@@ -430,7 +636,7 @@ pub fn check_empty_nodes(nodes: &[Node]) -> Result<(), Error> {
 ///     assert!(first == &ast::Node::Rule(("hello".to_string(), vec![])));
 ///```
 ///
-pub fn peek_first_node(nodes: &[Node]) -> Result<&Node, Error> {
+pub fn peek_first_node<V>(nodes: &[Node<V>]) -> Result<&Node<V>, Error> {
     if nodes.is_empty() {
         Err(error("exptected node on peek_first_node", None))
     } else {
@@ -0,0 +1,254 @@
+//! Compact textual representation of an AST as s-expressions
+//!
+//! It's a stable and human editable format. Less verbose than
+//! `{:#?}` or json. Useful i.e. to keep expected trees on test
+//! fixtures
+//!
+//! ```ignore
+//!     (main (letter "a") (letter_or_num (number "2")))
+//! ```
+//!
+//! * A `Node::Rule` is a list, the first element is the rule name
+//! * A `Node::Val` is a string delimited by `"`
+//! * A `Node::EOF` is the symbol `EOF`
+//!
+//! Rule names with special chars will be written between `"`
+//!
+
+use crate::ast::{self, error, Error};
+use std::iter::Peekable;
+use std::result::Result;
+use std::str::Chars;
+
+impl ast::Node {
+    /// Write the AST as an s-expression
+    ///
+    /// ```
+    ///    use dynparser::ast;
+    ///
+    ///    let ast = ast::Node::Rule((
+    ///        "main".to_string(),
+    ///        vec![
+    ///            ast::Node::Rule(("letter".to_string(), vec![ast::Node::Val("a".to_string())])),
+    ///            ast::Node::Val("say \"hi\"".to_string()),
+    ///            ast::Node::Rule(("_\"".to_string(), vec![])),
+    ///            ast::Node::EOF,
+    ///        ],
+    ///    ));
+    ///
+    ///    assert_eq!(
+    ///        ast.to_sexpr(),
+    ///        r#"(main (letter "a") "say \"hi\"" ("_\"") EOF)"#
+    ///    );
+    /// ```
+    pub fn to_sexpr(&self) -> String {
+        match self {
+            ast::Node::EOF => "EOF".to_string(),
+            ast::Node::Val(v) => quote(v),
+            ast::Node::Rule((name, nodes)) => {
+                let name = rule_name(name);
+                nodes.iter().fold(format!("({}", name), |acc, n| {
+                    format!("{} {}", acc, n.to_sexpr())
+                }) + ")"
+            }
+        }
+    }
+
+    /// Same as ```to_sexpr```, but with a line per node (indented),
+    /// unless the node fits on a short line
+    ///
+    /// ```
+    ///    use dynparser::ast;
+    ///
+    ///    let ast = ast::Node::from_sexpr(
+    ///        r#"(main (letter "a") (letter_or_num (number "22222222222222222222222222222222")))"#,
+    ///    ).unwrap();
+    ///
+    ///    assert_eq!(
+    ///        ast.to_sexpr_pretty(),
+    ///        "(main\n  (letter \"a\")\n  (letter_or_num\n    (number \"22222222222222222222222222222222\")))"
+    ///    );
+    /// ```
+    pub fn to_sexpr_pretty(&self) -> String {
+        pretty(self, 0)
+    }
+
+    /// Build an AST from an s-expression
+    ///
+    /// Spaces and new lines between elements are ignored
+    ///
+    /// ```
+    ///    use dynparser::ast;
+    ///
+    ///    let ast = ast::Node::from_sexpr(
+    ///        r#"
+    ///        (main
+    ///            (letter "a")
+    ///            "\n"
+    ///            EOF)
+    ///        "#,
+    ///    ).unwrap();
+    ///
+    ///    assert!(
+    ///        ast == ast::Node::Rule((
+    ///            "main".to_string(),
+    ///            vec![
+    ///                ast::Node::Rule(("letter".to_string(), vec![ast::Node::Val("a".to_string())])),
+    ///                ast::Node::Val("\n".to_string()),
+    ///                ast::Node::EOF,
+    ///            ],
+    ///        ))
+    ///    );
+    ///
+    ///    assert!(ast::Node::from_sexpr(&ast.to_sexpr()).unwrap() == ast);
+    ///    assert!(ast::Node::from_sexpr("(main").is_err());
+    /// ```
+    pub fn from_sexpr(text: &str) -> Result<Self, Error> {
+        let mut chars = text.chars().peekable();
+        let node = parse_node(&mut chars)?;
+        skip_spaces(&mut chars);
+        match chars.next() {
+            None => Ok(node),
+            Some(ch) => Err(error(
+                &format!("unexpected char after s-expression <{}>", ch),
+                None,
+            )),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------
+//  SUPPORT
+
+//  longest node written on a line
+const PRETTY_WIDTH: usize = 60;
+
+fn pretty(node: &ast::Node, indent: usize) -> String {
+    let line = node.to_sexpr();
+    let fits = indent + line.len() <= PRETTY_WIDTH;
+    match node {
+        ast::Node::Rule((name, nodes)) if !fits => {
+            let (open, margin) = (format!("({}", rule_name(name)), " ".repeat(indent + 2));
+            nodes.iter().fold(open, |acc, n| {
+                format!("{}\n{}{}", acc, margin, pretty(n, indent + 2))
+            }) + ")"
+        }
+        _ => line,
+    }
+}
+
+fn rule_name(name: &str) -> String {
+    if is_symbol(name) {
+        name.to_string()
+    } else {
+        quote(name)
+    }
+}
+
+fn is_symbol_char(ch: char) -> bool {
+    !(ch.is_whitespace() || ch == '(' || ch == ')' || ch == '"' || ch == '\\')
+}
+
+fn is_symbol(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(is_symbol_char)
+}
+
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace("\\", r#"\\"#)
+        .replace("\"", r#"\""#)
+        .replace("\n", r#"\n"#)
+        .replace("\r", r#"\r"#)
+        .replace("\t", r#"\t"#);
+    format!(r#""{}""#, escaped)
+}
+
+fn skip_spaces(chars: &mut Peekable<Chars>) {
+    while let Some(ch) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else {
+            break;
+        }
+    }
+}
+
+fn parse_node(chars: &mut Peekable<Chars>) -> Result<ast::Node, Error> {
+    skip_spaces(chars);
+    match chars.peek() {
+        Some('(') => {
+            chars.next();
+            parse_rule(chars)
+        }
+        Some('"') => Ok(ast::Node::Val(parse_string(chars)?)),
+        Some(_) => match parse_symbol(chars).as_ref() {
+            "EOF" => Ok(ast::Node::EOF),
+            symbol => Err(error(&format!("unexpected symbol <{}>", symbol), None)),
+        },
+        None => Err(error("expected node on s-expression", None)),
+    }
+}
+
+fn parse_rule(chars: &mut Peekable<Chars>) -> Result<ast::Node, Error> {
+    skip_spaces(chars);
+    let name = match chars.peek() {
+        Some('"') => parse_string(chars)?,
+        Some(_) => parse_symbol(chars),
+        None => String::new(),
+    };
+    if name.is_empty() {
+        return Err(error("expected rule name on s-expression", None));
+    }
+
+    let mut nodes = vec![];
+    loop {
+        skip_spaces(chars);
+        match chars.peek() {
+            Some(')') => {
+                chars.next();
+                return Ok(ast::Node::Rule((name, nodes)));
+            }
+            Some(_) => nodes.push(parse_node(chars)?),
+            None => {
+                return Err(error(
+                    &format!("unbalanced parenthesis on rule <{}>", name),
+                    None,
+                ))
+            }
+        }
+    }
+}
+
+fn parse_symbol(chars: &mut Peekable<Chars>) -> String {
+    let mut symbol = String::new();
+    while let Some(&ch) = chars.peek() {
+        if is_symbol_char(ch) {
+            symbol.push(ch);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    symbol
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, Error> {
+    chars.next(); //  opening "
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('\\') => s.push('\\'),
+                Some('"') => s.push('"'),
+                Some(ch) => return Err(error(&format!("unknown escape char <{}>", ch), None)),
+                None => return Err(error("unterminated string on s-expression", None)),
+            },
+            Some(ch) => s.push(ch),
+            None => return Err(error("unterminated string on s-expression", None)),
+        }
+    }
+}
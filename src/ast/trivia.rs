@@ -0,0 +1,180 @@
+//! Keep spaces and comments attached to the values
+//!
+//! ```prune``` removes the rules for spaces and comments, and their
+//! text is lost. ```attach_trivia``` removes them too, but their text
+//! is kept on the nearest value, as ```leading``` or ```trailing```
+//! trivia. Formatters and documentation tools need it
+//!
+//! The trivia after a value, up to the end of its line (new line
+//! included), is its ```trailing```. The rest is the ```leading``` of
+//! the next value. The trivia at the end of the input is the
+//! ```trailing``` of the last value
+//!
+//! ```
+//! use dynparser::{parse, rules_from_peg};
+//!
+//! let rules = rules_from_peg(
+//!     r#"
+//! main    =   (_  item)*  _
+//! item    =   [a-z]+
+//! _       =   (' '  /  "\n"  /  comment)*
+//! comment =   '#'  (!"\n"  .)*
+//!     "#,
+//! ).unwrap();
+//!
+//! let ast = parse("# items\nfoo # first\nbar\n", &rules).unwrap();
+//! let ast = ast.compact().attach_trivia(&["_"]);
+//!
+//! let (_, items) = ast.rule_parts();
+//! let (_, foo) = items[0].rule_parts();
+//! match &foo[0] {
+//!     dynparser::ast::Node::Val(foo) => {
+//!         assert_eq!(foo.leading, "# items\n");
+//!         assert_eq!(foo.value, "foo");
+//!         assert_eq!(foo.trailing, " # first\n");
+//!     }
+//!     _ => panic!("expected a value"),
+//! }
+//!
+//! //  nothing is lost
+//! assert_eq!(ast.text_with_trivia(), "# items\nfoo # first\nbar\n");
+//! ```
+
+use crate::ast::Node;
+
+/// A value, with the trivia around it
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithTrivia<V = String> {
+    /// Trivia before the value
+    pub leading: String,
+    /// The value
+    pub value: V,
+    /// Trivia after the value, up to the end of the line
+    pub trailing: String,
+}
+
+impl<V: AsRef<str>> AsRef<str> for WithTrivia<V> {
+    fn as_ref(&self) -> &str {
+        self.value.as_ref()
+    }
+}
+
+impl<V: Clone + AsRef<str>> Node<V> {
+    /// Remove the nodes with one of the names in the list, keeping
+    /// their text as trivia of the nearest value
+    ///
+    /// Without values on the tree, the trivia is lost
+    pub fn attach_trivia(&self, trivia_rules: &[&str]) -> Node<WithTrivia<V>> {
+        let mut gaps = vec![String::new()];
+        collect_gaps(self, trivia_rules, &mut gaps);
+
+        let last = gaps.len() - 1;
+        let mut leading = std::mem::take(&mut gaps[0]);
+        let mut trivia = vec![];
+        for (i, gap) in gaps.into_iter().enumerate().skip(1) {
+            let (trailing, next_leading) = if i == last {
+                (gap.as_str(), "")
+            } else {
+                split_line(&gap)
+            };
+            trivia.push((leading, trailing.to_string()));
+            leading = next_leading.to_string();
+        }
+
+        self.with_trivia(trivia_rules, &mut trivia.into_iter())
+            .unwrap_or(Node::EOF)
+    }
+
+    /// Concatenated text of the values
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        push_text(self, &mut text);
+        text
+    }
+
+    fn with_trivia(
+        &self,
+        trivia_rules: &[&str],
+        trivia: &mut impl Iterator<Item = (String, String)>,
+    ) -> Option<Node<WithTrivia<V>>> {
+        match self {
+            Node::Rule((name, _)) if trivia_rules.contains(&name.as_str()) => None,
+            Node::Rule((name, nodes)) => Some(Node::Rule((
+                name.clone(),
+                nodes
+                    .iter()
+                    .filter_map(|n| n.with_trivia(trivia_rules, trivia))
+                    .collect(),
+            ))),
+            Node::Val(value) => {
+                let (leading, trailing) = trivia.next().unwrap_or_default();
+                Some(Node::Val(WithTrivia {
+                    leading,
+                    value: value.clone(),
+                    trailing,
+                }))
+            }
+            Node::EOF => Some(Node::EOF),
+        }
+    }
+}
+
+impl<V: Clone + AsRef<str>> Node<WithTrivia<V>> {
+    /// The same tree, without the trivia
+    pub fn without_trivia(&self) -> Node<V> {
+        self.map_vals(&|v: &WithTrivia<V>| v.value.clone())
+    }
+
+    /// Concatenated text of the values and the trivia
+    pub fn text_with_trivia(&self) -> String {
+        let mut text = String::new();
+        push_text(
+            &self.map_vals(&|v: &WithTrivia<V>| {
+                format!("{}{}{}", v.leading, v.value.as_ref(), v.trailing)
+            }),
+            &mut text,
+        );
+        text
+    }
+}
+
+//-----------------------------------------------------------------------
+//  I N T E R N A L
+
+//  trivia before every value, and after the last one
+fn collect_gaps<V: AsRef<str>>(node: &Node<V>, trivia_rules: &[&str], gaps: &mut Vec<String>) {
+    match node {
+        Node::Rule((name, _)) if trivia_rules.contains(&name.as_str()) => {
+            if let Some(gap) = gaps.last_mut() {
+                push_text(node, gap);
+            }
+        }
+        Node::Rule((_, nodes)) => {
+            for n in nodes {
+                collect_gaps(n, trivia_rules, gaps);
+            }
+        }
+        Node::Val(_) => gaps.push(String::new()),
+        Node::EOF => (),
+    }
+}
+
+fn push_text<V: AsRef<str>>(node: &Node<V>, text: &mut String) {
+    match node {
+        Node::Rule((_, nodes)) => {
+            for n in nodes {
+                push_text(n, text);
+            }
+        }
+        Node::Val(v) => text.push_str(v.as_ref()),
+        Node::EOF => (),
+    }
+}
+
+//  up to the end of the first line (included), and the rest
+fn split_line(s: &str) -> (&str, &str) {
+    match s.find('\n') {
+        Some(nl) => s.split_at(nl + 1),
+        None => (s, ""),
+    }
+}
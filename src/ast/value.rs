@@ -0,0 +1,163 @@
+//! Convert the values of the AST to numbers
+//!
+//! Numeric literals are usually processed after parsing. These helpers
+//! report wrong nodes, invalid digits and overflows as errors
+//!
+//! ```
+//! use dynparser::ast::value::ValueError;
+//! use dynparser::{parse, rules_from_peg};
+//!
+//! let rules = rules_from_peg(
+//!     r#"
+//! main    =   num  ' '  hex
+//! num     =   '-'? [0-9]+
+//! hex     =   [0-9a-fA-F]+
+//!     "#,
+//! ).unwrap();
+//!
+//! let ast = parse("-12 ff", &rules).unwrap().compact();
+//! let (_, nodes) = ast.rule_parts();
+//! assert_eq!(nodes[0].value_as::<i32>(), Ok(-12));
+//! assert_eq!(nodes[2].value_as_radix::<u8>(16), Ok(255));
+//!
+//! assert_eq!(
+//!     nodes[0].value_as::<u32>(),
+//!     Err(ValueError::InvalidDigit("-12".to_string()))
+//! );
+//!
+//! let ast = parse("300 fff", &rules).unwrap().compact();
+//! let (_, nodes) = ast.rule_parts();
+//! assert_eq!(
+//!     nodes[0].value_as::<u8>(),
+//!     Err(ValueError::Overflow("300".to_string()))
+//! );
+//! assert!(matches!(ast.value_as::<u8>(), Err(ValueError::WrongNode(_))));
+//! ```
+
+use crate::ast::{error, Error, Node};
+use std::fmt::{self, Debug};
+use std::num::{IntErrorKind, ParseIntError};
+
+/// Error converting the value of a node
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueError {
+    /// The node is not a value, nor a rule with only values
+    WrongNode(String),
+    /// There is no text to convert
+    Empty,
+    /// The text is not a valid number for the type (or the radix)
+    InvalidDigit(String),
+    /// The number doesn't fit on the type
+    Overflow(String),
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueError::WrongNode(node) => write!(f, "expected value, found {}", node),
+            ValueError::Empty => write!(f, "expected number, found empty value"),
+            ValueError::InvalidDigit(v) => write!(f, "invalid number <{}>", v),
+            ValueError::Overflow(v) => write!(f, "number out of range <{}>", v),
+        }
+    }
+}
+
+impl From<ValueError> for Error {
+    fn from(e: ValueError) -> Self {
+        match e {
+            ValueError::WrongNode(node) => error("expected value", Some(&node)),
+            e => error(&e.to_string(), None),
+        }
+    }
+}
+
+/// Types that can be created from the text of a value
+pub trait FromValue: Sized {
+    /// Convert the text
+    fn from_value(text: &str) -> Result<Self, ValueError>;
+}
+
+/// Integer types, also from a text on a radix
+pub trait FromValueRadix: FromValue {
+    /// Convert the text written on the radix (from 2 to 36)
+    fn from_value_radix(text: &str, radix: u32) -> Result<Self, ValueError>;
+}
+
+impl<V: AsRef<str> + Debug, K: Debug> Node<V, K> {
+    /// Convert the value of the node
+    ///
+    /// The node can be a ```Val``` or a rule with only values as
+    /// children (they will be concatenated)
+    pub fn value_as<T: FromValue>(&self) -> Result<T, ValueError> {
+        T::from_value(&self.value_text()?)
+    }
+
+    /// Convert the value of the node, written on the radix
+    pub fn value_as_radix<T: FromValueRadix>(&self, radix: u32) -> Result<T, ValueError> {
+        T::from_value_radix(&self.value_text()?, radix)
+    }
+
+    fn value_text(&self) -> Result<String, ValueError> {
+        let wrong_node = || ValueError::WrongNode(format!("{:?}", self));
+        match self {
+            Node::Val(v) => Ok(v.as_ref().to_string()),
+            Node::Rule((_, children)) => {
+                children.iter().try_fold(String::new(), |acc, n| match n {
+                    Node::Val(v) => Ok(acc + v.as_ref()),
+                    _ => Err(wrong_node()),
+                })
+            }
+            Node::EOF => Err(wrong_node()),
+        }
+    }
+}
+
+fn int_error(text: &str, e: &ParseIntError) -> ValueError {
+    match e.kind() {
+        IntErrorKind::Empty => ValueError::Empty,
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+            ValueError::Overflow(text.to_string())
+        }
+        _ => ValueError::InvalidDigit(text.to_string()),
+    }
+}
+
+macro_rules! from_value_int {
+    ($($t:ty),*) => {$(
+        impl FromValue for $t {
+            fn from_value(text: &str) -> Result<Self, ValueError> {
+                Self::from_value_radix(text, 10)
+            }
+        }
+
+        impl FromValueRadix for $t {
+            fn from_value_radix(text: &str, radix: u32) -> Result<Self, ValueError> {
+                if !(2..=36).contains(&radix) {
+                    return Err(ValueError::InvalidDigit(format!("{} (radix {})", text, radix)));
+                }
+                <$t>::from_str_radix(text, radix).map_err(|e| int_error(text, &e))
+            }
+        }
+    )*};
+}
+
+from_value_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+macro_rules! from_value_float {
+    ($($t:ty),*) => {$(
+        impl FromValue for $t {
+            fn from_value(text: &str) -> Result<Self, ValueError> {
+                match text.parse::<$t>() {
+                    _ if text.is_empty() => Err(ValueError::Empty),
+                    Ok(v) if v.is_infinite() && !text.to_lowercase().contains("inf") => {
+                        Err(ValueError::Overflow(text.to_string()))
+                    }
+                    Ok(v) => Ok(v),
+                    Err(_) => Err(ValueError::InvalidDigit(text.to_string())),
+                }
+            }
+        }
+    )*};
+}
+
+from_value_float!(f32, f64);
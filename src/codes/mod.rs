@@ -0,0 +1,179 @@
+#![warn(missing_docs)]
+//! Stable codes of the diagnostics
+//!
+//! Every error and warning the crate can emit has a code, i.e.
+//! ```E0101``` (```ExpectedLiteral```). Tools can link them to the
+//! documentation, or suppress some warnings
+//!
+//! ```E``` is for errors and ```W``` for warnings. The first two digits
+//! are the group
+//!
+//! * ```00``` The grammar (compiling a peg, references, plugins)
+//! * ```01``` The input (parsing)
+//! * ```02``` Warnings on the grammar (```analysis```) and on the input
+//!
+//! Once released, a code will not change its meaning. New diagnostics
+//! will get new codes
+//!
+//! ```
+//! use dynparser::codes::{self, Code, Severity};
+//! use dynparser::{parse, rules_from_peg};
+//!
+//! let rules = rules_from_peg("main = 'a'  [0-9]").unwrap();
+//! let err = parse("ab", &rules).unwrap_err();
+//! assert_eq!(err.code, Code::UnexpectedChar);
+//! assert_eq!(err.code.id(), "E0102");
+//!
+//! assert_eq!(Code::from_id("W0205"), Some(Code::DeprecatedRule));
+//! assert!(codes::catalog()
+//!     .iter()
+//!     .filter(|c| c.severity() == Severity::Warning)
+//!     .all(|c| c.id().starts_with('W')));
+//! ```
+
+use std::fmt;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Is it an error or a warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// The grammar or the input is not valid
+    Error,
+    /// Non fatal
+    Warning,
+}
+
+//  the code (as variant), its id and its description
+macro_rules! catalog {
+    ($($code:ident $id:literal $descr:literal,)*) => {
+        /// Identifier of a diagnostic
+        ///
+        /// Look at the module documentation
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum Code {
+            $(
+                #[doc = $descr]
+                $code,
+            )*
+        }
+
+        const CATALOG: &[Code] = &[$(Code::$code,)*];
+
+        impl Code {
+            /// Stable identifier, i.e. ```E0001```
+            pub fn id(self) -> &'static str {
+                match self {
+                    $(Code::$code => $id,)*
+                }
+            }
+
+            /// Name of the code, i.e. ```MissingRule```
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Code::$code => stringify!($code),)*
+                }
+            }
+
+            /// What it means
+            pub fn descr(self) -> &'static str {
+                match self {
+                    $(Code::$code => $descr,)*
+                }
+            }
+        }
+    };
+}
+
+catalog! {
+    MissingRule                 "E0001" "Reference to a rule not defined",
+    Syntax                      "E0002" "The grammar is not valid peg",
+    UnbalancedParen             "E0003" "An open parenthesis without the closing one",
+    UnsupportedVersion          "E0004" "Syntax version not supported",
+    InvalidDirective            "E0005" "Wrong directive (%syntax, %dot, %recover, %if...)",
+    UnknownRepetition           "E0006" "Repetition symbol not supported",
+    LazyRepetitionWithoutStop   "E0007" "A lazy repetition at the end of a sequence",
+    InvalidEscape               "E0008" "Escape or hex char not valid on a literal",
+    EmptyMatch                  "E0009" "A match ([...]) without chars nor ranges",
+    InvalidAnnotation           "E0010" "An annotation with a wrong value",
+    IdentifierWithoutKeywords   "E0011" "@identifier rules without keywords",
+    MissingIdentifierRule       "E0012" "@identifier on a rule not found",
+    UnknownTestRule             "E0013" "%test on a rule not defined",
+    InternalRuleReference       "E0014" "Reference to an internal rule from out of its scope",
+    SealedRule                  "E0015" "A plugin redefining or extending a sealed rule",
+    InternalRule                "E0016" "A plugin redefining or extending an internal rule",
+    ExtendMissingRule           "E0017" "Extending a rule not defined",
+    UnexpectedNodeShape         "E0018" "Unexpected AST compiling the grammar (a bug)",
+    CompileOther                "E0019" "Error on the grammar created out of the compiler",
+    InvalidRepetitionCount      "E0020" "A repetition count ({n,m}) not valid",
+    ExpectedLiteral             "E0101" "A literal was expected",
+    UnexpectedChar              "E0102" "The char is not the expected one (match, dot...)",
+    ExpectedEof                 "E0103" "The end of the input was expected",
+    ExpectedPosition            "E0104" "Beginning of input, beginning or end of line expected",
+    NegationMatched             "E0105" "A negated expression (!expr) matched",
+    ReservedWord                "E0106" "A keyword where an identifier was expected",
+    IncompleteInput             "E0107" "The input was not consumed completely",
+    UserError                   "E0108" "Error of the grammar (error(...), assert(...))",
+    DelimiterNotFound           "E0109" "Delimiter of until(...) or balanced(...) not found",
+    CustomRejected              "E0110" "A custom matcher rejected the input",
+    EmptyRecord                 "E0111" "Rule of consecutive records matching empty input",
+    EmptyOr                     "E0112" "An or without alternatives",
+    TooDeepNesting              "E0113" "Stack limit reached by nested rules",
+    TooManyRepetitions          "E0114" "Limit of repetitions reached",
+    LeftRecursion               "E0115" "Left recursive rule without a seed",
+    InvalidOffset               "E0116" "Starting offset not on a char boundary",
+    InvalidUtf8                 "E0117" "The input is not valid UTF-8",
+    ReadError                   "E0118" "The input could not be read",
+    UnknownEncoding             "E0119" "The label is not a known encoding",
+    ExpectedOneOf               "E0120" "None of the tokens expected on the position matched",
+    EmptyRepetition             "W0201" "Repetition of an expression that can match empty",
+    RecursivePrefix             "W0202" "Alternatives starting with the same recursive rule",
+    RepeatedPrefix              "W0203" "Alternatives starting with the same rule, repeated",
+    UserWarning                 "W0204" "Warning of the grammar (warning(...))",
+    DeprecatedRule              "W0205" "A deprecated rule was used",
+    UnusedRule                  "W0206" "A rule not reachable from main",
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// All the codes, sorted by id
+pub fn catalog() -> &'static [Code] {
+    CATALOG
+}
+
+impl Code {
+    /// Error or warning
+    pub fn severity(self) -> Severity {
+        if self.id().starts_with('W') {
+            Severity::Warning
+        } else {
+            Severity::Error
+        }
+    }
+
+    /// The code with the identifier (i.e. ```E0001```)
+    pub fn from_id(id: &str) -> Option<Code> {
+        CATALOG.iter().cloned().find(|c| c.id() == id)
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
@@ -0,0 +1,74 @@
+//-----------------------------------------------------------------------
+//
+//  mod codes  TEST
+//
+//-----------------------------------------------------------------------
+use super::{catalog, Code, Severity};
+use crate::parser::analysis;
+use crate::{parse, parse_with_warnings, peg, rules_from_peg};
+use std::collections::HashSet;
+
+#[test]
+fn test_catalog_ids() {
+    let ids: Vec<&str> = catalog().iter().map(|c| c.id()).collect();
+    let unique: HashSet<&str> = ids.iter().cloned().collect();
+    assert_eq!(unique.len(), ids.len());
+
+    let mut sorted = ids.clone();
+    sorted.sort();
+    assert_eq!(sorted, ids);
+
+    for code in catalog() {
+        assert_eq!(code.id().len(), 5);
+        let expected = if code.id().starts_with('E') {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        assert_eq!(code.severity(), expected);
+        assert_eq!(Code::from_id(code.id()), Some(*code));
+        assert_eq!(code.to_string(), code.id());
+        assert!(!code.descr().is_empty());
+    }
+    assert_eq!(Code::MissingRule.name(), "MissingRule");
+    assert_eq!(Code::from_id("E9999"), None);
+}
+
+#[test]
+fn test_codes_on_errors() {
+    let error = peg::rules_from_peg("main = 'a'*?").unwrap_err();
+    assert_eq!(error.code(), Code::LazyRepetitionWithoutStop);
+
+    let rules = rules_from_peg("main = 'ab'").unwrap();
+    assert_eq!(parse("ac", &rules).unwrap_err().code, Code::ExpectedLiteral);
+    assert_eq!(
+        parse("abc", &rules).unwrap_err().code,
+        Code::IncompleteInput
+    );
+
+    let rules = rules_from_peg("main = !'a' .").unwrap();
+    assert_eq!(parse("a", &rules).unwrap_err().code, Code::NegationMatched);
+}
+
+#[test]
+fn test_codes_on_warnings() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   old  ('a'*)*
+
+        @deprecated("don't")
+        old     =   'x'
+        "#,
+    )
+    .unwrap();
+
+    let parsed = parse_with_warnings("x", &rules).unwrap();
+    let codes: Vec<Code> = parsed.warnings.iter().map(|w| w.code).collect();
+    assert_eq!(codes, vec![Code::DeprecatedRule]);
+
+    let warnings = analysis::backtracking(&rules);
+    assert!(warnings.iter().any(|w| w.code == Code::EmptyRepetition));
+    assert!(warnings
+        .iter()
+        .all(|w| w.code.severity() == Severity::Warning));
+}
@@ -0,0 +1,116 @@
+#![warn(missing_docs)]
+//! Parse inputs not encoded as UTF-8 (i.e. legacy data on windows-1252)
+//!
+//! Available with the ```encoding``` feature
+//!
+//! The input is decoded before parsing. Errors positions refer to the
+//! decoded text, and the offset on the original bytes is also
+//! available
+//!
+//! Invalid sequences on the input will be replaced by
+//! ```U+FFFD REPLACEMENT CHARACTER```
+
+use crate::ast;
+use crate::codes::Code;
+use crate::parser::{self, expression};
+use encoding_rs::{CoderResult, Encoding};
+
+#[cfg(test)]
+mod test;
+
+/// Error parsing an encoded input
+#[derive(Debug)]
+pub enum Error {
+    /// The label is not a known encoding
+    UnknownEncoding(String),
+    /// Error parsing the decoded text
+    /// The second field is the offset of the error on the original bytes
+    Parser(parser::Error, usize),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::UnknownEncoding(label) => write!(f, "unknown encoding {}", label),
+            Error::Parser(e, offset) => write!(f, "{} (byte offset {})", e, offset),
+        }
+    }
+}
+
+impl Error {
+    /// Stable code of the error (look at ```codes```)
+    pub fn code(&self) -> Code {
+        match self {
+            Error::UnknownEncoding(_) => Code::UnknownEncoding,
+            Error::Parser(e, _) => e.code,
+        }
+    }
+}
+
+/// Decode the input with the encoding and parse it
+///
+/// The encoding label is one of the WHATWG Encoding Standard
+/// (```windows-1252```, ```latin1```, ```iso-8859-15```, ```shift_jis```...)
+///
+/// A BOM at the beginning of the input will be removed
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{encoding, parse_bytes_with_encoding};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(lit!("caf"), lit!("é"))
+///     };
+///
+///     //  "café" on windows-1252
+///     let bytes = b"caf\xE9";
+///     assert!(parse_bytes_with_encoding(bytes, "windows-1252", &rules).is_ok());
+///
+///     match parse_bytes_with_encoding(b"caf\xE9!", "windows-1252", &rules) {
+///         Err(encoding::Error::Parser(e, offset)) => {
+///             assert_eq!(e.descr, "not consumed full input");
+///             assert_eq!(offset, 4);
+///         }
+///         _ => panic!("expected parser error"),
+///     }
+/// }
+/// ```
+pub fn parse_bytes_with_encoding(
+    bytes: &[u8],
+    encoding: &str,
+    rules: &expression::SetOfRules,
+) -> Result<ast::Node, Error> {
+    let encoding = Encoding::for_label(encoding.as_bytes())
+        .ok_or_else(|| Error::UnknownEncoding(encoding.to_string()))?;
+    let (text, _) = encoding.decode_with_bom_removal(bytes);
+
+    crate::parse(&text, rules).map_err(|e| {
+        let offset = byte_offset(encoding, bytes, e.pos.n);
+        Error::Parser(e, offset)
+    })
+}
+
+//  offset on the original bytes, for the char position on decoded text
+//  it's only needed on errors, the input is decoded again byte by byte
+fn byte_offset(encoding: &'static Encoding, bytes: &[u8], n_char: usize) -> usize {
+    let mut decoder = encoding.new_decoder_with_bom_removal();
+    let mut decoded = String::with_capacity(16);
+    let mut chars = 0;
+    let mut sequence_start = 0;
+
+    for (i, byte) in bytes.iter().enumerate() {
+        decoded.clear();
+        let (result, _, _) = decoder.decode_to_string(&[*byte], &mut decoded, false);
+        debug_assert!(result == CoderResult::InputEmpty);
+        let produced = decoded.chars().count();
+        if produced > 0 {
+            if chars + produced > n_char {
+                return sequence_start;
+            }
+            chars += produced;
+            sequence_start = i + 1;
+        }
+    }
+    bytes.len()
+}
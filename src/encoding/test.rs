@@ -0,0 +1,45 @@
+//-----------------------------------------------------------------------
+//
+//  mod encoding  TEST
+//
+//-----------------------------------------------------------------------
+
+use super::{byte_offset, parse_bytes_with_encoding, Error};
+use encoding_rs::{SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+#[test]
+fn parse_latin1_lines() {
+    let rules = rules! {
+        "main"  => rep!(and!(ref_rule!("word"), lit!("\n")), 1),
+        "word"  => rep!(ematch!(chlist "ñçéü", from 'a', to 'z'), 1)
+    };
+
+    let bytes = b"ni\xF1o\nfa\xE7ade\n";
+    assert!(parse_bytes_with_encoding(bytes, "latin1", &rules).is_ok());
+
+    match parse_bytes_with_encoding(b"ni\xF1o\nfa\xE7ADE\n", "latin1", &rules) {
+        Err(Error::Parser(e, offset)) => {
+            assert_eq!(e.pos.row, 1);
+            assert!(e.line_before.starts_with("façA"));
+            //  one byte per char on latin1
+            assert_eq!(offset, e.pos.n);
+        }
+        _ => panic!("expected parser error"),
+    }
+
+    assert!(
+        match parse_bytes_with_encoding(bytes, "latin-9000", &rules) {
+            Err(Error::UnknownEncoding(label)) => label == "latin-9000",
+            _ => false,
+        }
+    );
+}
+
+#[test]
+fn byte_offsets_multibyte_encodings() {
+    //  "aあb" on shift_jis and utf-8
+    assert_eq!(byte_offset(SHIFT_JIS, b"a\x82\xA0b", 2), 3);
+    assert_eq!(byte_offset(SHIFT_JIS, b"a\x82\xA0b", 1), 1);
+    assert_eq!(byte_offset(UTF_8, b"\xEF\xBB\xBFa\xE3\x81\x82b", 2), 7);
+    assert_eq!(byte_offset(WINDOWS_1252, b"abc", 3), 3);
+}
@@ -0,0 +1,123 @@
+//! CSV and TSV records (RFC 4180)
+//!
+//! Fields can be quoted with ```"```. Inside a quoted field, separators
+//! and new lines are allowed, and ```""``` is an escaped quote
+//!
+//! The input is processed record by record with ```parse_iter```. It's
+//! not necessary to build the AST for the whole file
+//!
+//! ```
+//! extern crate dynparser;
+//! use dynparser::grammars::csv;
+//!
+//! fn main() {
+//!     let rules = csv::rules(',');
+//!     let text = "name,comment\nJohn,\"Hi, \"\"all\"\"\"\n";
+//!
+//!     let records: Vec<Vec<String>> = csv::records(text, &rules)
+//!         .map(|r| r.unwrap().fields)
+//!         .collect();
+//!
+//!     assert_eq!(records, vec![vec!["name", "comment"], vec!["John", "Hi, \"all\""]]);
+//! }
+//! ```
+
+use crate::parser::{self, expression::SetOfRules};
+use crate::{ast, parse_iter, ParseIter};
+
+const PEG: &str = r#"
+    record      =   field  (sep  field)*  end_record
+
+    field       =   quoted  /  unquoted
+    quoted      =   '"'  qchars  '"'
+    qchars      =   ('""'  /  !'"' .)*
+    unquoted    =   (!(sep / '"' / "\r" / "\n") .)*
+
+    end_record  =   "\r\n"  /  "\n"  /  "\r"  /  eof
+"#;
+
+/// A record (line) of the input
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    /// Fields of the record, unquoted
+    pub fields: Vec<String>,
+    /// Byte offset on input where the record starts
+    pub start: usize,
+    /// Byte offset on input where the record ends (after end of line)
+    pub end: usize,
+}
+
+/// Iterator over the records of an input
+///
+/// Created with ```records```
+pub struct Records<'a> {
+    it: ParseIter<'a>,
+}
+
+/// Rules to parse records with the separator provided
+///
+/// ```','``` for CSV, ```'\t'``` for TSV
+pub fn rules(separator: char) -> SetOfRules {
+    crate::rules_from_peg(PEG)
+        .expect("invalid csv grammar")
+        .add("sep", lit!(separator.to_string()))
+}
+
+/// Iterate over the records on the text
+///
+/// It will stop after the first error
+pub fn records<'a>(text: &'a str, rules: &'a SetOfRules) -> Records<'a> {
+    Records {
+        it: parse_iter(text, rules, "record"),
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record, parser::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.it.offset();
+        let node = self.it.next()?;
+
+        Some(node.map(|node| Record {
+            fields: fields(&node),
+            start,
+            end: self.it.offset(),
+        }))
+    }
+}
+
+//-----------------------------------------------------------------------
+//  SUPPORT
+
+fn fields(record: &ast::Node) -> Vec<String> {
+    sub_rules(record)
+        .filter(|(name, _)| *name == "field")
+        .filter_map(|(_, field)| sub_rules(field).next())
+        .map(|(name, node)| match name {
+            "quoted" => sub_rules(node)
+                .map(|(_, qchars)| text(qchars).replace("\"\"", "\""))
+                .collect(),
+            _ => text(node),
+        })
+        .collect()
+}
+
+fn sub_rules(node: &ast::Node) -> impl Iterator<Item = (&str, &ast::Node)> {
+    let nodes: &[ast::Node] = match node {
+        ast::Node::Rule((_, nodes)) => nodes,
+        _ => &[],
+    };
+    nodes.iter().filter_map(|n| match n {
+        ast::Node::Rule((name, _)) => Some((name.as_str(), n)),
+        _ => None,
+    })
+}
+
+fn text(node: &ast::Node) -> String {
+    match node {
+        ast::Node::Val(v) => v.clone(),
+        ast::Node::Rule((_, nodes)) => nodes.iter().map(text).collect(),
+        ast::Node::EOF => String::new(),
+    }
+}
@@ -0,0 +1,10 @@
+#![warn(missing_docs)]
+//! Ready to use grammars
+//!
+//! They are useful by themselves, and also as examples about how to
+//! write grammars and how to process the AST
+
+pub mod csv;
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,99 @@
+//-----------------------------------------------------------------------
+//
+//  mod grammars  TEST
+//
+//-----------------------------------------------------------------------
+
+use crate::grammars::csv;
+
+fn csv_fields(text: &str, separator: char) -> Vec<Vec<String>> {
+    let rules = csv::rules(separator);
+    csv::records(text, &rules)
+        .map(|r| r.unwrap().fields)
+        .collect()
+}
+
+#[test]
+fn csv_simple() {
+    assert_eq!(
+        csv_fields("a,b,c\n1,2,3\n", ','),
+        vec![vec!["a", "b", "c"], vec!["1", "2", "3"]]
+    );
+}
+
+#[test]
+fn csv_without_final_eol() {
+    assert_eq!(
+        csv_fields("a,b\r\n1,2", ','),
+        vec![vec!["a", "b"], vec!["1", "2"]]
+    );
+}
+
+#[test]
+fn csv_empty_fields() {
+    assert_eq!(
+        csv_fields(",a,\n\n", ','),
+        vec![vec!["", "a", ""], vec![""]]
+    );
+    assert!(csv_fields("", ',').is_empty());
+}
+
+#[test]
+fn csv_quoted() {
+    assert_eq!(
+        csv_fields("\"a,b\",\"line\nbreak\",\"say \"\"hi\"\"\"\n", ','),
+        vec![vec!["a,b", "line\nbreak", "say \"hi\""]]
+    );
+}
+
+#[test]
+fn tsv() {
+    assert_eq!(
+        csv_fields("a\tb,c\n\"1\t2\"\t3\n", '\t'),
+        vec![vec!["a", "b,c"], vec!["1\t2", "3"]]
+    );
+}
+
+#[test]
+fn csv_spans() {
+    let rules = csv::rules(',');
+    let text = "ab,c\n\"d\ne\",f\ng";
+    let spans: Vec<(usize, usize)> = csv::records(text, &rules)
+        .map(|r| r.unwrap())
+        .map(|r| (r.start, r.end))
+        .collect();
+
+    assert_eq!(spans, vec![(0, 5), (5, 13), (13, 14)]);
+}
+
+#[test]
+fn csv_errors() {
+    let rules = csv::rules(',');
+    let mut records = csv::records("a,b\n\"c\"d,e\nf\n", &rules);
+
+    assert!(records.next().unwrap().is_ok());
+    let error = records.next().unwrap().unwrap_err();
+    assert_eq!(error.pos.row, 1);
+    assert!(records.next().is_none());
+
+    let mut records = csv::records("a,\"b\n", &rules);
+    assert!(records.next().unwrap().is_err());
+}
+
+#[test]
+fn csv_many_records() {
+    let rules = csv::rules(',');
+    let text: String = (0..2000)
+        .map(|i| format!("{},\"name {}\",{}\n", i, i, i * 2))
+        .collect();
+
+    let count = csv::records(&text, &rules)
+        .enumerate()
+        .map(|(i, r)| {
+            let r = r.unwrap();
+            assert_eq!(r.fields[1], format!("name {}", i));
+        })
+        .count();
+
+    assert_eq!(count, 2000);
+}
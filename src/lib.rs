@@ -170,6 +170,30 @@ macro_rules! lit {
     }};
 }
 
+/// Create a literal of a single char
+///
+/// Same as ```lit!``` with one char, but faster
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(lit_char!('a'), lit_char!('\u{1F600}'))
+///     };
+///
+///     assert!(parse("a😀", &rules).is_ok())
+/// }
+/// ```
+#[macro_export]
+macro_rules! lit_char {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Char($e))
+    }};
+}
+
 /// Generate an error
 ///
 /// example
@@ -215,55 +239,93 @@ macro_rules! error {
     }};
 }
 
-/// Atom::Dot (any character)
+/// Generate a warning
 ///
-/// example
+/// It's zero width and it never fails. The warning will be reported
+/// by ```parse_with_warnings``` if it's on the accepted parsing path
+///
+/// ```rust
+/// extern crate dynparser;
+/// use dynparser::{parse_with_warnings, rules_from_peg};
+/// fn main() {
+///     let rules = rules_from_peg(
+///         r#"
+///
+///     main    =   '('  main   ( ')'  /  warning("missing parenthesis") )
+///             /   'hello'
+///
+///         "#,
+///     ).unwrap();
+///
+///     let parsed = parse_with_warnings("((hello)", &rules).unwrap();
+///     assert_eq!(parsed.warnings.len(), 1);
+///     assert_eq!(parsed.warnings[0].descr, "missing parenthesis");
+/// }
 /// ```
+#[macro_export]
+macro_rules! warning {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Warning(
+            $e.to_string(),
+        ))
+    }};
+}
+
+/// Register the position with a name (```@pos(name)``` on peg)
+///
+/// It's zero width and it never fails. The position will be on the
+/// captures of ```parse_with_captures```, as an empty span
+///
+/// ```rust
 /// #[macro_use]  extern crate dynparser;
-/// use dynparser::parse;
+/// use dynparser::parse_with_captures;
 ///
 /// fn main() {
 ///     let rules = rules!{
-///        "main"   =>  and!(dot!(), dot!())
+///        "main"   =>  and!(lit!("title:"), pos!("body"), rep!(dot!(), 0))
 ///     };
 ///
-///     assert!(parse("aa", &rules).is_ok())
+///     let parsed = parse_with_captures("title:hello", &rules).unwrap();
+///     let body = &parsed.captures["body"][0];
+///     assert_eq!(body.start.n, 6);
+///     assert_eq!(body.start, body.end);
 /// }
 /// ```
 #[macro_export]
-macro_rules! dot {
-    () => {{
-        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Dot)
+macro_rules! pos {
+    ($name:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Pos(
+            $name.to_string(),
+        ))
     }};
 }
 
-/// Generate a match expression with optional characters and a list
-/// of bounds
-///
-///  "String", from 'a', to 'b', from 'c', to 'd'
-/// The first string, is a set of chars.
-/// Later you can write a list of tuples with ranges to validate
+/// The expression will fail if the matched text is a keyword
 ///
-/// example
 /// ```
 /// #[macro_use]  extern crate dynparser;
 /// use dynparser::parse;
 ///
 /// fn main() {
 ///     let rules = rules!{
-///        "main"   =>  rep!(ematch!(    chlist "cd",
-///                                         from 'a', to 'b',
-///                                         from 'j', to 'p'
-///                     ), 0)
+///        "main"   =>  not_keyword!(
+///                         rep!(ematch!(chlist "", from 'a', to 'z'), 1),
+///                         "if", "else"
+///                     )
 ///     };
 ///
-///     assert!(parse("aabcdj", &rules).is_ok())
+///     assert!(parse("iff", &rules).is_ok());
+///     assert!(parse("if", &rules).is_err());
 /// }
 /// ```
-///
-///
-/// You can also pass a list of chars and a vector of char bounds as next
-/// example
+#[macro_export]
+macro_rules! not_keyword {
+    ($e:expr $(, $k:expr)*) => {{
+        $crate::parser::expression::not_keyword(&[$($k),*], $e)
+    }};
+}
+
+/// The keywords are reserved only while parsing the expression
 ///
 /// ```
 /// #[macro_use]  extern crate dynparser;
@@ -271,40 +333,49 @@ macro_rules! dot {
 ///
 /// fn main() {
 ///     let rules = rules!{
-///        "main"   =>  rep!(ematch!(    chlist "cd",
-///                                      from2   vec![
-///                                             ('a', 'b'),
-///                                             ('j', 'p')
-///                                         ]
-///                     ), 0)
+///        "main"   =>  or!(
+///                         keywords!(and!(lit!("if "), ref_rule!("id")), "if"),
+///                         ref_rule!("id")
+///                     ),
+///        "id"     =>  not_keyword!(rep!(ematch!(chlist "", from 'a', to 'z'), 1))
 ///     };
 ///
-///     assert!(parse("aabcdj", &rules).is_ok())
+///     assert!(parse("if", &rules).is_ok());
+///     assert!(parse("if iff", &rules).is_ok());
+///     assert!(parse("if if", &rules).is_err());
 /// }
 /// ```
-
 #[macro_export]
-macro_rules! ematch {
-    (chlist $chars:expr, $(from $from:expr,  to $to:expr),*) => {{
-        //use idata::cont::IVec;  //  pending macros by example 2.0
-        use $crate::parser;
-        let mut v = Vec::<(char, char)>::new();
-
-        //$(let v = v.ipush(($from, $to));)+  //  pending macros by example 2.0
-        $(v.push(($from, $to));)+
-        let amatch = parser::atom::Atom::Match(parser::atom::MatchRules::init($chars, v));
-        parser::expression::Expression::Simple(amatch)
+macro_rules! keywords {
+    ($e:expr $(, $k:expr)*) => {{
+        $crate::parser::expression::keywords(&[$($k),*], $e)
     }};
+}
 
-    (chlist $chars:expr, from2 $vfrom2:expr) => {{
-        use $crate::parser;
-
-        let amatch = parser::atom::Atom::Match(parser::atom::MatchRules::init($chars, $vfrom2));
-        parser::expression::Expression::Simple(amatch)
+/// Literals and matches on the expression will ignore the case
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  no_case!(and!(lit!("straße"), ematch!(chlist "", from 'a', to 'c')))
+///     };
+///
+///     assert!(parse("STRAẞEB", &rules).is_ok());
+///     assert!(parse("Straßec", &rules).is_ok());
+///     assert!(parse("STRASSEb", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! no_case {
+    ($e:expr) => {{
+        $crate::parser::expression::no_case($e)
     }};
 }
 
-/// Concat expressions (and)
+/// Atom::Dot (any character)
 ///
 /// example
 /// ```
@@ -320,15 +391,38 @@ macro_rules! ematch {
 /// }
 /// ```
 #[macro_export]
-macro_rules! and {
-    ($($e:expr),*) => {{
-        use $crate::parser::expression::{Expression, MultiExpr};
+macro_rules! dot {
+    () => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Dot)
+    }};
+}
 
-        Expression::And(MultiExpr::new(vec![$($e ,)*]))
+/// Atom::DotNoNewline (any character but a new line)
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(dot_no_newline!(), dot_no_newline!())
+///     };
+///
+///     assert!(parse("aa", &rules).is_ok());
+///     assert!(parse("a\n", &rules).is_err())
+/// }
+/// ```
+#[macro_export]
+macro_rules! dot_no_newline {
+    () => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::DotNoNewline)
     }};
 }
 
-/// Choose expressions (or)
+/// Atom::EOF (end of file)
+///
+/// It will not move the parsing position
 ///
 /// example
 /// ```
@@ -337,22 +431,22 @@ macro_rules! and {
 ///
 /// fn main() {
 ///     let rules = rules!{
-///        "main"   =>  or!(lit!("z"), lit!("a"))
+///        "main"   =>  and!(lit!("a"), eof!())
 ///     };
 ///
 ///     assert!(parse("a", &rules).is_ok())
 /// }
 /// ```
 #[macro_export]
-macro_rules! or {
-    ($($e:expr),*) => {{
-        use $crate::parser::expression::{Expression, MultiExpr};
-
-        Expression::Or(MultiExpr::new(vec![$($e ,)*]))
+macro_rules! eof {
+    () => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::EOF)
     }};
 }
 
-/// negate expression
+/// Atom::BOF (beginning of file)
+///
+/// It will not move the parsing position
 ///
 /// example
 /// ```
@@ -361,24 +455,22 @@ macro_rules! or {
 ///
 /// fn main() {
 ///     let rules = rules!{
-///        "main"   =>  and!(not!(lit!("b")), dot!())
+///        "main"   =>  and!(bof!(), lit!("a"))
 ///     };
 ///
 ///     assert!(parse("a", &rules).is_ok())
 /// }
 /// ```
-///
-/// not! will not move the parsing position
 #[macro_export]
-macro_rules! not {
-    ($e:expr) => {{
-        $crate::parser::expression::Expression::Not(Box::new($e))
+macro_rules! bof {
+    () => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::BOF)
     }};
 }
 
-/// repeat expression.
-/// You have to define minimum repetitions and optionally
-/// maximum repetitions (if missing, infinite)
+/// Atom::BOL (beginning of line)
+///
+/// It will not move the parsing position
 ///
 /// example
 /// ```
@@ -387,139 +479,2044 @@ macro_rules! not {
 ///
 /// fn main() {
 ///     let rules = rules!{
-///        "main"   =>  rep!(lit!("a"), 0)
+///        "main"   =>  and!(lit!("a\n"), bol!(), lit!("b"))
 ///     };
 ///
-///     assert!(parse("aaaaaaaa", &rules).is_ok())
+///     assert!(parse("a\nb", &rules).is_ok())
 /// }
 /// ```
-/// repeating from 0 to infinite
+#[macro_export]
+macro_rules! bol {
+    () => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::BOL)
+    }};
+}
+
+/// Atom::XidStart (a char that can start an identifier)
 ///
+/// Unicode property XID_Start. Requires the ```unicode``` feature
+///
+/// example
 /// ```
 /// #[macro_use]  extern crate dynparser;
 /// use dynparser::parse;
 ///
 /// fn main() {
 ///     let rules = rules!{
-///        "main"   =>  rep!(lit!("a"), 0, 3)
+///        "main"   =>  and!(xid_start!(), rep!(xid_continue!(), 0))
 ///     };
 ///
-///     assert!(parse("aaa", &rules).is_ok())
+///     assert!(parse("año_2", &rules).is_ok());
+///     assert!(parse("2año", &rules).is_err());
 /// }
 /// ```
+#[cfg(feature = "unicode")]
 #[macro_export]
-macro_rules! rep {
-    ($e:expr, $min:expr) => {{
-        use $crate::parser::expression;
-
-        expression::Expression::Repeat(expression::RepInfo::new(Box::new($e), $min, None))
+macro_rules! xid_start {
+    () => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::XidStart)
     }};
+}
 
-    ($e:expr, $min:expr, $max:expr) => {{
-        use $crate::parser::expression;
-
-        expression::Expression::Repeat(expression::RepInfo::new(Box::new($e), $min, Some($max)))
+/// Atom::XidContinue (a char that can continue an identifier)
+///
+/// Unicode property XID_Continue. Requires the ```unicode``` feature
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(xid_continue!(), 1)
+///     };
+///
+///     assert!(parse("2_ñ", &rules).is_ok());
+///     assert!(parse("a-b", &rules).is_err());
+/// }
+/// ```
+#[cfg(feature = "unicode")]
+#[macro_export]
+macro_rules! xid_continue {
+    () => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::XidContinue)
     }};
 }
 
-/// This will create a subexpression referring to a "rule name"
+/// Atom::Grapheme (an extended grapheme cluster)
+///
+/// What a user sees as a char. A letter and its combining marks, an
+/// emoji sequence, ```\r\n```... Requires the ```graphemes``` feature
 ///
+/// example
 /// ```
 /// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
 ///
 /// fn main() {
 ///     let rules = rules!{
-///        "main" => ref_rule!("3a"),
-///        "3a"   => lit!("aaa")
+///        "main"   =>  and!(grapheme!(), lit!("!"))
 ///     };
 ///
-///     assert!(dynparser::parse("aaa", &rules).is_ok())
+///     //  e + COMBINING ACUTE ACCENT
+///     assert!(parse("e\u{301}!", &rules).is_ok());
+///     assert!(parse("ab!", &rules).is_err());
 /// }
 /// ```
+#[cfg(feature = "graphemes")]
 #[macro_export]
-macro_rules! ref_rule {
-    ($e:expr) => {{
-        $crate::parser::expression::Expression::RuleName($e.to_owned())
+macro_rules! grapheme {
+    () => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Grapheme)
     }};
 }
 
-//  M A C R O S
-// -------------------------------------------------------------------------------------
-
-pub mod ast;
-pub mod parser;
-pub mod peg;
-
-// -------------------------------------------------------------------------------------
-//  T Y P E S
-
-//  T Y P E S
-// -------------------------------------------------------------------------------------
-
-// -------------------------------------------------------------------------------------
-//  A P I
-
-/// Parse a string with a set of rules
-///
-/// the `main` rule is the starting point to parse
+/// Atom::EOL (end of line)
 ///
-/// # Examples
+/// Next char is a new line, or there are no more chars.
+/// It will not move the parsing position
 ///
-/// Parse a simple literal
+/// The Unicode line terminators (NEL, LS, PS) are new lines if they
+/// are enabled on ```ParseOptions::newlines```
 ///
+/// example
 /// ```
 /// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
 ///
 /// fn main() {
 ///     let rules = rules!{
-///        "main" => ref_rule!("3a"),
-///        "3a"   => lit!("aaa")
+///        "main"   =>  and!(lit!("a"), eol!(), lit!("\n"), lit!("b"), eol!())
 ///     };
 ///
-///     assert!(dynparser::parse("aaa", &rules).is_ok())
+///     assert!(parse("a\nb", &rules).is_ok())
 /// }
+/// ```
+#[macro_export]
+macro_rules! eol {
+    () => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::EOL)
+    }};
+}
+
+/// Atom::Until (any text till the delimiter)
+///
+/// The delimiter is not consumed. With an escape, the escape and the
+/// next char are skipped. It fails if there is no delimiter
 ///
+/// example
 /// ```
-/// More examples in macros
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
 ///
-
-pub fn parse(s: &str, rules: &parser::expression::SetOfRules) -> Result<ast::Node, parser::Error> {
-    parse_with_debug(s, rules, false)
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(lit!("'"), until!("'", "\\"), lit!("'"))
+///     };
+///
+///     assert!(parse(r"'it\'s'", &rules).is_ok());
+///     assert!(parse(r"'unterminated\'", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! until {
+    ($delimiter:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Until(
+            $delimiter.to_string(),
+            None,
+        ))
+    }};
+    ($delimiter:expr, $escape:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Until(
+            $delimiter.to_string(),
+            Some($escape.to_string()),
+        ))
+    }};
 }
 
-/// Same as parser, but with debug info
+/// Atom::Balanced (region with nested delimiters)
 ///
-/// It will trace the rules called
-///
-/// It's expensive, use it just to develop and locate errors
+/// It consumes from the open delimiter, till the close one at the
+/// same nesting level. The node value is the text inside (without the
+/// outer delimiters)
 ///
-pub fn parse_debug(
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{ast, parse};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(lit!("f"), balanced!("(", ")"))
+///     };
+///
+///     let ast = parse("f(a, (b, c))", &rules).unwrap();
+///     assert_eq!(
+///         ast,
+///         ast::Node::Rule((
+///             "main".to_string(),
+///             vec![
+///                 ast::Node::Val("f".to_string()),
+///                 ast::Node::Val("a, (b, c)".to_string())
+///             ]
+///         ))
+///     );
+///     assert!(parse("f(a, (b, c)", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! balanced {
+    ($open:expr, $close:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Balanced(
+            $open.to_string(),
+            $close.to_string(),
+        ))
+    }};
+}
+
+/// Generate a match expression with optional characters and a list
+/// of bounds
+///
+///  "String", from 'a', to 'b', from 'c', to 'd'
+/// The first string, is a set of chars.
+/// Later you can write a list of tuples with ranges to validate
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(ematch!(    chlist "cd",
+///                                         from 'a', to 'b',
+///                                         from 'j', to 'p'
+///                     ), 0)
+///     };
+///
+///     assert!(parse("aabcdj", &rules).is_ok())
+/// }
+/// ```
+///
+///
+/// You can also pass a list of chars and a vector of char bounds as next
+/// example
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(ematch!(    chlist "cd",
+///                                      from2   vec![
+///                                             ('a', 'b'),
+///                                             ('j', 'p')
+///                                         ]
+///                     ), 0)
+///     };
+///
+///     assert!(parse("aabcdj", &rules).is_ok())
+/// }
+/// ```
+///
+/// Starting with ```not```, it matches any char but the chars and the
+/// ranges (as ```[^a-z]``` on peg)
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(lit!("\""), rep!(ematch!(not chlist "\"", from2 vec![]), 0), lit!("\""))
+///     };
+///
+///     assert!(parse(r#""a b-c""#, &rules).is_ok());
+///     assert!(parse(r#""a"b""#, &rules).is_err());
+/// }
+/// ```
+
+#[macro_export]
+macro_rules! ematch {
+    (not $($match:tt)*) => {{
+        use $crate::parser::{atom::Atom, expression::Expression};
+
+        match $crate::ematch!($($match)*) {
+            Expression::Simple(Atom::Match(mrules)) => Expression::Simple(Atom::Match(mrules.negate())),
+            expr => expr,
+        }
+    }};
+
+    (chlist $chars:expr, $(from $from:expr,  to $to:expr),*) => {{
+        //use idata::cont::IVec;  //  pending macros by example 2.0
+        use $crate::parser;
+        let mut v = Vec::<(char, char)>::new();
+
+        //$(let v = v.ipush(($from, $to));)+  //  pending macros by example 2.0
+        $(v.push(($from, $to));)+
+        let amatch = parser::atom::Atom::Match(parser::atom::MatchRules::init($chars, v));
+        parser::expression::Expression::Simple(amatch)
+    }};
+
+    (chlist $chars:expr, from2 $vfrom2:expr) => {{
+        use $crate::parser;
+
+        let amatch = parser::atom::Atom::Match(parser::atom::MatchRules::init($chars, $vfrom2));
+        parser::expression::Expression::Simple(amatch)
+    }};
+}
+
+/// Concat expressions (and)
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(dot!(), dot!())
+///     };
+///
+///     assert!(parse("aa", &rules).is_ok())
+/// }
+/// ```
+#[macro_export]
+macro_rules! and {
+    ($($e:expr),*) => {{
+        use $crate::parser::expression::{Expression, MultiExpr};
+
+        Expression::And(MultiExpr::new(vec![$($e ,)*]))
+    }};
+}
+
+/// Sequence of expressions, same as ```and!```
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  seq!(lit!("a"), choice!(lit!("b"), lit!("c")))
+///     };
+///
+///     assert!(parse("ac", &rules).is_ok())
+/// }
+/// ```
+#[macro_export]
+macro_rules! seq {
+    ($($e:expr),*) => {{
+        $crate::and!($($e),*)
+    }};
+}
+
+/// Choose expressions (or)
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  or!(lit!("z"), lit!("a"))
+///     };
+///
+///     assert!(parse("a", &rules).is_ok())
+/// }
+/// ```
+#[macro_export]
+macro_rules! or {
+    ($($e:expr),*) => {{
+        use $crate::parser::expression::{Expression, MultiExpr};
+
+        Expression::Or(MultiExpr::new(vec![$($e ,)*]))
+    }};
+}
+
+/// Ordered choice of expressions, same as ```or!```
+///
+/// The first one matching is selected
+#[macro_export]
+macro_rules! choice {
+    ($($e:expr),*) => {{
+        $crate::or!($($e),*)
+    }};
+}
+
+/// negate expression
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(not!(lit!("b")), dot!())
+///     };
+///
+///     assert!(parse("a", &rules).is_ok())
+/// }
+/// ```
+///
+/// not! will not move the parsing position
+#[macro_export]
+macro_rules! not {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::Not(Box::new($e))
+    }};
+}
+
+/// zero width assertion
+///
+/// If the expression doesn't match at this point, it will fail with the
+/// message as description. It will not move the parsing position
+///
+/// Useful to locate where a long sequence is failing
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(
+///                         lit!("a"),
+///                         assert_expr!(lit!("b"), "expected b after a"),
+///                         dot!()
+///                     )
+///     };
+///
+///     assert!(parse("ab", &rules).is_ok());
+///     match parse("ac", &rules) {
+///         Err(e) => assert!(e.descr == "expected b after a"),
+///         Ok(_) => panic!("it should fail")
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! assert_expr {
+    ($e:expr, $msg:expr) => {{
+        $crate::parser::expression::assertion($e, $msg)
+    }};
+}
+
+/// label the nodes produced by an expression
+///
+/// The result will be a ```Node::Rule``` with the label as name.
+/// Useful to know which alternative matched on an ```or!```
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{ast, parse};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  or!(
+///                         label!("Hi", lit!("hello")),
+///                         label!("Bye", lit!("bye"))
+///                     )
+///     };
+///
+///     let ast = parse("bye", &rules).unwrap();
+///     assert!(
+///         ast == ast::Node::Rule((
+///             "main".to_string(),
+///             vec![ast::Node::Rule((
+///                 "Bye".to_string(),
+///                 vec![ast::Node::Val("bye".to_string())]
+///             ))]
+///         ))
+///     )
+/// }
+/// ```
+#[macro_export]
+macro_rules! label {
+    ($label:expr, $e:expr) => {{
+        $crate::parser::expression::Expression::Labeled($label.to_string(), Box::new($e))
+    }};
+}
+
+/// repeat expression.
+/// You have to define minimum repetitions and optionally
+/// maximum repetitions (if missing, infinite)
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(lit!("a"), 0)
+///     };
+///
+///     assert!(parse("aaaaaaaa", &rules).is_ok())
+/// }
+/// ```
+/// repeating from 0 to infinite
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(lit!("a"), 0, 3)
+///     };
+///
+///     assert!(parse("aaa", &rules).is_ok())
+/// }
+/// ```
+/// Mark an expression as deprecated
+///
+/// When it matches, a warning with the message and the matched span
+/// will be registered. Usually it's used on a full rule, with the
+/// ```@deprecated("...")``` annotation on peg grammars
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse_with_warnings;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(or!(ref_rule!("new"), ref_rule!("old")), 0),
+///        "new"    =>  lit!("n"),
+///        "old"    =>  deprecated!("use n instead", lit!("oo"))
+///     };
+///
+///     let parsed = parse_with_warnings("noo", &rules).unwrap();
+///     assert_eq!(parsed.warnings.len(), 1);
+///     assert_eq!(parsed.warnings[0].descr, "use n instead");
+///     assert_eq!(parsed.warnings[0].pos.n, 1);
+///     assert_eq!(parsed.warnings[0].end.n, 3);
+/// }
+/// ```
+#[macro_export]
+macro_rules! deprecated {
+    ($message:expr, $e:expr) => {{
+        $crate::parser::expression::Expression::Deprecated($message.to_string(), Box::new($e))
+    }};
+}
+
+/// Atom matched by a function written by the user
+///
+/// The function receives the pending input and the ```ParseContext```
+/// and returns the number of bytes matched (or None).
+/// Returning ```Some(0)``` works as a semantic predicate
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(custom!("even digits", |rest, _ctx| {
+///                         let len = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+///                         if len > 0 && len % 2 == 0 { Some(len) } else { None }
+///                     }), eof!())
+///     };
+///
+///     assert!(parse("1234", &rules).is_ok());
+///     assert!(parse("123", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! custom {
+    ($name:expr, $f:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Custom(
+            $crate::parser::atom::Custom::new($name, $f),
+        ))
+    }};
+}
+
+/// Repeat an expression
+///
+/// The limits can be a minimum, a minimum and a maximum, or a range
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(rep!(lit!("a"), 1), rep!(lit!("b"), 2..=3), rep!(lit!("c"), 0, 1))
+///     };
+///
+///     assert!(parse("abb", &rules).is_ok());
+///     assert!(parse("aabbbc", &rules).is_ok());
+///     assert!(parse("ab", &rules).is_err());
+///     assert!(parse("abbbb", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! rep {
+    ($e:expr, $bounds:expr) => {{
+        use $crate::parser::expression;
+
+        let (min, max) = expression::RepBounds::bounds($bounds);
+        expression::Expression::Repeat(expression::RepInfo::new(Box::new($e), min, max))
+    }};
+
+    ($e:expr, $min:expr, $max:expr) => {{
+        use $crate::parser::expression;
+
+        expression::Expression::Repeat(expression::RepInfo::new(Box::new($e), $min, Some($max)))
+    }};
+}
+
+/// Optional expression, same as ```rep!(e, 0, 1)```
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(optional!(lit!("-")), lit!("1"))
+///     };
+///
+///     assert!(parse("-1", &rules).is_ok());
+///     assert!(parse("1", &rules).is_ok());
+/// }
+/// ```
+#[macro_export]
+macro_rules! optional {
+    ($e:expr) => {{
+        $crate::rep!($e, 0, 1)
+    }};
+}
+
+/// One or more expressions with a separator between them
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  sep_by!(ematch!(chlist "", from '0', to '9'), lit!(","))
+///     };
+///
+///     assert!(parse("1,2,3", &rules).is_ok());
+///     assert!(parse("1,2,", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! sep_by {
+    ($e:expr, $sep:expr) => {{
+        $crate::parser::expression::separated($e, $sep, false)
+    }};
+}
+
+/// Same as ```sep_by!```, accepting a separator after the last one
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  sep_by_trailing!(ematch!(chlist "", from '0', to '9'), lit!(","))
+///     };
+///
+///     assert!(parse("1,2,3", &rules).is_ok());
+///     assert!(parse("1,2,", &rules).is_ok());
+///     assert!(parse("1,,", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! sep_by_trailing {
+    ($e:expr, $sep:expr) => {{
+        $crate::parser::expression::separated($e, $sep, true)
+    }};
+}
+
+/// non greedy repetition.
+/// It will repeat the expression (at least min times) till
+/// the stop expression matches. The stop expression will be
+/// consumed
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep_lazy!(dot!(), 0, lit!("end"))
+///     };
+///
+///     assert!(parse("abcend", &rules).is_ok());
+///     assert!(parse("abcendend", &rules).is_err())
+/// }
+/// ```
+///
+/// With min 1, the first repetition will not check the stop expression
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep_lazy!(dot!(), 1, lit!("a"))
+///     };
+///
+///     assert!(parse("aa", &rules).is_ok());
+///     assert!(parse("a", &rules).is_err())
+/// }
+/// ```
+#[macro_export]
+macro_rules! rep_lazy {
+    ($e:expr, $min:expr, $stop:expr) => {{
+        $crate::parser::expression::lazy_repeat($e, $min, $stop)
+    }};
+}
+
+/// This will create a subexpression referring to a "rule name"
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main" => ref_rule!("3a"),
+///        "3a"   => lit!("aaa")
+///     };
+///
+///     assert!(dynparser::parse("aaa", &rules).is_ok())
+/// }
+/// ```
+#[macro_export]
+macro_rules! ref_rule {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::RuleName($e.to_owned())
+    }};
+}
+
+/// Destructure an AST node by rule name and children shape
+///
+/// Every arm has the rule name, a slice pattern for the children and
+/// the result. The first matching arm is chosen. The last arm has to
+/// be ```_```
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{ast::Node, parse, rules_from_peg};
+///
+/// fn eval(node: &Node) -> Option<i64> {
+///     node_match!(node, {
+///         "main" => [expr] => eval(expr),
+///         "num" => [Node::Val(v)] => v.parse().ok(),
+///         "sum" => [a, Node::Val(op), rest @ ..] if op == "+" => {
+///             Some(eval(a)? + eval(&Node::Rule(("sum".to_string(), rest.to_vec())))?)
+///         },
+///         "sum" => [a] => eval(a),
+///         _ => None,
+///     })
+/// }
+///
+/// fn main() {
+///     let rules = rules_from_peg(
+///         r#"
+///         main    =   sum
+///         sum     =   num  ('+'  num)*
+///         num     =   [0-9]+
+///         "#,
+///     ).unwrap();
+///
+///     let ast = parse("1+20+300", &rules).unwrap().compact();
+///     assert_eq!(eval(&ast), Some(321));
+/// }
+/// ```
+#[macro_export]
+macro_rules! node_match {
+    ($node:expr, {
+        $($name:literal => [$($children:tt)*] $(if $guard:expr)? => $body:expr,)*
+        _ => $default:expr $(,)?
+    }) => {{
+        match ($node).rule_parts() {
+            $((Some($name), [$($children)*]) $(if $guard)? => $body,)*
+            _ => $default,
+        }
+    }};
+}
+
+//  M A C R O S
+// -------------------------------------------------------------------------------------
+
+pub mod actions;
+pub mod ast;
+pub mod codes;
+#[cfg(feature = "encoding")]
+pub mod encoding;
+pub mod grammars;
+pub mod parser;
+pub mod peg;
+pub mod records;
+pub mod span;
+pub mod stream;
+pub mod testing;
+
+#[cfg(feature = "encoding")]
+pub use crate::encoding::parse_bytes_with_encoding;
+pub use crate::records::extract_records;
+pub use crate::stream::{parse_reader, StreamingParse};
+
+// -------------------------------------------------------------------------------------
+//  T Y P E S
+
+/// Result of a successful parsing with the non fatal messages
+///
+/// Created with ```parse_with``` (or its shorthands
+/// ```parse_with_warnings``` and ```parse_with_captures```)
+#[derive(Debug)]
+pub struct Parsed {
+    /// Abstract syntax tree
+    pub ast: ast::Node,
+    /// Warnings produced on the accepted parsing path
+    pub warnings: Vec<parser::Diagnostic>,
+    /// Spans matched by every label on the accepted parsing path, in
+    /// order. Empty without the ```capture``` option
+    pub captures: std::collections::HashMap<String, Vec<parser::Span>>,
+    /// Alternative matched by every ```or``` on the accepted parsing
+    /// path. Empty without the ```decisions``` option
+    pub decisions: Vec<parser::Decision>,
+    /// Errors skipped by the rules with a sync token, on the accepted
+    /// parsing path. Empty if not created with ```parse_recovering```
+    pub errors: Vec<parser::Error>,
+}
+
+/// How the end of the input is checked after ```main```, limits
+/// while parsing, and what is registered on the result
+///
+/// Used with ```parse_with``` (and with ```parse_with_context```,
+/// ```parse_with_kinds``` and ```parse_with_builder```). The default
+/// is the behaviour of ```parse```, all the input has to be consumed
+/// by the grammar
+///
+/// Every field has a setter with the same name. Out of this crate,
+/// options are built from ```ParseOptions::default()``` and the
+/// setters. New options will be added with a default value
+///
+/// ```
+/// use dynparser::ParseOptions;
+///
+/// let options = ParseOptions::default()
+///     .memoize(true)
+///     .max_repetitions(10_000)
+///     .trim_trailing_whitespace(true);
+/// assert!(options.memoize);
+/// assert!(options.require_eof);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// Whitespace after ```main``` is accepted and ignored
+    pub trim_trailing_whitespace: bool,
+    /// ```main``` has to consume all the input. If false, the text
+    /// not consumed is ignored
+    pub require_eof: bool,
+    /// Iterations allowed on every repetition. Exceeding it is a
+    /// critical error, with the rule name and the limit
+    /// (default ```parser::MAX_REPETITIONS```)
+    pub max_repetitions: usize,
+    /// Stack (bytes) used by nested rules. Exceeding it is a critical
+    /// error, instead of a stack overflow
+    /// (by default, measured on the thread parsing with
+    /// ```parser::default_max_stack()```)
+    pub max_stack: Option<usize>,
+    /// A UTF-8 byte order mark at the start is ignored
+    pub skip_bom: bool,
+    /// A first line starting with ```#!``` (after the BOM, if skipped)
+    /// is ignored. ```bof``` and ```bol``` match after the skipped header,
+    /// and positions are still on the full text
+    pub skip_shebang: bool,
+    /// Chars ending a line, for row and col on positions and for
+    /// ```eol``` (by default, just ```\n```)
+    pub newlines: parser::Newlines,
+    /// Results of every rule on every position are kept, and reused
+    /// on backtracking (packrat). Every rule is parsed once on every
+    /// position, at the cost of memory. A reused result copies its
+    /// nodes, the time also grows with the size of the reused subtrees.
+    /// Custom matchers have to depend only on the input
+    pub memoize: bool,
+    /// The spans matched by the labels are registered on
+    /// ```Parsed::captures```
+    pub capture: bool,
+    /// The alternative matched by every ```or``` is registered on
+    /// ```Parsed::decisions```
+    pub decisions: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            trim_trailing_whitespace: false,
+            require_eof: true,
+            max_repetitions: parser::MAX_REPETITIONS,
+            max_stack: None,
+            skip_bom: false,
+            skip_shebang: false,
+            newlines: parser::Newlines::default(),
+            memoize: false,
+            capture: false,
+            decisions: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Set ```trim_trailing_whitespace```
+    pub fn trim_trailing_whitespace(mut self, trim: bool) -> Self {
+        self.trim_trailing_whitespace = trim;
+        self
+    }
+
+    /// Set ```require_eof```
+    pub fn require_eof(mut self, require: bool) -> Self {
+        self.require_eof = require;
+        self
+    }
+
+    /// Set ```max_repetitions```
+    pub fn max_repetitions(mut self, max: usize) -> Self {
+        self.max_repetitions = max;
+        self
+    }
+
+    /// Set ```max_stack```
+    pub fn max_stack(mut self, max: usize) -> Self {
+        self.max_stack = Some(max);
+        self
+    }
+
+    /// Set ```skip_bom```
+    pub fn skip_bom(mut self, skip: bool) -> Self {
+        self.skip_bom = skip;
+        self
+    }
+
+    /// Set ```skip_shebang```
+    pub fn skip_shebang(mut self, skip: bool) -> Self {
+        self.skip_shebang = skip;
+        self
+    }
+
+    /// Set ```newlines```
+    pub fn newlines(mut self, newlines: parser::Newlines) -> Self {
+        self.newlines = newlines;
+        self
+    }
+
+    /// Set ```memoize```
+    pub fn memoize(mut self, memoize: bool) -> Self {
+        self.memoize = memoize;
+        self
+    }
+
+    /// Set ```capture```
+    pub fn capture(mut self, capture: bool) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Set ```decisions```
+    pub fn decisions(mut self, decisions: bool) -> Self {
+        self.decisions = decisions;
+        self
+    }
+}
+
+/// Best effort parsing, with the errors found
+///
+/// Created with ```parse_recovering```
+#[derive(Debug)]
+pub struct Recovered {
+    /// Abstract syntax tree of the input without the lines with
+    /// errors. None if it couldn't be parsed even without them
+    pub ast: Option<ast::Node>,
+    /// Errors, one per skipped line (or per skipped text), with
+    /// positions on the full input
+    pub errors: Vec<parser::Error>,
+    /// Rows of the skipped lines (starting on 0), in the order of
+    /// ```errors```. Empty with ```%recover sync```
+    pub skipped: Vec<usize>,
+}
+
+/// Grammars matching an input
+///
+/// Created with ```classify```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classification<'a> {
+    /// Grammars matching the full input
+    pub full: Vec<&'a str>,
+    /// Grammars matching the longest prefix (full input included)
+    pub longest: Vec<&'a str>,
+    /// Bytes matched by the ```longest``` grammars
+    pub longest_len: usize,
+}
+
+/// How to order grammars matching the same length on
+/// ```classify_ranked```
+///
+/// The criteria are applied in order, and the registration order is
+/// always the last one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TieBreak<'a> {
+    /// Grammars on the list first, in the order of the list
+    Priority(&'a [&'a str]),
+    /// Grammars with more rules first (usually more specific)
+    MoreRules,
+    /// Grammars with fewer rules first
+    FewerRules,
+    /// Order of the grammars on the input
+    Registration,
+}
+
+/// A grammar matching the input, created with ```classify_ranked```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranked<'a> {
+    /// Name of the grammar
+    pub name: &'a str,
+    /// Position of the grammar on the input
+    pub index: usize,
+    /// Bytes matched
+    pub len: usize,
+    /// The full input was matched
+    pub full: bool,
+    /// Fraction of the input matched (from 0 to 1)
+    pub score: f64,
+    /// Number of rules of the grammar
+    pub rules: usize,
+}
+
+/// Iterator parsing consecutive matches of a rule
+///
+/// Created with ```parse_iter```
+pub struct ParseIter<'a> {
+    rule_name: String,
+    offset: usize,
+    status: Option<parser::Status<'a>>,
+}
+
+impl<'a> ParseIter<'a> {
+    /// Byte offset on input where next parsing will start
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for ParseIter<'a> {
+    type Item = Result<ast::Node, parser::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let status = self.status.take()?;
+        if status.it_parsing.as_str().is_empty() {
+            self.status = Some(status);
+            return None;
+        }
+
+        let start = status.pos.n;
+        match parser::expression::parse_rule(status, &self.rule_name) {
+            Ok((st, _)) if st.pos.n == start => Some(Err(parser::Error::from_status_normal(
+                &st,
+                codes::Code::EmptyRecord,
+                &format!("rule {} matched empty input", self.rule_name),
+            ))),
+            Ok((mut st, node)) => {
+                st.potential_error = None;
+                self.offset = st.text2parse.len() - st.it_parsing.as_str().len();
+                self.status = Some(st);
+                Some(Ok(node))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+//  T Y P E S
+// -------------------------------------------------------------------------------------
+
+// -------------------------------------------------------------------------------------
+//  A P I
+
+/// Parse a string with a set of rules
+///
+/// the `main` rule is the starting point to parse
+///
+/// The result is deterministic. The same rules and input give always
+/// the same AST, or the same error
+///
+/// # Examples
+///
+/// Parse a simple literal
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main" => ref_rule!("3a"),
+///        "3a"   => lit!("aaa")
+///     };
+///
+///     assert!(dynparser::parse("aaa", &rules).is_ok())
+/// }
+///
+/// ```
+/// More examples in macros
+///
+
+pub fn parse(s: &str, rules: &parser::expression::SetOfRules) -> Result<ast::Node, parser::Error> {
+    parse_with_debug(s, rules, false).map(|parsed| parsed.ast)
+}
+
+/// Same as parse, but the result will contain the warnings too
+///
+/// Warnings on discarded branches (backtracking) are not reported.
+/// It's ```parse_with``` with the default options
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse_with_warnings;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  or!(
+///                         and!(lit!("a"), warning!("discarded")),
+///                         and!(warning!("old syntax"), lit!("b"))
+///                     )
+///     };
+///
+///     let parsed = parse_with_warnings("b", &rules).unwrap();
+///     assert_eq!(parsed.warnings.len(), 1);
+///     assert_eq!(parsed.warnings[0].descr, "old syntax");
+/// }
+/// ```
+pub fn parse_with_warnings(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+) -> Result<Parsed, parser::Error> {
+    parse_with(s, rules, &ParseOptions::default())
+}
+
+/// Same as parse, but the result will contain the spans matched by
+/// the labels (named captures), without walking the AST
+///
+/// Labels on discarded branches (backtracking) are not reported.
+/// It's ```parse_with``` with the ```capture``` option
+///
+/// ```
+/// use dynparser::{parse_with_captures, rules_from_peg};
+///
+/// let rules = rules_from_peg(
+///     r#"
+/// main    =   (link / word / ' ')*
+/// link    =   'http://' ([a-z] / '.')+   #Url
+/// word    =   [a-z]+
+///     "#,
+/// ).unwrap();
+///
+/// let text = "see http://a.io and http://b.org";
+/// let parsed = parse_with_captures(text, &rules).unwrap();
+/// let urls: Vec<&str> = parsed.captures["Url"].iter().map(|s| s.text(text)).collect();
+/// assert_eq!(urls, vec!["http://a.io", "http://b.org"]);
+/// assert_eq!(parsed.captures["Url"][1].start.col, 20);
+/// ```
+pub fn parse_with_captures(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+) -> Result<Parsed, parser::Error> {
+    parse_with(s, rules, &ParseOptions::default().capture(true))
+}
+
+/// Parse with the options provided (look at ```ParseOptions```)
+///
+/// ```parse_with(text, &rules, &ParseOptions::default())``` is the
+/// same as ```parse```, with the warnings
+///
+/// The start and the end of the input are checked as configured on
+/// the options. The grammar doesn't need to end ```main``` with spaces
+/// and ```eof```, nor to start with an optional BOM or shebang line.
+/// Positions are on the full input
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse_with, ParseOptions};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(lit!("a"), 1)
+///     };
+///
+///     let options = ParseOptions::default()
+///         .trim_trailing_whitespace(true)
+///         .max_repetitions(3);
+///     assert!(parse_with("aaa \n", &rules, &options).is_ok());
+///     assert!(parse_with("aa b", &rules, &options).is_err());
+///     assert!(dynparser::parse("aa \n", &rules).is_err());
+///
+///     let err = parse_with("aaaa", &rules, &options).unwrap_err();
+///     assert_eq!(err.descr, "too many repetitions on rule main (limit 3)");
+///
+///     let prefix = ParseOptions::default().require_eof(false);
+///     assert!(parse_with("aa b", &rules, &prefix).is_ok());
+///
+///     let script = ParseOptions::default().skip_bom(true).skip_shebang(true);
+///     assert!(parse_with("\u{feff}#!/usr/bin/dsl\naa", &rules, &script).is_ok());
+///     let err = parse_with("#!/usr/bin/dsl\nab", &rules, &script).unwrap_err();
+///     assert_eq!((err.pos.row, err.pos.n), (1, 17));
+/// }
+/// ```
+pub fn parse_with(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    options: &ParseOptions,
+) -> Result<Parsed, parser::Error> {
+    parse_status_with_options(parser::Status::init(s, rules), options)
+}
+
+/// Same as parser, but with debug info
+///
+/// It will trace the rules called
+///
+/// It's expensive, use it just to develop and locate errors
+///
+pub fn parse_debug(
     s: &str,
     rules: &parser::expression::SetOfRules,
 ) -> Result<ast::Node, parser::Error> {
-    parse_with_debug(s, rules, true)
+    parse_with_debug(s, rules, true).map(|parsed| parsed.ast)
+}
+
+/// Same as ```parse_with```, but custom matchers will receive the
+/// user data on the ```ParseContext```
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse_with_context, ParseOptions};
+/// use std::collections::HashSet;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(ref_rule!("type"), lit!(" x")),
+///        "type"   =>  custom!("known type", |rest, ctx| {
+///                         let types = ctx.user_data::<HashSet<&str>>()?;
+///                         let len = rest.find(' ').unwrap_or(rest.len());
+///                         if types.contains(&rest[..len]) {
+///                             Some(len)
+///                         } else {
+///                             None
+///                         }
+///                     })
+///     };
+///
+///     let types: HashSet<&str> = vec!["int", "point"].into_iter().collect();
+///     let options = ParseOptions::default();
+///     assert!(parse_with_context("point x", &rules, &options, &types).is_ok());
+///     assert!(parse_with_context("float x", &rules, &options, &types).is_err());
+///     assert!(dynparser::parse("point x", &rules).is_err());
+/// }
+/// ```
+pub fn parse_with_context(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    options: &ParseOptions,
+    user_data: &dyn std::any::Any,
+) -> Result<Parsed, parser::Error> {
+    let status = parser::Status::init(s, rules).set_context(parser::ParseContext::new(user_data));
+    parse_status_with_options(status, options)
+}
+
+/// Same as parse, but it will collect calls, matches and time per rule
+///
+/// Measuring has a cost, use it to look for slow rules
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse_profile;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  or!(and!(ref_rule!("a"), lit!("b")), and!(ref_rule!("a"), lit!("c"))),
+///        "a"      =>  lit!("a")
+///     };
+///
+///     let (result, profile) = parse_profile("ac", &rules);
+///     assert!(result.is_ok());
+///     assert_eq!(profile.rule("a").unwrap().calls, 2);
+///     assert_eq!(profile.rule("main").unwrap().matches, 1);
+/// }
+/// ```
+pub fn parse_profile(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+) -> (Result<ast::Node, parser::Error>, parser::profile::Profile) {
+    let profile = std::rc::Rc::new(std::cell::RefCell::new(parser::profile::Profile::default()));
+    let result = parse_status(parser::Status::init(s, rules).set_profile(profile.clone()));
+    let profile = profile.borrow().clone();
+    (result.map(|parsed| parsed.ast), profile)
+}
+
+/// Same as ```parse_with```, but every value knows the kind of
+/// expression that produced it (literal, class, dot...)
+///
+/// Look at ```ast::kind```
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::ast::kind::NodeKind;
+/// use dynparser::{parse_with_kinds, ParseOptions};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  or!(and!(lit!("a"), lit!("b")), and!(lit!("a"), dot!()))
+///     };
+///
+///     let ast = parse_with_kinds("ac", &rules, &ParseOptions::default()).unwrap();
+///     let (_, nodes) = ast.rule_parts();
+///     assert_eq!(nodes[0].kind(), NodeKind::Literal);
+///     assert_eq!(nodes[1].kind(), NodeKind::Dot);
+/// }
+/// ```
+pub fn parse_with_kinds(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    options: &ParseOptions,
+) -> Result<ast::Node<ast::kind::Leaf>, parser::Error> {
+    let kinds = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let status = parser::Status::init(s, rules).set_leaf_kinds(kinds.clone());
+    let parsed = parse_status_with_options(status, options)?;
+    //  the values on the tree were registered first
+    let kinds = kinds.replace(vec![]);
+    Ok(parsed.ast.with_kinds(&mut kinds.into_iter()))
+}
+
+/// Same as parse, with the alternative matched by every ```or``` on
+/// the accepted parsing path (the decision trace)
+///
+/// Decisions are sorted by position (an ```or``` before the ones
+/// inside it). Much smaller than a full trace, it explains why an
+/// input was parsed in an unexpected way. It's ```parse_with``` with
+/// the ```decisions``` option
+///
+/// ```
+/// use dynparser::{parse_with_decisions, rules_from_peg};
+///
+/// let rules = rules_from_peg(
+///     r#"
+/// main    =   item+
+/// item    =   kw  /  ident
+/// kw      =   'if'
+/// ident   =   [a-z]+
+///     "#,
+/// ).unwrap();
+///
+/// //  "ifx" is not an identifier
+/// let (_, decisions) = parse_with_decisions("ifx", &rules).unwrap();
+/// let matched: Vec<(&str, usize, usize)> = decisions
+///     .iter()
+///     .map(|d| (d.rule.as_str(), d.alternative, d.span.end.n))
+///     .collect();
+/// assert_eq!(matched, vec![("item", 0, 2), ("item", 1, 3)]);
+/// assert_eq!(decisions[0].to_string(), "0:0..0:2  item (or 0): alternative 0 of 2");
+/// ```
+pub fn parse_with_decisions(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+) -> Result<(ast::Node, Vec<parser::Decision>), parser::Error> {
+    let parsed = parse_with(s, rules, &ParseOptions::default().decisions(true))?;
+    Ok((parsed.ast, parsed.decisions))
+}
+
+/// Same as ```parse_with```, but the tree is sent to a builder,
+/// instead of creating an AST
+///
+/// On error, it's also sent to the builder. Look at ```ast::builder```
+///
+/// ```
+/// use dynparser::ast::builder::TreeBuilder;
+/// use dynparser::ast::kind::NodeKind;
+/// use dynparser::{parse_with_builder, rules_from_peg, ParseOptions};
+///
+/// //  just counting the rules matched
+/// #[derive(Default)]
+/// struct Count(usize);
+///
+/// impl TreeBuilder for Count {
+///     fn open_node(&mut self, _name: &str) {
+///         self.0 += 1;
+///     }
+///     fn token(&mut self, _kind: &NodeKind, _text: &str) {}
+///     fn close_node(&mut self) {}
+/// }
+///
+/// let rules = rules_from_peg(
+///     r#"
+/// main    =   item+
+/// item    =   'a'  /  'b'
+///     "#,
+/// ).unwrap();
+///
+/// let options = ParseOptions::default();
+/// let mut count = Count::default();
+/// parse_with_builder("abba", &rules, &options, &mut count).unwrap();
+/// assert_eq!(count.0, 5);
+///
+/// assert!(parse_with_builder("abc", &rules, &options, &mut ()).is_err());
+/// ```
+pub fn parse_with_builder(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    options: &ParseOptions,
+    builder: &mut impl ast::builder::TreeBuilder,
+) -> Result<(), parser::Error> {
+    let events = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let status = parser::Status::init(s, rules).set_tree_events(events.clone());
+    match parse_status_with_options(status, options) {
+        Ok(_) => {
+            //  main was the last one registering events
+            for event in events.borrow().iter() {
+                event.send(builder);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            builder.error(&e);
+            Err(e)
+        }
+    }
+}
+
+/// Same as parse, but on grammars with a recovery strategy, it will
+/// continue after an error
+///
+/// Declare the strategy on the grammar with ```%recover lines``` (it's
+/// the ```recover``` annotation of ```main```). Then, a line with an
+/// error is skipped, and the parsing continues on the next one. The
+/// result has an error per skipped line, and the AST of the rest
+///
+/// An error just after a new line (column 0) is considered from the
+/// previous line, the one not finished properly
+///
+/// When ```main``` is a repetition, maybe followed by other expressions
+/// (```main = (line "\n")* eof```), the lines accepted are not parsed
+/// again, the parsing continues after the skipped line. If the item
+/// that failed started on a previous line, those lines are skipped too.
+/// On other grammars, the kept lines are parsed again after every error
+///
+/// Useful for line oriented inputs (config files, logs...). Without a
+/// recovery strategy, it stops on the first error
+///
+/// With ```%recover sync```, the rules annotated with a sync token
+/// (```@sync(";")```) recover by themselves. When one of them fails
+/// after consuming some input, the input is skipped till the token
+/// (included), and the parsing continues as if the rule matched. On
+/// the AST, the rule has the skipped text as its only value. If the
+/// token is not found, or the rule fails without consuming input, it
+/// fails as usual
+///
+/// ```
+/// use dynparser::{parse_recovering, rules_from_peg};
+///
+/// let rules = rules_from_peg(
+///     r#"
+///     %recover sync
+///
+///     main    =   '{'  stmt*  '}'
+///
+///     @sync(";")
+///     stmt    =   name  '='  value  ';'
+///     name    =   [a-z]+
+///     value   =   [0-9]+
+///     "#,
+/// ).unwrap();
+///
+/// let recovered = parse_recovering("{a=1;b=x;c=3;d 4;}", &rules);
+/// let cols: Vec<usize> = recovered.errors.iter().map(|e| e.pos.col).collect();
+/// assert_eq!(cols, vec![8, 15]);
+/// assert!(recovered.ast.is_some());
+///
+/// //  the error on the closing '}' can't be recovered
+/// let recovered = parse_recovering("{a=x;b=2;", &rules);
+/// assert_eq!(recovered.errors.len(), 2);
+/// assert!(recovered.ast.is_none());
+/// ```
+///
+/// ```
+/// use dynparser::{parse_recovering, rules_from_peg};
+///
+/// let rules = rules_from_peg(
+///     r#"
+///     %recover lines
+///
+///     main    =   (line  "\n")*
+///     line    =   name  ' = '  value
+///     name    =   [a-z]+
+///     value   =   [0-9]+
+///     "#,
+/// ).unwrap();
+///
+/// let recovered = parse_recovering("a = 1\nb = x\nc = 3\nd\n", &rules);
+/// assert_eq!(recovered.skipped, vec![1, 3]);
+/// assert_eq!(recovered.errors[0].line_before, "b = x");
+///
+/// let ast = recovered.ast.unwrap();
+/// assert_eq!(ast, dynparser::parse("a = 1\nc = 3\n", &rules).unwrap());
+/// ```
+pub fn parse_recovering(s: &str, rules: &parser::expression::SetOfRules) -> Recovered {
+    let strategy = rules
+        .rule_meta("main")
+        .and_then(|meta| meta.value("recover"));
+    if strategy == Some("sync") {
+        return parse_recovering_sync(s, rules);
+    }
+    let skip_lines = strategy == Some("lines");
+    if let (true, Some(items)) = (skip_lines, parser::expression::main_items(rules)) {
+        return parse_recovering_lines(s, rules, items);
+    }
+    let lines: Vec<&str> = s.split_inclusive('\n').collect();
+    let mut kept: Vec<usize> = (0..lines.len()).collect();
+    let mut recovered = Recovered {
+        ast: None,
+        errors: vec![],
+        skipped: vec![],
+    };
+
+    loop {
+        let text: String = kept.iter().map(|i| lines[*i]).collect();
+        match parse(&text, rules) {
+            Ok(ast) => {
+                recovered.ast = Some(ast);
+                return recovered;
+            }
+            Err(mut e) => {
+                let row = match (e.pos.row, e.pos.col) {
+                    (row, 0) if row > 0 => row - 1,
+                    (row, _) => row,
+                };
+                e.pos = original_pos(&e.pos, &lines, &kept);
+                recovered.errors.push(e);
+                if !skip_lines || row >= kept.len() {
+                    return recovered;
+                }
+                recovered.skipped.push(kept.remove(row));
+            }
+        }
+    }
+}
+
+//  main = item* tail, parsed item by item. After an error, the parsing
+//  continues on the line after it, keeping the items accepted before
+fn parse_recovering_lines<'a>(
+    s: &'a str,
+    rules: &'a parser::expression::SetOfRules,
+    (rep, tail): (
+        &'a parser::expression::RepInfo,
+        &'a [parser::expression::Expression],
+    ),
+) -> Recovered {
+    let item = std::slice::from_ref(rep.expression.as_ref());
+    let mut recovered = Recovered {
+        ast: None,
+        errors: vec![],
+        skipped: vec![],
+    };
+    let mut status = parser::Status::init(s, rules);
+    let mut nodes: Vec<ast::Node> = vec![];
+    //  status and nodes before every item on the accepted path
+    let mut starts: Vec<(parser::Status, usize)> = vec![];
+    //  row of the first line after the last skipped one
+    let mut first_row = 0;
+
+    loop {
+        starts.push((status.clone(), nodes.len()));
+        let ended = match parser::expression::parse_main_exprs(status.clone(), item) {
+            Ok((st, vnodes)) if st.pos.n > status.pos.n => {
+                status = st;
+                nodes.extend(vnodes);
+                continue;
+            }
+            //  an empty match ends the repetition
+            Ok((st, vnodes)) => {
+                nodes.extend(vnodes);
+                Ok(st)
+            }
+            Err(e) if e.priority != parser::ErrPriority::Critical && starts.len() > rep.min.0 => {
+                Ok(status.clone().set_potential_error(e))
+            }
+            Err(e) => Err(e),
+        };
+        let error = match ended.and_then(|st| parser::expression::parse_main_exprs(st, tail)) {
+            Ok((st, vnodes)) if st.it_parsing.as_str().is_empty() => {
+                nodes.extend(vnodes);
+                recovered.ast = Some(ast::Node::Rule(("main".to_string(), nodes)));
+                return recovered;
+            }
+            Ok((st, _)) => match st.potential_error {
+                Some(e) => *e,
+                None => parser::Error::from_status_normal(
+                    &st,
+                    codes::Code::IncompleteInput,
+                    "not consumed full input",
+                ),
+            },
+            Err(e) => e,
+        };
+
+        //  an error just after a new line is from the previous one
+        let row = match (error.pos.row, error.pos.col) {
+            (row, 0) if row > first_row => row - 1,
+            (row, _) => row,
+        };
+        recovered.errors.push(error);
+        //  from the last item starting before the line, to its end
+        let i = starts
+            .iter()
+            .rposition(|(st, _)| st.pos.row <= row)
+            .unwrap_or(0);
+        let (mut st, n_nodes) = starts[i].clone();
+        let rest = st.it_parsing.as_str();
+        let line_start = match row - st.pos.row {
+            0 => Some(0),
+            n => rest.match_indices('\n').nth(n - 1).map(|(nl, _)| nl + 1),
+        };
+        let line_start = match line_start {
+            Some(start) if start < rest.len() => start,
+            //  no line to skip
+            _ => return recovered,
+        };
+        let line_end = rest[line_start..]
+            .find('\n')
+            .map_or(rest.len(), |nl| line_start + nl + 1);
+        recovered.skipped.extend(st.pos.row..=row);
+        let (skipped, newlines) = (&rest[..line_end], st.newlines);
+        st.pos.advance(skipped, newlines);
+        st.it_parsing = rest[line_end..].chars();
+        st.potential_error = None;
+        status = st;
+        first_row = status.pos.row;
+        nodes.truncate(n_nodes);
+        starts.truncate(i);
+    }
+}
+
+fn parse_recovering_sync(s: &str, rules: &parser::expression::SetOfRules) -> Recovered {
+    let recovered = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let status = parser::Status::init(s, rules).set_recovered(recovered.clone());
+    let (ast, errors) = match parse_status(status) {
+        Ok(parsed) => (Some(parsed.ast), parsed.errors),
+        //  the ones registered before the failure, on the last path tried
+        Err(e) => {
+            let mut errors: Vec<parser::Error> = recovered
+                .borrow()
+                .iter()
+                .filter(|r| r.pos.n < e.pos.n)
+                .cloned()
+                .collect();
+            errors.push(e);
+            (None, errors)
+        }
+    };
+    Recovered {
+        ast,
+        errors,
+        skipped: vec![],
+    }
 }
 
 fn parse_with_debug(
     s: &str,
     rules: &parser::expression::SetOfRules,
     debug: bool,
-) -> Result<ast::Node, parser::Error> {
-    let (st, ast) = if debug {
-        parser::expression::parse(parser::Status::init_debug(s, &rules, debug))?
+) -> Result<Parsed, parser::Error> {
+    if debug {
+        parse_status(parser::Status::init_debug(s, &rules, debug))
     } else {
-        parser::expression::parse(parser::Status::init(s, &rules))?
+        parse_status(parser::Status::init(s, &rules))
+    }
+}
+
+fn parse_status(status: parser::Status) -> Result<Parsed, parser::Error> {
+    parse_status_with_options(status, &ParseOptions::default())
+}
+
+fn parse_status_with_options(
+    status: parser::Status,
+    options: &ParseOptions,
+) -> Result<Parsed, parser::Error> {
+    let status = match options.max_stack {
+        Some(max_stack) => status.set_max_stack(max_stack),
+        None => status,
+    };
+    let status = status
+        .set_max_repetitions(options.max_repetitions)
+        .set_newlines(options.newlines)
+        .set_memoize(options.memoize)
+        .set_capture(options.capture);
+    let status = if options.decisions {
+        status.set_decisions(std::rc::Rc::new(std::cell::RefCell::new(vec![])))
+    } else {
+        status
+    };
+    let status = skip_input_header(status, options);
+    let (mut st, ast) = parser::expression::parse(status)?;
+    //  discarded branches could have registered decisions after the last one
+    let decisions = match &st.decisions {
+        Some(decisions) => {
+            let mut decisions = decisions.borrow_mut();
+            decisions.truncate(st.n_decisions);
+            decision_trace(st.rules, &decisions)
+        }
+        None => vec![],
     };
-    match (st.pos.n == s.len(), st.potential_error.clone()) {
-        (true, _) => Ok(ast),
-        (false, Some(e)) => Err(e),
+    let errors = match &st.recovered {
+        Some(recovered) => recovered.borrow()[..st.n_recovered].to_vec(),
+        None => vec![],
+    };
+    if options.trim_trailing_whitespace {
+        let rest = st.it_parsing.as_str();
+        let trimmed = rest.trim_start();
+        let (spaces, newlines) = (&rest[..rest.len() - trimmed.len()], st.newlines);
+        st.pos.advance(spaces, newlines);
+        st.it_parsing = trimmed.chars();
+    }
+    match (
+        st.it_parsing.as_str().is_empty() || !options.require_eof,
+        st.potential_error.clone(),
+    ) {
+        (true, _) => Ok(Parsed {
+            ast,
+            warnings: st.warnings,
+            errors,
+            decisions,
+            captures: st.captured.into_iter().fold(
+                std::collections::HashMap::new(),
+                |mut acc, (label, span)| {
+                    acc.entry(label).or_insert_with(Vec::new).push(span);
+                    acc
+                },
+            ),
+        }),
+        (false, Some(e)) => Err(*e),
         (false, None) => Err(parser::Error::from_status_normal(
             &st,
+            codes::Code::IncompleteInput,
             "not consumed full input",
         )),
     }
 }
 
+/// Check if the input is valid for the rules, starting on ```main```
+///
+/// It will not build the AST nor the error information. Use it when
+/// you just need to know if the input matches
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::matches;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(lit!("a"), 1)
+///     };
+///
+///     assert!(matches("aaa", &rules));
+///     assert!(!matches("aab", &rules));
+/// }
+/// ```
+pub fn matches(s: &str, rules: &parser::expression::SetOfRules) -> bool {
+    matches_rule(s, rules, "main")
+}
+
+/// Same as ```matches```, but starting on the rule provided
+///
+/// If the rule doesn't exist, it will return false
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::matches_rule;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(ref_rule!("as"), lit!("b")),
+///        "as"     =>  rep!(lit!("a"), 1)
+///     };
+///
+///     assert!(matches_rule("aaa", &rules, "as"));
+///     assert!(!matches_rule("aaab", &rules, "as"));
+///     assert!(!matches_rule("aaa", &rules, "bs"));
+/// }
+/// ```
+pub fn matches_rule(s: &str, rules: &parser::expression::SetOfRules, rule_name: &str) -> bool {
+    let status = parser::Status::init(s, &rules).set_fail_fast(true);
+    match parser::expression::parse_rule(status, rule_name) {
+        Ok((st, _)) => st.it_parsing.as_str().is_empty(),
+        Err(_) => false,
+    }
+}
+
+/// Validate the input and get the text matched by some rules
+///
+/// The AST will not be built. It returns, per rule name (or label),
+/// the texts matched by it in order. Rules not listed, or not found
+/// on the input, will not be on the result
+///
+/// It's cheaper than a full parse. On error, it will only report
+/// the position
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{extract, rules_from_peg};
+///
+/// fn main() {
+///     let rules = rules_from_peg(
+///         r#"
+///     main        =   (line  "\n")+
+///     line        =   name  ' = '  (version / value)
+///     name        =   [a-z]+
+///     version     =   [0-9]+ '.' [0-9]+
+///     value       =   [a-z]+
+///         "#,
+///     ).unwrap();
+///
+///     let values = extract("dep = 1.2\nmode = fast\n", &rules, &["name", "version"]).unwrap();
+///     assert_eq!(values["name"], vec!["dep", "mode"]);
+///     assert_eq!(values["version"], vec!["1.2"]);
+///     assert!(values.get("value").is_none());
+///
+///     assert!(extract("dep = \n", &rules, &["name"]).is_err());
+/// }
+/// ```
+pub fn extract(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    names: &[&str],
+) -> Result<std::collections::HashMap<String, Vec<String>>, parser::Error> {
+    let status = parser::Status::init(s, rules)
+        .set_fail_fast(true)
+        .set_extract(names);
+    let (st, _) = parser::expression::parse(status)?;
+
+    if st.it_parsing.as_str().is_empty() {
+        Ok(st.extracted.into_iter().fold(
+            std::collections::HashMap::new(),
+            |mut acc, (name, text)| {
+                acc.entry(name).or_insert_with(Vec::new).push(text);
+                acc
+            },
+        ))
+    } else {
+        Err(parser::Error::from_status_normal(
+            &st,
+            codes::Code::IncompleteInput,
+            "not consumed full input",
+        ))
+    }
+}
+
+/// Check the input against several grammars (starting on ```main```)
+///
+/// It returns the grammars matching the full input, and the ones
+/// matching the longest prefix. Empty prefixes are not considered for
+/// the longest one (but an empty input is fully matched by the
+/// grammars accepting it)
+///
+/// It runs on fail fast mode (no AST nor error information). Every
+/// grammar is parsed on its own, nothing is shared between them (a
+/// grammar not starting with the first char fails there)
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{classify, rules_from_peg};
+///
+/// fn main() {
+///     let metric = rules_from_peg(r#"main = [a-z]+ ' ' [0-9]+"#).unwrap();
+///     let log = rules_from_peg(r#"main = ('INFO' / 'WARN') ' ' .*"#).unwrap();
+///     let word = rules_from_peg(r#"main = [a-zA-Z]+"#).unwrap();
+///     let grammars = [("metric", &metric), ("log", &log), ("word", &word)];
+///
+///     let class = classify("cpu 95", &grammars);
+///     assert_eq!(class.full, vec!["metric"]);
+///
+///     let class = classify("INFO started", &grammars);
+///     assert_eq!(class.full, vec!["log"]);
+///
+///     let class = classify("cpu high", &grammars);
+///     assert!(class.full.is_empty());
+///     assert_eq!(class.longest, vec!["word"]);
+///     assert_eq!(class.longest_len, 3);
+///
+///     let any = rules_from_peg(r#"main = .*"#).unwrap();
+///     let class = classify("", &[("word", &word), ("any", &any)]);
+///     assert_eq!(class.full, vec!["any"]);
+///     assert!(class.longest.is_empty());
+/// }
+/// ```
+pub fn classify<'a>(
+    s: &str,
+    grammars: &[(&'a str, &parser::expression::SetOfRules)],
+) -> Classification<'a> {
+    let prefix_len = |rules| prefix_len(s, rules);
+
+    grammars.iter().fold(
+        Classification {
+            full: vec![],
+            longest: vec![],
+            longest_len: 0,
+        },
+        |mut acc, &(name, rules)| {
+            match prefix_len(rules) {
+                None => (),
+                //  empty prefixes don't count for the longest
+                Some(0) => {
+                    if s.is_empty() {
+                        acc.full.push(name);
+                    }
+                }
+                Some(len) => {
+                    if len == s.len() {
+                        acc.full.push(name);
+                    }
+                    if len > acc.longest_len {
+                        acc.longest = vec![name];
+                        acc.longest_len = len;
+                    } else if len == acc.longest_len {
+                        acc.longest.push(name);
+                    }
+                }
+            }
+            acc
+        },
+    )
+}
+
+/// Check the input against several grammars (as ```classify```), and
+/// rank the ones matching a prefix
+///
+/// Longer matches go first. Grammars matching the same length are
+/// ordered with the tie breaking criteria (look at ```TieBreak```)
+///
+/// ```
+/// use dynparser::{classify_ranked, rules_from_peg, TieBreak};
+///
+/// let csv = rules_from_peg(
+///     r#"
+///     main    =   field  (',' field)*
+///     field   =   [a-z0-9]+
+///     "#,
+/// ).unwrap();
+/// let word = rules_from_peg(r#"main = [a-z]+"#).unwrap();
+/// let text = rules_from_peg(r#"main = [,a-z0-9]+"#).unwrap();
+/// let grammars = [("word", &word), ("csv", &csv), ("text", &text)];
+///
+/// let ranked = classify_ranked("a,b1", &grammars, &[]);
+/// let names: Vec<&str> = ranked.iter().map(|r| r.name).collect();
+/// assert_eq!(names, vec!["csv", "text", "word"]);
+/// assert!(ranked[0].full);
+/// assert_eq!(ranked[2].score, 0.25);
+///
+/// let ranked = classify_ranked("a,b1", &grammars, &[TieBreak::Priority(&["text"])]);
+/// assert_eq!(ranked[0].name, "text");
+/// let ranked = classify_ranked("a,b1", &grammars, &[TieBreak::FewerRules]);
+/// assert_eq!(ranked[0].name, "text");
+/// let ranked = classify_ranked("a,b1", &grammars, &[TieBreak::MoreRules]);
+/// assert_eq!((ranked[0].name, ranked[0].rules), ("csv", 2));
+/// ```
+pub fn classify_ranked<'a>(
+    s: &str,
+    grammars: &[(&'a str, &parser::expression::SetOfRules)],
+    tie_break: &[TieBreak],
+) -> Vec<Ranked<'a>> {
+    let mut ranked: Vec<Ranked<'a>> = grammars
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &(name, rules))| match prefix_len(s, rules) {
+            Some(0) | None => None,
+            Some(len) => Some(Ranked {
+                name,
+                index,
+                len,
+                full: len == s.len(),
+                score: len as f64 / s.len() as f64,
+                rules: rules.exprs.len(),
+            }),
+        })
+        .collect();
+
+    let priority = |list: &[&str], r: &Ranked| {
+        list.iter()
+            .position(|name| *name == r.name)
+            .unwrap_or(list.len())
+    };
+    ranked.sort_by(|a, b| {
+        tie_break
+            .iter()
+            .fold(b.len.cmp(&a.len), |order, criterion| {
+                order.then_with(|| match criterion {
+                    TieBreak::Priority(list) => priority(list, a).cmp(&priority(list, b)),
+                    TieBreak::MoreRules => b.rules.cmp(&a.rules),
+                    TieBreak::FewerRules => a.rules.cmp(&b.rules),
+                    TieBreak::Registration => a.index.cmp(&b.index),
+                })
+            })
+    });
+    ranked
+}
+
+/// Parse the input as a sequence of the rule provided
+///
+/// Each iteration will parse the rule from the end of previous one,
+/// till the input is consumed. It will stop after the first error
+///
+/// Useful to process big inputs record by record
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{ast, parse_iter};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "line"   =>  and!(rep!(ematch!(chlist "", from 'a', to 'z'), 1), lit!(";"))
+///     };
+///
+///     let lines: Vec<ast::Node> = parse_iter("abc;de;", &rules, "line")
+///         .collect::<Result<_, _>>()
+///         .unwrap();
+///     assert_eq!(lines.len(), 2);
+///
+///     let mut it = parse_iter("abc;d3;", &rules, "line");
+///     assert!(it.next().unwrap().is_ok());
+///     assert_eq!(it.offset(), 4);
+///     assert!(it.next().unwrap().is_err());
+///     assert!(it.next().is_none());
+/// }
+/// ```
+pub fn parse_iter<'a>(
+    s: &'a str,
+    rules: &'a parser::expression::SetOfRules,
+    rule_name: &str,
+) -> ParseIter<'a> {
+    ParseIter {
+        rule_name: rule_name.to_string(),
+        offset: 0,
+        status: Some(parser::Status::init(s, rules)),
+    }
+}
+
 pub use peg::rules_from_peg;
 
 //  A P I
@@ -527,3 +2524,71 @@ pub use peg::rules_from_peg;
 
 //-----------------------------------------------------------------------
 //  I N T E R N A L
+
+//  bytes matched by the grammar from the start of the input, if any
+fn prefix_len(s: &str, rules: &parser::expression::SetOfRules) -> Option<usize> {
+    let status = parser::Status::init(s, rules).set_fail_fast(true);
+    parser::expression::parse(status)
+        .ok()
+        .map(|(st, _)| s.len() - st.it_parsing.as_str().len())
+}
+
+//  decisions sorted by position, the ones registered by the ors
+//  still on the grammar
+fn decision_trace(
+    rules: &parser::expression::SetOfRules,
+    raw: &[parser::RawDecision],
+) -> Vec<parser::Decision> {
+    let ors = parser::expression::or_positions(rules);
+    //  registered when the or finishes, the ones inside first
+    let mut decisions: Vec<parser::Decision> = raw
+        .iter()
+        .rev()
+        .filter_map(|(or, alternative, span)| {
+            let (rule, index, alternatives) = ors.get(or)?;
+            Some(parser::Decision {
+                rule: rule.to_string(),
+                index: *index,
+                alternative: *alternative,
+                alternatives: *alternatives,
+                span: span.clone(),
+            })
+        })
+        .collect();
+    decisions.sort_by_key(|d| (d.span.start.n, std::cmp::Reverse(d.span.end.n)));
+    decisions
+}
+
+//  BOM and shebang line, if enabled on the options
+fn skip_input_header<'a>(
+    mut status: parser::Status<'a>,
+    options: &ParseOptions,
+) -> parser::Status<'a> {
+    let input = status.it_parsing.as_str();
+    let mut rest = input;
+    if options.skip_bom {
+        rest = rest.strip_prefix('\u{feff}').unwrap_or(rest);
+    }
+    if options.skip_shebang && rest.starts_with("#!") {
+        rest = rest.find('\n').map_or("", |nl| &rest[nl + 1..]);
+    }
+    let (skipped, newlines) = (&input[..input.len() - rest.len()], status.newlines);
+    status.pos.advance(skipped, newlines);
+    status.input_start = status.pos.n;
+    status.it_parsing = rest.chars();
+    status
+}
+
+//  position on the kept lines, to the position on the full input
+fn original_pos(pos: &parser::Possition, lines: &[&str], kept: &[usize]) -> parser::Possition {
+    let line_len = |i: usize| lines[i].chars().count();
+    let row = kept.get(pos.row).cloned().unwrap_or(lines.len());
+    let kept_start: usize = kept.iter().take(pos.row).map(|i| line_len(*i)).sum();
+    let original_start: usize = (0..row).map(line_len).sum();
+    parser::Possition {
+        n: pos.n - kept_start + original_start,
+        row,
+        col: pos.col,
+        start_line: pos.start_line - kept_start + original_start,
+    }
+}
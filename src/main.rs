@@ -1,8 +1,106 @@
 extern crate dynparser;
-use dynparser::peg::peg2code;
-
+use dynparser::parser::analysis;
+use dynparser::peg::{self, peg2code};
+use std::{env, fs};
+
+mod repl;
+
+//  without params, it will print the rules to parse peg grammars
+//
+//      dynparser repl <grammar.peg>
+//
+//  will start an interactive session to try the grammar
+//
+//      dynparser check <grammar.peg>
+//
+//  will compile the grammar, look for excessive backtracking and run
+//  the tests declared on it
+//
+//      dynparser trace <grammar.peg> <input> > trace.json
+//
+//  (with the profiling feature) will parse the input file and write
+//  the rule calls in Chrome trace event format, to see them on
+//  chrome://tracing
 fn main() {
-    peg2code::print_rules2parse_peg();
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(|a| a.as_str()).collect::<Vec<_>>().as_slice() {
+        [] => peg2code::print_rules2parse_peg(),
+        ["repl", grammar] => {
+            if let Err(e) = repl::run(grammar) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        ["check", grammar] => {
+            if let Err(e) = check(grammar) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "profiling")]
+        ["trace", grammar, input] => {
+            if let Err(e) = trace(grammar, input) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            #[cfg(not(feature = "profiling"))]
+            eprintln!("usage: dynparser [repl <grammar.peg> | check <grammar.peg>]");
+            #[cfg(feature = "profiling")]
+            eprintln!(
+                "usage: dynparser [repl <grammar.peg> | check <grammar.peg> | \
+                 trace <grammar.peg> <input>]"
+            );
+            std::process::exit(2);
+        }
+    }
+}
+
+fn check(path: &str) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("reading {}: {}", path, e))?;
+    let grammar = peg::compile(&text).map_err(|e| match e {
+        peg::Error::Parser(e) => format!("invalid grammar: {}", e),
+        e => format!("invalid grammar: {}", e),
+    })?;
+
+    println!("fingerprint {:016x}", grammar.rules.fingerprint());
+    for w in analysis::backtracking(&grammar.rules) {
+        println!("WARNING {}", w);
+    }
+
+    let failed = grammar.run_inline_tests();
+    for t in &failed {
+        println!(
+            "FAILED  %test {} {} {:?}",
+            t.rule,
+            if t.accepts { "accepts" } else { "rejects" },
+            t.input
+        );
+    }
+    println!(
+        "{} tests, {} passed, {} failed",
+        grammar.tests.len(),
+        grammar.tests.len() - failed.len(),
+        failed.len()
+    );
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err("inline tests failed".to_string())
+    }
+}
+
+#[cfg(feature = "profiling")]
+fn trace(grammar_path: &str, input_path: &str) -> Result<(), String> {
+    let read =
+        |path: &str| fs::read_to_string(path).map_err(|e| format!("reading {}: {}", path, e));
+    let grammar =
+        peg::compile(&read(grammar_path)?).map_err(|e| format!("invalid grammar: {}", e))?;
+
+    let (result, profile) = dynparser::parse_profile(&read(input_path)?, &grammar.rules);
+    print!("{}", profile.to_chrome_trace());
+    result.map(|_| ()).map_err(|e| format!("parsing {}: {}", input_path, e))
 }
 
 //  --------------------------
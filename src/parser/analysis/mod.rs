@@ -0,0 +1,531 @@
+#![warn(missing_docs)]
+//! Static checks on a set of rules, without parsing
+//!
+//! There is no memoization by default. Some grammar shapes are fine on
+//! small inputs, but the time grows very fast with the nesting or with
+//! the size of the input. These checks look for them (or turn on
+//! ```ParseOptions::memoize```)
+//!
+//! ```
+//! use dynparser::parser::analysis;
+//! use dynparser::rules_from_peg;
+//!
+//! let rules = rules_from_peg(
+//!     r#"
+//!     main    =   expr
+//!     expr    =   term  '+'  expr
+//!             /   term
+//!     term    =   '('  expr  ')'
+//!             /   [0-9]+
+//!     "#,
+//! ).unwrap();
+//!
+//! let warnings = analysis::backtracking(&rules);
+//! assert_eq!(warnings.len(), 1);
+//! assert_eq!(warnings[0].rule, "expr");
+//! ```
+
+use crate::codes::Code;
+use crate::parser;
+use crate::parser::atom::Atom;
+use crate::parser::expression::{rule_references, Expression, SetOfRules, UndefinedRule};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Grammar shape that can produce excessive backtracking
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktrackingWarning {
+    /// Rule containing the expression
+    pub rule: String,
+    /// What was found
+    pub descr: String,
+    /// How to rewrite it
+    pub suggestion: String,
+    /// Stable code of the warning (look at ```codes```)
+    pub code: Code,
+}
+
+impl fmt::Display for BacktrackingWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "rule {}: {} ({})",
+            self.rule, self.descr, self.suggestion
+        )
+    }
+}
+
+/// Problem on the references of a set of rules (look at ```validate```)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    /// Reference to a rule not defined
+    Undefined(UndefinedRule),
+    /// The rule calls itself without consuming input, it never ends.
+    /// The rules called, from the rule back to it
+    LeftRecursion(Vec<String>),
+    /// Rule not reachable from ```main```
+    Unused(String),
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::Undefined(undefined) => write!(f, "{}", undefined),
+            ValidationIssue::LeftRecursion(path) => write!(
+                f,
+                "rule {} calls itself without consuming input ({})",
+                path[0],
+                path.join(" -> ")
+            ),
+            ValidationIssue::Unused(rule) => write!(f, "rule {} is not used from main", rule),
+        }
+    }
+}
+
+impl ValidationIssue {
+    /// Stable code of the issue (look at ```codes```)
+    pub fn code(&self) -> Code {
+        match self {
+            ValidationIssue::Undefined(undefined) => undefined.code(),
+            ValidationIssue::LeftRecursion(_) => Code::LeftRecursion,
+            ValidationIssue::Unused(_) => Code::UnusedRule,
+        }
+    }
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Look for grammar shapes with excessive backtracking
+///
+/// * Repetitions of expressions that can match empty. They never end
+/// * Alternatives starting with the same recursive rule. On failure,
+///   the rule is parsed again, and the cost doubles on every nesting
+///   level
+/// * Alternatives starting with the same rule inside a repetition
+///   or an optional. The rule is parsed again on every iteration
+///
+/// Rules are checked in alphabetical order
+pub fn backtracking(rules: &SetOfRules) -> Vec<BacktrackingWarning> {
+    let nullable = nullable_rules(rules);
+    let mut names: Vec<&String> = rules.exprs.keys().collect();
+    names.sort();
+
+    parser::map_per_rule(&names, |name| {
+        let mut warnings = vec![];
+        let ctx = Context {
+            rule: name,
+            rules,
+            nullable: &nullable,
+        };
+        check_expr(&ctx, &rules.exprs[*name], false, &mut warnings);
+        warnings
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Check the references between the rules, without parsing
+///
+/// * References to rules not defined (as ```SetOfRules::check_refs```)
+/// * Rules calling themselves without consuming input, i.e.
+///   ```a = a``` or ```a = b? a```. Not checked if left recursion is
+///   allowed (look at ```SetOfRules::left_recursion```)
+/// * Rules not reachable from ```main```. Not checked without ```main```
+///
+/// Issues are reported in this order, and by rule name
+///
+/// ```
+/// use dynparser::parser::analysis::{self, ValidationIssue};
+/// use dynparser::rules_from_peg;
+///
+/// let rules = rules_from_peg(
+///     r#"
+///     main    =   list
+///     list    =   item? list  /  item
+///     item    =   [0-9]+
+///     old     =   'x'
+///     "#,
+/// ).unwrap();
+///
+/// let issues = analysis::validate(&rules);
+/// assert_eq!(
+///     issues[0].to_string(),
+///     "rule list calls itself without consuming input (list -> list)"
+/// );
+/// assert_eq!(issues[1], ValidationIssue::Unused("old".to_string()));
+/// ```
+pub fn validate(rules: &SetOfRules) -> Vec<ValidationIssue> {
+    let mut issues: Vec<ValidationIssue> = match rules.check_refs() {
+        Ok(()) => vec![],
+        Err(undefined) => undefined
+            .into_iter()
+            .map(ValidationIssue::Undefined)
+            .collect(),
+    };
+
+    let mut names: Vec<&String> = rules.exprs.keys().collect();
+    names.sort();
+    if !rules.allows_left_recursion() {
+        let nullable = nullable_rules(rules);
+        issues.extend(
+            names
+                .iter()
+                .filter_map(|name| left_loop(rules, &nullable, name))
+                .map(ValidationIssue::LeftRecursion),
+        );
+    }
+    if rules.exprs.contains_key("main") {
+        let reachable = reachable_rules(rules, "main");
+        issues.extend(
+            names
+                .iter()
+                .filter(|name| !reachable.contains(name.as_str()))
+                .map(|name| ValidationIssue::Unused(name.to_string())),
+        );
+    }
+    issues
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  I N T E R N A L
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+struct Context<'a> {
+    rule: &'a str,
+    rules: &'a SetOfRules,
+    nullable: &'a HashMap<&'a str, bool>,
+}
+
+fn check_expr(
+    ctx: &Context,
+    expr: &Expression,
+    in_repeat: bool,
+    warnings: &mut Vec<BacktrackingWarning>,
+) {
+    match expr {
+        Expression::Repeat(rep) => {
+            if rep.max.is_none() && is_nullable(ctx.nullable, &rep.expression) {
+                warnings.push(BacktrackingWarning {
+                    rule: ctx.rule.to_string(),
+                    descr: "repetition of an expression that can match empty, it never ends"
+                        .to_string(),
+                    suggestion: "make the repeated expression consume input, \
+                                 i.e. (a*)* could be a*"
+                        .to_string(),
+                    code: Code::EmptyRepetition,
+                });
+            }
+            check_expr(ctx, &rep.expression, true, warnings)
+        }
+        Expression::Or(mexpr) => {
+            for prefix in common_prefixes(&mexpr.0) {
+                if let Some((code, descr)) = common_prefix_cost(ctx, prefix, in_repeat) {
+                    warnings.push(BacktrackingWarning {
+                        rule: ctx.rule.to_string(),
+                        descr,
+                        suggestion: format!(
+                            "factor the common prefix, i.e. {p} a / {p} b could be {p} (a / b)",
+                            p = prefix
+                        ),
+                        code,
+                    });
+                }
+            }
+            mexpr
+                .0
+                .iter()
+                .for_each(|e| check_expr(ctx, e, in_repeat, warnings))
+        }
+        Expression::And(mexpr) => mexpr
+            .0
+            .iter()
+            .for_each(|e| check_expr(ctx, e, in_repeat, warnings)),
+        Expression::Not(e)
+        | Expression::Labeled(_, e)
+        | Expression::Deprecated(_, e)
+        | Expression::NotKeyword(_, e)
+        | Expression::Keywords(_, e) => check_expr(ctx, e, in_repeat, warnings),
+        Expression::Simple(_) | Expression::RuleName(_) => (),
+    }
+}
+
+fn common_prefix_cost(ctx: &Context, prefix: &str, in_repeat: bool) -> Option<(Code, String)> {
+    if is_recursive(ctx.rules, prefix) {
+        let descr = format!(
+            "several alternatives start with the recursive rule {}, \
+             the cost grows exponentially with the nesting",
+            prefix
+        );
+        Some((Code::RecursivePrefix, descr))
+    } else if in_repeat {
+        let descr = format!(
+            "several alternatives start with the rule {} inside a repetition, \
+             it is parsed again on every iteration",
+            prefix
+        );
+        Some((Code::RepeatedPrefix, descr))
+    } else {
+        None
+    }
+}
+
+//  rules starting more than one alternative, in order of appearance
+fn common_prefixes(alternatives: &[Expression]) -> Vec<&str> {
+    let firsts: Vec<&str> = alternatives.iter().filter_map(first_rule).collect();
+    let mut result: Vec<&str> = vec![];
+    for (i, first) in firsts.iter().enumerate() {
+        if firsts[i + 1..].contains(first) && !result.contains(first) {
+            result.push(first);
+        }
+    }
+    result
+}
+
+//  rule that will be parsed first by the expression (if any)
+fn first_rule(expr: &Expression) -> Option<&str> {
+    match expr {
+        Expression::RuleName(name) => Some(name),
+        Expression::And(mexpr) => mexpr.0.first().and_then(first_rule),
+        Expression::Repeat(rep) if rep.min.0 > 0 => first_rule(&rep.expression),
+        Expression::Labeled(_, e)
+        | Expression::Deprecated(_, e)
+        | Expression::NotKeyword(_, e)
+        | Expression::Keywords(_, e) => first_rule(e),
+        _ => None,
+    }
+}
+
+fn is_recursive(rules: &SetOfRules, name: &str) -> bool {
+    let mut pending: Vec<&str> = vec![];
+    let mut visited: BTreeSet<&str> = BTreeSet::new();
+    if let Some(e) = rules.exprs.get(name) {
+        pending.extend(rule_references(e));
+    }
+    while let Some(current) = pending.pop() {
+        if current == name {
+            return true;
+        }
+        if visited.insert(current) {
+            if let Some(e) = rules.exprs.get(current) {
+                pending.extend(rule_references(e));
+            }
+        }
+    }
+    false
+}
+
+//  rules reachable from the rule (included)
+fn reachable_rules<'a>(rules: &'a SetOfRules, name: &'a str) -> BTreeSet<&'a str> {
+    let mut pending: Vec<&str> = vec![name];
+    let mut visited: BTreeSet<&str> = BTreeSet::new();
+    while let Some(current) = pending.pop() {
+        if visited.insert(current) {
+            if let Some(e) = rules.exprs.get(current) {
+                pending.extend(rule_references(e));
+            }
+        }
+    }
+    visited
+}
+
+//  shortest loop of rules called without consuming input, from the rule
+//  back to it
+fn left_loop(
+    rules: &SetOfRules,
+    nullable: &HashMap<&str, bool>,
+    name: &str,
+) -> Option<Vec<String>> {
+    let mut pending: VecDeque<Vec<&str>> = VecDeque::new();
+    let mut visited: BTreeSet<&str> = BTreeSet::new();
+    pending.push_back(vec![name]);
+    while let Some(path) = pending.pop_front() {
+        let mut calls = vec![];
+        if let Some(e) = rules.exprs.get(path[path.len() - 1]) {
+            left_calls(nullable, e, &mut calls);
+        }
+        for call in calls {
+            let mut next = path.clone();
+            next.push(call);
+            if call == name {
+                return Some(next.into_iter().map(str::to_string).collect());
+            }
+            if visited.insert(call) {
+                pending.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+//  rules called by the expression before consuming input
+fn left_calls<'a>(nullable: &HashMap<&str, bool>, expr: &'a Expression, calls: &mut Vec<&'a str>) {
+    match expr {
+        Expression::RuleName(name) => calls.push(name),
+        Expression::And(mexpr) => {
+            for e in &mexpr.0 {
+                left_calls(nullable, e, calls);
+                if !is_nullable(nullable, e) {
+                    break;
+                }
+            }
+        }
+        Expression::Or(mexpr) => mexpr.0.iter().for_each(|e| left_calls(nullable, e, calls)),
+        Expression::Repeat(rep) => left_calls(nullable, &rep.expression, calls),
+        Expression::Not(e)
+        | Expression::Labeled(_, e)
+        | Expression::Deprecated(_, e)
+        | Expression::NotKeyword(_, e)
+        | Expression::Keywords(_, e) => left_calls(nullable, e, calls),
+        Expression::Simple(_) => (),
+    }
+}
+
+//  alternatives that can't start with the same char
+//  at most one of them can match, whatever the order
+pub(crate) fn disjoint_alternatives(rules: &SetOfRules, alternatives: &[Expression]) -> bool {
+    let nullable = nullable_rules(rules);
+    let firsts: Option<Vec<Vec<(char, char)>>> = alternatives
+        .iter()
+        .map(|e| first_chars(rules, &nullable, e, &mut vec![]))
+        .collect();
+    let overlap = |a: &[(char, char)], b: &[(char, char)]| {
+        a.iter().any(|(from_a, to_a)| {
+            b.iter()
+                .any(|(from_b, to_b)| from_a <= to_b && from_b <= to_a)
+        })
+    };
+
+    match firsts {
+        Some(firsts) => firsts
+            .iter()
+            .enumerate()
+            .all(|(i, a)| firsts[i + 1..].iter().all(|b| !overlap(a, b))),
+        None => false,
+    }
+}
+
+//  ranges of the chars the expression can start with
+//  None if unknown (predicates, custom matchers, nullable...)
+fn first_chars<'a>(
+    rules: &'a SetOfRules,
+    nullable: &HashMap<&str, bool>,
+    expr: &'a Expression,
+    visiting: &mut Vec<&'a str>,
+) -> Option<Vec<(char, char)>> {
+    match expr {
+        Expression::Simple(Atom::Literal(l)) => l.chars().next().map(|ch| vec![(ch, ch)]),
+        Expression::Simple(Atom::Char(ch)) => Some(vec![(*ch, *ch)]),
+        Expression::Simple(Atom::LiteralNoCase(l)) => l.chars().next().map(|ch| {
+            ch.to_lowercase()
+                .chain(ch.to_uppercase())
+                .map(|c| (c, c))
+                .collect()
+        }),
+        Expression::Simple(Atom::Match(mrules)) if !mrules.is_negated() => Some(
+            mrules
+                .0
+                .chars()
+                .map(|ch| (ch, ch))
+                .chain(mrules.1.iter().cloned())
+                .collect(),
+        ),
+        Expression::Simple(Atom::Balanced(open, _)) => open.chars().next().map(|ch| vec![(ch, ch)]),
+        Expression::Simple(_) => None,
+        Expression::And(mexpr) => match mexpr.0.first() {
+            Some(e) if !is_nullable(nullable, e) => first_chars(rules, nullable, e, visiting),
+            _ => None,
+        },
+        Expression::Or(mexpr) => mexpr
+            .0
+            .iter()
+            .map(|e| first_chars(rules, nullable, e, visiting))
+            .collect::<Option<Vec<_>>>()
+            .map(|firsts| firsts.concat()),
+        Expression::Repeat(rep) if rep.min.0 > 0 => {
+            first_chars(rules, nullable, &rep.expression, visiting)
+        }
+        Expression::RuleName(name) if !visiting.contains(&name.as_str()) => {
+            visiting.push(name);
+            let firsts = rules
+                .exprs
+                .get(name)
+                .and_then(|e| first_chars(rules, nullable, e, visiting));
+            visiting.pop();
+            firsts
+        }
+        Expression::Labeled(_, e)
+        | Expression::Deprecated(_, e)
+        | Expression::NotKeyword(_, e)
+        | Expression::Keywords(_, e) => first_chars(rules, nullable, e, visiting),
+        Expression::Not(_) | Expression::Repeat(_) | Expression::RuleName(_) => None,
+    }
+}
+
+//  rules able to match without consuming input
+//  iterate till no changes, rules can be recursive
+fn nullable_rules(rules: &SetOfRules) -> HashMap<&str, bool> {
+    let mut nullable: HashMap<&str, bool> =
+        rules.exprs.keys().map(|k| (k.as_str(), false)).collect();
+    loop {
+        let changed: Vec<&str> = rules
+            .exprs
+            .iter()
+            .filter(|(name, e)| !nullable[name.as_str()] && is_nullable(&nullable, e))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if changed.is_empty() {
+            return nullable;
+        }
+        changed.into_iter().for_each(|name| {
+            nullable.insert(name, true);
+        });
+    }
+}
+
+//  custom matchers are considered not nullable
+fn is_nullable(nullable: &HashMap<&str, bool>, expr: &Expression) -> bool {
+    match expr {
+        Expression::Simple(Atom::Literal(l)) | Expression::Simple(Atom::LiteralNoCase(l)) => {
+            l.is_empty()
+        }
+        Expression::Simple(Atom::Warning(_))
+        | Expression::Simple(Atom::Pos(_))
+        | Expression::Simple(Atom::EOF)
+        | Expression::Simple(Atom::BOF)
+        | Expression::Simple(Atom::BOL)
+        | Expression::Simple(Atom::EOL)
+        | Expression::Simple(Atom::Until(_, _)) => true,
+        Expression::Simple(_) => false,
+        Expression::And(mexpr) => mexpr.0.iter().all(|e| is_nullable(nullable, e)),
+        Expression::Or(mexpr) => mexpr.0.iter().any(|e| is_nullable(nullable, e)),
+        Expression::Not(_) => true,
+        Expression::Repeat(rep) => rep.min.0 == 0 || is_nullable(nullable, &rep.expression),
+        Expression::RuleName(name) => nullable.get(name.as_str()).cloned().unwrap_or(false),
+        Expression::Labeled(_, e)
+        | Expression::Deprecated(_, e)
+        | Expression::NotKeyword(_, e)
+        | Expression::Keywords(_, e) => is_nullable(nullable, e),
+    }
+}
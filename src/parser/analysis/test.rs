@@ -0,0 +1,116 @@
+//-----------------------------------------------------------------------
+//
+//  mod parser::analysis  TEST
+//
+//-----------------------------------------------------------------------
+use super::{backtracking, validate, ValidationIssue};
+use crate::codes::Code;
+use crate::parser::expression::UndefinedRule;
+use crate::rules_from_peg;
+
+#[test]
+fn test_backtracking_empty_loop() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   ('a'? spaces)*  'b'
+        spaces  =   ' '*
+        "#,
+    )
+    .unwrap();
+
+    let warnings = backtracking(&rules);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].rule, "main");
+    assert!(warnings[0].descr.contains("match empty"));
+}
+
+#[test]
+fn test_backtracking_common_prefix_in_repetition() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   (item ';' / item ',')*
+        item    =   [a-z]+
+        "#,
+    )
+    .unwrap();
+
+    let warnings = backtracking(&rules);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].descr.contains("inside a repetition"));
+    assert!(warnings[0].suggestion.contains("item (a / b)"));
+}
+
+#[test]
+fn test_backtracking_no_warnings() {
+    //  common prefix, not recursive and not repeated
+    let rules = rules_from_peg(
+        r#"
+        main    =   item ';' / item ','
+        item    =   [a-z]+
+        "#,
+    )
+    .unwrap();
+    assert_eq!(backtracking(&rules), vec![]);
+
+    //  recursive but factored
+    let rules = rules_from_peg(
+        r#"
+        main    =   expr
+        expr    =   term  ('+'  expr)?
+        term    =   '('  expr  ')'  /  [0-9]+
+        "#,
+    )
+    .unwrap();
+    assert_eq!(backtracking(&rules), vec![]);
+}
+
+#[test]
+fn test_validate() {
+    let rules = rules! {
+        "main"  =>  and!(ref_rule!("expr"), ref_rule!("missing")),
+        "expr"  =>  or!(and!(ref_rule!("term"), lit!("+")), lit!("x")),
+        "term"  =>  and!(rep!(lit!(" "), 0), not!(lit!("-")), ref_rule!("expr")),
+        "lost"  =>  ref_rule!("lost2"),
+        "lost2" =>  and!(lit!("a"), ref_rule!("lost"))
+    };
+
+    let issues = validate(&rules);
+    let path = |p: &[&str]| p.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        issues,
+        vec![
+            ValidationIssue::Undefined(UndefinedRule {
+                rule: "missing".to_string(),
+                from: "main".to_string()
+            }),
+            ValidationIssue::LeftRecursion(path(&["expr", "term", "expr"])),
+            ValidationIssue::LeftRecursion(path(&["term", "expr", "term"])),
+            ValidationIssue::Unused("lost".to_string()),
+            ValidationIssue::Unused("lost2".to_string()),
+        ]
+    );
+    let codes: Vec<Code> = issues.iter().map(|i| i.code()).collect();
+    assert_eq!(
+        codes,
+        vec![
+            Code::MissingRule,
+            Code::LeftRecursion,
+            Code::LeftRecursion,
+            Code::UnusedRule,
+            Code::UnusedRule
+        ]
+    );
+    assert_eq!(
+        issues[1].to_string(),
+        "rule expr calls itself without consuming input (expr -> term -> expr)"
+    );
+
+    //  allowed left recursion, and no main
+    let rules = rules.left_recursion(true);
+    assert_eq!(validate(&rules).len(), 3);
+    let rules = rules! { "a" => ref_rule!("a") };
+    assert_eq!(
+        validate(&rules),
+        vec![ValidationIssue::LeftRecursion(path(&["a", "a"]))]
+    );
+}
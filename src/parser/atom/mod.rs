@@ -1,9 +1,14 @@
-use crate::ast;
+use crate::ast::{self, builder::TreeEvent, kind::NodeKind};
+use crate::codes::Code;
 /// Support for minimum expressions elements
 /// Here we have the parser and types for non dependencies kind
-use crate::parser::{ErrPriority, Error, Result, Status};
+use crate::parser::{Diagnostic, ErrPriority, Error, ParseContext, Result, Span, Status};
+use std::fmt;
 use std::result;
+use std::sync::Arc;
 
+#[cfg(feature = "nom")]
+pub mod nom;
 #[cfg(test)]
 mod test;
 
@@ -16,26 +21,171 @@ mod test;
 //-----------------------------------------------------------------------
 
 /// This is a minimum expression element
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Atom {
     /// Literal string
     Literal(String),
+    /// Literal of a single char. Cheaper than a ```Literal```, peg
+    /// literals of one char are compiled to it
+    Char(char),
+    /// Literal string, ignoring the case (unicode simple case folding)
+    LiteralNoCase(String),
     /// Character matches a list of chars or a list of ranges
     Match(MatchRules),
+    /// Same as ```Match```, ignoring the case
+    MatchNoCase(MatchRules),
     /// Indicates an error.
     /// It will propagate an error while processing
     Error(String),
+    /// Zero width. It will register a warning and continue
+    Warning(String),
+    /// Zero width. It will register the position on the captures with
+    /// the name (look at ```parse_with_captures```)
+    Pos(String),
     /// Any char
     Dot,
+    /// Any char but a new line (```\n``` or ```\r```)
+    DotNoNewline,
     /// End Of File
     EOF,
+    /// Beginning Of File (zero width)
+    BOF,
+    /// Beginning Of Line (zero width)
+    BOL,
+    /// End Of Line (zero width). Next char is a new line or EOF
+    EOL,
+    /// Any text till the delimiter (not included). It fails if the
+    /// delimiter is not found
+    /// With an escape, the escape and the next char are skipped
+    Until(String, Option<String>),
+    /// From the open delimiter till the close one on the same nesting
+    /// level. The value is the text inside
+    Balanced(String, String),
+    /// Matcher provided by the user
+    ///
+    /// It can't be serialized (```serde``` feature). The rules using it
+    /// have to be added again after loading them
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(Custom),
+    /// Char with the unicode property XID_Start
+    #[cfg(feature = "unicode")]
+    XidStart,
+    /// Char with the unicode property XID_Continue
+    #[cfg(feature = "unicode")]
+    XidContinue,
+    /// An extended grapheme cluster (what a user sees as a char, i.e.
+    /// ```e``` followed by a combining accent)
+    #[cfg(feature = "graphemes")]
+    Grapheme,
+}
+
+/// Function to match the input written by the user
+///
+/// It receives the pending input and the parse context, and returns
+/// the number of bytes matched, or None if it doesn't match.
+/// Returning ```Some(0)``` is a zero width semantic predicate
+pub type CustomFn = dyn Fn(&str, &ParseContext) -> Option<usize> + Send + Sync;
+
+/// Same as ```CustomFn```, with an optional value for the node
+///
+/// If there is a value, the node will have it instead of the matched
+/// text
+pub type CustomValueFn =
+    dyn Fn(&str, &ParseContext) -> Option<(usize, Option<String>)> + Send + Sync;
+
+/// A named matcher written by the user
+///
+/// Usually created with the ```custom!``` macro
+#[derive(Clone)]
+pub struct Custom {
+    name: String,
+    matcher: Arc<CustomValueFn>,
+}
+
+impl Custom {
+    /// Create a custom matcher. The name will be used on errors
+    ///
+    /// Generated code will call a function with this name
+    pub fn new<F>(name: &str, matcher: F) -> Self
+    where
+        F: Fn(&str, &ParseContext) -> Option<usize> + Send + Sync + 'static,
+    {
+        Self::with_value(name, move |rest: &str, context: &ParseContext| {
+            matcher(rest, context).map(|len| (len, None))
+        })
+    }
+
+    /// Create a custom matcher producing a value (look at
+    /// ```CustomValueFn```)
+    pub fn with_value<F>(name: &str, matcher: F) -> Self
+    where
+        F: Fn(&str, &ParseContext) -> Option<(usize, Option<String>)> + Send + Sync + 'static,
+    {
+        Custom {
+            name: name.to_string(),
+            matcher: Arc::new(matcher),
+        }
+    }
+
+    ///  name of the custom matcher
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Debug for Custom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Custom({})", self.name)
+    }
 }
 
 /// contains a char slice and a (char,char) slice
 /// if char matches one in char slice -> OK
 /// if char matches between tuple in elems slice -> OK
-#[derive(Debug)]
-pub struct MatchRules(pub(crate) String, pub(crate) Vec<(char, char)>);
+/// If it's negated (```[^a-z]```), any char not matching them -> OK
+///
+/// Both are compiled on creation to a bitset for the first 256 chars
+/// and a sorted table of ranges for the rest
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "MatchRulesDef", into = "MatchRulesDef")
+)]
+pub struct MatchRules(
+    pub(crate) String,
+    pub(crate) Vec<(char, char)>,
+    CharClass,
+    //  negated
+    bool,
+);
+
+impl fmt::Debug for MatchRules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("MatchRules")
+            .field(&self.0)
+            .field(&self.1)
+            .field(&self.3)
+            .finish()
+    }
+}
+
+//  chars and ranges of a MatchRules, ready to check a char
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CharClass {
+    latin1: [u64; 4],
+    others: Vec<(char, char)>,
+}
+
+//  serialized form of a MatchRules. The CharClass is built again on load
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MatchRulesDef {
+    chars: String,
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
 
 impl MatchRules {
     ///  get a reference to set of chars of match rule
@@ -47,6 +197,11 @@ impl MatchRules {
     pub fn ranges(&self) -> &Vec<(char, char)> {
         &self.1
     }
+
+    ///  it matches the chars not in the chars and ranges
+    pub fn is_negated(&self) -> bool {
+        self.3
+    }
 }
 
 //-----------------------------------------------------------------------
@@ -58,33 +213,88 @@ impl MatchRules {
 //-----------------------------------------------------------------------
 
 pub(crate) fn parse<'a>(status: Status<'a>, atom: &'a Atom) -> Result<'a> {
+    let start = status.text2parse.len() - status.it_parsing.as_str().len();
+    let consumed = |st: &Status| start..st.text2parse.len() - st.it_parsing.as_str().len();
+    match parse_atom(status, atom)? {
+        (st, ast::Node::Val(val)) if st.tree_events.is_some() => {
+            let event = TreeEvent::Token(leaf_kind(atom), val.clone(), consumed(&st));
+            Ok((st.push_tree_event(event), ast::Node::Val(val)))
+        }
+        (st, node @ ast::Node::Val(_)) if st.leaf_kinds.is_some() => {
+            Ok((st.push_leaf_kind(leaf_kind(atom)), node))
+        }
+        result => Ok(result),
+    }
+}
+
+fn parse_atom<'a>(status: Status<'a>, atom: &'a Atom) -> Result<'a> {
     match atom {
         Atom::Literal(literal) => parse_literal(status, &literal),
+        Atom::Char(ch) => parse_char_literal(status, *ch),
+        Atom::LiteralNoCase(literal) => parse_literal_no_case(status, literal),
         Atom::Error(error) => parse_error(&status, &error),
+        Atom::Warning(warning) => parse_warning(status, warning),
+        Atom::Pos(name) => parse_pos(status, name),
         Atom::Match(ref match_rules) => parse_match(status, &match_rules),
+        Atom::MatchNoCase(ref match_rules) => parse_match_no_case(status, match_rules),
         Atom::Dot => parse_dot(status),
+        Atom::DotNoNewline => parse_dot_no_newline(status),
         Atom::EOF => parse_eof(status),
+        Atom::BOF => parse_bof(status),
+        Atom::BOL => parse_bol(status),
+        Atom::EOL => parse_eol(status),
+        Atom::Until(delimiter, escape) => parse_until(status, delimiter, escape.as_deref()),
+        Atom::Balanced(open, close) => parse_balanced(status, open, close),
+        Atom::Custom(ref custom) => parse_custom(status, custom),
+        #[cfg(feature = "unicode")]
+        Atom::XidStart => parse_xid(status, true),
+        #[cfg(feature = "unicode")]
+        Atom::XidContinue => parse_xid(status, false),
+        #[cfg(feature = "graphemes")]
+        Atom::Grapheme => parse_grapheme(status),
+    }
+}
+
+fn leaf_kind(atom: &Atom) -> NodeKind {
+    match atom {
+        Atom::Literal(_) | Atom::LiteralNoCase(_) | Atom::Char(_) => NodeKind::Literal,
+        Atom::Match(_) | Atom::MatchNoCase(_) => NodeKind::Class,
+        #[cfg(feature = "unicode")]
+        Atom::XidStart | Atom::XidContinue => NodeKind::Class,
+        Atom::Dot | Atom::DotNoNewline => NodeKind::Dot,
+        #[cfg(feature = "graphemes")]
+        Atom::Grapheme => NodeKind::Dot,
+        Atom::Custom(custom) => NodeKind::Custom(custom.name.clone()),
+        Atom::EOF => NodeKind::EOF,
+        _ => NodeKind::Other,
     }
 }
 
 impl MatchRules {
     /// Create a MatchRules instance based on string and bounds
     pub fn init(s: &str, bounds: Vec<(char, char)>) -> Self {
-        MatchRules(s.to_string(), bounds)
+        let class = CharClass::new(s, &bounds);
+        MatchRules(s.to_string(), bounds, class, false)
+    }
+    /// Any char but the chars and ranges (```[^...]``` on peg)
+    pub fn negate(self) -> Self {
+        MatchRules(self.0, self.1, self.2, !self.3)
+    }
+    //  the char is accepted (case sensitive)
+    pub(crate) fn accepts(&self, ch: char) -> bool {
+        self.2.contains(ch) != self.3
     }
     #[allow(dead_code)] //  used in tests
     pub(crate) fn new() -> Self {
-        MatchRules("".to_string(), vec![])
+        MatchRules::init("", vec![])
     }
     #[allow(dead_code)] //  used in tests
-    pub(crate) fn with_chars(mut self, chrs: &str) -> Self {
-        self.0 = chrs.to_string();
-        self
+    pub(crate) fn with_chars(self, chrs: &str) -> Self {
+        MatchRules::init(chrs, self.1)
     }
     #[allow(dead_code)] //  used in tests
-    pub(crate) fn with_bound_chars(mut self, bounds: Vec<(char, char)>) -> Self {
-        self.1 = bounds;
-        self
+    pub(crate) fn with_bound_chars(self, bounds: Vec<(char, char)>) -> Self {
+        MatchRules::init(&self.0, bounds)
     }
 }
 
@@ -95,70 +305,481 @@ impl MatchRules {
 //-----------------------------------------------------------------------
 
 macro_rules! ok {
-    ($st:expr, $val:expr) => {
-        Ok(($st, ast::Node::Val($val.to_owned())))
-    };
+    ($st:expr, $val:expr) => {{
+        let st = $st;
+        let val = if st.fail_fast {
+            String::new()
+        } else {
+            $val.to_owned()
+        };
+        Ok((st, ast::Node::Val(val)))
+    }};
 }
 
 fn parse_literal<'a>(mut status: Status<'a>, literal: &'a str) -> Result<'a> {
+    if let Some(rest) = status.it_parsing.as_str().strip_prefix(literal) {
+        status.it_parsing = rest.chars();
+        status.pos.advance(literal, status.newlines);
+        return ok!(status, literal);
+    }
+
+    //  walk char by char to locate the error
     for ch in literal.chars() {
         status = parse_char(status, ch).map_err(|st| {
-            Error::from_status_normal(&st, &format!("expected literal: <{}>", literal))
+            Error::from_status_expected(
+                &st,
+                Code::ExpectedLiteral,
+                &format!("expected literal: <{}>", literal),
+                || format!("<{}>", literal),
+            )
         })?;
     }
     ok!(status, literal)
 }
 
+fn parse_char_literal(status: Status, ch: char) -> Result {
+    match parse_char(status, ch) {
+        Ok(st) => ok!(st, ch.to_string()),
+        Err(st) => Err(Error::from_status_expected(
+            &st,
+            Code::ExpectedLiteral,
+            &format!("expected literal: <{}>", ch),
+            || format!("<{}>", ch),
+        )),
+    }
+}
+
+fn parse_literal_no_case<'a>(status: Status<'a>, literal: &'a str) -> Result<'a> {
+    let rest = status.it_parsing.as_str();
+    let status = literal
+        .chars()
+        .try_fold(status, |st, ch| {
+            let (st, got_ch) = st.get_char()?;
+            if fold_case(got_ch) == fold_case(ch) {
+                Ok(st)
+            } else {
+                Err(st)
+            }
+        })
+        .map_err(|st| {
+            Error::from_status_expected(
+                &st,
+                Code::ExpectedLiteral,
+                &format!("expected literal (no case): <{}>", literal),
+                || format!("<{}> (no case)", literal),
+            )
+        })?;
+
+    let matched = &rest[..rest.len() - status.it_parsing.as_str().len()];
+    ok!(status, matched)
+}
+
 fn parse_error<'a>(status: &Status<'a>, error: &'a str) -> Result<'a> {
-    Err(Error::from_status(&status, &error, ErrPriority::Critical))
+    Err(Error::from_status(
+        &status,
+        Code::UserError,
+        &error,
+        ErrPriority::Critical,
+    ))
 }
 
-fn parse_dot(status: Status) -> Result {
-    let (status, ch) = status
-        .get_char()
-        .map_err(|st| Error::from_status_normal(&st, "dot"))?;
+fn parse_warning<'a>(mut status: Status<'a>, warning: &'a str) -> Result<'a> {
+    if !status.fail_fast {
+        status.warnings.push(Diagnostic {
+            pos: status.pos.clone(),
+            end: status.pos.clone(),
+            descr: warning.to_string(),
+            code: Code::UserWarning,
+        });
+    }
+    ok!(status, "")
+}
 
-    ok!(status, ch.to_string())
+fn parse_pos<'a>(mut status: Status<'a>, name: &'a str) -> Result<'a> {
+    if status.capture {
+        let span = Span {
+            start: status.pos.clone(),
+            end: status.pos.clone(),
+        };
+        status.captured.push((name.to_owned(), span));
+    }
+    ok!(status, "")
 }
 
-fn parse_match<'a>(status: Status<'a>, match_rules: &MatchRules) -> Result<'a> {
-    let match_char = |ch: char| -> bool {
-        if match_rules.0.find(ch).is_some() {
-            true
-        } else {
-            for &(b, t) in &match_rules.1 {
-                if b <= ch && ch <= t {
-                    return true;
+fn parse_custom<'a>(mut status: Status<'a>, custom: &Custom) -> Result<'a> {
+    let rest = status.it_parsing.as_str();
+    match (custom.matcher)(rest, &status.context) {
+        Some((len, value)) if len <= rest.len() && rest.is_char_boundary(len) => {
+            status.it_parsing = rest[len..].chars();
+            status.pos.advance(&rest[..len], status.newlines);
+            match value {
+                Some(value) => ok!(status, value),
+                None => ok!(status, &rest[..len]),
+            }
+        }
+        Some((len, _)) => Err(Error::from_status(
+            &status,
+            Code::CustomRejected,
+            &format!("custom {} matched invalid length {}", custom.name, len),
+            ErrPriority::Critical,
+        )),
+        None => Err(Error::from_status_expected(
+            &status,
+            Code::CustomRejected,
+            &format!("expected {}", custom.name),
+            || custom.name.clone(),
+        )),
+    }
+}
+
+//  delimiter and escape are searched with str::find, no char by char
+fn parse_until<'a>(mut status: Status<'a>, delimiter: &str, escape: Option<&str>) -> Result<'a> {
+    let rest = status.it_parsing.as_str();
+    let escape = escape.filter(|e| !e.is_empty());
+
+    let mut from = 0;
+    let end = loop {
+        let pending = &rest[from..];
+        let escape_at = escape.and_then(|e| pending.find(e).map(|i| (i, e.len())));
+        match (pending.find(delimiter), escape_at) {
+            (Some(d), Some((e, _))) if d < e => break Some(from + d),
+            (Some(d), None) => break Some(from + d),
+            (_, Some((e, len))) => {
+                let escaped = pending[e + len..].chars().next().map_or(0, char::len_utf8);
+                from += e + len + escaped;
+            }
+            (None, None) => break None,
+        }
+    };
+
+    match end {
+        Some(end) => {
+            status.it_parsing = rest[end..].chars();
+            status.pos.advance(&rest[..end], status.newlines);
+            ok!(status, &rest[..end])
+        }
+        None => Err(Error::from_status_normal(
+            &status,
+            Code::DelimiterNotFound,
+            &format!("expected delimiter: <{}>", delimiter),
+        )),
+    }
+}
+
+fn parse_balanced<'a>(mut status: Status<'a>, open: &str, close: &str) -> Result<'a> {
+    let rest = status.it_parsing.as_str();
+    if open.is_empty() || close.is_empty() || !rest.starts_with(open) {
+        return Err(Error::from_status_normal(
+            &status,
+            Code::DelimiterNotFound,
+            &format!("expected balanced: <{}...{}>", open, close),
+        ));
+    }
+
+    //  on same position, close has priority (open and close can be equal)
+    let (mut depth, mut from) = (1, open.len());
+    let end = loop {
+        let pending = &rest[from..];
+        match (pending.find(close), pending.find(open)) {
+            (Some(c), o) if o.is_none_or(|o| c <= o) => {
+                depth -= 1;
+                from += c + close.len();
+                if depth == 0 {
+                    break Some(from);
                 }
             }
-            false
+            (_, Some(o)) => {
+                depth += 1;
+                from += o + open.len();
+            }
+            _ => break None,
         }
     };
 
+    match end {
+        Some(end) => {
+            status.it_parsing = rest[end..].chars();
+            status.pos.advance(&rest[..end], status.newlines);
+            ok!(status, &rest[open.len()..end - close.len()])
+        }
+        None => Err(Error::from_status_normal(
+            &status,
+            Code::DelimiterNotFound,
+            &format!("unbalanced: <{}> without <{}>", open, close),
+        )),
+    }
+}
+
+fn parse_dot(status: Status) -> Result {
+    let (status, ch) = status.get_char().map_err(|st| {
+        Error::from_status_expected(&st, Code::UnexpectedChar, "dot", || "any char".to_string())
+    })?;
+
+    ok!(status, ch.to_string())
+}
+
+fn parse_dot_no_newline(status: Status) -> Result {
+    status
+        .get_char()
+        .and_then(|(st, ch)| match ch {
+            '\n' | '\r' => Err(st),
+            _ => ok!(st, ch.to_string()),
+        })
+        .map_err(|st| {
+            Error::from_status_expected(&st, Code::UnexpectedChar, "dot (no new line)", || {
+                "any char but new line".to_string()
+            })
+        })
+}
+
+#[cfg(feature = "unicode")]
+fn parse_xid(status: Status, start: bool) -> Result {
+    use unicode_xid::UnicodeXID;
+
+    let (descr, is_xid): (_, fn(char) -> bool) = if start {
+        ("xid_start", UnicodeXID::is_xid_start)
+    } else {
+        ("xid_continue", UnicodeXID::is_xid_continue)
+    };
+    status
+        .get_char()
+        .and_then(|(st, ch)| {
+            if is_xid(ch) {
+                ok!(st, ch.to_string())
+            } else {
+                Err(st)
+            }
+        })
+        .map_err(|st| {
+            Error::from_status_expected(&st, Code::UnexpectedChar, descr, || descr.to_string())
+        })
+}
+
+#[cfg(feature = "graphemes")]
+fn parse_grapheme(mut status: Status) -> Result {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    let rest = status.it_parsing.as_str();
+    match rest.graphemes(true).next() {
+        Some(grapheme) => {
+            let newlines = status.newlines;
+            for ch in grapheme.chars() {
+                status.it_parsing.next();
+                status.pos.advance_char(ch, newlines);
+            }
+            ok!(status, grapheme)
+        }
+        None => Err(Error::from_status_expected(
+            &status,
+            Code::UnexpectedChar,
+            "grapheme",
+            || "grapheme".to_string(),
+        )),
+    }
+}
+
+fn parse_match<'a>(status: Status<'a>, match_rules: &MatchRules) -> Result<'a> {
+    parse_match_case(status, match_rules, false)
+}
+
+fn parse_match_no_case<'a>(status: Status<'a>, match_rules: &MatchRules) -> Result<'a> {
+    parse_match_case(status, match_rules, true)
+}
+
+fn parse_match_case<'a>(status: Status<'a>, match_rules: &MatchRules, no_case: bool) -> Result<'a> {
+    let match_char = |ch: char| match_rules.2.contains(ch);
+
     status
         .get_char()
         .and_then(|(st, ch)| {
-            if match_char(ch) {
+            if let Some(profile) = &st.profile {
+                profile.borrow_mut().register_class(ch);
+            }
+            let matched = if no_case {
+                //  ranges can be written in upper or lower case
+                let folded = fold_case(ch);
+                match_char(ch)
+                    || match_char(folded)
+                    || single_char_upper(folded).is_some_and(match_char)
+            } else {
+                match_char(ch)
+            };
+            if matched != match_rules.3 {
                 ok!(st, ch.to_string())
             } else {
                 Err(st)
             }
         })
         .map_err(|st| {
-            Error::from_status_normal(
+            Error::from_status_expected(
                 &st,
-                &format!("match. expected {} {:?}", match_rules.0, match_rules.1),
+                Code::UnexpectedChar,
+                &format!(
+                    "match. expected {}{} {:?}",
+                    if match_rules.3 { "not " } else { "" },
+                    match_rules.0,
+                    match_rules.1
+                ),
+                || match_token(match_rules, no_case),
             )
         })
 }
 
+//  a match as on the peg grammar, i.e. [^_a-z]
+fn match_token(match_rules: &MatchRules, no_case: bool) -> String {
+    let ranges: String = match_rules
+        .1
+        .iter()
+        .map(|(from, to)| format!("{}-{}", from, to))
+        .collect();
+    format!(
+        "[{}{}{}]{}",
+        if match_rules.3 { "^" } else { "" },
+        match_rules.0,
+        ranges,
+        if no_case { "i" } else { "" }
+    )
+}
+
+#[cfg(feature = "serde")]
+impl From<MatchRulesDef> for MatchRules {
+    fn from(def: MatchRulesDef) -> Self {
+        let rules = MatchRules::init(&def.chars, def.ranges);
+        if def.negated {
+            rules.negate()
+        } else {
+            rules
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<MatchRules> for MatchRulesDef {
+    fn from(rules: MatchRules) -> Self {
+        MatchRulesDef {
+            chars: rules.0,
+            ranges: rules.1,
+            negated: rules.3,
+        }
+    }
+}
+
+impl CharClass {
+    fn new(chars: &str, ranges: &[(char, char)]) -> Self {
+        let mut class = CharClass::default();
+        let chars = chars.chars().map(|ch| (ch, ch));
+        for (from, to) in chars.chain(ranges.iter().cloned()).filter(|(f, t)| f <= t) {
+            for n in from as usize..=(to as usize).min(255) {
+                class.latin1[n / 64] |= 1 << (n % 64);
+            }
+            if to as u32 > 255 {
+                class.others.push((from.max('\u{100}'), to));
+            }
+        }
+
+        //  sorted and without overlaps, for the binary search
+        class.others.sort_unstable();
+        let mut others: Vec<(char, char)> = vec![];
+        for (from, to) in class.others {
+            match others.last_mut() {
+                Some(last) if from <= last.1 => last.1 = last.1.max(to),
+                _ => others.push((from, to)),
+            }
+        }
+        CharClass { others, ..class }
+    }
+
+    fn contains(&self, ch: char) -> bool {
+        let n = ch as usize;
+        if n < 256 {
+            self.latin1[n / 64] & (1 << (n % 64)) != 0
+        } else {
+            let i = self.others.partition_point(|&(_, to)| to < ch);
+            self.others.get(i).is_some_and(|&(from, _)| from <= ch)
+        }
+    }
+}
+
+//  unicode simple case folding, approximated with the lowercase when
+//  it is a single char, plus the chars with a different folding
+fn fold_case(ch: char) -> char {
+    match ch {
+        'ς' => 'σ',
+        'ſ' => 's',
+        'ϐ' => 'β',
+        'ϑ' => 'θ',
+        'ϕ' => 'φ',
+        'ϖ' => 'π',
+        'ϰ' => 'κ',
+        'ϱ' => 'ρ',
+        'ϵ' => 'ε',
+        '\u{345}' | '\u{1fbe}' => 'ι',
+        _ => {
+            let mut lower = ch.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(l), None) => l,
+                _ => ch,
+            }
+        }
+    }
+}
+
+fn single_char_upper(ch: char) -> Option<char> {
+    let mut upper = ch.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) => Some(u),
+        _ => None,
+    }
+}
+
 fn parse_eof(status: Status) -> Result {
     match status.get_char() {
-        Ok((st, _ch)) => Err(Error::from_status_normal(&st, "expected EOF")),
+        Ok((st, _ch)) => Err(Error::from_status_expected(
+            &st,
+            Code::ExpectedEof,
+            "expected EOF",
+            || "EOF".to_string(),
+        )),
         Err(st) => ok!(st, "EOF"),
     }
 }
 
+fn parse_bof(status: Status) -> Result {
+    if status.pos.n == status.input_start {
+        ok!(status, "")
+    } else {
+        Err(Error::from_status_normal(
+            &status,
+            Code::ExpectedPosition,
+            "expected BOF",
+        ))
+    }
+}
+
+fn parse_bol(status: Status) -> Result {
+    if status.pos.col == 0 || status.pos.n == status.input_start {
+        ok!(status, "")
+    } else {
+        Err(Error::from_status_normal(
+            &status,
+            Code::ExpectedPosition,
+            "expected BOL",
+        ))
+    }
+}
+
+fn parse_eol(status: Status) -> Result {
+    match status.it_parsing.clone().next() {
+        None | Some('\r') => ok!(status, ""),
+        Some(ch) if status.newlines.is_newline(ch) => ok!(status, ""),
+        Some(_) => Err(Error::from_status_normal(
+            &status,
+            Code::ExpectedPosition,
+            "expected EOL",
+        )),
+    }
+}
+
 fn parse_char(status: Status, ch: char) -> result::Result<Status, Status> {
     let (st, got_ch) = status.get_char()?;
     if ch == got_ch {
@@ -173,20 +794,7 @@ impl<'a> Status<'a> {
         match self.it_parsing.next() {
             None => Err(self),
             Some(ch) => {
-                self.pos.n += 1;
-                match ch {
-                    '\n' => {
-                        self.pos.col = 0;
-                        self.pos.row += 1;
-                        self.pos.start_line = self.pos.n;
-                    }
-                    '\r' => {
-                        self.pos.col = 0;
-                    }
-                    _ => {
-                        self.pos.col += 1;
-                    }
-                }
+                self.pos.advance_char(ch, self.newlines);
                 Ok((self, ch))
             }
         }
@@ -0,0 +1,76 @@
+//! Use parsers written with ```nom``` as atoms
+//!
+//! Available with the ```nom``` feature. The nom parser receives the
+//! pending input. If it succeeds, the consumed bytes are the matched
+//! text. Any nom error (or incomplete) is a no match
+//!
+//! The parser is a function or a closure on ```&str``` (for any
+//! lifetime). Nom combinators can be called from a closure
+//!
+//! ```
+//! #[macro_use]  extern crate dynparser;
+//! use dynparser::parse;
+//! use dynparser::parser::atom::nom::{nom_atom, nom_value};
+//! use nom::character::complete::{digit1, hex_digit1};
+//! use nom::combinator::map_res;
+//!
+//! fn main() {
+//!     let rules = rules!{
+//!        "main"   =>  and!(ref_rule!("num"), lit!("+"), ref_rule!("hex")),
+//!        "num"    =>  nom_atom("number", |i: &str| digit1(i)),
+//!        "hex"    =>  nom_value("hex number", |i: &str| {
+//!                         map_res(hex_digit1, |h| u32::from_str_radix(h, 16))(i)
+//!                     })
+//!     };
+//!
+//!     let ast = parse("12+ff", &rules).unwrap();
+//!     let (_, nodes) = ast.rule_parts();
+//!     assert_eq!(nodes[0].rule_parts().1[0], dynparser::ast::Node::Val("12".to_string()));
+//!     assert_eq!(nodes[2].rule_parts().1[0], dynparser::ast::Node::Val("255".to_string()));
+//!
+//!     assert!(parse("12+xx", &rules).is_err());
+//! }
+//! ```
+
+use crate::parser::atom::{Atom, Custom};
+use crate::parser::expression::Expression;
+use crate::parser::ParseContext;
+
+/// Atom matched by a nom parser returning a slice of the input
+/// (```tag```, ```digit1```, ```recognize(...)```...)
+///
+/// The node will have the consumed text. The name is used on errors
+pub fn nom_atom<F>(name: &str, parser: F) -> Expression
+where
+    F: for<'i> Fn(&'i str) -> ::nom::IResult<&'i str, &'i str> + Send + Sync + 'static,
+{
+    custom(name, move |rest| {
+        let (pending, _) = parser(rest).ok()?;
+        Some((rest.len() - pending.len(), None))
+    })
+}
+
+/// Atom matched by a nom parser returning a value
+///
+/// The node will have the value (as text) instead of the consumed
+/// text. The name is used on errors
+pub fn nom_value<F, O>(name: &str, parser: F) -> Expression
+where
+    F: for<'i> Fn(&'i str) -> ::nom::IResult<&'i str, O> + Send + Sync + 'static,
+    O: ToString,
+{
+    custom(name, move |rest| {
+        let (pending, value) = parser(rest).ok()?;
+        Some((rest.len() - pending.len(), Some(value.to_string())))
+    })
+}
+
+fn custom<F>(name: &str, matcher: F) -> Expression
+where
+    F: Fn(&str) -> Option<(usize, Option<String>)> + Send + Sync + 'static,
+{
+    Expression::Simple(Atom::Custom(Custom::with_value(
+        name,
+        move |rest: &str, _: &ParseContext| matcher(rest),
+    )))
+}
@@ -4,7 +4,10 @@
 //
 //-----------------------------------------------------------------------
 use super::Status;
-use super::{parse_dot, parse_eof, parse_literal, parse_match, MatchRules};
+use super::{
+    parse_balanced, parse_bof, parse_bol, parse_char_literal, parse_custom, parse_dot, parse_eof,
+    parse_eol, parse_literal, parse_match, parse_until, CharClass, Custom, MatchRules,
+};
 
 #[test]
 fn test_parse_literal_ok() {
@@ -86,6 +89,62 @@ fn test_parse_dot() {
     assert!(parse_dot(status).is_err());
 }
 
+#[test]
+fn test_parse_char_literal() {
+    let rules = rules!{};
+    let status = Status::init("a😀b", &rules);
+
+    let (status, _) = parse_char_literal(status, 'a').ok().unwrap();
+    let (status, ch) = parse_char_literal(status, '😀').ok().unwrap();
+    assert_eq!(ch, crate::ast::Node::Val("😀".to_string()));
+    assert_eq!(status.pos.n, 2);
+
+    let error = parse_char_literal(status, 'c').err().unwrap();
+    assert_eq!(error.pos.n, 3);
+    assert_eq!(error.descr, "expected literal: <c>");
+}
+
+#[test]
+fn test_parse_dot_multibyte() {
+    let rules = rules!{};
+    let status = Status::init("ñ€😀x", &rules);
+
+    let (status, ch) = parse_dot(status).ok().unwrap();
+    assert_eq!(ch, crate::ast::Node::Val("ñ".to_string()));
+    let (status, _) = parse_dot(status).ok().unwrap();
+    let (status, ch) = parse_dot(status).ok().unwrap();
+    assert_eq!(ch, crate::ast::Node::Val("😀".to_string()));
+    assert_eq!(status.pos.n, 3);
+    assert_eq!(status.pos.col, 3);
+
+    let (status, _) = parse_literal(status, "x").ok().unwrap();
+    assert_eq!(status.pos.n, 4);
+    assert!(parse_dot(status).is_err());
+
+    let status = Status::init("añb", &rules);
+    let error = parse_literal(status, "añc").err().unwrap();
+    assert_eq!(error.pos.n, 3);
+    assert_eq!(error.pos.col, 3);
+}
+
+#[cfg(feature = "graphemes")]
+#[test]
+fn test_parse_grapheme() {
+    let rules = rules!{};
+    //  e + COMBINING ACUTE ACCENT, a flag (two regional indicators), \r\n
+    let status = Status::init("e\u{301}🇪🇸\r\n", &rules);
+
+    let (status, g) = super::parse_grapheme(status).ok().unwrap();
+    assert_eq!(g, crate::ast::Node::Val("e\u{301}".to_string()));
+    assert_eq!(status.pos.n, 2);
+    let (status, g) = super::parse_grapheme(status).ok().unwrap();
+    assert_eq!(g, crate::ast::Node::Val("🇪🇸".to_string()));
+    let (status, _) = super::parse_grapheme(status).ok().unwrap();
+    assert_eq!(status.pos.n, 6);
+    assert_eq!(status.pos.row, 1);
+    assert!(super::parse_grapheme(status).is_err());
+}
+
 #[test]
 fn test_parse_match_ok() {
     let rules = rules!{};
@@ -123,6 +182,36 @@ fn test_parse_match_err() {
     assert!(parse_match(status, &match_rules).is_err());
 }
 
+#[test]
+fn test_parse_match_negated() {
+    let rules = rules!{};
+    let status = Status::init("€a", &rules);
+
+    let match_rules = MatchRules::init("_", vec![('a', 'z')]).negate();
+    assert!(match_rules.is_negated());
+    let (status, _) = parse_match(status, &match_rules).ok().unwrap();
+    assert_eq!(status.pos.n, 1);
+
+    let err = parse_match(status, &match_rules).err().unwrap();
+    assert!(err.descr.contains("expected not _"), "{}", err.descr);
+}
+
+#[test]
+fn test_char_class() {
+    let class = CharClass::new(
+        "zé€",
+        &[('0', '9'), ('ā', 'ž'), ('ÿ', 'ą'), ('а', 'я'), ('b', 'a')],
+    );
+
+    for ch in "0359zéÿĀāąžаюя€".chars() {
+        assert!(class.contains(ch), "{}", ch);
+    }
+    for ch in "aby/:Ýſ\u{3ff}ё".chars() {
+        assert!(!class.contains(ch), "{}", ch);
+    }
+    assert_eq!(class.others, vec![('Ā', 'ž'), ('а', 'я'), ('€', '€')]);
+}
+
 #[test]
 fn test_parse_match_eof_ok() {
     let rules = rules!{};
@@ -131,7 +220,8 @@ fn test_parse_match_eof_ok() {
     let match_rules = MatchRules::new().with_bound_chars(vec![('a', 'z'), ('0', '9')]);
     let (status, _) = parse_match(status, &match_rules).ok().unwrap();
 
-    assert!(parse_eof(status).is_ok());
+    let (_, node) = parse_eof(status).ok().unwrap();
+    assert_eq!(node, crate::ast::Node::Val("EOF".to_string()));
 }
 
 #[test]
@@ -144,3 +234,162 @@ fn test_parse_match_eof_error() {
 
     assert!(parse_eof(status).is_err());
 }
+
+#[test]
+fn test_parse_bof() {
+    let rules = rules!{};
+    let status = Status::init("ab", &rules);
+
+    let (status, _) = parse_bof(status).ok().unwrap();
+    assert_eq!(status.pos.n, 0);
+
+    let (status, _) = parse_dot(status).ok().unwrap();
+    assert!(parse_bof(status).is_err());
+}
+
+#[test]
+fn test_parse_bol_eol() {
+    let rules = rules!{};
+    let status = Status::init("a\nb", &rules);
+
+    let (status, _) = parse_bol(status).ok().unwrap();
+    assert!(parse_eol(status.clone()).is_err());
+
+    let (status, _) = parse_dot(status).ok().unwrap();
+    assert!(parse_bol(status.clone()).is_err());
+    let (status, _) = parse_eol(status).ok().unwrap();
+    assert_eq!(status.pos.n, 1);
+
+    let (status, _) = parse_dot(status).ok().unwrap();
+    let (status, _) = parse_bol(status).ok().unwrap();
+    let (status, _) = parse_dot(status).ok().unwrap();
+    assert!(parse_eol(status).is_ok());
+}
+
+#[test]
+fn test_parse_literal_multiline_pos() {
+    let rules = rules!{};
+    let status = Status::init("ab\r\nñc\nd", &rules);
+    let (status, _) = parse_literal(status, "ab\r\nñc\n").ok().unwrap();
+    assert_eq!(status.pos.n, 7);
+    assert_eq!(status.pos.row, 2);
+    assert_eq!(status.pos.col, 0);
+    assert_eq!(status.pos.start_line, 7);
+
+    let (status, _) = parse_literal(status, "d").ok().unwrap();
+    assert_eq!(status.pos.row, 2);
+    assert_eq!(status.pos.col, 1);
+    assert!(parse_eof(status).is_ok());
+}
+
+#[test]
+fn test_parse_literal_error_line_multibyte() {
+    let rules = rules!{};
+    let status = Status::init("ññ\nñc", &rules);
+    let (status, _) = parse_literal(status, "ññ\n").ok().unwrap();
+    let err = parse_literal(status, "ñb").err().unwrap();
+    assert_eq!(err.line_before, "ñc");
+    assert_eq!(err.pos.row, 1);
+    assert_eq!(err.pos.col, 2);
+}
+
+#[test]
+fn test_parse_custom() {
+    let rules = rules!{};
+    let words = Custom::new("words", |rest, _| {
+        Some(rest.find(|ch: char| ch.is_ascii_digit()).unwrap_or(rest.len()))
+    });
+    let status = Status::init("ñ a\nb1", &rules);
+
+    let (status, node) = parse_custom(status, &words).ok().unwrap();
+    assert!(node == crate::ast::Node::Val("ñ a\nb".to_string()));
+    assert_eq!(status.pos.n, 5);
+    assert_eq!(status.pos.row, 1);
+    assert_eq!(status.pos.col, 1);
+
+    let (status, _) = parse_custom(status, &words).ok().unwrap();
+    assert_eq!(status.pos.n, 5);
+    let (status, _) = parse_dot(status).ok().unwrap();
+    assert!(parse_eof(status).is_ok());
+}
+
+#[test]
+fn test_parse_custom_fail() {
+    let rules = rules!{};
+    let never = Custom::new("never", |_, _| None);
+    let broken = Custom::new("broken", |_, _| Some(1));
+
+    assert!(parse_custom(Status::init("a", &rules), &never).is_err());
+    let err = parse_custom(Status::init("ñ", &rules), &broken).err().unwrap();
+    assert_eq!(err.priority, crate::parser::ErrPriority::Critical);
+}
+
+#[test]
+fn test_parse_custom_value() {
+    let rules = rules!{};
+    let bool_word = Custom::with_value("bool", |rest, _| match rest {
+        _ if rest.starts_with("yes") => Some((3, Some("true".to_string()))),
+        _ if rest.starts_with("no") => Some((2, None)),
+        _ => None,
+    });
+
+    let (status, node) = parse_custom(Status::init("yesno", &rules), &bool_word)
+        .ok()
+        .unwrap();
+    assert!(node == crate::ast::Node::Val("true".to_string()));
+    assert_eq!(status.pos.n, 3);
+
+    let (status, node) = parse_custom(status, &bool_word).ok().unwrap();
+    assert!(node == crate::ast::Node::Val("no".to_string()));
+    assert!(parse_eof(status).is_ok());
+}
+
+#[test]
+fn test_parse_until() {
+    let rules = rules!{};
+
+    let status = Status::init("ñ\\*/a*/b", &rules);
+    let (status, node) = parse_until(status, "*/", Some("\\")).ok().unwrap();
+    assert!(node == crate::ast::Node::Val("ñ\\*/a".to_string()));
+    assert_eq!(status.pos.n, 5);
+    assert!(parse_literal(status, "*/b").is_ok());
+
+    //  escape at the end, or without escape
+    assert!(parse_until(Status::init("abc\\", &rules), "\"", Some("\\")).is_err());
+    let (status, _) = parse_until(Status::init("a\\\"b\"", &rules), "\"", None)
+        .ok()
+        .unwrap();
+    assert_eq!(status.pos.n, 2);
+
+    //  empty match and not found
+    let (status, _) = parse_until(Status::init(")", &rules), ")", Some("\\"))
+        .ok()
+        .unwrap();
+    assert_eq!(status.pos.n, 0);
+    assert!(parse_until(Status::init("abc", &rules), ")", None).is_err());
+}
+
+#[test]
+fn test_parse_balanced() {
+    let rules = rules!{};
+
+    let status = Status::init("{{a}\n{ñ}}b", &rules);
+    let (status, node) = parse_balanced(status, "{", "}").ok().unwrap();
+    assert!(node == crate::ast::Node::Val("{a}\n{ñ}".to_string()));
+    assert_eq!(status.pos.n, 9);
+    assert_eq!(status.pos.row, 1);
+    assert!(parse_literal(status, "b").is_ok());
+
+    //  multi char delimiters, and equal delimiters
+    let (_, node) = parse_balanced(Status::init("{%a{%b%}%}", &rules), "{%", "%}")
+        .ok()
+        .unwrap();
+    assert!(node == crate::ast::Node::Val("a{%b%}".to_string()));
+    let (_, node) = parse_balanced(Status::init("|a|b|", &rules), "|", "|")
+        .ok()
+        .unwrap();
+    assert!(node == crate::ast::Node::Val("a".to_string()));
+
+    assert!(parse_balanced(Status::init("{{a}", &rules), "{", "}").is_err());
+    assert!(parse_balanced(Status::init("a{}", &rules), "{", "}").is_err());
+}
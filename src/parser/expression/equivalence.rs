@@ -0,0 +1,349 @@
+//! Check if two expressions accept the same inputs
+//!
+//! Useful to verify a refactor of a grammar (factoring out a sub-rule,
+//! reordering, removing parenthesis...) on the tests
+//!
+//! First, both expressions are normalized (nested sequences and
+//! alternatives flattened, labels removed, consecutive literals joined,
+//! repeated alternatives removed). If they are the same, they are
+//! equivalent
+//!
+//! If not, inputs are generated from both expressions, some of them
+//! modified (truncated, a char removed or added), and both expressions
+//! are parsed on them. The inputs are generated from a fixed seed, the
+//! result is always the same
+
+use super::{parse_expr, Expression, MultiExpr, RepInfo, SetOfRules};
+use crate::parser::{atom::Atom, Status};
+use std::collections::HashSet;
+
+/// Result of ```equivalent```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Equivalence {
+    /// The same expression, once normalized
+    Structural,
+    /// The same result on all the inputs tried (number of inputs)
+    Tested(usize),
+    /// An input with a different result
+    Different {
+        /// The input
+        input: String,
+        /// Bytes matched by the first expression (None if it fails)
+        a: Option<usize>,
+        /// Bytes matched by the second expression (None if it fails)
+        b: Option<usize>,
+    },
+}
+
+impl Equivalence {
+    /// No difference found
+    pub fn is_equivalent(&self) -> bool {
+        !matches!(self, Equivalence::Different { .. })
+    }
+}
+
+/// Check if two expressions match the same inputs (the same bytes, or
+/// both fail), with the rules they reference
+///
+/// ```budget``` is the max number of inputs to try. Without
+/// differences, it's a probable equivalence, not a proof
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parser::expression::{equivalent, Equivalence};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "sign"   =>  or!(lit!("+"), lit!("-")),
+///        "digit"  =>  ematch!(chlist "", from '0', to '9')
+///     };
+///
+///     //  factoring out the sign
+///     let before = or!(
+///         and!(lit!("+"), rep!(ref_rule!("digit"), 1)),
+///         and!(lit!("-"), rep!(ref_rule!("digit"), 1))
+///     );
+///     let after = and!(ref_rule!("sign"), rep!(ref_rule!("digit"), 1));
+///     assert!(equivalent(&before, &after, &rules, 500).is_equivalent());
+///
+///     //  the optional sign accepts more inputs
+///     let wrong = and!(rep!(ref_rule!("sign"), 0, 1), rep!(ref_rule!("digit"), 1));
+///     match equivalent(&before, &wrong, &rules, 500) {
+///         Equivalence::Different { a: None, b: Some(_), .. } => (),
+///         other => panic!("unexpected {:?}", other),
+///     }
+///
+///     let grouped = and!(lit!("a"), and!(lit!("b"), lit!("c")));
+///     assert_eq!(equivalent(&grouped, &lit!("abc"), &rules, 0), Equivalence::Structural);
+/// }
+/// ```
+pub fn equivalent(
+    a: &Expression,
+    b: &Expression,
+    rules: &SetOfRules,
+    budget: usize,
+) -> Equivalence {
+    if format!("{:?}", normalize(a)) == format!("{:?}", normalize(b)) {
+        return Equivalence::Structural;
+    }
+
+    let mut generator = Generator::new(rules);
+    let mut tried = HashSet::new();
+    for i in 0..budget {
+        let mut input = String::new();
+        generator.tokens.clear();
+        generator.generate(if i % 2 == 0 { a } else { b }, 0, &mut input);
+        if i % 4 >= 2 {
+            input = generator.mutate(&input);
+        }
+        if !tried.insert(input.clone()) {
+            continue;
+        }
+
+        let (matched_a, matched_b) = (matched(rules, a, &input), matched(rules, b, &input));
+        if matched_a != matched_b {
+            return Equivalence::Different {
+                input,
+                a: matched_a,
+                b: matched_b,
+            };
+        }
+    }
+    Equivalence::Tested(tried.len())
+}
+
+//-----------------------------------------------------------------------
+//  I N T E R N A L
+
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+//  rule calls followed generating an input, to stop on recursive rules
+const MAX_DEPTH: usize = 16;
+
+//  extra iterations on repetitions without max
+const MAX_EXTRA_REPS: usize = 3;
+
+fn matched(rules: &SetOfRules, expr: &Expression, input: &str) -> Option<usize> {
+    let status = Status::init(input, rules).set_fail_fast(true);
+    parse_expr(status, expr)
+        .ok()
+        .map(|(st, _)| input.len() - st.it_parsing.as_str().len())
+}
+
+fn normalize(expr: &Expression) -> Expression {
+    match expr {
+        Expression::And(mexpr) => {
+            let mut items: Vec<Expression> = vec![];
+            for e in mexpr.0.iter().map(normalize) {
+                match (items.last_mut(), e) {
+                    (_, Expression::And(inner)) => items.extend(inner.0),
+                    (_, Expression::Simple(Atom::Literal(ref l))) if l.is_empty() => (),
+                    (
+                        Some(Expression::Simple(Atom::Literal(prev))),
+                        Expression::Simple(Atom::Literal(l)),
+                    ) => prev.push_str(&l),
+                    (_, e) => items.push(e),
+                }
+            }
+            single_or(items, Expression::And)
+        }
+        Expression::Or(mexpr) => {
+            let mut items: Vec<Expression> = vec![];
+            let mut seen = HashSet::new();
+            for e in mexpr.0.iter().map(normalize) {
+                let alternatives = match e {
+                    Expression::Or(inner) => inner.0,
+                    e => vec![e],
+                };
+                //  a repeated alternative will never match
+                for alternative in alternatives {
+                    if seen.insert(format!("{:?}", alternative)) {
+                        items.push(alternative);
+                    }
+                }
+            }
+            single_or(items, Expression::Or)
+        }
+        Expression::Not(e) => Expression::Not(Box::new(normalize(e))),
+        Expression::Repeat(rep) => Expression::Repeat(RepInfo {
+            expression: Box::new(normalize(&rep.expression)),
+            min: rep.min.clone(),
+            max: rep.max.clone(),
+        }),
+        //  they don't change the inputs accepted
+        Expression::Labeled(_, e) | Expression::Deprecated(_, e) => normalize(e),
+        Expression::NotKeyword(keywords, e) => {
+            Expression::NotKeyword(keywords.clone(), Box::new(normalize(e)))
+        }
+        Expression::Keywords(keywords, e) => {
+            Expression::Keywords(keywords.clone(), Box::new(normalize(e)))
+        }
+        //  joined with the literals around
+        Expression::Simple(Atom::Char(ch)) => Expression::Simple(Atom::Literal(ch.to_string())),
+        Expression::Simple(_) | Expression::RuleName(_) => expr.clone(),
+    }
+}
+
+fn single_or(mut items: Vec<Expression>, build: fn(MultiExpr) -> Expression) -> Expression {
+    if items.len() == 1 {
+        items.remove(0)
+    } else {
+        build(MultiExpr(items))
+    }
+}
+
+//  random inputs following the expressions (xorshift)
+pub(super) struct Generator<'a> {
+    rules: &'a SetOfRules,
+    seed: u64,
+    //  atoms written on the output, to be mutated as tokens
+    pub(super) tokens: Vec<Token>,
+}
+
+//  bytes of the output written by an atom
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Token {
+    pub(super) start: usize,
+    pub(super) end: usize,
+    pub(super) literal: bool,
+}
+
+impl<'a> Generator<'a> {
+    pub(super) fn new(rules: &'a SetOfRules) -> Self {
+        Generator {
+            rules,
+            seed: SEED,
+            tokens: vec![],
+        }
+    }
+
+    pub(super) fn below(&mut self, n: usize) -> usize {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed % n.max(1) as u64) as usize
+    }
+
+    pub(super) fn generate(&mut self, expr: &Expression, depth: usize, out: &mut String) {
+        match expr {
+            Expression::Simple(atom) => {
+                let start = out.len();
+                self.atom(atom, out);
+                if out.len() > start {
+                    self.tokens.push(Token {
+                        start,
+                        end: out.len(),
+                        literal: matches!(
+                            atom,
+                            Atom::Literal(_) | Atom::LiteralNoCase(_) | Atom::Char(_)
+                        ),
+                    });
+                }
+            }
+            Expression::And(mexpr) => {
+                for e in &mexpr.0 {
+                    self.generate(e, depth, out);
+                }
+            }
+            Expression::Or(mexpr) => {
+                let i = self.below(mexpr.0.len());
+                if let Some(e) = mexpr.0.get(i) {
+                    self.generate(e, depth, out);
+                }
+            }
+            Expression::Repeat(rep) => {
+                let min = rep.min.0;
+                let max = rep
+                    .max
+                    .as_ref()
+                    .map_or(min + MAX_EXTRA_REPS, |max| max.0.min(min + MAX_EXTRA_REPS));
+                for _ in 0..min + self.below(max.saturating_sub(min) + 1) {
+                    self.generate(&rep.expression, depth, out);
+                }
+            }
+            Expression::RuleName(name) if depth < MAX_DEPTH => {
+                if let Some(e) = self.rules.exprs.get(name) {
+                    self.generate(e, depth + 1, out);
+                }
+            }
+            Expression::Labeled(_, e)
+            | Expression::Deprecated(_, e)
+            | Expression::NotKeyword(_, e)
+            | Expression::Keywords(_, e) => self.generate(e, depth, out),
+            Expression::Not(_) | Expression::RuleName(_) => (),
+        }
+    }
+
+    fn atom(&mut self, atom: &Atom, out: &mut String) {
+        match atom {
+            Atom::Literal(l) => out.push_str(l),
+            Atom::Char(ch) => out.push(*ch),
+            Atom::LiteralNoCase(l) => {
+                for ch in l.chars() {
+                    if self.below(2) == 0 {
+                        out.extend(ch.to_uppercase());
+                    } else {
+                        out.extend(ch.to_lowercase());
+                    }
+                }
+            }
+            Atom::Match(mrules) | Atom::MatchNoCase(mrules) if mrules.is_negated() => {
+                //  a few tries to find a char out of the class
+                let ch = (0..8)
+                    .map(|_| self.any_char())
+                    .find(|ch| mrules.accepts(*ch));
+                out.extend(ch);
+            }
+            Atom::Match(mrules) | Atom::MatchNoCase(mrules) => {
+                let chars: Vec<char> = mrules.0.chars().collect();
+                let i = self.below(chars.len() + mrules.1.len());
+                match (chars.get(i), mrules.1.get(i.saturating_sub(chars.len()))) {
+                    (Some(ch), _) => out.push(*ch),
+                    (None, Some((from, to))) => {
+                        let n = self.below(*to as usize - *from as usize + 1);
+                        out.push(std::char::from_u32(*from as u32 + n as u32).unwrap_or(*from));
+                    }
+                    (None, None) => (),
+                }
+            }
+            Atom::Dot => out.push(self.any_char()),
+            Atom::DotNoNewline => match self.any_char() {
+                '\n' => out.push(' '),
+                ch => out.push(ch),
+            },
+            Atom::Balanced(open, close) => {
+                out.push_str(open);
+                out.push_str(close);
+            }
+            _ => (),
+        }
+    }
+
+    pub(super) fn any_char(&mut self) -> char {
+        const CHARS: &str = "az09 _-+.,;()'\"\n\tñ€";
+        CHARS
+            .chars()
+            .nth(self.below(CHARS.chars().count()))
+            .unwrap_or(' ')
+    }
+
+    //  truncated, a char removed or a char added
+    fn mutate(&mut self, input: &str) -> String {
+        let mut chars: Vec<char> = input.chars().collect();
+        let at = self.below(chars.len() + 1);
+        match self.below(3) {
+            0 => chars.truncate(at),
+            1 if at < chars.len() => {
+                chars.remove(at);
+            }
+            _ => {
+                let ch = match chars.get(self.below(chars.len())) {
+                    Some(ch) if self.below(2) == 0 => *ch,
+                    _ => self.any_char(),
+                };
+                chars.insert(at, ch);
+            }
+        }
+        chars.into_iter().collect()
+    }
+}
@@ -1,18 +1,28 @@
 #![warn(missing_docs)]
 //! Here we have the parser for non atomic things
 
-use super::super::idata::{
-    cont::IVec,
-    tc::{tail_call, TailCall},
+use super::super::idata::tc::{tail_call, TailCall};
+use crate::ast::{self, builder::TreeEvent};
+use crate::codes::Code;
+use crate::parser::profile::{OrStats, Profile};
+use crate::parser::{
+    analysis, atom, atom::Atom, scratch, Diagnostic, ErrPriority, Error, Memo, Memoized, Result,
+    Seed, Seeds, Span, Status,
 };
-use crate::ast;
-use crate::parser::{atom, atom::Atom, ErrPriority, Error, Result, Status};
-use std::collections::HashMap;
+use crate::peg::{gcode, gdoc};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::result;
 
+mod equivalence;
+mod mutation;
+mod strict;
 #[cfg(test)]
 mod test;
 
+pub use self::equivalence::{equivalent, Equivalence};
+pub use self::mutation::{near_misses, Mutation, NearMiss};
+pub use self::strict::StrictRules;
+
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
 //
@@ -21,8 +31,35 @@ mod test;
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
 
-#[derive(Clone, Copy, Debug)]
-pub(crate) struct Started(usize);
+/// Region of the input consumed by an expression, as byte offsets
+///
+/// Returned by ```parse_expression_at```. ```start``` is included and
+/// ```end``` is not. Predicates and empty matches have
+/// ```start == end```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match {
+    /// Offset where the expression started
+    pub start: usize,
+    /// Offset after the consumed input
+    pub end: usize,
+}
+
+impl Match {
+    /// Bytes consumed
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Nothing was consumed
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Text consumed, on the parsed input
+    pub fn text<'a>(&self, input: &'a str) -> &'a str {
+        &input[self.start..self.end]
+    }
+}
 
 pub(crate) type ResultExpr<'a> = result::Result<(Status<'a>, Vec<ast::Node>), Error>;
 
@@ -30,14 +67,215 @@ pub(crate) type ResultExpr<'a> = result::Result<(Status<'a>, Vec<ast::Node>), Er
 /// Any rule has a name
 /// A rule can be registered just once
 /// The starting rule is main
+///
+/// Rules can have an access policy, checked when a plugin extends
+/// the grammar (look for ```set_access```), and metadata (look for
+/// ```rule_meta```)
+///
+/// The declaration order is kept for generated code and docs (look for
+/// ```names```)
 #[derive(Debug)]
-pub struct SetOfRules(pub HashMap<String, Expression>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetOfRules {
+    pub(crate) exprs: HashMap<String, Expression>,
+    policies: HashMap<String, RuleAccess>,
+    meta: HashMap<String, RuleMeta>,
+    //  names as added, they could be repeated or removed from the map
+    order: Vec<String>,
+    //  left recursive rules are allowed
+    allow_left_recursion: bool,
+}
+
+/// Metadata of a rule, written as annotations on the grammar
+///
+/// ```@key``` or ```@key("value")```. The parser uses some of them
+/// (```@deprecated```, ```@identifier```, ```@keywords```,
+/// ```@passthrough```, ```@inline```, ```@raw```, ```@internal```,
+/// ```@sync```, ```@recover``` on ```main```),
+/// the rest are for the application
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuleMeta(BTreeMap<String, Option<String>>);
+
+impl RuleMeta {
+    /// The rule has the annotation (with or without value)
+    pub fn has(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Value of the annotation, if it has one
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.as_deref())
+    }
+
+    /// Annotations sorted by key
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_deref()))
+    }
+}
+
+/// What a plugin can do with a rule of the host grammar
+///
+/// Checked by ```merge_plugin``` and ```extend_rule```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RuleAccess {
+    /// It can be referenced, redefined and extended (default)
+    Extensible,
+    /// It can be referenced, but not redefined or extended
+    Sealed,
+    /// Only for the host grammar. It can't be referenced, redefined
+    /// or extended
+    Internal,
+}
+
+/// A plugin doesn't respect the access policy of the host grammar
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessError {
+    /// Redefining or extending a sealed rule
+    Sealed(String),
+    /// Redefining or extending an internal rule
+    Internal(String),
+    /// Reference to an internal rule (internal rule, referenced from)
+    InternalReference(String, String),
+    /// Extending a rule not defined
+    MissingRule(String),
+}
+
+impl std::fmt::Display for AccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AccessError::Sealed(rule) => write!(f, "rule {} is sealed, it can't be modified", rule),
+            AccessError::Internal(rule) => {
+                write!(f, "rule {} is internal, it can't be modified", rule)
+            }
+            AccessError::InternalReference(rule, from) => write!(
+                f,
+                "rule {} is internal, it can't be referenced (from rule {})",
+                rule, from
+            ),
+            AccessError::MissingRule(rule) => write!(f, "extending missing rule {}", rule),
+        }
+    }
+}
+
+impl AccessError {
+    /// Stable code of the error (look at ```codes```)
+    pub fn code(&self) -> Code {
+        match self {
+            AccessError::Sealed(_) => Code::SealedRule,
+            AccessError::Internal(_) => Code::InternalRule,
+            AccessError::InternalReference(_, _) => Code::InternalRuleReference,
+            AccessError::MissingRule(_) => Code::ExtendMissingRule,
+        }
+    }
+}
+
+/// Reference to a rule not defined on the set
+///
+/// Look at ```SetOfRules::check_refs``` and ```StrictRules```
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndefinedRule {
+    /// Name of the rule referenced
+    pub rule: String,
+    /// Rule with the reference
+    pub from: String,
+}
+
+impl std::fmt::Display for UndefinedRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "undefined rule {} (referenced from rule {})",
+            self.rule, self.from
+        )
+    }
+}
+
+impl UndefinedRule {
+    /// Stable code of the error (look at ```codes```)
+    pub fn code(&self) -> Code {
+        Code::MissingRule
+    }
+}
+
+/// New order for the alternatives of an ```or```
+///
+/// Created with ```SetOfRules::suggest_reordering```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reordering {
+    /// Rule containing the ```or```
+    pub rule: String,
+    /// Position of the ```or``` on the rule (look at ```OrStats```)
+    pub index: usize,
+    /// Current positions of the alternatives, in the new order
+    pub order: Vec<usize>,
+}
+
+impl std::fmt::Display for Reordering {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "rule {}: alternatives of or {} could be in order {:?}",
+            self.rule, self.index, self.order
+        )
+    }
+}
+
+/// Add rules from an iterator (i.e. generated from a list)
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let mut rules = rules!{
+///        "main"   =>  or!(ref_rule!("kw_if"), ref_rule!("kw_else"))
+///     };
+///     rules.extend(["if", "else"].iter().map(|kw| (format!("kw_{}", kw), lit!(kw))));
+///
+///     assert!(parse("else", &rules).is_ok())
+/// }
+/// ```
+impl Extend<(String, Expression)> for SetOfRules {
+    fn extend<I: IntoIterator<Item = (String, Expression)>>(&mut self, rules: I) {
+        for (name, expr) in rules {
+            self.add_mut(&name, expr);
+        }
+    }
+}
 
 impl SetOfRules {
     /// Initialize a set of rules with a hashmap of <String, Expression>
     /// In general, is better to use the ```rules!``` macro
     pub fn new(mrules: HashMap<String, Expression>) -> Self {
-        SetOfRules(mrules)
+        SetOfRules {
+            exprs: mrules,
+            policies: HashMap::new(),
+            meta: HashMap::new(),
+            order: vec![],
+            allow_left_recursion: false,
+        }
+    }
+
+    /// Expression of a rule
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    ///
+    /// fn main() {
+    ///     let rules = rules!{"main" => lit!("a")};
+    ///     assert!(rules.get("main").is_some());
+    ///     assert!(rules.get("other").is_none());
+    /// }
+    /// ```
+    pub fn get(&self, name: &str) -> Option<&Expression> {
+        self.exprs.get(name)
+    }
+
+    /// Expressions of the rules, by name (```names``` has the order)
+    pub fn expressions(&self) -> &HashMap<String, Expression> {
+        &self.exprs
     }
 
     /// As this is a dynamic parser, it is necessary to add rules on
@@ -48,7 +286,11 @@ impl SetOfRules {
     /// In this way, you don't need to declare mutable vars.
     /// You could need recursion in some cases
     ///
-    /// To add several rules at once, look for merge
+    /// The set is moved, not copied. Adding a rule is O(1) (a hash map
+    /// insertion). If the rule exists, it will be replaced
+    ///
+    /// To add several rules at once, look for merge. To add rules from
+    /// loops or conditions, look for ```add_mut``` and ```extend```
     ///
     /// ```
     /// #[macro_use]  extern crate dynparser;
@@ -68,10 +310,83 @@ impl SetOfRules {
     /// }
     /// ```
     pub fn add(mut self, name: &str, expr: Expression) -> Self {
-        self.0.insert(name.to_owned(), expr);
+        self.add_mut(name, expr);
+        self
+    }
+
+    /// Same as ```add```, on a mutable reference
+    ///
+    /// Useful when the rules depend on conditions or configuration
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::parse;
+    ///
+    /// fn main() {
+    ///     let allow_hex = true;
+    ///
+    ///     let mut rules = rules!{
+    ///        "main"   =>  or!(ref_rule!("hex"), ref_rule!("dec")),
+    ///        "dec"    =>  rep!(ematch!(chlist "", from '0', to '9'), 1)
+    ///     };
+    ///     if allow_hex {
+    ///         let digit = ematch!(chlist "abcdef", from '0', to '9');
+    ///         rules.add_mut("hex", and!(lit!("0x"), rep!(digit, 1)));
+    ///     } else {
+    ///         rules.add_mut("hex", error!("hex numbers not allowed"));
+    ///     }
+    ///
+    ///     assert!(parse("0xff", &rules).is_ok())
+    /// }
+    /// ```
+    pub fn add_mut(&mut self, name: &str, expr: Expression) -> &mut Self {
+        if self.exprs.insert(name.to_owned(), expr).is_none() {
+            self.order.push(name.to_owned());
+        }
         self
     }
 
+    /// Rule names on declaration order
+    ///
+    /// As added to the set (or declared on the grammar). A replaced rule
+    /// keeps its place. Rules inserted directly on the map go at the
+    /// end, sorted by name
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::rules_from_peg;
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  ref_rule!("num"),
+    ///        "num"    =>  rep!(ref_rule!("digit"), 1),
+    ///        "digit"  =>  ematch!(chlist "", from '0', to '9')
+    ///     }
+    ///     .add("main", ref_rule!("digit"));
+    ///     assert_eq!(rules.names(), vec!["main", "num", "digit"]);
+    ///
+    ///     let rules = rules_from_peg("z = 'z' \n main = z  a \n a = 'a'").unwrap();
+    ///     assert_eq!(rules.names(), vec!["z", "main", "a"]);
+    /// }
+    /// ```
+    pub fn names(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        let declared: Vec<&str> = self
+            .order
+            .iter()
+            .filter(|name| self.exprs.contains_key(*name) && seen.insert(name.as_str()))
+            .map(|name| name.as_str())
+            .collect();
+        let mut others: Vec<&str> = self
+            .exprs
+            .keys()
+            .map(|name| name.as_str())
+            .filter(|name| !seen.contains(name))
+            .collect();
+        others.sort_unstable();
+        declared.into_iter().chain(others).collect()
+    }
+
     /// As this is a dynamic parser, it is necessary to add rules on
     /// runtime.
     ///
@@ -100,12 +415,541 @@ impl SetOfRules {
     /// }
     /// ```
     pub fn merge(self, rules2merge: Self) -> Self {
-        SetOfRules(rules2merge.0.into_iter().chain(self.0).collect())
+        SetOfRules {
+            exprs: rules2merge.exprs.into_iter().chain(self.exprs).collect(),
+            policies: rules2merge
+                .policies
+                .into_iter()
+                .chain(self.policies)
+                .collect(),
+            meta: rules2merge.meta.into_iter().chain(self.meta).collect(),
+            order: self.order.into_iter().chain(rules2merge.order).collect(),
+            allow_left_recursion: self.allow_left_recursion || rules2merge.allow_left_recursion,
+        }
+    }
+
+    /// Allow left recursive rules (i.e. ```expr = expr '+' term / term```)
+    ///
+    /// By default, left recursion is a critical error (too deep nesting
+    /// of rules). Allowed, the recursive call fails the first time, and
+    /// the rule is parsed again using the previous result, while it
+    /// matches more input (seed growing). Indirect left recursion is
+    /// also supported. The AST is left associative
+    ///
+    /// Every rule call on a position is registered, parsing is slower.
+    /// ```ParseOptions::memoize``` is ignored
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::{parse, rules_from_peg};
+    ///
+    /// fn main() {
+    ///     let rules = rules_from_peg(
+    ///         r#"
+    ///         main    =   expr
+    ///         expr    =   expr  '-'  num
+    ///                 /   num
+    ///         num     =   [0-9]+
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    ///     assert!(parse("7-2-1", &rules).is_err());
+    ///
+    ///     let rules = rules.left_recursion(true);
+    ///     let ast = parse("7-2-1", &rules).unwrap();
+    ///     assert_eq!(
+    ///         ast.compact().to_sexpr(),
+    ///         "(main (expr (expr (expr (num \"7\")) \"-\" (num \"2\")) \"-\" (num \"1\")))"
+    ///     );
+    /// }
+    /// ```
+    pub fn left_recursion(mut self, allow: bool) -> Self {
+        self.allow_left_recursion = allow;
+        self
+    }
+
+    /// Left recursive rules are allowed (look at ```left_recursion```)
+    pub fn allows_left_recursion(&self) -> bool {
+        self.allow_left_recursion
+    }
+
+    /// Access policy of a rule for plugins
+    ///
+    /// It will be checked by ```merge_plugin``` and ```extend_rule```
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::parse;
+    /// use dynparser::parser::expression::{AccessError, RuleAccess};
+    ///
+    /// fn main() {
+    ///     let host = rules!{
+    ///        "main"   =>  rep!(ref_rule!("stmt"), 1),
+    ///        "stmt"   =>  or!(ref_rule!("assign"), lit!(";")),
+    ///        "assign" =>  and!(ref_rule!("_id"), lit!("="), ref_rule!("_id")),
+    ///        "_id"    =>  rep!(ematch!(chlist "", from 'a', to 'z'), 1)
+    ///     }
+    ///     .set_access("main", RuleAccess::Sealed)
+    ///     .set_access("assign", RuleAccess::Sealed)
+    ///     .set_access("_id", RuleAccess::Internal);
+    ///
+    ///     let host = host.extend_rule("stmt", lit!("nop;")).unwrap();
+    ///     assert!(parse("a=b;nop;", &host).is_ok());
+    ///
+    ///     let plugin = rules!{"main" => lit!("x")};
+    ///     let err = host.merge_plugin(plugin).unwrap_err();
+    ///     assert_eq!(err, AccessError::Sealed("main".to_string()));
+    /// }
+    /// ```
+    pub fn set_access(mut self, name: &str, access: RuleAccess) -> Self {
+        self.policies.insert(name.to_owned(), access);
+        self
+    }
+
+    /// Access policy of a rule (```Extensible``` if not defined)
+    pub fn access(&self, name: &str) -> RuleAccess {
+        self.policies
+            .get(name)
+            .cloned()
+            .unwrap_or(RuleAccess::Extensible)
+    }
+
+    /// Add an annotation to a rule, as ```@key("value")``` on a grammar
+    ///
+    /// ```
+    /// use dynparser::rules_from_peg;
+    ///
+    /// let rules = rules_from_peg(
+    ///     r#"
+    /// main    =   (kw / id / ' ')*
+    ///
+    /// @highlight("keyword")
+    /// kw      =   'if' / 'else'
+    ///
+    /// @highlight("name")  @token
+    /// id      =   [a-z]+
+    ///     "#,
+    /// ).unwrap();
+    ///
+    /// let meta = rules.rule_meta("id").unwrap();
+    /// assert_eq!(meta.value("highlight"), Some("name"));
+    /// assert!(meta.has("token"));
+    /// assert!(rules.rule_meta("main").is_none());
+    ///
+    /// let rules = rules.set_meta("main", "highlight", None);
+    /// assert!(rules.rule_meta("main").unwrap().has("highlight"));
+    /// ```
+    pub fn set_meta(mut self, name: &str, key: &str, value: Option<&str>) -> Self {
+        self.meta
+            .entry(name.to_owned())
+            .or_default()
+            .0
+            .insert(key.to_owned(), value.map(|v| v.to_owned()));
+        self
+    }
+
+    /// Annotations of a rule, if it has any
+    pub fn rule_meta(&self, name: &str) -> Option<&RuleMeta> {
+        self.meta.get(name)
+    }
+
+    /// Add the rules of a plugin, checking the access policy
+    ///
+    /// Plugin rules will replace the extensible rules with the same
+    /// name. The plugin can't modify sealed or internal rules, nor
+    /// reference the internal ones
+    ///
+    /// With several violations, the error is for the first rule on
+    /// ```names``` order of the plugin
+    pub fn merge_plugin(self, plugin: Self) -> result::Result<Self, AccessError> {
+        for name in plugin.names() {
+            self.check_modify(name)?;
+            self.check_references(name, &plugin.exprs[name])?;
+        }
+        //  the plugin can't change the policy of the host
+        Ok(SetOfRules {
+            exprs: self.exprs.into_iter().chain(plugin.exprs).collect(),
+            policies: plugin.policies.into_iter().chain(self.policies).collect(),
+            meta: self.meta.into_iter().chain(plugin.meta).collect(),
+            order: self.order.into_iter().chain(plugin.order).collect(),
+            allow_left_recursion: self.allow_left_recursion,
+        })
+    }
+
+    /// Add an alternative to a rule, checking the access policy
+    ///
+    /// The new alternative will be tried after the current ones
+    pub fn extend_rule(
+        mut self,
+        name: &str,
+        expr: Expression,
+    ) -> result::Result<Self, AccessError> {
+        self.check_modify(name)?;
+        self.check_references(name, &expr)?;
+        let current = self
+            .exprs
+            .remove(name)
+            .ok_or_else(|| AccessError::MissingRule(name.to_owned()))?;
+        let extended = match current {
+            Expression::Or(MultiExpr(mut alternatives)) => {
+                alternatives.push(expr);
+                Expression::Or(MultiExpr(alternatives))
+            }
+            current => Expression::Or(MultiExpr(vec![current, expr])),
+        };
+        Ok(self.add(name, extended))
+    }
+
+    /// References to rules not defined on the set, sorted by the rule
+    /// with the reference
+    ///
+    /// Building rules by code, a typo on a ```ref_rule!``` is only
+    /// found when the parser reaches it
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::parser::expression::UndefinedRule;
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  rep!(ref_rule!("exprr"), 1),
+    ///        "expr"   =>  lit!("a")
+    ///     };
+    ///
+    ///     let undefined = rules.check_refs().unwrap_err();
+    ///     assert_eq!(
+    ///         undefined,
+    ///         vec![UndefinedRule {
+    ///             rule: "exprr".to_string(),
+    ///             from: "main".to_string()
+    ///         }]
+    ///     );
+    /// }
+    /// ```
+    pub fn check_refs(&self) -> result::Result<(), Vec<UndefinedRule>> {
+        let mut undefined: Vec<UndefinedRule> = self
+            .exprs
+            .iter()
+            .flat_map(|(from, expr)| {
+                rule_references(expr)
+                    .into_iter()
+                    .filter(|r| !self.exprs.contains_key(*r))
+                    .map(move |r| UndefinedRule {
+                        rule: r.to_owned(),
+                        from: from.clone(),
+                    })
+            })
+            .collect();
+        undefined.sort_by(|a, b| (&a.from, &a.rule).cmp(&(&b.from, &b.rule)));
+        undefined.dedup();
+
+        if undefined.is_empty() {
+            Ok(())
+        } else {
+            Err(undefined)
+        }
+    }
+
+    /// Check the references between the rules: undefined rules, rules
+    /// calling themselves without consuming input and rules not
+    /// reachable from ```main``` (look at ```analysis::validate```)
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::parser::analysis::ValidationIssue;
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  ref_rule!("a"),
+    ///        "a"      =>  or!(ref_rule!("a"), lit!("a")),
+    ///        "b"      =>  lit!("b")
+    ///     };
+    ///
+    ///     assert_eq!(
+    ///         rules.validate(),
+    ///         vec![
+    ///             ValidationIssue::LeftRecursion(vec!["a".to_string(), "a".to_string()]),
+    ///             ValidationIssue::Unused("b".to_string())
+    ///         ]
+    ///     );
+    /// }
+    /// ```
+    pub fn validate(&self) -> Vec<analysis::ValidationIssue> {
+        analysis::validate(self)
+    }
+
+    /// Continue adding rules checking their references (look at
+    /// ```StrictRules```)
+    pub fn strict(self) -> StrictRules {
+        StrictRules::new(self)
+    }
+
+    /// Lookup table to convert names to ```RuleId``` and back
+    ///
+    /// If you need several ids, build it once and reuse it
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  or!(label!("A", lit!("a")), ref_rule!("b")),
+    ///        "b"      =>  lit!("b")
+    ///     };
+    ///
+    ///     let names = rules.rule_names();
+    ///
+    ///     assert_eq!(names.len(), 3);
+    ///     let id = names.id("main").unwrap();
+    ///     assert_eq!(names.name(id), Some("main"));
+    ///     assert!(names.id("A").is_some());
+    ///     assert!(names.id("c").is_none());
+    /// }
+    /// ```
+    pub fn rule_names(&self) -> RuleNames {
+        fn add_labels(names: &mut Vec<String>, expr: &Expression) {
+            match expr {
+                Expression::Labeled(label, e) => {
+                    names.push(label.clone());
+                    add_labels(names, e)
+                }
+                Expression::And(mexpr) | Expression::Or(mexpr) => {
+                    mexpr.0.iter().for_each(|e| add_labels(names, e))
+                }
+                Expression::Not(e)
+                | Expression::Deprecated(_, e)
+                | Expression::NotKeyword(_, e)
+                | Expression::Keywords(_, e) => add_labels(names, e),
+                Expression::Repeat(rep) => add_labels(names, &rep.expression),
+                Expression::Simple(_) | Expression::RuleName(_) => (),
+            }
+        }
+
+        let mut names: Vec<String> = self.exprs.keys().cloned().collect();
+        self.exprs.values().for_each(|e| add_labels(&mut names, e));
+        names.sort();
+        names.dedup();
+        RuleNames(names)
+    }
+
+    /// Hash of the rules, stable between executions and platforms
+    ///
+    /// It's computed over a canonical form. Rules are sorted by name
+    /// and the grammar text is not used (comments, spaces and the
+    /// order of declarations are not relevant). Custom matchers
+    /// contribute only with their name. The access policy is not
+    /// included
+    ///
+    /// Useful to detect changes on a grammar between deployments or to
+    /// key caches
+    ///
+    /// ```
+    /// use dynparser::rules_from_peg;
+    ///
+    /// let rules = rules_from_peg("main = 'a' b \n b = [0-9]").unwrap();
+    /// let same = rules_from_peg("// digit\nb = [0-9]\nmain = 'a'  b").unwrap();
+    /// let other = rules_from_peg("main = 'a' b \n b = [0-8]").unwrap();
+    ///
+    /// assert_eq!(rules.fingerprint(), same.fingerprint());
+    /// assert_ne!(rules.fingerprint(), other.fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        let mut names: Vec<&String> = self.exprs.keys().collect();
+        names.sort();
+
+        //  FNV-1a, std hashers can change between compiler versions
+        names.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, name| {
+            let rule = format!("{}\0{}\0", name, gcode::expr2code(&self.exprs[name]));
+            rule.bytes().fold(hash, |hash, b| {
+                (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+            })
+        })
+    }
+
+    /// Markdown documentation of the rules
+    ///
+    /// Look at ```peg::gdoc``` for the content, and for a version with
+    /// links to railroad diagrams
+    pub fn to_markdown(&self) -> String {
+        gdoc::markdown_from_rules(self)
+    }
+
+    /// Tries and matches of the alternatives of every ```or```, on a
+    /// profile created with ```parse_profile``` and these rules
+    ///
+    /// Rules are in alphabetical order. Alternatives never tried are
+    /// not reported
+    pub fn alternative_stats(&self, profile: &Profile) -> Vec<OrStats> {
+        let mut names: Vec<&String> = self.exprs.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .flat_map(|rule| {
+                ors_preorder(&self.exprs[rule])
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(move |(index, mexpr)| {
+                        profile.alternatives(or_id(mexpr)).map(|stats| OrStats {
+                            rule: rule.clone(),
+                            index,
+                            alternatives: stats.to_vec(),
+                        })
+                    })
+            })
+            .collect()
+    }
+
+    /// Propose a new order for the alternatives of the ```or```
+    /// expressions, the ones with more matches first
+    ///
+    /// Only when the order doesn't change the result. The alternatives
+    /// have to start with different chars (and they can't be empty).
+    /// Then, at most one of them can match
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::{parse, parse_profile, rules_from_peg};
+    ///
+    /// fn main() {
+    ///     let rules = rules_from_peg(
+    ///         r#"
+    ///         main    =   (kw  ' ')*
+    ///         kw      =   'if'  /  'else'  /  'let'
+    ///         "#,
+    ///     ).unwrap();
+    ///
+    ///     let (_, profile) = parse_profile("let let else let ", &rules);
+    ///     let reorderings = rules.suggest_reordering(&profile);
+    ///     assert_eq!(reorderings.len(), 1);
+    ///     assert_eq!(reorderings[0].rule, "kw");
+    ///     assert_eq!(reorderings[0].order, vec![2, 1, 0]);
+    ///
+    ///     let rules = rules.apply_reordering(&reorderings);
+    ///     assert!(parse("if let ", &rules).is_ok());
+    /// }
+    /// ```
+    pub fn suggest_reordering(&self, profile: &Profile) -> Vec<Reordering> {
+        self.alternative_stats(profile)
+            .into_iter()
+            .filter_map(|stats| {
+                let mut order: Vec<usize> = (0..stats.alternatives.len()).collect();
+                order.sort_by_key(|i| std::cmp::Reverse(stats.alternatives[*i].matches));
+                let mexpr = ors_preorder(&self.exprs[&stats.rule])[stats.index];
+                let sorted = order.windows(2).all(|w| w[0] < w[1]);
+                if sorted || !analysis::disjoint_alternatives(self, &mexpr.0) {
+                    None
+                } else {
+                    Some(Reordering {
+                        rule: stats.rule,
+                        index: stats.index,
+                        order,
+                    })
+                }
+            })
+            .collect()
+    }
+
+    /// Change the order of the alternatives
+    ///
+    /// Reorderings on missing rules or ```or```, or with a wrong
+    /// number of alternatives, are ignored
+    pub fn apply_reordering(mut self, reorderings: &[Reordering]) -> Self {
+        for (name, expr) in self.exprs.iter_mut() {
+            let orders: HashMap<usize, &[usize]> = reorderings
+                .iter()
+                .filter(|r| &r.rule == name)
+                .map(|r| (r.index, r.order.as_slice()))
+                .collect();
+            if !orders.is_empty() {
+                *expr = reorder_ors(expr.clone(), &orders, &mut 0);
+            }
+        }
+        self
+    }
+
+    //  access policy and metadata are kept when the rules are rebuilt
+    pub(crate) fn with_rules(&self, rules: HashMap<String, Expression>) -> Self {
+        SetOfRules {
+            exprs: rules,
+            policies: self.policies.clone(),
+            meta: self.meta.clone(),
+            order: self.order.clone(),
+            allow_left_recursion: self.allow_left_recursion,
+        }
+    }
+
+    //  the names on the order of a new declaration
+    pub(crate) fn with_order(mut self, names: Vec<String>) -> Self {
+        self.order = names;
+        self
+    }
+
+    fn check_modify(&self, name: &str) -> result::Result<(), AccessError> {
+        match (self.exprs.contains_key(name), self.access(name)) {
+            (true, RuleAccess::Sealed) => Err(AccessError::Sealed(name.to_owned())),
+            (true, RuleAccess::Internal) => Err(AccessError::Internal(name.to_owned())),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_references(&self, from: &str, expr: &Expression) -> result::Result<(), AccessError> {
+        match rule_references(expr)
+            .into_iter()
+            .find(|r| self.access(r) == RuleAccess::Internal)
+        {
+            Some(internal) => Err(AccessError::InternalReference(
+                internal.to_owned(),
+                from.to_owned(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Small identifier for a rule name
+///
+/// Ids are assigned in alphabetical order of names. Then, the same
+/// set of rules will produce always the same ids. They are stable only
+/// for an identical grammar: adding, removing or renaming a rule (or a
+/// label) can change the ids of the others
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RuleId(pub u32);
+
+/// Lookup table between rule names and ```RuleId```
+///
+/// It contains the rules and the labels (they also produce nodes on
+/// the AST)
+#[derive(Debug, Clone)]
+pub struct RuleNames(Vec<String>);
+
+impl RuleNames {
+    /// ```RuleId``` for a name (if exists)
+    pub fn id(&self, name: &str) -> Option<RuleId> {
+        self.0
+            .binary_search_by(|n| n.as_str().cmp(name))
+            .ok()
+            .map(|i| RuleId(i as u32))
+    }
+
+    /// Name for a ```RuleId``` (if exists)
+    pub fn name(&self, id: RuleId) -> Option<&str> {
+        self.0.get(id.0 as usize).map(|n| n.as_str())
+    }
+
+    /// Number of names registered
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// There are no names
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 }
 
 #[allow(missing_docs)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expression {
     Simple(Atom),
     And(MultiExpr),
@@ -113,10 +957,23 @@ pub enum Expression {
     Not(Box<Expression>),
     Repeat(RepInfo),
     RuleName(String),
+    /// The nodes produced by the expression will be grouped
+    /// on a ```Node::Rule``` with the label as name
+    Labeled(String, Box<Expression>),
+    /// On match, a warning with the message and the matched
+    /// span will be registered
+    Deprecated(String, Box<Expression>),
+    /// It will fail if the text matched by the expression is one
+    /// of the keywords (sorted list)
+    NotKeyword(Vec<String>, Box<Expression>),
+    /// While parsing the expression, the keywords (sorted list) will
+    /// also be rejected by the ```NotKeyword``` expressions
+    Keywords(Vec<String>, Box<Expression>),
 }
 
 /// Opaque type to manage multiple expressions
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MultiExpr(pub Vec<Expression>);
 
 impl MultiExpr {
@@ -127,7 +984,8 @@ impl MultiExpr {
 }
 
 /// Opaque type to manage repetition subexpression
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RepInfo {
     /// expresion
     pub expression: Box<Expression>,
@@ -140,7 +998,14 @@ pub struct RepInfo {
 impl RepInfo {
     /// Creates a Repeticion Info for an expression with min and
     /// optionally max values to repeat
+    ///
+    /// # Panics
+    ///
+    /// If ```min``` is bigger than ```max```
     pub fn new(expression: Box<Expression>, min: usize, max: Option<usize>) -> Self {
+        if let Some(max) = max {
+            assert!(min <= max, "repetition with min {} > max {}", min, max);
+        }
         RepInfo {
             expression,
             min: NRep(min),
@@ -149,8 +1014,53 @@ impl RepInfo {
     }
 }
 
+/// Limits of a repetition, used by the ```rep!``` macro
+///
+/// A number is the minimum, without maximum. Ranges give both limits
+/// (```2..=5```, ```2..6```, ```2..```, ```..=5```). A maximum of 0 is
+/// no repetition at all (```0..1```, ```..=0```)
+///
+/// Empty ranges (```2..2```, ```3..=2```) panic, as they can't match
+pub trait RepBounds {
+    /// Minimum and optional maximum
+    fn bounds(self) -> (usize, Option<usize>);
+}
+
+impl RepBounds for usize {
+    fn bounds(self) -> (usize, Option<usize>) {
+        (self, None)
+    }
+}
+
+impl RepBounds for std::ops::RangeInclusive<usize> {
+    fn bounds(self) -> (usize, Option<usize>) {
+        assert!(!self.is_empty(), "empty repetition range {:?}", self);
+        (*self.start(), Some(*self.end()))
+    }
+}
+
+impl RepBounds for std::ops::Range<usize> {
+    fn bounds(self) -> (usize, Option<usize>) {
+        assert!(self.start < self.end, "empty repetition range {:?}", self);
+        (self.start, Some(self.end - 1))
+    }
+}
+
+impl RepBounds for std::ops::RangeFrom<usize> {
+    fn bounds(self) -> (usize, Option<usize>) {
+        (self.start, None)
+    }
+}
+
+impl RepBounds for std::ops::RangeToInclusive<usize> {
+    fn bounds(self) -> (usize, Option<usize>) {
+        (0, Some(self.end))
+    }
+}
+
 /// Number of repetitions of rule
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NRep(pub(crate) usize);
 
 impl std::fmt::Display for NRep {
@@ -167,53 +1077,736 @@ impl std::fmt::Display for NRep {
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
 
+//-----------------------------------------------------------------------
+/// Non greedy repetition
+///
+/// PEG repetitions are greedy, and they never give back what they
+/// consumed. Then ```.* "end"``` will never match.
+///
+/// A lazy repetition of ```expression``` (at least ```min``` times)
+/// will stop as soon as ```stop``` matches, and ```stop``` will be
+/// consumed after it
+///
+/// It's the same as writing...
+///
+/// ```ignore
+///     expression{min}  (!stop expression)*  stop
+/// ```
+///
+/// Usually you will use it with the ```rep_lazy!``` macro or with
+/// ```*?``` and ```+?``` on peg grammars
+pub fn lazy_repeat(expression: Expression, min: usize, stop: Expression) -> Expression {
+    let lazy = Expression::Repeat(RepInfo::new(
+        Box::new(Expression::And(MultiExpr::new(vec![
+            Expression::Not(Box::new(stop.clone())),
+            expression.clone(),
+        ]))),
+        0,
+        None,
+    ));
+
+    if min == 0 {
+        Expression::And(MultiExpr::new(vec![lazy, stop]))
+    } else {
+        let first = Expression::Repeat(RepInfo::new(Box::new(expression), min, Some(min)));
+        Expression::And(MultiExpr::new(vec![first, lazy, stop]))
+    }
+}
+
+//-----------------------------------------------------------------------
+/// One or more ```expression``` separated by ```separator```
+///
+/// With ```trailing```, a separator after the last element is accepted
+/// (and consumed). It's the same as writing...
+///
+/// ```ignore
+///     expression  (separator expression)*  separator?
+/// ```
+///
+/// Usually you will use it with the ```sep_by!``` and
+/// ```sep_by_trailing!``` macros or with ```%``` and ```%,``` on peg
+/// grammars
+pub fn separated(expression: Expression, separator: Expression, trailing: bool) -> Expression {
+    let rest = Expression::Repeat(RepInfo::new(
+        Box::new(Expression::And(MultiExpr::new(vec![
+            separator.clone(),
+            expression.clone(),
+        ]))),
+        0,
+        None,
+    ));
+
+    if trailing {
+        let last = Expression::Repeat(RepInfo::new(Box::new(separator), 0, Some(1)));
+        Expression::And(MultiExpr::new(vec![expression, rest, last]))
+    } else {
+        Expression::And(MultiExpr::new(vec![expression, rest]))
+    }
+}
+
+//-----------------------------------------------------------------------
+/// Zero width assertion
+///
+/// It will not move the parsing position. If ```expression```
+/// doesn't match at this point, it will fail with a critical error
+/// with ```message``` as description
+///
+/// It's the same as writing...
+///
+/// ```ignore
+///     !!expression  /  error(message)
+/// ```
+///
+/// Usually you will use it with the ```assert_expr!``` macro or with
+/// ```assert(expr, "message")``` on peg grammars
+pub fn assertion(expression: Expression, message: &str) -> Expression {
+    Expression::Or(MultiExpr::new(vec![
+        Expression::Not(Box::new(Expression::Not(Box::new(expression)))),
+        Expression::Simple(Atom::Error(message.to_owned())),
+    ]))
+}
+
+//-----------------------------------------------------------------------
+/// Literals and matches on the expression will ignore the case
+///
+/// It uses unicode simple case folding. i.e. ```"straße"``` matches
+/// ```"STRAẞE"``` but not ```"STRASSE"```
+///
+/// Usually you will use it with the ```no_case!``` macro or with
+/// ```"..."i``` and ```[...]i``` on peg grammars
+pub fn no_case(expression: Expression) -> Expression {
+    let no_case_mexpr = |mexpr: MultiExpr| MultiExpr(mexpr.0.into_iter().map(no_case).collect());
+
+    match expression {
+        Expression::Simple(Atom::Literal(l)) => Expression::Simple(Atom::LiteralNoCase(l)),
+        Expression::Simple(Atom::Char(ch)) => {
+            Expression::Simple(Atom::LiteralNoCase(ch.to_string()))
+        }
+        Expression::Simple(Atom::Match(m)) => Expression::Simple(Atom::MatchNoCase(m)),
+        Expression::Simple(_) | Expression::RuleName(_) => expression,
+        Expression::And(mexpr) => Expression::And(no_case_mexpr(mexpr)),
+        Expression::Or(mexpr) => Expression::Or(no_case_mexpr(mexpr)),
+        Expression::Not(e) => Expression::Not(Box::new(no_case(*e))),
+        Expression::Repeat(rep) => Expression::Repeat(RepInfo {
+            expression: Box::new(no_case(*rep.expression)),
+            ..rep
+        }),
+        Expression::Labeled(label, e) => Expression::Labeled(label, Box::new(no_case(*e))),
+        Expression::Deprecated(message, e) => {
+            Expression::Deprecated(message, Box::new(no_case(*e)))
+        }
+        Expression::NotKeyword(keywords, e) => {
+            Expression::NotKeyword(keywords, Box::new(no_case(*e)))
+        }
+        Expression::Keywords(keywords, e) => Expression::Keywords(keywords, Box::new(no_case(*e))),
+    }
+}
+
+//-----------------------------------------------------------------------
+/// Dots on the expression will not match a new line (```\n``` or
+/// ```\r```)
+///
+/// Usually you will use it with ```%dot no_newline``` on peg grammars
+pub fn no_newline_dots(expression: Expression) -> Expression {
+    let no_newline_mexpr =
+        |mexpr: MultiExpr| MultiExpr(mexpr.0.into_iter().map(no_newline_dots).collect());
+
+    match expression {
+        Expression::Simple(Atom::Dot) => Expression::Simple(Atom::DotNoNewline),
+        Expression::Simple(_) | Expression::RuleName(_) => expression,
+        Expression::And(mexpr) => Expression::And(no_newline_mexpr(mexpr)),
+        Expression::Or(mexpr) => Expression::Or(no_newline_mexpr(mexpr)),
+        Expression::Not(e) => Expression::Not(Box::new(no_newline_dots(*e))),
+        Expression::Repeat(rep) => Expression::Repeat(RepInfo {
+            expression: Box::new(no_newline_dots(*rep.expression)),
+            ..rep
+        }),
+        Expression::Labeled(label, e) => Expression::Labeled(label, Box::new(no_newline_dots(*e))),
+        Expression::Deprecated(message, e) => {
+            Expression::Deprecated(message, Box::new(no_newline_dots(*e)))
+        }
+        Expression::NotKeyword(keywords, e) => {
+            Expression::NotKeyword(keywords, Box::new(no_newline_dots(*e)))
+        }
+        Expression::Keywords(keywords, e) => {
+            Expression::Keywords(keywords, Box::new(no_newline_dots(*e)))
+        }
+    }
+}
+
+//-----------------------------------------------------------------------
+/// The expression will fail if the matched text is one of the keywords
+///
+/// Useful on identifiers, to reject the reserved words without writing
+/// a negation for every keyword
+///
+/// Usually you will use it with the ```not_keyword!``` macro or with
+/// ```%keywords``` and ```@identifier``` on peg grammars
+pub fn not_keyword(keywords: &[&str], expression: Expression) -> Expression {
+    let mut keywords: Vec<String> = keywords.iter().map(|k| k.to_string()).collect();
+    keywords.sort();
+    keywords.dedup();
+    Expression::NotKeyword(keywords, Box::new(expression))
+}
+
+//-----------------------------------------------------------------------
+/// The keywords will be reserved only while parsing the expression
+///
+/// Identifiers (```NotKeyword``` expressions) called from the expression
+/// will reject them too. Out of it, they are valid identifiers
+///
+/// Usually you will use it with the ```keywords!``` macro or with
+/// ```@keywords("...")``` on peg grammars
+pub fn keywords(keywords: &[&str], expression: Expression) -> Expression {
+    let mut keywords: Vec<String> = keywords.iter().map(|k| k.to_string()).collect();
+    keywords.sort();
+    keywords.dedup();
+    Expression::Keywords(keywords, Box::new(expression))
+}
+
+//-----------------------------------------------------------------------
+/// Parse an expression from a byte offset of the input
+///
+/// For custom drivers and combinators. The expression doesn't need to
+/// consume all the input. The result has the consumed region and the
+/// nodes produced
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parser::expression::{parse_expression_at, Match};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "num"    =>  rep!(ematch!(chlist "", from '0', to '9'), 1)
+///     };
+///     let expr = and!(ref_rule!("num"), lit!("+"), ref_rule!("num"));
+///
+///     let input = "x = 12+3;";
+///     let (m, nodes) = parse_expression_at(input, 4, &rules, &expr).unwrap();
+///     assert_eq!(m, Match { start: 4, end: 8 });
+///     assert_eq!(m.text(input), "12+3");
+///     assert_eq!(nodes.len(), 3);
+///
+///     assert!(parse_expression_at(input, 0, &rules, &expr).is_err());
+///     assert!(parse_expression_at(input, 20, &rules, &expr).is_err());
+/// }
+/// ```
+pub fn parse_expression_at(
+    input: &str,
+    offset: usize,
+    rules: &SetOfRules,
+    expression: &Expression,
+) -> result::Result<(Match, Vec<ast::Node>), Error> {
+    let mut status = Status::init(input, rules);
+    match input.get(offset..) {
+        Some(rest) => {
+            status.pos.advance(&input[..offset], status.newlines);
+            status.it_parsing = rest.chars();
+        }
+        None => {
+            return Err(Error::from_status(
+                &status,
+                Code::InvalidOffset,
+                &format!("offset {} is not a char boundary on input", offset),
+                ErrPriority::Critical,
+            ))
+        }
+    }
+
+    let (st, nodes) = parse_expr(status, expression)?;
+    let end = input.len() - st.it_parsing.as_str().len();
+    Ok((Match { start: offset, end }, nodes))
+}
+
+//-----------------------------------------------------------------------
+//  rules called from the expression (repeated if called several times)
+pub(crate) fn rule_references(expr: &Expression) -> Vec<&str> {
+    fn add<'a>(expr: &'a Expression, refs: &mut Vec<&'a str>) {
+        match expr {
+            Expression::RuleName(name) => refs.push(name),
+            Expression::And(mexpr) | Expression::Or(mexpr) => {
+                mexpr.0.iter().for_each(|e| add(e, refs))
+            }
+            Expression::Repeat(rep) => add(&rep.expression, refs),
+            Expression::Not(e)
+            | Expression::Labeled(_, e)
+            | Expression::Deprecated(_, e)
+            | Expression::NotKeyword(_, e)
+            | Expression::Keywords(_, e) => add(e, refs),
+            Expression::Simple(_) => (),
+        }
+    }
+
+    let mut refs = vec![];
+    add(expr, &mut refs);
+    refs
+}
+
+//-----------------------------------------------------------------------
+//  an or is identified by its address, while the rules are not modified
+fn or_id(mexpr: &MultiExpr) -> usize {
+    mexpr as *const MultiExpr as usize
+}
+
+//  or id -> (rule, index on the rule, number of alternatives)
+pub(crate) fn or_positions(rules: &SetOfRules) -> HashMap<usize, (&str, usize, usize)> {
+    rules
+        .exprs
+        .iter()
+        .flat_map(|(rule, expr)| {
+            ors_preorder(expr)
+                .into_iter()
+                .enumerate()
+                .map(move |(index, mexpr)| (or_id(mexpr), (rule.as_str(), index, mexpr.0.len())))
+        })
+        .collect()
+}
+
+//  or expressions, the containing one before the nested ones
+fn ors_preorder(expr: &Expression) -> Vec<&MultiExpr> {
+    fn add<'a>(expr: &'a Expression, ors: &mut Vec<&'a MultiExpr>) {
+        match expr {
+            Expression::Or(mexpr) => {
+                ors.push(mexpr);
+                mexpr.0.iter().for_each(|e| add(e, ors));
+            }
+            Expression::And(mexpr) => mexpr.0.iter().for_each(|e| add(e, ors)),
+            Expression::Not(e)
+            | Expression::Labeled(_, e)
+            | Expression::Deprecated(_, e)
+            | Expression::NotKeyword(_, e)
+            | Expression::Keywords(_, e) => add(e, ors),
+            Expression::Repeat(rep) => add(&rep.expression, ors),
+            Expression::Simple(_) | Expression::RuleName(_) => (),
+        }
+    }
+
+    let mut ors = vec![];
+    add(expr, &mut ors);
+    ors
+}
+
+//  the same numbering as ors_preorder, before moving the alternatives
+fn reorder_ors(
+    expr: Expression,
+    orders: &HashMap<usize, &[usize]>,
+    next: &mut usize,
+) -> Expression {
+    let reorder = |e: Box<Expression>, next: &mut usize| Box::new(reorder_ors(*e, orders, next));
+    match expr {
+        Expression::Or(mexpr) => {
+            let index = *next;
+            *next += 1;
+            let mut alternatives: Vec<Option<Expression>> = mexpr
+                .0
+                .into_iter()
+                .map(|e| Some(reorder_ors(e, orders, next)))
+                .collect();
+            match orders.get(&index) {
+                Some(order) if is_permutation(order, alternatives.len()) => {
+                    Expression::Or(MultiExpr(
+                        order
+                            .iter()
+                            .filter_map(|i| alternatives[*i].take())
+                            .collect(),
+                    ))
+                }
+                _ => Expression::Or(MultiExpr(alternatives.into_iter().flatten().collect())),
+            }
+        }
+        Expression::And(mexpr) => Expression::And(MultiExpr(
+            mexpr
+                .0
+                .into_iter()
+                .map(|e| reorder_ors(e, orders, next))
+                .collect(),
+        )),
+        Expression::Not(e) => Expression::Not(reorder(e, next)),
+        Expression::Labeled(label, e) => Expression::Labeled(label, reorder(e, next)),
+        Expression::Deprecated(message, e) => Expression::Deprecated(message, reorder(e, next)),
+        Expression::NotKeyword(keywords, e) => Expression::NotKeyword(keywords, reorder(e, next)),
+        Expression::Keywords(keywords, e) => Expression::Keywords(keywords, reorder(e, next)),
+        Expression::Repeat(rep) => Expression::Repeat(RepInfo {
+            expression: reorder(rep.expression, next),
+            ..rep
+        }),
+        Expression::Simple(_) | Expression::RuleName(_) => expr,
+    }
+}
+
+fn is_permutation(order: &[usize], len: usize) -> bool {
+    let mut sorted = order.to_vec();
+    sorted.sort_unstable();
+    sorted == (0..len).collect::<Vec<_>>()
+}
+
 //-----------------------------------------------------------------------
 pub(crate) fn parse(status: Status) -> Result {
-    parse_rule_name(status, "main")
+    parse_rule_name(status.reset_stack(), "main")
+}
+
+//-----------------------------------------------------------------------
+pub(crate) fn parse_rule<'a>(status: Status<'a>, rule_name: &str) -> Result<'a> {
+    parse_rule_name(status.reset_stack(), rule_name)
+}
+
+//-----------------------------------------------------------------------
+//  main as a repetition of items and the expressions after it
+//  (```main = (line "\n")* eof```), to be parsed item by item
+//  None on other shapes, or if main replaces its node (raw, passthrough)
+pub(crate) fn main_items(rules: &SetOfRules) -> Option<(&RepInfo, &[Expression])> {
+    if has_annotation(rules, "main", "raw") || has_annotation(rules, "main", "passthrough") {
+        return None;
+    }
+    match rules.exprs.get("main")? {
+        Expression::Repeat(rep) if rep.max.is_none() => Some((rep, &[])),
+        Expression::And(MultiExpr(exprs)) => match exprs.split_first()? {
+            (Expression::Repeat(rep), tail) if rep.max.is_none() => Some((rep, tail)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+//  expressions in sequence, as a part of main
+pub(crate) fn parse_main_exprs<'a>(status: Status<'a>, exprs: &'a [Expression]) -> ResultExpr<'a> {
+    let init = Ok((
+        Status {
+            rule_name: "main",
+            ..status
+        },
+        scratch::take(),
+    ));
+    exprs.iter().fold(init, |acc, expr| {
+        let (st, nodes) = acc?;
+        let (st, vnodes) = parse_expr(st, expr)?;
+        Ok((st, scratch::append(nodes, vnodes)))
+    })
 }
 
 //-----------------------------------------------------------------------
 //  SUPPORT
 
 //-----------------------------------------------------------------------
+#[inline]
 fn parse_rule_name<'a>(status: Status<'a>, rule_name: &str) -> Result<'a> {
-    // use std::time::{Duration, Instant};
-    // let start = Instant::now();
+    let sync = match status.recovered {
+        Some(_) => status
+            .rules
+            .rule_meta(rule_name)
+            .and_then(|meta| meta.value("sync")),
+        None => None,
+    };
+    match sync {
+        Some(token) => parse_rule_name_sync(status, rule_name, token),
+        None => parse_rule_name_traced(status, rule_name),
+    }
+}
+
+//  out of parse_rule_name, to keep the stack used by every rule small
+#[inline(never)]
+fn parse_rule_name_sync<'a>(status: Status<'a>, rule_name: &str, token: &str) -> Result<'a> {
+    match parse_rule_name_traced(status.clone(), rule_name) {
+        Err(e) => recover_rule(status, rule_name, token, e),
+        result => result,
+    }
+}
+
+fn parse_rule_name_traced<'a>(status: Status<'a>, rule_name: &str) -> Result<'a> {
+    //  a span per rule call, with level trace
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("rule", name = rule_name, start = status.pos.n).entered();
+
+    let result = match status.profile.clone() {
+        None => parse_rule_name_memo(status, rule_name),
+        Some(profile) => {
+            let start = std::time::Instant::now();
+            let result = parse_rule_name_memo(status, rule_name);
+            #[cfg(not(feature = "profiling"))]
+            profile
+                .borrow_mut()
+                .register(rule_name, result.is_ok(), start.elapsed());
+            #[cfg(feature = "profiling")]
+            profile
+                .borrow_mut()
+                .register_call(rule_name, result.is_ok(), start, start.elapsed());
+            result
+        }
+    };
+
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok((st, _)) => tracing::trace!(end = st.pos.n, "matched"),
+        Err(e) => tracing::trace!(pos = e.pos.n, error = %e.descr, "failed"),
+    }
+    result
+}
+
+//  the rule failed after consuming some input. The input till the sync
+//  token (included) is skipped, and the error registered
+//  Errors on the first char are not recovered, they are the usual end
+//  of a repetition or a discarded alternative (the position of an error
+//  is after the unexpected char)
+fn recover_rule<'a>(status: Status<'a>, rule_name: &str, token: &str, error: Error) -> Result<'a> {
+    let recoverable = error.priority == ErrPriority::Normal || error.code == Code::UserError;
+    if !recoverable || error.pos.n <= status.pos.n + 1 || status.fail_fast {
+        return Err(error);
+    }
+    let rest = status.it_parsing.as_str();
+    let failed_at = rest
+        .char_indices()
+        .nth(error.pos.n - status.pos.n)
+        .map_or(rest.len(), |(i, _)| i);
+    let end = match rest[failed_at..].find(token) {
+        Some(found) => failed_at + found + token.len(),
+        None => return Err(error),
+    };
+
+    let skipped = &rest[..end];
+    let start = status.text2parse.len() - rest.len();
+    let mut st = status
+        .push_tree_event(TreeEvent::Open(rule_name.to_owned()))
+        .push_leaf_kind(ast::kind::NodeKind::Other)
+        .push_tree_event(TreeEvent::Token(
+            ast::kind::NodeKind::Other,
+            skipped.to_owned(),
+            start..start + end,
+        ))
+        .push_tree_event(TreeEvent::Close);
+    let newlines = st.newlines;
+    st.pos.advance(skipped, newlines);
+    st.it_parsing = rest[end..].chars();
+    let st = st.push_recovered(error);
+    let nodes = nodes_from_node(&st, ast::Node::Val(skipped.to_owned()));
+    Ok((
+        st,
+        ast::Node::Rule((rule_name.to_owned(), scratch::compact(nodes))),
+    ))
+}
 
+fn parse_rule_name_memo<'a>(status: Status<'a>, rule_name: &str) -> Result<'a> {
+    //  instead of a stack overflow (i.e. left recursion)
+    if status.stack_used() > status.max_stack {
+        return Err(Error::from_status(
+            &status,
+            Code::TooDeepNesting,
+            &format!(
+                "too deep nesting of rules on rule {} (stack limit {} bytes), left recursion?",
+                rule_name, status.max_stack
+            ),
+            ErrPriority::Critical,
+        ));
+    }
+    if let Some(seeds) = status.seeds.clone() {
+        return parse_rule_name_grow(status, rule_name, &seeds);
+    }
+    match status.memo.clone() {
+        Some(memo) if status.can_memoize() => parse_rule_name_memoized(status, rule_name, &memo),
+        _ => parse_rule_name_no_profile(status, rule_name),
+    }
+}
+
+//  left recursion, growing a seed. The first time, the recursive call
+//  fails. While the rule matches more input using the previous result
+//  on the recursive call, it's parsed again
+fn parse_rule_name_grow<'a>(status: Status<'a>, rule_name: &str, seeds: &Seeds<'a>) -> Result<'a> {
+    let rules = status.rules;
+    let name = match rules.exprs.get_key_value(rule_name) {
+        Some((name, _)) => name.as_str(),
+        None => return parse_rule_name_no_profile(status, rule_name),
+    };
+    let key = (name, status.pos.n);
+    if let Some(seed) = seeds.borrow_mut().get_mut(&key) {
+        seed.used = true;
+        return seed.restore(status);
+    }
+
+    let failed = Error::from_status_normal(
+        &status,
+        Code::LeftRecursion,
+        &format!("left recursion on rule {} without a seed", rule_name),
+    );
+    let seed = Seed::new(&status, &Err(failed));
+    seeds.borrow_mut().insert(key, seed);
+    let mut result = parse_rule_name_no_profile(status.clone(), rule_name);
+    let mut growing = false;
+    loop {
+        let mut growing_seeds = seeds.borrow_mut();
+        let seed = match growing_seeds.get_mut(&key) {
+            Some(seed) if seed.used || growing => seed,
+            _ => break,
+        };
+        match &result {
+            Err(e) if e.priority == ErrPriority::Critical || !growing => break,
+            _ if seed.grows(&result) => *seed = Seed::new(&status, &result),
+            _ => {
+                result = seed.restore(status.clone());
+                break;
+            }
+        }
+        drop(growing_seeds);
+        growing = true;
+        result = parse_rule_name_no_profile(status.clone(), rule_name);
+    }
+    seeds.borrow_mut().remove(&key);
+    result
+}
+
+//  packrat, a rule on a position is parsed once
+//  the result (and the warnings) are reused on backtracking
+fn parse_rule_name_memoized<'a>(
+    mut status: Status<'a>,
+    rule_name: &str,
+    memo: &Memo<'a>,
+) -> Result<'a> {
+    let rules = status.rules;
+    let name = match rules.exprs.get_key_value(rule_name) {
+        Some((name, _)) => name.as_str(),
+        None => return parse_rule_name_no_profile(status, rule_name),
+    };
+    let key = (name, status.pos.n);
+    if memo.borrow().contains_key(&key) {
+        return memo.borrow()[&key].restore(status);
+    }
+
+    //  the potential error registered by the rule is the one to reuse
+    let previous_error = status.potential_error.take();
+    let n_warnings = status.warnings.len();
+    let result = parse_rule_name_no_profile(status, rule_name);
+    let memoized = match &result {
+        Ok((st, node)) => Memoized::Matched {
+            pos: st.pos.clone(),
+            rest: st.it_parsing.as_str().len(),
+            node: node.clone(),
+            warnings: st.warnings[n_warnings..].to_vec(),
+            potential_error: st.potential_error.clone(),
+        },
+        Err(e) => Memoized::Failed(e.clone()),
+    };
+    memo.borrow_mut().insert(key, memoized);
+
+    result.map(|(mut st, node)| {
+        if st.potential_error.is_none() {
+            st.potential_error = previous_error;
+        }
+        (st, node)
+    })
+}
+
+fn parse_rule_name_no_profile<'a>(status: Status<'a>, rule_name: &str) -> Result<'a> {
     let status = if status.trace_rules {
         status.push_rule(&format!("r:{}", rule_name))
     } else {
         status
     };
 
-    let rules = &status.rules.0;
-    let expression = rules.get(rule_name).ok_or_else(|| {
+    let rules = &status.rules.exprs;
+    let (name, expression) = rules.get_key_value(rule_name).ok_or_else(|| {
         Error::from_status(
             &status,
+            Code::MissingRule,
             &format!("Missing rule: {}", rule_name),
             ErrPriority::Critical,
         )
     })?;
-    let (st, nodes) = parse_expr(status, &expression)?;
+    let start = status.it_parsing.as_str();
+    let n_leaf_kinds = status.n_leaf_kinds;
+    let open_event = status.n_tree_events;
+    let caller = status.rule_name;
+    let (mut st, nodes) = parse_expr(
+        Status {
+            rule_name: name,
+            ..status.push_tree_event(TreeEvent::Open(name.clone()))
+        },
+        &expression,
+    )?;
+    st.rule_name = caller;
+    let st = register_extract(st, rule_name, start);
+
+    if st.fail_fast {
+        Ok((st, ast::Node::Rule((String::new(), vec![]))))
+    } else if has_annotation(st.rules, rule_name, "raw") {
+        //  the source text replaces the children (and their kinds)
+        let matched = &start[..start.len() - st.it_parsing.as_str().len()];
+        let end = st.text2parse.len() - st.it_parsing.as_str().len();
+        let consumed = end - matched.len()..end;
+        let st = Status {
+            n_leaf_kinds,
+            n_tree_events: open_event + 1,
+            ..st
+        }
+        .push_leaf_kind(ast::kind::NodeKind::Raw)
+        .push_tree_event(TreeEvent::Token(
+            ast::kind::NodeKind::Raw,
+            matched.to_owned(),
+            consumed,
+        ))
+        .push_tree_event(TreeEvent::Close);
+        scratch::give(nodes);
+        let raw = nodes_from_node(&st, ast::Node::Val(matched.to_owned()));
+        Ok((
+            st,
+            ast::Node::Rule((rule_name.to_owned(), scratch::compact(raw))),
+        ))
+    } else if has_annotation(st.rules, rule_name, "passthrough")
+        && (is_chain(&nodes) || st.is_tree_chain(open_event))
+    {
+        let mut nodes = nodes;
+        let child = nodes.pop().unwrap_or(ast::Node::EOF);
+        scratch::give(nodes);
+        Ok((st.remove_tree_event(open_event), child))
+    } else {
+        Ok((
+            st.push_tree_event(TreeEvent::Close),
+            ast::Node::Rule((rule_name.to_owned(), scratch::compact(nodes))),
+        ))
+    }
+}
+
+//  a single child, and no value of its own
+fn is_chain(nodes: &[ast::Node]) -> bool {
+    matches!(nodes, [ast::Node::Rule(_)])
+}
+
+//  rules annotated with @passthrough are replaced by their only child
+//  rules annotated with @raw have the source text instead of children
+fn has_annotation(rules: &SetOfRules, rule_name: &str, key: &str) -> bool {
+    rules.rule_meta(rule_name).is_some_and(|meta| meta.has(key))
+}
 
-    // let elapsed = start.elapsed();
-    // println!(
-    //     "____ elapsed time parsing {} {}.{}",
-    //     rule_name,
-    //     elapsed.as_secs(),
-    //     elapsed.subsec_millis()
-    // );
-    Ok((st, ast::Node::Rule((rule_name.to_owned(), nodes))))
+//  if requested, keep the text matched by the rule or label
+fn register_extract<'a>(mut status: Status<'a>, name: &str, start: &'a str) -> Status<'a> {
+    if status.extract.contains(&name) {
+        let matched = &start[..start.len() - status.it_parsing.as_str().len()];
+        status
+            .extracted
+            .push((name.to_string(), matched.to_string()));
+    }
+    status
+}
+
+//  on fail_fast mode, no nodes will be added to the AST
+//  with a tree builder, the events replace the AST
+fn nodes_from_node(status: &Status, node: ast::Node) -> Vec<ast::Node> {
+    if status.fail_fast || status.tree_events.is_some() {
+        vec![]
+    } else {
+        let mut nodes = scratch::take();
+        nodes.push(node);
+        nodes
+    }
 }
 
 fn parse_atom_as_expr<'a>(status: Status<'a>, a: &'a Atom) -> ResultExpr<'a> {
     let (st, node) = atom::parse(status, a)?;
-    Ok((st, vec![node]))
+    let nodes = nodes_from_node(&st, node);
+    Ok((st, nodes))
 }
 
 fn parse_rule_name_as_expr<'a>(status: Status<'a>, rule_name: &str) -> ResultExpr<'a> {
     let (st, ast) = parse_rule_name(status, rule_name)?;
-    Ok((st, vec![ast]))
+    let nodes = nodes_from_node(&st, ast);
+    Ok((st, nodes))
 }
 
 fn parse_expr<'a>(status: Status<'a>, expression: &'a Expression) -> ResultExpr<'a> {
@@ -224,6 +1817,10 @@ fn parse_expr<'a>(status: Status<'a>, expression: &'a Expression) -> ResultExpr<
         Expression::Not(ref val) => parse_not(status, &val),
         Expression::Repeat(ref val) => parse_repeat(status, &val),
         Expression::RuleName(ref val) => parse_rule_name_as_expr(status, &val),
+        Expression::Labeled(ref label, ref val) => parse_labeled(status, label, &val),
+        Expression::Deprecated(ref message, ref val) => parse_deprecated(status, message, &val),
+        Expression::NotKeyword(ref keywords, ref val) => parse_not_keyword(status, keywords, val),
+        Expression::Keywords(ref keywords, ref val) => parse_keywords(status, keywords, val),
     }
 }
 
@@ -238,9 +1835,12 @@ fn parse_and<'a>(status: Status<'a>, multi_expr: &'a MultiExpr) -> ResultExpr<'a
             let result_parse = parse_expr(acc.0, &acc.1[0]);
             match result_parse {
                 Ok((status, vnodes)) => {
-                    TailCall::Call((status, &acc.1[1..], acc.2.iappend(vnodes)))
+                    TailCall::Call((status, &acc.1[1..], scratch::append(acc.2, vnodes)))
+                }
+                Err(err) => {
+                    scratch::give(acc.2);
+                    TailCall::Return(Err(err))
                 }
-                Err(err) => TailCall::Return(Err(err)),
             }
         }
     })
@@ -252,7 +1852,7 @@ fn parse_or<'a>(status: &Status<'a>, multi_expr: &'a MultiExpr) -> ResultExpr<'a
         Some(e1) => match (e1.priority > e2.priority, e1.pos.n > e2.pos.n) {
             (true, _) => Some(e1),
             (false, true) => Some(e1),
-            (false, false) => Some(e2),
+            (false, false) => Some(e2.merge_expected(&e1)),
         },
         None => Some(e2),
     };
@@ -263,15 +1863,24 @@ fn parse_or<'a>(status: &Status<'a>, multi_expr: &'a MultiExpr) -> ResultExpr<'a
         if acc.1.is_empty() {
             TailCall::Return(Err(match acc.2 {
                 Some(err) => err,
-                _ => Error::from_status_normal(
-                    &status,
-                    "LOGIC ERROR!!! checked all options in or with ¿NO? errors",
-                ),
+                _ => Error::from_status_normal(&status, Code::EmptyOr, "or without alternatives"),
             }))
         } else {
             let try_parse = parse_expr(acc.0.clone(), &acc.1[0]);
+            if let Some(profile) = &status.profile {
+                profile.borrow_mut().register_alternative(
+                    or_id(multi_expr),
+                    multi_expr.0.len(),
+                    multi_expr.0.len() - acc.1.len(),
+                    try_parse.is_ok(),
+                );
+            }
             match try_parse {
-                Ok(result) => TailCall::Return(Ok(result)),
+                Ok((st, nodes)) => {
+                    let alternative = multi_expr.0.len() - acc.1.len();
+                    let st = st.push_decision(or_id(multi_expr), alternative, &status.pos);
+                    TailCall::Return(Ok((st, nodes)))
+                }
                 Err(e) => {
                     if e.priority == ErrPriority::Critical {
                         TailCall::Return(Err(e))
@@ -284,10 +1893,105 @@ fn parse_or<'a>(status: &Status<'a>, multi_expr: &'a MultiExpr) -> ResultExpr<'a
     })
 }
 
+//-----------------------------------------------------------------------
+fn parse_labeled<'a>(
+    status: Status<'a>,
+    label: &str,
+    expression: &'a Expression,
+) -> ResultExpr<'a> {
+    let start = status.it_parsing.as_str();
+    let start_pos = status.pos.clone();
+    let status = status.push_tree_event(TreeEvent::Open(label.to_owned()));
+    let (mut st, nodes) = parse_expr(status, expression)?;
+    if st.capture {
+        let span = Span {
+            start: start_pos,
+            end: st.pos.clone(),
+        };
+        st.captured.push((label.to_owned(), span));
+    }
+    let st = register_extract(st, label, start).push_tree_event(TreeEvent::Close);
+    let nodes = nodes_from_node(
+        &st,
+        ast::Node::Rule((label.to_owned(), scratch::compact(nodes))),
+    );
+    Ok((st, nodes))
+}
+
+//-----------------------------------------------------------------------
+fn parse_deprecated<'a>(
+    status: Status<'a>,
+    message: &str,
+    expression: &'a Expression,
+) -> ResultExpr<'a> {
+    let start = status.pos.clone();
+    let idx = status.warnings.len();
+    let (mut st, nodes) = parse_expr(status, expression)?;
+    if !st.fail_fast {
+        //  keep the warnings sorted by starting position
+        let warning = Diagnostic {
+            pos: start,
+            end: st.pos.clone(),
+            descr: message.to_owned(),
+            code: Code::DeprecatedRule,
+        };
+        st.warnings.insert(idx, warning);
+    }
+    Ok((st, nodes))
+}
+
+//-----------------------------------------------------------------------
+fn parse_not_keyword<'a>(
+    status: Status<'a>,
+    keywords: &[String],
+    expression: &'a Expression,
+) -> ResultExpr<'a> {
+    let start = status.clone();
+    let (st, nodes) = parse_expr(status, expression)?;
+
+    let rest = start.it_parsing.as_str();
+    let matched = &rest[..rest.len() - st.it_parsing.as_str().len()];
+    let reserved = |keywords: &[String]| {
+        keywords
+            .binary_search_by(|k| k.as_str().cmp(matched))
+            .is_ok()
+    };
+    if reserved(keywords) || start.scoped_keywords.iter().any(|ks| reserved(ks)) {
+        Err(Error::from_status_normal(
+            &start,
+            Code::ReservedWord,
+            &format!("reserved word <{}>", matched),
+        ))
+    } else {
+        Ok((st, nodes))
+    }
+}
+
+//-----------------------------------------------------------------------
+fn parse_keywords<'a>(
+    mut status: Status<'a>,
+    keywords: &'a [String],
+    expression: &'a Expression,
+) -> ResultExpr<'a> {
+    status.scoped_keywords.push(keywords);
+    let (mut st, nodes) = parse_expr(status, expression)?;
+    st.scoped_keywords.pop();
+    Ok((st, nodes))
+}
+
 //-----------------------------------------------------------------------
 fn parse_not<'a>(status: Status<'a>, expression: &'a Expression) -> ResultExpr<'a> {
     match parse_expr(status.clone(), expression) {
-        Ok(_) => Err(Error::from_status_normal(&status, "not")),
+        Ok((st, nodes)) => {
+            scratch::give(nodes);
+            let rest = status.it_parsing.as_str();
+            let matched = &rest[..rest.len() - st.it_parsing.as_str().len()];
+            Err(Error::from_status_normal(
+                &status,
+                Code::NegationMatched,
+                &format!("unexpected <{}>", matched),
+            ))
+        }
         Err(_) => Ok((status, vec![])),
     }
 }
@@ -299,6 +2003,10 @@ fn parse_repeat<'a>(status: Status<'a>, rep_info: &'a RepInfo) -> ResultExpr<'a>
         Some(ref m) => counter + 1 == m.0,
         None => false,
     };
+    //  nothing to repeat
+    if rep_info.max.as_ref().map(|m| m.0) == Some(0) {
+        return Ok((status, vec![]));
+    }
 
     let init_tc: (_, _, Vec<ast::Node>) = (status, 0, vec![]);
     Ok(tail_call(init_tc, |acc| {
@@ -306,21 +2014,42 @@ fn parse_repeat<'a>(status: Status<'a>, rep_info: &'a RepInfo) -> ResultExpr<'a>
         match (try_parse, big_min_bound(acc.1), touch_max_bound(acc.1)) {
             (Err(e), true, _) => {
                 if e.priority == ErrPriority::Critical {
+                    scratch::give(acc.2);
                     TailCall::Return(Err(e))
                 } else {
                     TailCall::Return(Ok((acc.0.set_potential_error(e), acc.2)))
                 }
             }
-            (Err(e), false, _) => TailCall::Return(Err(e)),
+            (Err(e), false, _) => {
+                scratch::give(acc.2);
+                TailCall::Return(Err(e))
+            }
             //     Err(Error::from_status(
             //     &acc.0,
             //     &format!("inside repeat {:#?}", e),
             // ))),
+            //  an empty match would repeat forever (same result every time)
+            (Ok((status, vnodes)), _, _) if status.pos.n == acc.0.pos.n => {
+                TailCall::Return(Ok((status, scratch::append(acc.2, vnodes))))
+            }
+            (Ok((status, vnodes)), _, _) if acc.1 >= status.max_repetitions => {
+                scratch::give(acc.2);
+                scratch::give(vnodes);
+                TailCall::Return(Err(Error::from_status(
+                    &status,
+                    Code::TooManyRepetitions,
+                    &format!(
+                        "too many repetitions on rule {} (limit {})",
+                        status.rule_name, status.max_repetitions
+                    ),
+                    ErrPriority::Critical,
+                )))
+            }
             (Ok((status, vnodes)), _, false) => {
-                TailCall::Call((status, acc.1 + 1, acc.2.iappend(vnodes)))
+                TailCall::Call((status, acc.1 + 1, scratch::append(acc.2, vnodes)))
             }
             (Ok((status, vnodes)), _, true) => {
-                TailCall::Return(Ok((status, acc.2.iappend(vnodes))))
+                TailCall::Return(Ok((status, scratch::append(acc.2, vnodes))))
             }
         }
     })?)
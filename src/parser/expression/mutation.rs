@@ -0,0 +1,147 @@
+//! Near-miss inputs, to test the errors of a grammar
+//!
+//! Valid inputs are generated from ```main``` (as ```equivalent```
+//! does), and they are mutated on their tokens (the text of a literal,
+//! a match, a dot...). A token deleted, two adjacent tokens swapped, or
+//! a char of a literal changed. The mutations still accepted are
+//! discarded, the rest are near misses, inputs almost valid
+//!
+//! The text before the mutation is valid. Then, a good error is on the
+//! mutation or after it. ```testing::check_near_misses``` checks it.
+//! The inputs are generated from a fixed seed, the result is always the
+//! same
+
+use super::equivalence::{Generator, Token};
+use super::{Expression, SetOfRules};
+use std::collections::HashSet;
+
+/// Change applied to a valid input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+    /// A token removed
+    DeleteToken,
+    /// A token swapped with the next one
+    SwapTokens,
+    /// A char of a literal replaced
+    CorruptLiteral,
+}
+
+/// An input rejected, generated mutating a valid one
+#[derive(Debug, Clone, PartialEq)]
+pub struct NearMiss {
+    /// The rejected input
+    pub input: String,
+    /// The valid input before the mutation
+    pub valid: String,
+    /// The mutation applied
+    pub mutation: Mutation,
+    /// Char position of the mutation (the text before is the same)
+    pub at: usize,
+}
+
+/// Inputs rejected by the grammar, close to valid ones
+///
+/// ```budget``` is the max number of valid inputs to generate, and to
+/// mutate. Look at the module documentation
+///
+/// ```
+/// use dynparser::parser::expression::{near_misses, Mutation};
+/// use dynparser::rules_from_peg;
+///
+/// let rules = rules_from_peg(
+///     r#"
+/// main    =   'let'  ' '  [a-z]  ' = '  [0-9]  ';'
+///     "#,
+/// ).unwrap();
+///
+/// let near_misses = near_misses(&rules, 100);
+/// assert!(near_misses.iter().any(|m| m.mutation == Mutation::DeleteToken));
+/// assert!(near_misses.iter().any(|m| m.mutation == Mutation::CorruptLiteral));
+/// for m in &near_misses {
+///     assert!(dynparser::parse(&m.input, &rules).is_err());
+///     assert_eq!(m.input[..m.at], m.valid[..m.at]);
+/// }
+/// ```
+pub fn near_misses(rules: &SetOfRules, budget: usize) -> Vec<NearMiss> {
+    let main = Expression::RuleName("main".to_string());
+    let mut generator = Generator::new(rules);
+    let mut tried = HashSet::new();
+    let mut found = vec![];
+    for i in 0..budget {
+        let mut valid = String::new();
+        generator.tokens.clear();
+        generator.generate(&main, 0, &mut valid);
+        //  not all the generated inputs are valid (predicates are skipped)
+        if generator.tokens.is_empty() || crate::parse(&valid, rules).is_err() {
+            continue;
+        }
+
+        let tokens = std::mem::take(&mut generator.tokens);
+        let mutation = MUTATIONS[i % MUTATIONS.len()];
+        if let Some((input, start)) = mutate(&mut generator, &valid, &tokens, mutation) {
+            if tried.insert(input.clone()) && crate::parse(&input, rules).is_err() {
+                found.push(NearMiss {
+                    at: valid[..start].chars().count(),
+                    input,
+                    valid,
+                    mutation,
+                });
+            }
+        }
+    }
+    found
+}
+
+//-----------------------------------------------------------------------
+//  I N T E R N A L
+
+const MUTATIONS: [Mutation; 3] = [
+    Mutation::DeleteToken,
+    Mutation::SwapTokens,
+    Mutation::CorruptLiteral,
+];
+
+//  the mutated input, and the byte where it starts to be different
+fn mutate(
+    generator: &mut Generator,
+    valid: &str,
+    tokens: &[Token],
+    mutation: Mutation,
+) -> Option<(String, usize)> {
+    let i = generator.below(tokens.len());
+    let token = tokens.get(i)?;
+    match mutation {
+        Mutation::DeleteToken => Some((
+            format!("{}{}", &valid[..token.start], &valid[token.end..]),
+            token.start,
+        )),
+        Mutation::SwapTokens => {
+            let next = tokens.get(i + 1).filter(|next| next.start == token.end)?;
+            let (a, b) = (&valid[token.start..token.end], &valid[next.start..next.end]);
+            if a == b {
+                None
+            } else {
+                Some((
+                    format!("{}{}{}{}", &valid[..token.start], b, a, &valid[next.end..]),
+                    token.start,
+                ))
+            }
+        }
+        Mutation::CorruptLiteral => {
+            let literals: Vec<&Token> = tokens.iter().filter(|t| t.literal).collect();
+            let token = literals.get(generator.below(literals.len()))?;
+            let chars: Vec<(usize, char)> = valid[token.start..token.end].char_indices().collect();
+            let (offset, ch) = chars[generator.below(chars.len())];
+            let new_ch = generator.any_char();
+            let at = token.start + offset;
+            if new_ch == ch {
+                None
+            } else {
+                Some((
+                    format!("{}{}{}", &valid[..at], new_ch, &valid[at + ch.len_utf8()..]),
+                    at,
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Add rules checking their references as they are added
+//!
+//! A reference to a rule already on the set (or to itself) is valid.
+//! References to rules added later have to be declared with ```defer```,
+//! and they are checked on ```compile```. Any other reference is an
+//! error on ```add```, close to the typo
+//!
+//! ```
+//! #[macro_use]  extern crate dynparser;
+//! use dynparser::parse;
+//! use dynparser::parser::expression::UndefinedRule;
+//!
+//! fn main() {
+//!     let rules = rules!{
+//!        "digit"  =>  ematch!(chlist "", from '0', to '9')
+//!     }
+//!     .strict()
+//!     .defer("expr")
+//!     .add("main", ref_rule!("expr")).unwrap()
+//!     .add("expr", rep!(ref_rule!("digit"), 1)).unwrap()
+//!     .compile()
+//!     .unwrap();
+//!     assert!(parse("123", &rules).is_ok());
+//!
+//!     //  the typo is found on add
+//!     let err = rules!{}.strict().add("main", ref_rule!("exprr")).unwrap_err();
+//!     assert_eq!(err.rule, "exprr");
+//!
+//!     //  a deferred reference not added is found on compile
+//!     let err = rules!{}
+//!         .strict()
+//!         .defer("expr")
+//!         .add("main", ref_rule!("expr")).unwrap()
+//!         .compile()
+//!         .unwrap_err();
+//!     assert_eq!(
+//!         err,
+//!         vec![UndefinedRule { rule: "expr".to_string(), from: "main".to_string() }]
+//!     );
+//! }
+//! ```
+
+use super::{rule_references, Expression, SetOfRules, UndefinedRule};
+use std::collections::HashSet;
+use std::result;
+
+/// Set of rules checking the references on ```add```
+///
+/// Created with ```SetOfRules::strict```
+#[derive(Debug)]
+pub struct StrictRules {
+    rules: SetOfRules,
+    //  forward references, checked on compile
+    deferred: HashSet<String>,
+}
+
+impl StrictRules {
+    pub(super) fn new(rules: SetOfRules) -> Self {
+        StrictRules {
+            rules,
+            deferred: HashSet::new(),
+        }
+    }
+
+    /// Allow references to a rule that will be added later
+    pub fn defer(mut self, name: &str) -> Self {
+        self.deferred.insert(name.to_owned());
+        self
+    }
+
+    /// Add a rule (look at ```SetOfRules::add```). It fails if it
+    /// references a rule not added or deferred
+    pub fn add(mut self, name: &str, expr: Expression) -> result::Result<Self, UndefinedRule> {
+        let undefined = rule_references(&expr).into_iter().find(|r| {
+            *r != name && !self.rules.exprs.contains_key(*r) && !self.deferred.contains(*r)
+        });
+        match undefined {
+            Some(rule) => Err(UndefinedRule {
+                rule: rule.to_owned(),
+                from: name.to_owned(),
+            }),
+            None => {
+                self.rules.add_mut(name, expr);
+                Ok(self)
+            }
+        }
+    }
+
+    /// The rules, once all the references are defined (look at
+    /// ```SetOfRules::check_refs```)
+    pub fn compile(self) -> result::Result<SetOfRules, Vec<UndefinedRule>> {
+        self.rules.check_refs()?;
+        Ok(self.rules)
+    }
+}
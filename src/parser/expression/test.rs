@@ -218,3 +218,272 @@ fn test_parse_repeat_fail() {
         assert!(parse_expr(status_init, &expr).is_err());
     }
 }
+
+#[test]
+fn test_rules_access_policy() {
+    use super::{AccessError, RuleAccess};
+
+    let host = || {
+        rules! {
+            "main"  => or!(ref_rule!("a"), ref_rule!("b")),
+            "a"     => lit!("a"),
+            "b"     => ref_rule!("_b"),
+            "_b"    => lit!("b")
+        }
+        .set_access("main", RuleAccess::Sealed)
+        .set_access("_b", RuleAccess::Internal)
+    };
+
+    //  extensible rules can be replaced, sealed ones referenced
+    let rules = host()
+        .merge_plugin(rules! {"a" => lit!("A"), "c" => ref_rule!("main")})
+        .unwrap();
+    assert!(crate::parse("A", &rules).is_ok());
+    assert!(crate::parse("a", &rules).is_err());
+
+    assert_eq!(
+        host().merge_plugin(rules! {"_b" => lit!("x")}).unwrap_err(),
+        AccessError::Internal("_b".to_string())
+    );
+    assert_eq!(
+        host()
+            .merge_plugin(rules! {"c" => and!(lit!("x"), ref_rule!("_b"))})
+            .unwrap_err(),
+        AccessError::InternalReference("_b".to_string(), "c".to_string())
+    );
+    assert_eq!(
+        host().extend_rule("main", lit!("c")).unwrap_err(),
+        AccessError::Sealed("main".to_string())
+    );
+    assert_eq!(
+        host().extend_rule("d", lit!("d")).unwrap_err(),
+        AccessError::MissingRule("d".to_string())
+    );
+
+    //  the plugin can't change the policy
+    let rules = host()
+        .merge_plugin(rules! {"d" => lit!("d")}.set_access("_b", RuleAccess::Extensible))
+        .unwrap();
+    assert_eq!(rules.access("_b"), RuleAccess::Internal);
+
+    let rules = host().extend_rule("b", lit!("B")).unwrap();
+    assert!(crate::parse("b", &rules).is_ok());
+    assert!(crate::parse("B", &rules).is_ok());
+}
+
+#[test]
+fn test_rules_fingerprint() {
+    let rules = rules! {
+        "main"  =>  and!(lit!("a"), ref_rule!("b")),
+        "b"     =>  ematch!(chlist "", from 'a', to 'z')
+    };
+    let same = rules! {
+        "b"     =>  ematch!(chlist "", from 'a', to 'z'),
+        "main"  =>  and!(lit!("a"), ref_rule!("b"))
+    };
+
+    //  it can't change between versions
+    assert_eq!(rules.fingerprint(), 5_335_783_249_148_397_696);
+    assert_eq!(rules.fingerprint(), same.fingerprint());
+    assert_ne!(rules.fingerprint(), same.add("b", dot!()).fingerprint());
+}
+
+#[test]
+fn test_rep_bounds() {
+    use super::RepBounds;
+
+    assert_eq!(3.bounds(), (3, None));
+    assert_eq!((2..=5).bounds(), (2, Some(5)));
+    assert_eq!((2..6).bounds(), (2, Some(5)));
+    assert_eq!((2..).bounds(), (2, None));
+    assert_eq!((..=4).bounds(), (0, Some(4)));
+    assert_eq!((0..1).bounds(), (0, Some(0)));
+
+    //  a max of 0 is no repetition
+    for rules in &[
+        rules! {"main" => and!(rep!(lit!("a"), 0..1), lit!("b"))},
+        rules! {"main" => and!(rep!(lit!("a"), ..=0), lit!("b"))},
+    ] {
+        assert!(crate::parse("b", rules).is_ok());
+        assert!(crate::parse("ab", rules).is_err());
+        assert!(crate::parse("aaab", rules).is_err());
+    }
+
+    let rules = rules! {"main" => rep!(lit!("a"), 2..3)};
+    assert!(crate::parse("aa", &rules).is_ok());
+    assert!(crate::parse("aaa", &rules).is_err());
+    let rules = rules! {"main" => rep!(lit!("a"), 2..=2)};
+    assert!(crate::parse("aa", &rules).is_ok());
+}
+
+#[test]
+#[should_panic(expected = "empty repetition range 2..2")]
+fn test_rep_empty_range() {
+    use super::RepBounds;
+
+    let _ = (2..2).bounds();
+}
+
+#[test]
+#[should_panic(expected = "empty repetition range 3..=2")]
+fn test_rep_empty_inclusive_range() {
+    let (min, max) = (3, 2);
+    let _ = rep!(lit!("a"), min..=max);
+}
+
+#[test]
+fn test_max_repetitions() {
+    use crate::parser::ErrPriority;
+
+    let rules = rules! {
+        "main"  =>  or!(ref_rule!("as"), rep!(dot!(), 0)),
+        "as"    =>  and!(lit!("b"), rep!(lit!("a"), 0))
+    };
+    let parse = |text, max| {
+        let status = Status::init(text, &rules).set_max_repetitions(max);
+        super::parse(status)
+    };
+
+    assert!(parse("baaa", 3).is_ok());
+
+    //  critical, the alternative is not tried
+    let err = parse("baaaa", 3).unwrap_err();
+    assert_eq!(err.descr, "too many repetitions on rule as (limit 3)");
+    assert_eq!(err.priority, ErrPriority::Critical);
+    assert_eq!(err.pos.n, 5);
+
+    let err = parse("xxxx", 3).unwrap_err();
+    assert_eq!(err.descr, "too many repetitions on rule main (limit 3)");
+}
+
+#[test]
+fn test_parse_expression_at() {
+    use super::{parse_expression_at, Match};
+
+    let rules = rules! {};
+    let input = "ñaab";
+
+    let (m, _) = parse_expression_at(input, 2, &rules, &rep!(lit!("a"), 1)).unwrap();
+    assert_eq!(m, Match { start: 2, end: 4 });
+    assert_eq!(m.len(), 2);
+    assert_eq!(m.text(input), "aa");
+
+    //  predicates don't consume
+    let (m, nodes) = parse_expression_at(input, 4, &rules, &not!(lit!("a"))).unwrap();
+    assert!(m.is_empty());
+    assert!(nodes.is_empty());
+
+    let err = parse_expression_at(input, 1, &rules, &lit!("a")).unwrap_err();
+    assert_eq!(err.descr, "offset 1 is not a char boundary on input");
+}
+
+#[test]
+fn test_equivalent_refactors() {
+    use super::{equivalent, Equivalence};
+
+    let rules = crate::rules_from_peg(
+        r#"
+        before  =   'let' ' '+ [a-z]+ ' '* '=' ' '* [0-9]+
+                /   'var' ' '+ [a-z]+ ' '* '=' ' '* [0-9]+
+        after   =   ('let' / 'var')  ' '+  assign
+        assign  =   [a-z]+ ' '* '=' ' '* [0-9]+
+        short   =   'a' / 'ab'
+        long    =   'ab' / 'a'
+        "#,
+    )
+    .unwrap();
+    let rule = |name: &str| rules.exprs[name].clone();
+
+    let result = equivalent(&rule("before"), &rule("after"), &rules, 1000);
+    assert!(matches!(result, Equivalence::Tested(n) if n > 100));
+
+    //  on peg, the order of the alternatives matters
+    match equivalent(&rule("short"), &rule("long"), &rules, 1000) {
+        Equivalence::Different { input, a, b } => {
+            assert!(input.starts_with("ab"));
+            assert_eq!((a, b), (Some(1), Some(2)));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    //  labels, repeated alternatives and nested sequences
+    let a = or!(
+        and!(lit!("a"), and!(lit!("b"), lit!(""))),
+        Expression::Labeled("C".to_string(), Box::new(lit!("c"))),
+        lit!("ab")
+    );
+    let b = or!(and!(lit!("ab"), lit!("")), or!(lit!("c"), lit!("c")));
+    assert_eq!(equivalent(&a, &b, &rules, 0), Equivalence::Structural);
+    assert_eq!(
+        equivalent(&lit!("x"), &lit!("y"), &rules, 0),
+        Equivalence::Tested(0)
+    );
+}
+
+#[test]
+fn test_check_refs() {
+    use super::UndefinedRule;
+    let undefined = |rule: &str, from: &str| UndefinedRule {
+        rule: rule.to_string(),
+        from: from.to_string(),
+    };
+
+    let rules = rules! {
+        "main"  =>  and!(ref_rule!("b"), ref_rule!("a"), ref_rule!("a")),
+        "a"     =>  or!(ref_rule!("x"), ref_rule!("main")),
+        "b"     =>  not!(ref_rule!("y"))
+    };
+    assert_eq!(
+        rules.check_refs(),
+        Err(vec![undefined("x", "a"), undefined("y", "b")])
+    );
+
+    //  recursion and references to present rules are valid on add
+    let strict = rules! { "a" => lit!("a") }
+        .strict()
+        .add(
+            "list",
+            or!(and!(ref_rule!("a"), ref_rule!("list")), lit!("")),
+        )
+        .unwrap();
+    assert_eq!(
+        strict.add("main", ref_rule!("b")).unwrap_err(),
+        undefined("b", "main")
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_rules() {
+    use super::SetOfRules;
+    use crate::{parse, rules_from_peg};
+
+    let rules = rules_from_peg(
+        r#"
+        %syntax 2
+
+        main    =   (word / num / sp)*  end
+
+        @doc("a word")
+        word    =   [a-z]+  /  'x'i
+        num     =   [0-9]+  !'.'
+        sp      =   [^a-z0-9.]
+        end     =   '.'
+        "#,
+    )
+    .unwrap();
+
+    let json = serde_json::to_string(&rules).unwrap();
+    let loaded: SetOfRules = serde_json::from_str(&json).unwrap();
+
+    let input = "abc 12 X.";
+    assert_eq!(parse(input, &loaded).unwrap(), parse(input, &rules).unwrap());
+    assert_eq!(loaded.rule_meta("word"), rules.rule_meta("word"));
+    assert!(parse("12.3.", &loaded).is_err());
+
+    //  custom matchers can't be serialized
+    let rules = rules! {
+        "main" => custom!("any", |rest: &str, _| rest.chars().next().map(char::len_utf8))
+    };
+    assert!(serde_json::to_string(&rules).is_err());
+}
@@ -2,6 +2,13 @@
 //! Tools to execute parser of a expression
 
 use crate::ast;
+use crate::ast::builder::TreeEvent;
+use crate::codes::Code;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
 use std::result;
 
 //-----------------------------------------------------------------------
@@ -14,9 +21,12 @@ use std::result;
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
 
+pub mod analysis;
 /// Support for minimum expressions elements
 pub mod atom;
 pub mod expression;
+pub mod profile;
+mod scratch;
 
 use std::str::Chars;
 
@@ -28,6 +38,23 @@ use std::str::Chars;
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
 
+/// Default limit of iterations on a repetition (```*```, ```+```, ```{n,}```...)
+///
+/// Exceeding it is a critical error. It protects against runaway
+/// inputs (i.e. ```"x"*``` on a multi GB text)
+pub const MAX_REPETITIONS: usize = 100_000_000;
+
+/// Limit of stack (bytes) used by nested rules, when the stack bounds
+/// of the thread are unknown (look at ```default_max_stack```)
+///
+/// It fits on the smallest default stacks, threads created by ```std```
+/// (2 MB) and the main thread on Windows (1 MB)
+pub const MAX_STACK: usize = if cfg!(windows) {
+    768 * 1024
+} else {
+    1536 * 1024
+};
+
 /// Information about the possition on parsing
 #[derive(PartialEq, Clone, Debug)]
 pub struct Possition {
@@ -42,7 +69,7 @@ pub struct Possition {
 }
 
 impl Possition {
-    fn init() -> Self {
+    pub(crate) fn init() -> Self {
         Self {
             n: 0,
             row: 0,
@@ -50,6 +77,79 @@ impl Possition {
             start_line: 0,
         }
     }
+
+    //  update row and col for every char on matched text
+    //  multi char matches have to call it, to keep new lines on count
+    pub(crate) fn advance(&mut self, matched: &str, newlines: Newlines) {
+        for ch in matched.chars() {
+            self.advance_char(ch, newlines);
+        }
+    }
+
+    pub(crate) fn advance_char(&mut self, ch: char, newlines: Newlines) {
+        self.n += 1;
+        match ch {
+            '\r' => {
+                self.col = 0;
+            }
+            ch if newlines.is_newline(ch) => {
+                self.col = 0;
+                self.row += 1;
+                self.start_line = self.n;
+            }
+            _ => {
+                self.col += 1;
+            }
+        }
+    }
+}
+
+/// Chars ending a line, for row and col and for ```eol```
+///
+/// ```\n``` is always a new line (and ```\r``` goes to col 0). The
+/// other Unicode line terminators are optional (off by default)
+///
+/// ```
+/// use dynparser::parser::Newlines;
+///
+/// let newlines = Newlines {
+///     ls: true,
+///     ..Newlines::default()
+/// };
+/// assert!(newlines.is_newline('\u{2028}'));
+/// assert!(!newlines.is_newline('\u{2029}'));
+/// assert!(Newlines::unicode().is_newline('\u{85}'));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Newlines {
+    /// U+0085 NEXT LINE (NEL)
+    pub nel: bool,
+    /// U+2028 LINE SEPARATOR (LS)
+    pub ls: bool,
+    /// U+2029 PARAGRAPH SEPARATOR (PS)
+    pub ps: bool,
+}
+
+impl Newlines {
+    /// All the Unicode line terminators
+    pub fn unicode() -> Self {
+        Newlines {
+            nel: true,
+            ls: true,
+            ps: true,
+        }
+    }
+
+    /// The char ends a line
+    pub fn is_newline(self, ch: char) -> bool {
+        match ch {
+            '\n' => true,
+            '\u{85}' => self.nel,
+            '\u{2028}' => self.ls,
+            '\u{2029}' => self.ps,
+            _ => false,
+        }
+    }
 }
 
 /// Error priority
@@ -68,6 +168,14 @@ pub struct Error {
     pub pos: Possition,
     /// Error description parsing
     pub descr: String,
+    /// Stable code of the error (look at ```codes```)
+    pub code: Code,
+    /// Tokens expected on ```pos``` (literals, matches, ```EOF```...)
+    ///
+    /// The alternatives failing on the same position add their tokens.
+    /// With more than one, the description will be
+    /// ```expected one of: <a>, <b>```
+    pub expected: Vec<String>,
     /// Line content before where error was produced
     pub line_before: String,
     /// Line content after where error was produced
@@ -79,6 +187,235 @@ pub struct Error {
     pub parsing_rules: Vec<String>,
     /// error priority
     pub priority: ErrPriority,
+    /// Chars ending a line on the parsed input (to split it on ```render```)
+    pub newlines: Newlines,
+}
+
+/// Non fatal message produced while parsing
+///
+/// i.e. by a ```warning("...")``` production
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Possition where it was produced
+    pub pos: Possition,
+    /// End of the text related with the diagnostic
+    /// Same as ```pos``` for zero width productions
+    pub end: Possition,
+    /// Diagnostic description
+    pub descr: String,
+    /// Stable code of the diagnostic (look at ```codes```)
+    pub code: Code,
+}
+
+/// Region of the input, from ```start``` (included) to ```end``` (not
+/// included)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    /// First char of the region
+    pub start: Possition,
+    /// Position after the last char of the region
+    pub end: Possition,
+}
+
+impl Span {
+    /// Text of the region on the parsed input
+    ///
+    /// The input has to be the parsed one. It walks the chars till
+    /// the end of the region
+    pub fn text<'a>(&self, input: &'a str) -> &'a str {
+        let offset = |n: usize| input.char_indices().nth(n).map_or(input.len(), |(i, _)| i);
+        &input[offset(self.start.n)..offset(self.end.n)]
+    }
+}
+
+/// Alternative matched by an ```or``` on the accepted parsing path
+///
+/// Created with ```parse_with_decisions```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// Rule containing the ```or```
+    pub rule: String,
+    /// Position of the ```or``` on the rule (as on ```OrStats```)
+    pub index: usize,
+    /// Alternative matched (starting on 0)
+    pub alternative: usize,
+    /// Number of alternatives of the ```or```
+    pub alternatives: usize,
+    /// Region matched by the alternative
+    pub span: Span,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}..{}:{}  {} (or {}): alternative {} of {}",
+            self.span.start.row,
+            self.span.start.col,
+            self.span.end.row,
+            self.span.end.col,
+            self.rule,
+            self.index,
+            self.alternative,
+            self.alternatives
+        )
+    }
+}
+
+/// Information from the host application, available while parsing
+///
+/// Custom matchers receive it. i.e. a symbol table or feature flags
+#[derive(Clone, Copy, Default)]
+pub struct ParseContext<'u> {
+    user_data: Option<&'u dyn Any>,
+}
+
+impl<'u> ParseContext<'u> {
+    /// Context with user data
+    pub fn new(user_data: &'u dyn Any) -> Self {
+        ParseContext {
+            user_data: Some(user_data),
+        }
+    }
+
+    /// Get the user data if it's of type ```T```
+    pub fn user_data<T: Any>(&self) -> Option<&'u T> {
+        self.user_data.and_then(|data| data.downcast_ref::<T>())
+    }
+}
+
+impl<'u> fmt::Debug for ParseContext<'u> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ParseContext(user_data: {})", self.user_data.is_some())
+    }
+}
+
+//  (or id, alternative, span) registered while parsing
+pub(crate) type RawDecision = (usize, usize, Span);
+
+//  result of a rule on a position, to parse it once (packrat)
+//  the node is cloned on every reuse
+//  the shared logs (captures, extracted values, tree events...) are not
+//  kept, as they are on Seed. can_memoize is false with any of them
+#[derive(Debug)]
+pub(crate) enum Memoized {
+    Matched {
+        pos: Possition,
+        //  bytes not consumed
+        rest: usize,
+        node: ast::Node,
+        warnings: Vec<Diagnostic>,
+        potential_error: Option<Box<Error>>,
+    },
+    Failed(Error),
+}
+
+//  by (rule name, char position)
+pub(crate) type Memo<'a> = Rc<RefCell<HashMap<(&'a str, usize), Memoized>>>;
+
+impl Memoized {
+    //  the status after the rule, as if it was parsed again
+    pub(crate) fn restore<'a>(&self, mut status: Status<'a>) -> Result<'a> {
+        match self {
+            Memoized::Failed(e) => Err(e.clone()),
+            Memoized::Matched {
+                pos,
+                rest,
+                node,
+                warnings,
+                potential_error,
+            } => {
+                let text = status.text2parse;
+                status.it_parsing = text[text.len() - rest..].chars();
+                status.pos = pos.clone();
+                status.warnings.extend(warnings.iter().cloned());
+                if potential_error.is_some() {
+                    status.potential_error = potential_error.clone();
+                }
+                Ok((status, node.clone()))
+            }
+        }
+    }
+}
+
+//  result of a left recursive rule on a position, while it's growing
+//  (look at ```SetOfRules::left_recursion```). The shared logs written
+//  by the rule are kept, to be written again when the seed is reused
+#[derive(Debug)]
+pub(crate) struct Seed<'a> {
+    result: Result<'a>,
+    leaf_kinds: Vec<ast::kind::NodeKind>,
+    tree_events: Vec<TreeEvent>,
+    decisions: Vec<RawDecision>,
+    //  the rule reached its own seed, it's left recursive on the position
+    pub(crate) used: bool,
+}
+
+//  by (rule name, char position)
+pub(crate) type Seeds<'a> = Rc<RefCell<HashMap<(&'a str, usize), Seed<'a>>>>;
+
+impl<'a> Seed<'a> {
+    //  the result of the rule parsed from start
+    pub(crate) fn new(start: &Status<'a>, result: &Result<'a>) -> Self {
+        fn written<T: Clone>(log: &Option<Rc<RefCell<Vec<T>>>>, from: usize, to: usize) -> Vec<T> {
+            log.as_ref().map_or(vec![], |log| {
+                log.borrow().get(from..to).map_or(vec![], |w| w.to_vec())
+            })
+        }
+        let (leaf_kinds, tree_events, decisions) = match result {
+            Ok((st, _)) => (
+                written(&st.leaf_kinds, start.n_leaf_kinds, st.n_leaf_kinds),
+                written(&st.tree_events, start.n_tree_events, st.n_tree_events),
+                written(&st.decisions, start.n_decisions, st.n_decisions),
+            ),
+            Err(_) => (vec![], vec![], vec![]),
+        };
+        Seed {
+            result: result.clone(),
+            leaf_kinds,
+            tree_events,
+            decisions,
+            used: false,
+        }
+    }
+
+    //  a longer match than the seed
+    pub(crate) fn grows(&self, result: &Result<'a>) -> bool {
+        match (&self.result, result) {
+            (Ok((seed, _)), Ok((st, _))) => st.pos.n > seed.pos.n,
+            (Err(_), Ok(_)) => true,
+            (_, Err(_)) => false,
+        }
+    }
+
+    //  the status after the rule, as if it was parsed again
+    pub(crate) fn restore(&self, status: Status<'a>) -> Result<'a> {
+        let (seed, node) = match &self.result {
+            Ok(matched) => matched,
+            Err(e) => return Err(e.clone()),
+        };
+        let mut st = Status {
+            walking_rules: status.walking_rules,
+            rule_name: status.rule_name,
+            n_leaf_kinds: status.n_leaf_kinds,
+            n_tree_events: status.n_tree_events,
+            n_decisions: status.n_decisions,
+            ..seed.clone()
+        };
+        for kind in &self.leaf_kinds {
+            st = st.push_leaf_kind(kind.clone());
+        }
+        for event in &self.tree_events {
+            st = st.push_tree_event(event.clone());
+        }
+        if let Some(decisions) = &st.decisions {
+            let mut decisions = decisions.borrow_mut();
+            decisions.truncate(st.n_decisions);
+            decisions.extend(self.decisions.iter().cloned());
+            st.n_decisions += self.decisions.len();
+        }
+        Ok((st, node.clone()))
+    }
 }
 
 //-----------------------------------------------------------------------
@@ -87,18 +424,86 @@ pub(crate) struct Status<'a> {
     pub(crate) text2parse: &'a str,
     pub(crate) it_parsing: Chars<'a>,
     pub(crate) pos: Possition,
+    /// Char position where the grammar input starts (after a skipped
+    /// BOM or shebang line). Positions are on the full text
+    pub(crate) input_start: usize,
     pub(crate) rules: &'a expression::SetOfRules,
     //  main            =   ("a")*
     //  if you try to parse "abb" i.e.
     //  the error will not be processed full input
     //  It's true, but it could be more useful to know where
     //  it fail trying to repeat
-    pub(crate) potential_error: Option<Error>,
+    //  boxed, the status is on every frame of the nested rules
+    pub(crate) potential_error: Option<Box<Error>>,
 
     /// If true, it will fill walking rules
     /// too expensive. For use just to debug errors
     pub(crate) trace_rules: bool,
     pub(crate) walking_rules: Vec<String>,
+
+    /// If true, it will not build the AST nor the error context
+    /// Used when we just want to know if the input is valid
+    pub(crate) fail_fast: bool,
+
+    /// Warnings on current parsing path
+    /// They will be discarded with the status on backtracking
+    pub(crate) warnings: Vec<Diagnostic>,
+
+    pub(crate) context: ParseContext<'a>,
+
+    /// If present, time and counters per rule will be registered
+    /// It's shared, to keep the info on backtracking
+    pub(crate) profile: Option<Rc<RefCell<profile::Profile>>>,
+
+    /// Rules and labels to register the matched text
+    pub(crate) extract: &'a [&'a str],
+    /// Registered (name, text) on current parsing path
+    pub(crate) extracted: Vec<(String, String)>,
+
+    /// If true, the span of every label will be registered
+    pub(crate) capture: bool,
+    /// Registered (label, span) on current parsing path
+    pub(crate) captured: Vec<(String, Span)>,
+
+    /// Keywords reserved on current rule subtree (```@keywords```)
+    pub(crate) scoped_keywords: Vec<&'a [String]>,
+
+    /// Rule being parsed, to report errors
+    pub(crate) rule_name: &'a str,
+    /// Limit of iterations on a repetition
+    pub(crate) max_repetitions: usize,
+    /// Stack position when parsing started, and limit of stack used
+    pub(crate) stack_start: usize,
+    pub(crate) max_stack: usize,
+    /// Chars counted as new lines
+    pub(crate) newlines: Newlines,
+    /// If present, rules results are reused (packrat). It's shared, to
+    /// keep the results on backtracking
+    pub(crate) memo: Option<Memo<'a>>,
+    /// Left recursive rules growing, if they are allowed
+    pub(crate) seeds: Option<Seeds<'a>>,
+
+    /// If present, the kind of every value will be registered
+    /// It's shared, only the first ```n_leaf_kinds``` are from
+    /// current parsing path (the rest are truncated on next push)
+    pub(crate) leaf_kinds: Option<Rc<RefCell<Vec<ast::kind::NodeKind>>>>,
+    pub(crate) n_leaf_kinds: usize,
+
+    /// If present, the tree is registered as events for a builder,
+    /// instead of building the AST. Shared as ```leaf_kinds```
+    pub(crate) tree_events: Option<Rc<RefCell<Vec<TreeEvent>>>>,
+    pub(crate) n_tree_events: usize,
+
+    /// If present, the alternative matched by every ```or``` will be
+    /// registered as (or id, alternative, span). Shared as ```leaf_kinds```
+    pub(crate) decisions: Option<Rc<RefCell<Vec<RawDecision>>>>,
+    pub(crate) n_decisions: usize,
+
+    /// If present, rules with a sync token (```@sync```) skip the input
+    /// till the token when they fail, and the error is registered here
+    /// Shared as ```leaf_kinds```
+    pub(crate) recovered: Option<Rc<RefCell<Vec<Error>>>>,
+    pub(crate) n_recovered: usize,
 }
 
 impl<'a> Status<'a> {
@@ -107,10 +512,39 @@ impl<'a> Status<'a> {
             text2parse: t2p,
             it_parsing: t2p.chars(),
             pos: Possition::init(),
+            input_start: 0,
             trace_rules: false,
             walking_rules: vec![],
             rules,
             potential_error: None,
+            fail_fast: false,
+            warnings: vec![],
+            context: ParseContext::default(),
+            profile: None,
+            extract: &[],
+            extracted: vec![],
+            capture: false,
+            captured: vec![],
+            scoped_keywords: vec![],
+            rule_name: "",
+            max_repetitions: MAX_REPETITIONS,
+            stack_start: stack_position(),
+            max_stack: default_max_stack(),
+            newlines: Newlines::default(),
+            memo: None,
+            seeds: if rules.allows_left_recursion() {
+                Some(Seeds::default())
+            } else {
+                None
+            },
+            leaf_kinds: None,
+            n_leaf_kinds: 0,
+            tree_events: None,
+            n_tree_events: 0,
+            decisions: None,
+            n_decisions: 0,
+            recovered: None,
+            n_recovered: 0,
         }
     }
 
@@ -123,10 +557,39 @@ impl<'a> Status<'a> {
             text2parse: t2p,
             it_parsing: t2p.chars(),
             pos: Possition::init(),
+            input_start: 0,
             trace_rules,
             walking_rules: vec![],
             rules,
             potential_error: None,
+            fail_fast: false,
+            warnings: vec![],
+            context: ParseContext::default(),
+            profile: None,
+            extract: &[],
+            extracted: vec![],
+            capture: false,
+            captured: vec![],
+            scoped_keywords: vec![],
+            rule_name: "",
+            max_repetitions: MAX_REPETITIONS,
+            stack_start: stack_position(),
+            max_stack: default_max_stack(),
+            newlines: Newlines::default(),
+            memo: None,
+            seeds: if rules.allows_left_recursion() {
+                Some(Seeds::default())
+            } else {
+                None
+            },
+            leaf_kinds: None,
+            n_leaf_kinds: 0,
+            tree_events: None,
+            n_tree_events: 0,
+            decisions: None,
+            n_decisions: 0,
+            recovered: None,
+            n_recovered: 0,
         }
     }
     pub(crate) fn push_rule(mut self, on_node: &str) -> Self {
@@ -134,9 +597,161 @@ impl<'a> Status<'a> {
         self
     }
     pub(crate) fn set_potential_error(mut self, err: Error) -> Self {
-        self.potential_error = Some(err);
+        self.potential_error = Some(Box::new(match self.potential_error.take() {
+            Some(previous) => err.merge_expected(&previous),
+            None => err,
+        }));
+        self
+    }
+    pub(crate) fn set_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+    pub(crate) fn set_context(mut self, context: ParseContext<'a>) -> Self {
+        self.context = context;
         self
     }
+    pub(crate) fn set_extract(mut self, names: &'a [&'a str]) -> Self {
+        self.extract = names;
+        self
+    }
+    pub(crate) fn set_capture(mut self, capture: bool) -> Self {
+        self.capture = capture;
+        self
+    }
+    pub(crate) fn set_profile(mut self, profile: Rc<RefCell<profile::Profile>>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+    pub(crate) fn set_max_repetitions(mut self, max_repetitions: usize) -> Self {
+        self.max_repetitions = max_repetitions;
+        self
+    }
+    pub(crate) fn set_max_stack(mut self, max_stack: usize) -> Self {
+        self.max_stack = max_stack;
+        self
+    }
+    //  a parsing starts here (the status could be created on other place)
+    pub(crate) fn reset_stack(mut self) -> Self {
+        self.stack_start = stack_position();
+        self
+    }
+    pub(crate) fn stack_used(&self) -> usize {
+        stack_position().abs_diff(self.stack_start)
+    }
+    pub(crate) fn set_newlines(mut self, newlines: Newlines) -> Self {
+        self.newlines = newlines;
+        self
+    }
+    pub(crate) fn set_memoize(mut self, memoize: bool) -> Self {
+        self.memo = if memoize { Some(Memo::default()) } else { None };
+        self
+    }
+    //  results can be reused if nothing else is registered while parsing
+    //  and they don't depend on the caller (scoped keywords)
+    //  a new log on Status has to be added here (Memoized doesn't keep it)
+    pub(crate) fn can_memoize(&self) -> bool {
+        self.memo.is_some()
+            && self.seeds.is_none()
+            && !self.trace_rules
+            && self.extract.is_empty()
+            && !self.capture
+            && self.scoped_keywords.is_empty()
+            && self.leaf_kinds.is_none()
+            && self.tree_events.is_none()
+            && self.decisions.is_none()
+            && self.recovered.is_none()
+    }
+    pub(crate) fn set_leaf_kinds(mut self, kinds: Rc<RefCell<Vec<ast::kind::NodeKind>>>) -> Self {
+        self.leaf_kinds = Some(kinds);
+        self
+    }
+    pub(crate) fn push_leaf_kind(mut self, kind: ast::kind::NodeKind) -> Self {
+        if let Some(kinds) = &self.leaf_kinds {
+            let mut kinds = kinds.borrow_mut();
+            kinds.truncate(self.n_leaf_kinds);
+            kinds.push(kind);
+            self.n_leaf_kinds += 1;
+        }
+        self
+    }
+    pub(crate) fn set_tree_events(mut self, events: Rc<RefCell<Vec<TreeEvent>>>) -> Self {
+        self.tree_events = Some(events);
+        self
+    }
+    pub(crate) fn push_tree_event(mut self, event: TreeEvent) -> Self {
+        if let Some(events) = &self.tree_events {
+            let mut events = events.borrow_mut();
+            events.truncate(self.n_tree_events);
+            events.push(event);
+            self.n_tree_events += 1;
+        }
+        self
+    }
+    pub(crate) fn set_decisions(mut self, decisions: Rc<RefCell<Vec<RawDecision>>>) -> Self {
+        self.decisions = Some(decisions);
+        self
+    }
+    //  the alternative matched from start to current position
+    pub(crate) fn push_decision(
+        mut self,
+        or_id: usize,
+        alternative: usize,
+        start: &Possition,
+    ) -> Self {
+        if let Some(decisions) = &self.decisions {
+            let span = Span {
+                start: start.clone(),
+                end: self.pos.clone(),
+            };
+            let mut decisions = decisions.borrow_mut();
+            decisions.truncate(self.n_decisions);
+            decisions.push((or_id, alternative, span));
+            self.n_decisions += 1;
+        }
+        self
+    }
+    pub(crate) fn set_recovered(mut self, recovered: Rc<RefCell<Vec<Error>>>) -> Self {
+        self.recovered = Some(recovered);
+        self
+    }
+    pub(crate) fn push_recovered(mut self, error: Error) -> Self {
+        if let Some(recovered) = &self.recovered {
+            let mut recovered = recovered.borrow_mut();
+            recovered.truncate(self.n_recovered);
+            recovered.push(error);
+            self.n_recovered += 1;
+        }
+        self
+    }
+    //  the events after the open one are a single node
+    pub(crate) fn is_tree_chain(&self, open: usize) -> bool {
+        self.tree_events.as_ref().is_some_and(|events| {
+            ast::builder::is_single_node(&events.borrow()[open + 1..self.n_tree_events])
+        })
+    }
+    //  passthrough rules don't open a node
+    pub(crate) fn remove_tree_event(mut self, at: usize) -> Self {
+        if let Some(events) = &self.tree_events {
+            let mut events = events.borrow_mut();
+            events.truncate(self.n_tree_events);
+            events.remove(at);
+            self.n_tree_events -= 1;
+        }
+        self
+    }
+    //  pos.n counts chars, the text has to be sliced on bytes
+    pub(crate) fn line_before(&self) -> &'a str {
+        let parsed = &self.text2parse[..self.text2parse.len() - self.it_parsing.as_str().len()];
+        match parsed
+            .char_indices()
+            .rev()
+            .find(|&(_, ch)| self.newlines.is_newline(ch))
+        {
+            Some((nl, ch)) => &parsed[nl + ch.len_utf8()..],
+            None => parsed,
+        }
+    }
 }
 
 pub(crate) type Result<'a> = result::Result<(Status<'a>, ast::Node), Error>;
@@ -149,6 +764,117 @@ pub(crate) type Result<'a> = result::Result<(Status<'a>, ast::Node), Error>;
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
 
+/// Error description, with the line and a mark on the error position
+///
+/// Non printable chars (as ```\0``` or ```\x01```) will be escaped
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  lit!("a\0b")
+///     };
+///
+///     let error = parse("a\0c", &rules).unwrap_err();
+///     assert_eq!(
+///         error.to_string(),
+///         "expected literal: <a\\0b> (row 0, col 3)\na\\0c\n    ^"
+///     );
+/// }
+/// ```
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let before = escape_non_printable(&self.line_before);
+        write!(
+            f,
+            "{} (row {}, col {})\n{}{}\n{}^",
+            escape_non_printable(&self.descr),
+            self.pos.row,
+            self.pos.col,
+            before,
+            escape_non_printable(&self.line_after),
+            " ".repeat(before.chars().count())
+        )
+    }
+}
+
+impl Error {
+    /// Error description with some lines of the input around the
+    /// error, with their row numbers
+    ///
+    /// The error keeps only the line where it was produced, the input
+    /// has to be provided
+    ///
+    /// Lines are split with the same ```Newlines``` used parsing, so
+    /// rows match ```pos.row```
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::parse;
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  rep!(and!(lit!("ab"), lit!("\n")), 0)
+    ///     };
+    ///
+    ///     let input = "ab\nab\nab\naX\nab\nab\nab\n";
+    ///     let error = parse(input, &rules).unwrap_err();
+    ///     assert_eq!(
+    ///         error.render(input, 1),
+    ///         "expected literal: <ab> (row 3, col 2)\n\
+    ///          2 | ab\n\
+    ///          3 | aX\n\
+    ///          \x20 |   ^\n\
+    ///          4 | ab"
+    ///     );
+    /// }
+    /// ```
+    pub fn render(&self, text: &str, context_lines: usize) -> String {
+        let lines: Vec<&str> = text
+            .split(|ch| self.newlines.is_newline(ch))
+            .map(|l| l.strip_suffix('\r').unwrap_or(l))
+            .collect();
+        let first = self.pos.row.saturating_sub(context_lines);
+        let last = (self.pos.row + context_lines).min(lines.len().saturating_sub(1));
+        let width = last.to_string().len();
+
+        let before: String = text
+            .chars()
+            .skip(self.pos.start_line)
+            .take(self.pos.n.saturating_sub(self.pos.start_line))
+            .collect();
+        //  line end chars are not shown
+        let line_len = lines
+            .get(self.pos.row)
+            .map_or(0, |l| escape_non_printable(l).chars().count());
+        let mark = format!(
+            "{} | {}^",
+            " ".repeat(width),
+            " ".repeat(escape_non_printable(&before).chars().count().min(line_len))
+        );
+
+        (first..=last).fold(
+            format!(
+                "{} (row {}, col {})",
+                escape_non_printable(&self.descr),
+                self.pos.row,
+                self.pos.col
+            ),
+            |acc, row| {
+                let line = lines.get(row).map_or(String::new(), |l| escape_non_printable(l));
+                let acc = format!("{}\n{:>w$} | {}", acc, row, line, w = width);
+                if row == self.pos.row {
+                    format!("{}\n{}", acc, mark)
+                } else {
+                    acc
+                }
+            },
+        )
+    }
+}
+
 //-----------------------------------------------------------------------
 //  T E S T
 //-----------------------------------------------------------------------
@@ -159,26 +885,93 @@ mod test;
 //  I N T E R N A L
 //-----------------------------------------------------------------------
 impl Error {
-    pub(crate) fn from_status(status: &Status, descr: &str, prior: ErrPriority) -> Self {
+    pub(crate) fn from_status(
+        status: &Status,
+        code: Code,
+        descr: &str,
+        prior: ErrPriority,
+    ) -> Self {
+        if status.fail_fast {
+            //  position and priority are still needed to choose errors
+            return Error {
+                pos: status.pos.clone(),
+                descr: String::new(),
+                code,
+                expected: vec![],
+                line_before: String::new(),
+                line_after: String::new(),
+                parsing_rules: vec![],
+                priority: prior,
+                newlines: status.newlines,
+            };
+        }
         Error {
             pos: status.pos.clone(),
             descr: descr.to_owned(),
-            line_before: status.text2parse[status.pos.start_line..status.pos.n].to_string(),
+            code,
+            expected: vec![],
+            line_before: status.line_before().to_string(),
             line_after: status
                 .it_parsing
                 .clone()
-                .take_while(|&ch| ch != '\n' && ch != '\r')
+                .take_while(|&ch| !status.newlines.is_newline(ch) && ch != '\r')
                 .collect(),
             // errors: vec![],
             parsing_rules: status.walking_rules.clone(),
             priority: prior,
+            newlines: status.newlines,
+        }
+    }
+
+    pub(crate) fn from_status_normal(status: &Status, code: Code, descr: &str) -> Self {
+        Self::from_status(status, code, descr, ErrPriority::Normal)
+    }
+
+    //  error of a token not found (literal, match...)
+    //  a repetition stopped on the same position expected more tokens
+    pub(crate) fn from_status_expected(
+        status: &Status,
+        code: Code,
+        descr: &str,
+        token: impl FnOnce() -> String,
+    ) -> Self {
+        let mut error = Self::from_status_normal(status, code, descr);
+        if status.fail_fast {
+            return error;
+        }
+        error.expected.push(token());
+        match &status.potential_error {
+            Some(previous) => error.merge_expected(previous),
+            None => error,
         }
     }
 
-    pub(crate) fn from_status_normal(status: &Status, descr: &str) -> Self {
-        Self::from_status(status, descr, ErrPriority::Normal)
+    //  errors on the same position (alternatives, end of a repetition)
+    //  join the tokens expected, the ones of the previous error first
+    pub(crate) fn merge_expected(mut self, previous: &Error) -> Self {
+        if previous.pos.n != self.pos.n
+            || previous.priority != self.priority
+            || previous.expected.is_empty()
+            || self.expected.is_empty()
+        {
+            return self;
+        }
+        let mut expected = previous.expected.clone();
+        for token in self.expected.drain(..) {
+            if !expected.contains(&token) {
+                expected.push(token);
+            }
+        }
+        if expected.len() > 1 {
+            self.code = Code::ExpectedOneOf;
+            self.descr = format!("expected one of: {}", expected.join(", "));
+        }
+        self.expected = expected;
+        self
     }
+}
 
+impl Error {
     // pub(crate) fn from_st_errs(status: &Status, descr: &str, errors: Vec<Error>) -> Self {
     //     let max_pr = |verrors: &Vec<Error>| {
     //         use std::cmp::max;
@@ -198,3 +991,71 @@ impl Error {
     //     }
     // }
 }
+
+/// Limit of stack used by nested rules when not configured
+///
+/// Exceeding it is a critical error, instead of a stack overflow. It
+/// protects against deep nested inputs, and left recursive grammars.
+///
+/// It's measured on the current thread, three quarters of the stack
+/// still free. When the stack bounds can't be known, ```MAX_STACK```
+pub fn default_max_stack() -> usize {
+    stacker::remaining_stack().map_or(MAX_STACK, |free| free - free / 4)
+}
+
+/// Free the buffers kept on this thread to build the AST
+///
+/// The vectors for the nodes are reused between parsings on the same
+/// thread (limited in number and size). This returns the memory on a
+/// long lived thread that won't parse again
+pub fn release_scratch_buffers() {
+    scratch::release()
+}
+
+//  address of a local, to know the stack used
+#[inline(never)]
+pub(crate) fn stack_position() -> usize {
+    let mark = 0u8;
+    std::hint::black_box(&mark) as *const u8 as usize
+}
+
+//  control chars will be written as escape sequences
+pub(crate) fn escape_non_printable(s: &str) -> String {
+    s.chars()
+        .fold(String::with_capacity(s.len()), |mut acc, ch| {
+            match ch {
+                '\0' => acc.push_str(r"\0"),
+                '\t' => acc.push_str(r"\t"),
+                '\n' => acc.push_str(r"\n"),
+                '\r' => acc.push_str(r"\r"),
+                ch if ch.is_control() && (ch as u32) < 0x100 => {
+                    acc.push_str(&format!(r"\x{:02X}", ch as u32))
+                }
+                ch if ch.is_control() => acc.push_str(&format!(r"\u{{{:X}}}", ch as u32)),
+                ch => acc.push(ch),
+            }
+            acc
+        })
+}
+
+//  work per rule (compile, analyze...)
+//  with the rayon feature, it runs in parallel
+//  the results keep the order of the items, to be deterministic
+#[cfg(feature = "rayon")]
+pub(crate) fn map_per_rule<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    use rayon::prelude::*;
+    items.par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+pub(crate) fn map_per_rule<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    F: Fn(&T) -> R,
+{
+    items.iter().map(f).collect()
+}
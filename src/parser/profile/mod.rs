@@ -0,0 +1,357 @@
+#![warn(missing_docs)]
+//! Time and counters per rule, collected while parsing
+//!
+//! Created with ```parse_profile```. Times are inclusive (a rule
+//! time contains the time of the rules called from it)
+//!
+//! With the ```profiling``` feature, every rule call is also recorded
+//! to be exported as a timeline (```to_chrome_trace```)
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+#[cfg(feature = "profiling")]
+use std::time::Instant;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Upper bounds (in seconds) of the latency histogram buckets
+///
+/// There is an extra bucket for bigger values (+Inf)
+pub const BUCKETS: [f64; 7] = [1e-6, 1e-5, 1e-4, 1e-3, 1e-2, 1e-1, 1.0];
+
+/// Information collected for a rule
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleStats {
+    /// Times the rule was called
+    pub calls: u64,
+    /// Times the rule matched
+    pub matches: u64,
+    /// Time spent on the rule (all calls)
+    pub total: Duration,
+    /// Calls per latency bucket (not cumulative)
+    /// Last one is for calls bigger than any bound on ```BUCKETS```
+    pub buckets: [u64; BUCKETS.len() + 1],
+}
+
+/// Chars checked on character classes (```ematch!```)
+///
+/// Classes are compiled to a bitset for the first 256 chars. Bigger
+/// chars are checked on a table of ranges
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClassStats {
+    /// Chars checked on the bitset
+    pub bitset: u64,
+    /// Chars checked on the table of ranges
+    pub fallback: u64,
+}
+
+/// Tries and matches of an alternative of an ```or```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AltStats {
+    /// Times the alternative was tried
+    pub tries: u64,
+    /// Times the alternative matched
+    pub matches: u64,
+}
+
+/// Stats of the alternatives of an ```or```
+///
+/// Created with ```SetOfRules::alternative_stats```
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrStats {
+    /// Rule containing the ```or```
+    pub rule: String,
+    /// Position of the ```or``` on the rule (nested ones are after
+    /// the one containing them)
+    pub index: usize,
+    /// Per alternative, in order
+    pub alternatives: Vec<AltStats>,
+}
+
+/// Stats for all the rules called while parsing
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    rules: BTreeMap<String, RuleStats>,
+    classes: ClassStats,
+    //  per or, identified by its address on the rules
+    alternatives: HashMap<usize, Vec<AltStats>>,
+    #[cfg(feature = "profiling")]
+    calls: Vec<RuleCall>,
+}
+
+/// A rule call, recorded with the ```profiling``` feature
+#[cfg(feature = "profiling")]
+#[derive(Debug, Clone)]
+struct RuleCall {
+    rule_name: String,
+    matched: bool,
+    start: Instant,
+    elapsed: Duration,
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl Profile {
+    /// Stats for a rule, if it was called
+    pub fn rule(&self, name: &str) -> Option<&RuleStats> {
+        self.rules.get(name)
+    }
+
+    /// Iterate over the called rules, sorted by name
+    pub fn rules(&self) -> impl Iterator<Item = (&str, &RuleStats)> {
+        self.rules
+            .iter()
+            .map(|(name, stats)| (name.as_str(), stats))
+    }
+
+    /// Chars checked on character classes, for all the rules
+    pub fn classes(&self) -> &ClassStats {
+        &self.classes
+    }
+
+    /// Write the stats in OpenMetrics text format
+    ///
+    /// It contains a counter for calls, a counter for matches and
+    /// a latency histogram, per rule
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::parse_profile;
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  rep!(ref_rule!("a"), 0),
+    ///        "a"      =>  lit!("a")
+    ///     };
+    ///
+    ///     let (result, profile) = parse_profile("aa", &rules);
+    ///     assert!(result.is_ok());
+    ///
+    ///     let metrics = profile.to_openmetrics();
+    ///     assert!(metrics.contains("dynparser_rule_calls_total{rule=\"a\"} 3\n"));
+    ///     assert!(metrics.contains("dynparser_rule_matches_total{rule=\"a\"} 2\n"));
+    ///     assert!(metrics.contains(
+    ///         "dynparser_rule_duration_seconds_bucket{rule=\"a\",le=\"+Inf\"} 3\n"
+    ///     ));
+    ///     assert!(metrics.ends_with("# EOF\n"));
+    /// }
+    /// ```
+    pub fn to_openmetrics(&self) -> String {
+        let counter = |name: &str, help: &str, value: &dyn Fn(&RuleStats) -> u64| {
+            self.rules.iter().fold(
+                format!("# TYPE {0} counter\n# HELP {0} {1}\n", name, help),
+                |acc, (rule, stats)| {
+                    format!(
+                        "{}{}_total{{rule=\"{}\"}} {}\n",
+                        acc,
+                        name,
+                        escape_label(rule),
+                        value(stats)
+                    )
+                },
+            )
+        };
+
+        let histogram = self.rules.iter().fold(
+            "# TYPE dynparser_rule_duration_seconds histogram\n\
+             # HELP dynparser_rule_duration_seconds Time parsing the rule (inclusive).\n"
+                .to_string(),
+            |acc, (rule, stats)| acc + &histogram2text(&escape_label(rule), stats),
+        );
+
+        format!(
+            "{}{}{}# EOF\n",
+            counter("dynparser_rule_calls", "Times the rule was called.", &|s| s
+                .calls),
+            counter("dynparser_rule_matches", "Times the rule matched.", &|s| s
+                .matches),
+            histogram
+        )
+    }
+
+    /// Write the rule calls in Chrome trace event format (JSON)
+    ///
+    /// It can be loaded on ```chrome://tracing``` or
+    /// [Perfetto](https://ui.perfetto.dev) to see the calls on a
+    /// timeline. Times are in microseconds from the first call
+    ///
+    /// Requires the ```profiling``` feature
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::parse_profile;
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  rep!(ref_rule!("a"), 0),
+    ///        "a"      =>  lit!("a")
+    ///     };
+    ///
+    ///     let (_, profile) = parse_profile("aa", &rules);
+    ///
+    ///     let trace = profile.to_chrome_trace();
+    ///     assert!(trace.starts_with("{\"traceEvents\":["));
+    ///     assert_eq!(trace.matches("\"name\":\"a\"").count(), 3);
+    ///     assert!(trace.contains("\"name\":\"main\",\"ph\":\"X\",\"ts\":0,"));
+    /// }
+    /// ```
+    #[cfg(feature = "profiling")]
+    pub fn to_chrome_trace(&self) -> String {
+        let mut calls: Vec<&RuleCall> = self.calls.iter().collect();
+        //  callers first, when they start at the same instant
+        calls.sort_by_key(|c| (c.start, std::cmp::Reverse(c.elapsed)));
+        let origin = match calls.first() {
+            Some(call) => call.start,
+            None => return "{\"traceEvents\":[]}\n".to_string(),
+        };
+
+        let events: Vec<String> = calls
+            .iter()
+            .map(|c| {
+                format!(
+                    "{{\"name\":\"{}\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\
+                     \"pid\":1,\"tid\":1,\"args\":{{\"matched\":{}}}}}",
+                    escape_json(&c.rule_name),
+                    (c.start - origin).as_micros(),
+                    c.elapsed.as_micros(),
+                    c.matched
+                )
+            })
+            .collect();
+        format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"))
+    }
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  I N T E R N A L
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl Profile {
+    pub(crate) fn register(&mut self, rule_name: &str, matched: bool, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| secs <= bound)
+            .unwrap_or(BUCKETS.len());
+
+        let stats = self.rules.entry(rule_name.to_string()).or_default();
+        stats.calls += 1;
+        if matched {
+            stats.matches += 1;
+        }
+        stats.total += elapsed;
+        stats.buckets[bucket] += 1;
+    }
+
+    #[cfg(feature = "profiling")]
+    pub(crate) fn register_call(
+        &mut self,
+        rule_name: &str,
+        matched: bool,
+        start: Instant,
+        elapsed: Duration,
+    ) {
+        self.register(rule_name, matched, elapsed);
+        self.calls.push(RuleCall {
+            rule_name: rule_name.to_string(),
+            matched,
+            start,
+            elapsed,
+        });
+    }
+
+    pub(crate) fn register_alternative(
+        &mut self,
+        or_id: usize,
+        n_alternatives: usize,
+        index: usize,
+        matched: bool,
+    ) {
+        let stats = self
+            .alternatives
+            .entry(or_id)
+            .or_insert_with(|| vec![AltStats::default(); n_alternatives]);
+        stats[index].tries += 1;
+        if matched {
+            stats[index].matches += 1;
+        }
+    }
+
+    pub(crate) fn alternatives(&self, or_id: usize) -> Option<&[AltStats]> {
+        self.alternatives.get(&or_id).map(|stats| stats.as_slice())
+    }
+
+    pub(crate) fn register_class(&mut self, ch: char) {
+        if (ch as u32) < 256 {
+            self.classes.bitset += 1;
+        } else {
+            self.classes.fallback += 1;
+        }
+    }
+}
+
+fn histogram2text(rule: &str, stats: &RuleStats) -> String {
+    let name = "dynparser_rule_duration_seconds";
+    let les = BUCKETS
+        .iter()
+        .map(|b| format!("{:e}", b))
+        .chain(std::iter::once("+Inf".to_string()));
+
+    let (buckets, _) =
+        les.zip(stats.buckets.iter())
+            .fold((String::new(), 0), |(acc, cumulative), (le, count)| {
+                let cumulative = cumulative + count;
+                (
+                    format!(
+                        "{}{}_bucket{{rule=\"{}\",le=\"{}\"}} {}\n",
+                        acc, name, rule, le, cumulative
+                    ),
+                    cumulative,
+                )
+            });
+
+    format!(
+        "{0}{1}_sum{{rule=\"{2}\"}} {3}\n{1}_count{{rule=\"{2}\"}} {4}\n",
+        buckets,
+        name,
+        rule,
+        stats.total.as_secs_f64(),
+        stats.calls
+    )
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(feature = "profiling")]
+fn escape_json(value: &str) -> String {
+    value.chars().fold(String::new(), |acc, ch| match ch {
+        '"' => acc + r#"\""#,
+        '\\' => acc + r"\\",
+        ch if (ch as u32) < 0x20 => acc + &format!("\\u{:04x}", ch as u32),
+        ch => acc + &ch.to_string(),
+    })
+}
@@ -0,0 +1,183 @@
+//-----------------------------------------------------------------------
+//
+//  mod parser::profile  TEST
+//
+//-----------------------------------------------------------------------
+use super::{AltStats, ClassStats, Profile, BUCKETS};
+use crate::{parse, parse_profile, rules_from_peg};
+use std::time::Duration;
+
+#[test]
+fn test_register_buckets() {
+    let mut profile = Profile::default();
+    profile.register("a", true, Duration::from_nanos(500));
+    profile.register("a", false, Duration::from_micros(50));
+    profile.register("a", true, Duration::from_secs(2));
+
+    let stats = profile.rule("a").unwrap();
+    assert_eq!(stats.calls, 3);
+    assert_eq!(stats.matches, 2);
+    assert_eq!(stats.buckets[0], 1);
+    assert_eq!(stats.buckets[2], 1);
+    assert_eq!(stats.buckets[BUCKETS.len()], 1);
+    assert!(profile.rule("b").is_none());
+}
+
+#[test]
+fn test_openmetrics_format() {
+    let mut profile = Profile::default();
+    profile.register("r\"1", true, Duration::from_millis(5));
+
+    let text = profile.to_openmetrics();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            "# TYPE dynparser_rule_calls counter",
+            "# HELP dynparser_rule_calls Times the rule was called.",
+            r#"dynparser_rule_calls_total{rule="r\"1"} 1"#,
+            "# TYPE dynparser_rule_matches counter",
+            "# HELP dynparser_rule_matches Times the rule matched.",
+            r#"dynparser_rule_matches_total{rule="r\"1"} 1"#,
+            "# TYPE dynparser_rule_duration_seconds histogram",
+            "# HELP dynparser_rule_duration_seconds Time parsing the rule (inclusive).",
+            r#"dynparser_rule_duration_seconds_bucket{rule="r\"1",le="1e-6"} 0"#,
+            r#"dynparser_rule_duration_seconds_bucket{rule="r\"1",le="1e-5"} 0"#,
+            r#"dynparser_rule_duration_seconds_bucket{rule="r\"1",le="1e-4"} 0"#,
+            r#"dynparser_rule_duration_seconds_bucket{rule="r\"1",le="1e-3"} 0"#,
+            r#"dynparser_rule_duration_seconds_bucket{rule="r\"1",le="1e-2"} 1"#,
+            r#"dynparser_rule_duration_seconds_bucket{rule="r\"1",le="1e-1"} 1"#,
+            r#"dynparser_rule_duration_seconds_bucket{rule="r\"1",le="1e0"} 1"#,
+            r#"dynparser_rule_duration_seconds_bucket{rule="r\"1",le="+Inf"} 1"#,
+            r#"dynparser_rule_duration_seconds_sum{rule="r\"1"} 0.005"#,
+            r#"dynparser_rule_duration_seconds_count{rule="r\"1"} 1"#,
+            "# EOF",
+        ]
+    );
+}
+
+#[test]
+fn test_class_stats() {
+    let rules = rules!{
+       "main"   =>  rep!(ematch!(chlist "ñ€", from 'a', to 'z'), 0)
+    };
+
+    let (result, profile) = parse_profile("añb€", &rules);
+    assert!(result.is_ok());
+    assert_eq!(
+        profile.classes(),
+        &ClassStats {
+            bitset: 3,
+            fallback: 1,
+        }
+    );
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn test_chrome_trace() {
+    use std::time::Instant;
+
+    assert_eq!(
+        Profile::default().to_chrome_trace(),
+        "{\"traceEvents\":[]}\n"
+    );
+
+    let mut profile = Profile::default();
+    let start = Instant::now();
+    profile.register_call(
+        "in\"ner",
+        false,
+        start + Duration::from_micros(10),
+        Duration::from_micros(5),
+    );
+    profile.register_call("main", true, start, Duration::from_micros(30));
+
+    let lines: Vec<String> = profile
+        .to_chrome_trace()
+        .lines()
+        .map(|l| l.to_string())
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"traceEvents":["#,
+            r#"{"name":"main","ph":"X","ts":0,"dur":30,"pid":1,"tid":1,"args":{"matched":true}},"#,
+            r#"{"name":"in\"ner","ph":"X","ts":10,"dur":5,"pid":1,"tid":1,"args":{"matched":false}}"#,
+            "]}",
+        ]
+    );
+    assert_eq!(profile.rule("main").unwrap().calls, 1);
+}
+
+#[test]
+fn test_alternative_stats() {
+    let grammar = r#"
+        main    =   (item  ' '?)*
+        item    =   '+'  /  number  /  (letter  /  '_')
+        number  =   [0-9]+
+        letter  =   [a-z]
+        "#;
+    let rules = rules_from_peg(grammar).unwrap();
+
+    let (result, profile) = parse_profile("1 22 a _ 3 4", &rules);
+    assert!(result.is_ok());
+
+    let stats = rules.alternative_stats(&profile);
+    let item: Vec<_> = stats.iter().filter(|s| s.rule == "item").collect();
+    assert_eq!(item.len(), 2);
+    assert_eq!(item[0].index, 0);
+    assert_eq!(
+        item[0].alternatives,
+        vec![
+            AltStats {
+                tries: 7,
+                matches: 0
+            },
+            AltStats {
+                tries: 7,
+                matches: 4
+            },
+            AltStats {
+                tries: 3,
+                matches: 2
+            },
+        ]
+    );
+    assert_eq!(item[1].index, 1);
+
+    let reorderings = rules.suggest_reordering(&profile);
+    let item: Vec<_> = reorderings.iter().filter(|r| r.rule == "item").collect();
+    assert_eq!(item.len(), 1);
+    assert_eq!(item[0].index, 0);
+    assert_eq!(item[0].order, vec![1, 2, 0]);
+
+    let reordered = rules_from_peg(grammar)
+        .unwrap()
+        .apply_reordering(&reorderings);
+    for input in &["1 22 a _ 3 4", "+ +_ 9", "1+", "1-"] {
+        assert_eq!(parse(input, &rules).ok(), parse(input, &reordered).ok());
+    }
+    let (_, profile) = parse_profile("1 22 a _ 3 4", &reordered);
+    let item = reordered
+        .alternative_stats(&profile)
+        .into_iter()
+        .find(|s| s.rule == "item")
+        .unwrap();
+    assert_eq!(item.alternatives[0].matches, 4);
+    assert_eq!(item.alternatives[0].tries, 7);
+}
+
+#[test]
+fn test_no_reordering_on_shared_prefix() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   ('ab'  /  'a'  /  'b')*
+        "#,
+    )
+    .unwrap();
+
+    let (result, profile) = parse_profile("aaab", &rules);
+    assert!(result.is_ok());
+    assert!(rules.suggest_reordering(&profile).is_empty());
+}
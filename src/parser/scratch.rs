@@ -0,0 +1,74 @@
+//! Buffers reused to build the AST
+//!
+//! Sequences and repetitions accumulate the nodes of their parts on a
+//! vector, and most of them are discarded (failed alternatives) or
+//! moved to the parent. The vectors are kept per thread and reused,
+//! instead of allocating new ones for every node
+//!
+//! The children of the nodes on the final tree are moved to a vector of
+//! the exact size (look at ```compact```). It's not an arena, every node
+//! with children on the final tree still has its own allocation. The
+//! allocations saved are the ones of the discarded and intermediate
+//! vectors (```benches/grammars.rs``` measures it, ```*_no_scratch```)
+
+use crate::ast;
+use std::cell::RefCell;
+
+//  buffers kept per thread, and max capacity of a kept buffer
+const MAX_BUFFERS: usize = 64;
+const MAX_CAPACITY: usize = 4096;
+
+thread_local! {
+    static BUFFERS: RefCell<Vec<Vec<ast::Node>>> = const { RefCell::new(Vec::new()) };
+}
+
+//  an empty vector, reused if possible
+pub(crate) fn take() -> Vec<ast::Node> {
+    BUFFERS
+        .try_with(|buffers| buffers.borrow_mut().pop())
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+//  the vector will be reused (the nodes on it are dropped)
+pub(crate) fn give(mut nodes: Vec<ast::Node>) {
+    if nodes.capacity() == 0 || nodes.capacity() > MAX_CAPACITY {
+        return;
+    }
+    nodes.clear();
+    let _ = BUFFERS.try_with(|buffers| {
+        let mut buffers = buffers.borrow_mut();
+        if buffers.len() < MAX_BUFFERS {
+            buffers.push(nodes);
+        }
+    });
+}
+
+//  the nodes of other after the nodes, reusing the emptied buffer
+pub(crate) fn append(mut nodes: Vec<ast::Node>, mut other: Vec<ast::Node>) -> Vec<ast::Node> {
+    if nodes.is_empty() {
+        give(nodes);
+        other
+    } else {
+        nodes.append(&mut other);
+        give(other);
+        nodes
+    }
+}
+
+//  the nodes for the final tree, on a vector of the exact size
+pub(crate) fn compact(mut nodes: Vec<ast::Node>) -> Vec<ast::Node> {
+    if nodes.len() == nodes.capacity() {
+        return nodes;
+    }
+    let mut exact = Vec::with_capacity(nodes.len());
+    exact.append(&mut nodes);
+    give(nodes);
+    exact
+}
+
+//  free the buffers of the thread
+pub(crate) fn release() {
+    let _ = BUFFERS.try_with(|buffers| buffers.borrow_mut().clear());
+}
@@ -71,3 +71,743 @@ fn test_parse_expr_repeat_ok() {
     //     assert_eq!(result.status.pos.row, 0);
     // }
 }
+
+#[test]
+fn test_parse_fail_fast_no_ast() {
+    let rules = rules! {
+        "main" => and![ref_rule!("as"), lit!("b")],
+        "as"   => rep!(lit!("a"), 1)
+    };
+    let status_init = Status::init("aab", &rules).set_fail_fast(true);
+
+    let (status, ast) = parse(status_init).ok().unwrap();
+    assert_eq!(status.pos.n, 3);
+    assert!(ast == crate::ast::Node::Rule((String::new(), vec![])));
+
+    let status_init = Status::init("aac", &rules).set_fail_fast(true);
+    let error = parse(status_init).err().unwrap();
+    assert!(error.descr.is_empty());
+}
+
+#[test]
+fn test_parse_warnings_backtracking() {
+    let rules = rules! {
+        "main" => or![
+            and![lit!("a"), warning!("w1"), lit!("b")],
+            and![lit!("a"), warning!("w2"), not!(and![warning!("w3"), lit!("x")]), lit!("c")]
+        ]
+    };
+    let (status, _) = parse(Status::init("ac", &rules)).ok().unwrap();
+    assert_eq!(status.warnings.len(), 1);
+    assert_eq!(status.warnings[0].descr, "w2");
+    assert_eq!(status.warnings[0].pos.n, 1);
+
+    let status_init = Status::init("ac", &rules).set_fail_fast(true);
+    let (status, _) = parse(status_init).ok().unwrap();
+    assert!(status.warnings.is_empty());
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing_span_per_rule() {
+    use std::sync::{Arc, Mutex};
+    use tracing::{field, span, Event, Id, Metadata, Subscriber};
+
+    struct Rules(Arc<Mutex<Vec<String>>>);
+    struct NameField<'a>(&'a Mutex<Vec<String>>);
+
+    impl<'a> field::Visit for NameField<'a> {
+        fn record_str(&mut self, f: &field::Field, value: &str) {
+            if f.name() == "name" {
+                self.0.lock().unwrap().push(value.to_string());
+            }
+        }
+        fn record_debug(&mut self, _: &field::Field, _: &dyn std::fmt::Debug) {}
+    }
+
+    impl Subscriber for Rules {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+        fn new_span(&self, attrs: &span::Attributes) -> Id {
+            attrs.record(&mut NameField(&self.0));
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &span::Record) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let rules = rules! {
+        "main" => and![ref_rule!("as"), lit!("b")],
+        "as"   => rep!(lit!("a"), 1)
+    };
+    let names = Arc::new(Mutex::new(vec![]));
+    tracing::subscriber::with_default(Rules(names.clone()), || {
+        assert!(parse(Status::init("aab", &rules)).is_ok());
+    });
+    assert_eq!(*names.lock().unwrap(), vec!["main", "as"]);
+}
+
+#[test]
+fn test_parse_extract_backtracking() {
+    let rules = rules! {
+        "main"  => or![
+                        and![ref_rule!("num"), lit!("!")],
+                        and![ref_rule!("num"), label!("tail", lit!("?"))]
+                    ],
+        "num"   => rep!(ematch!(chlist "", from '0', to '9'), 1)
+    };
+    let names = ["num", "tail"];
+
+    let status = Status::init("12?", &rules)
+        .set_fail_fast(true)
+        .set_extract(&names);
+    let (status, _) = parse(status).ok().unwrap();
+    //  the match on the failed branch is discarded
+    assert_eq!(
+        status.extracted,
+        vec![
+            ("num".to_string(), "12".to_string()),
+            ("tail".to_string(), "?".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_parse_captures_backtracking() {
+    let rules = rules! {
+        "main"  => or![
+                        and![label!("word", ref_rule!("word")), lit!("!")],
+                        and![label!("word", ref_rule!("word")), label!("tail", lit!("?"))]
+                    ],
+        "word"  => rep!(ematch!(chlist "ñé", from 'a', to 'z'), 1)
+    };
+
+    let text = "añé?";
+    let parsed = crate::parse_with_captures(text, &rules).unwrap();
+    //  the capture on the failed branch is discarded
+    assert_eq!(parsed.captures.len(), 2);
+    let word = &parsed.captures["word"];
+    assert_eq!(word.len(), 1);
+    assert_eq!((word[0].start.n, word[0].end.n), (0, 3));
+    assert_eq!(word[0].text(text), "añé");
+    assert_eq!(parsed.captures["tail"][0].text(text), "?");
+
+    assert!(crate::parse_with_warnings(text, &rules)
+        .unwrap()
+        .captures
+        .is_empty());
+}
+
+#[test]
+fn test_parse_kinds_backtracking() {
+    use crate::ast::kind::NodeKind;
+
+    let rules = rules! {
+        "main"  => or![
+                        and![ref_rule!("num"), not!(lit!("x")), lit!("!")],
+                        and![ref_rule!("num"), dot!(), custom!("end", |rest, _| Some(rest.len()))]
+                    ],
+        "num"   => rep!(ematch!(chlist "", from '0', to '9'), 1)
+    };
+
+    let text = "12?ab";
+    let ast = crate::parse_with_kinds(text, &rules, &crate::ParseOptions::default()).unwrap();
+    let kinds = |nodes: &[crate::ast::Node<crate::ast::kind::Leaf>]| {
+        nodes.iter().map(|n| n.kind()).collect::<Vec<_>>()
+    };
+    //  the values on the failed branch are discarded
+    let (_, nodes) = ast.rule_parts();
+    assert_eq!(
+        kinds(nodes),
+        vec![
+            NodeKind::Rule("num".to_string()),
+            NodeKind::Dot,
+            NodeKind::Custom("end".to_string())
+        ]
+    );
+    assert_eq!(kinds(nodes[0].rule_parts().1), vec![NodeKind::Class; 2]);
+    assert_eq!(ast.without_kinds(), crate::parse(text, &rules).unwrap());
+}
+
+#[test]
+fn test_parse_recovering_lines() {
+    let rules = crate::rules_from_peg(
+        r#"
+        %recover lines
+        main    =   (item  "\n")*  eof
+        item    =   'ñ='  [0-9]+
+        "#,
+    )
+    .unwrap();
+
+    let recovered = crate::parse_recovering("ñ=1\nñ=x\nx\nñ=2\nñ=", &rules);
+    let lines: Vec<(usize, usize)> = recovered
+        .errors
+        .iter()
+        .map(|e| (e.pos.row, e.pos.start_line))
+        .collect();
+    assert_eq!(lines, vec![(1, 4), (2, 8), (4, 14)]);
+    assert_eq!(recovered.skipped, vec![1, 2, 4]);
+    assert!(recovered
+        .errors
+        .iter()
+        .all(|e| e.pos.n == e.pos.start_line + e.pos.col));
+    assert_eq!(
+        recovered.ast,
+        Some(crate::parse("ñ=1\nñ=2\n", &rules).unwrap())
+    );
+
+    //  without strategy, it stops on the first error
+    let rules = rules! {"main" => rep!(and!(lit!("a"), lit!("\n")), 0)};
+    let recovered = crate::parse_recovering("a\nb\na\n", &rules);
+    assert!(recovered.ast.is_none());
+    assert_eq!(recovered.errors.len(), 1);
+    assert_eq!(recovered.errors[0].pos.row, 1);
+    assert!(recovered.skipped.is_empty());
+}
+
+#[test]
+fn test_parse_recovering_lines_resume() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    let rules = rules! {
+        "main"  =>  rep!(and!(lit!("n"), ref_rule!("item"), lit!("\n")), 0),
+        "item"  =>  custom!("digit", |rest, _| {
+                        CALLS.fetch_add(1, Ordering::SeqCst);
+                        rest.chars().next().filter(char::is_ascii_digit).map(|_| 1)
+                    })
+    }
+    .set_meta("main", "recover", Some("lines"));
+
+    //  the accepted lines are not parsed again after every error
+    let input = "n1\nnx\n".repeat(50);
+    let recovered = crate::parse_recovering(&input, &rules);
+    assert_eq!(recovered.errors.len(), 50);
+    assert_eq!(
+        recovered.skipped,
+        (0..50).map(|i| 2 * i + 1).collect::<Vec<_>>()
+    );
+    assert_eq!(CALLS.load(Ordering::SeqCst), 100);
+    assert_eq!(
+        recovered.ast,
+        Some(crate::parse(&"n1\n".repeat(50), &rules).unwrap())
+    );
+}
+
+#[test]
+fn test_parse_recovering_sync() {
+    use crate::ast::Node;
+
+    let rules = crate::rules_from_peg(
+        r#"
+        %recover sync
+        main    =   stmt*  eof
+
+        @sync(";")
+        stmt    =   'let '  name  ' = '  (block / [0-9]+)  ';'
+        @sync("}")
+        block   =   '{'  stmt*  '}'
+        name    =   [a-z]+
+        "#,
+    )
+    .unwrap();
+
+    let text = "let a = 1;let b = {let c = x;let d = 2;};let 9;let e = {let f = 3;+};";
+    let recovered = crate::parse_recovering(text, &rules);
+    let errors: Vec<(usize, &str)> = recovered
+        .errors
+        .iter()
+        .map(|e| (e.pos.n, e.descr.as_str()))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (28, "expected one of: <{>, [0-9]"),
+            (46, "match. expected  [('a', 'z')]"),
+            (67, "expected one of: <let >, <}>")
+        ]
+    );
+    assert!(recovered.skipped.is_empty());
+
+    //  the skipped text is the value of the failed rule
+    let ast = recovered.ast.unwrap().compact();
+    let skipped = |rule: &str, text: &str| Node::Rule((rule.to_string(), vec![Node::Val(text.to_string())]));
+    let stmts = ast.rule_parts().1;
+    assert_eq!(stmts.len(), 5);
+    assert_eq!(stmts[2], skipped("stmt", "let 9;"));
+    assert_eq!(
+        stmts[3].rule_parts().1[3],
+        skipped("block", "{let f = 3;+}")
+    );
+
+    //  parse doesn't recover
+    assert!(crate::parse(text, &rules).is_err());
+    assert!(crate::rules_from_peg("@sync main = 'a'").is_err());
+}
+
+#[test]
+fn test_skip_bom_and_shebang() {
+    use crate::{parse_with, ParseOptions};
+
+    let rules = rules! {"main" => rep!(and!(lit!("a"), lit!("\n")), 0)};
+    let options = |skip_bom, skip_shebang| ParseOptions {
+        skip_bom,
+        skip_shebang,
+        ..ParseOptions::default()
+    };
+    let parse =
+        |text, skip_bom, skip_shebang| parse_with(text, &rules, &options(skip_bom, skip_shebang));
+
+    assert!(parse("\u{feff}a\n", false, false).is_err());
+    assert!(parse("\u{feff}a\n", true, false).is_ok());
+    assert!(parse("#!run\na\n", false, true).is_ok());
+    assert!(parse("\u{feff}#!run\na\n", false, true).is_err());
+    assert!(parse("\u{feff}#!run\na\n", true, true).is_ok());
+    assert!(parse("#!run", false, true).is_ok());
+    //  only on the first line
+    assert!(parse("a\n#!run\n", false, true).is_err());
+
+    //  positions on the original text
+    let err = parse("\u{feff}#!run\na\nb\n", true, true).unwrap_err();
+    assert_eq!((err.pos.row, err.pos.start_line), (2, 9));
+
+    //  the input starts after the skipped header
+    let rules = rules! {"main" => and!(bof!(), bol!(), lit!("a"))};
+    let parse =
+        |text, skip_bom, skip_shebang| parse_with(text, &rules, &options(skip_bom, skip_shebang));
+    assert!(parse("\u{feff}a", true, false).is_ok());
+    assert!(parse("\u{feff}#!run\na", true, true).is_ok());
+    assert!(parse("\u{feff}a", false, false).is_err());
+}
+
+#[test]
+fn test_parse_with_modes() {
+    use crate::{parse_with, parse_with_builder, parse_with_kinds, ParseOptions};
+
+    let rules = crate::rules_from_peg(
+        r#"
+        main    =   item+
+        item    =   num  /  name
+        num     =   [0-9]+   #Num
+        name    =   [a-z]+
+        "#,
+    )
+    .unwrap();
+
+    //  the modes combine with the other options
+    let options = ParseOptions::default()
+        .trim_trailing_whitespace(true)
+        .capture(true)
+        .decisions(true);
+    let parsed = parse_with("12ab3 \n", &rules, &options).unwrap();
+    assert_eq!(parsed.captures["Num"].len(), 2);
+    let alternatives: Vec<usize> = parsed.decisions.iter().map(|d| d.alternative).collect();
+    assert_eq!(alternatives, vec![0, 1, 0]);
+    assert!(parse_with("12ab3 \n", &rules, &ParseOptions::default()).is_err());
+
+    let prefix = ParseOptions::default().require_eof(false);
+    assert!(parse_with_kinds("12-", &rules, &prefix).is_ok());
+    assert!(parse_with_builder("12-", &rules, &prefix, &mut ()).is_ok());
+    assert!(parse_with_builder("12-", &rules, &ParseOptions::default(), &mut ()).is_err());
+}
+
+#[test]
+fn test_unicode_newlines() {
+    use crate::parser::Newlines;
+    use crate::{parse_with, ParseOptions};
+
+    let rules = rules! {"main" => rep!(and!(lit!("a"), eol!(), dot!()), 0)};
+    let parse = |text, newlines| {
+        let options = ParseOptions {
+            newlines,
+            ..ParseOptions::default()
+        };
+        parse_with(text, &rules, &options)
+    };
+
+    //  by default, only \n
+    assert!(parse("a\na\n", Newlines::default()).is_ok());
+    assert!(parse("a\u{2028}a", Newlines::default()).is_err());
+
+    let ls = Newlines {
+        ls: true,
+        ..Newlines::default()
+    };
+    assert!(parse("a\u{2028}a\n", ls).is_ok());
+    assert!(parse("a\u{2029}", ls).is_err());
+    assert!(parse("a\u{85}a\u{2029}", Newlines::unicode()).is_ok());
+
+    let err = parse("a\u{2028}a\u{2028}bc", ls).unwrap_err();
+    assert_eq!((err.pos.row, err.pos.col, err.pos.start_line), (2, 1, 4));
+    assert_eq!(err.line_before, "b");
+    assert_eq!(err.line_after, "c");
+    //  render splits the lines as the parser counts them
+    assert_eq!(
+        err.render("a\u{2028}a\u{2028}bc", 1),
+        "expected literal: <a> (row 2, col 1)\n1 | a\n2 | bc\n  |  ^"
+    );
+
+    let err = parse("a\u{85}b", Newlines::unicode()).unwrap_err();
+    assert_eq!((err.pos.row, err.pos.col), (1, 1));
+}
+
+#[test]
+fn test_memoize() {
+    use crate::{parse_with, ParseOptions};
+
+    //  without memoization, it's exponential with the nesting
+    let rules = crate::rules_from_peg(
+        r#"
+        main    =   expr
+        expr    =   term  '+'  expr
+                /   term
+        term    =   '('  expr  ')'
+                /   warning("number")  [0-9]+
+        "#,
+    )
+    .unwrap();
+    let memoize = ParseOptions {
+        memoize: true,
+        ..ParseOptions::default()
+    };
+
+    //  same result
+    for input in &["((1+2)+3)", "(1+(2+3)+4)", "(1+2", "((1)+2)+"] {
+        let plain = parse_with(input, &rules, &ParseOptions::default());
+        let memoized = parse_with(input, &rules, &memoize);
+        match (plain, memoized) {
+            (Ok(plain), Ok(memoized)) => {
+                assert!(plain.ast == memoized.ast);
+                assert_eq!(plain.warnings, memoized.warnings);
+            }
+            (Err(plain), Err(memoized)) => {
+                assert_eq!(plain.pos, memoized.pos);
+                assert_eq!(plain.descr, memoized.descr);
+            }
+            _ => panic!("different result on {}", input),
+        }
+    }
+
+    //  2^10 on the plain parser
+    let input = format!("{}1{}", "(".repeat(10), ")".repeat(10));
+    let parsed = parse_with(&input, &rules, &memoize).unwrap();
+    assert_eq!(parsed.warnings.len(), 1);
+}
+
+#[test]
+fn test_memoize_exclusive_modes() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    //  the memoized results don't keep the shared logs
+    let rules = rules! {"main" => lit!("a")};
+    let init = || Status::init("a", &rules).set_memoize(true);
+    assert!(init().can_memoize());
+
+    assert!(!init().set_capture(true).can_memoize());
+    assert!(!init().set_extract(&["main"]).can_memoize());
+    assert!(!init()
+        .set_tree_events(Rc::new(RefCell::new(vec![])))
+        .can_memoize());
+    assert!(!init()
+        .set_leaf_kinds(Rc::new(RefCell::new(vec![])))
+        .can_memoize());
+    assert!(!init()
+        .set_decisions(Rc::new(RefCell::new(vec![])))
+        .can_memoize());
+    assert!(!init()
+        .set_recovered(Rc::new(RefCell::new(vec![])))
+        .can_memoize());
+}
+
+#[test]
+fn test_stack_limit() {
+    use crate::parser::ErrPriority;
+    use crate::{parse, parse_with, ParseOptions};
+
+    //  left recursion, an error instead of a stack overflow
+    let rules = crate::rules_from_peg("main = main 'a' / 'a'").unwrap();
+    let err = parse("aaa", &rules).unwrap_err();
+    assert_eq!(err.priority, ErrPriority::Critical);
+    assert!(err.descr.contains("left recursion?"));
+
+    //  deep nested input, with a configured limit
+    let rules = crate::rules_from_peg("main = '(' main ')' / 'x'").unwrap();
+    let input = format!("{}x{}", "(".repeat(10), ")".repeat(10));
+    assert!(parse(&input, &rules).is_ok());
+    let small = ParseOptions::default().max_stack(1024);
+    let err = parse_with(&input, &rules, &small).unwrap_err();
+    assert!(err.descr.starts_with("too deep nesting of rules on rule main"));
+}
+
+#[test]
+fn test_measured_max_stack() {
+    use crate::{parse_with, ParseOptions};
+
+    let rules = crate::rules_from_peg("main = '(' main ')' / 'x'").unwrap();
+    let nested = |n: usize| format!("{}x{}", "(".repeat(n), ")".repeat(n));
+    let fixed = ParseOptions::default().max_stack(crate::parser::MAX_STACK);
+
+    //  on a thread with a big stack, the limit is measured on it
+    std::thread::Builder::new()
+        .stack_size(64 * 1024 * 1024)
+        .spawn(move || {
+            assert!(crate::parser::default_max_stack() > 32 * 1024 * 1024);
+            let n = (6..)
+                .map(|exp| 1 << exp)
+                .find(|&n| parse_with(&nested(n), &rules, &fixed).is_err())
+                .unwrap();
+            assert!(crate::parse(&nested(n), &rules).is_ok());
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_left_recursion() {
+    use crate::ast::builder::AstBuilder;
+
+    let rules = crate::rules_from_peg(
+        r#"
+        main    =   sum  eof
+        sum     =   prod  ('+' / '-')  term
+                /   term
+        prod    =   sum
+        term    =   term  '*'  num
+                /   num
+        num     =   [0-9]+
+        "#,
+    )
+    .unwrap()
+    .left_recursion(true);
+
+    //  direct (term) and indirect (sum, prod) left recursion
+    let ast = crate::parse("1*2-3", &rules).unwrap();
+    assert_eq!(
+        ast.compact().to_sexpr(),
+        concat!(
+            r#"(main (sum (prod (sum (term (term (num "1")) "*" (num "2")))) "-""#,
+            r#" (term (num "3"))) "EOF")"#
+        )
+    );
+
+    //  the error of the longest attempt
+    let err = crate::parse("1+2*", &rules).unwrap_err();
+    assert_eq!(err.pos.n, 4);
+    assert!(crate::parse("", &rules).is_err());
+
+    //  same tree with a builder
+    let build = |input: &str| {
+        let mut builder = AstBuilder::default();
+        crate::parse_with_builder(input, &rules, &crate::ParseOptions::default(), &mut builder)
+            .map(|_| builder.finish())
+    };
+    for input in &["1", "1+2", "1*2*3-4*5+6"] {
+        let ast = crate::parse(input, &rules).unwrap();
+        assert_eq!(build(input).unwrap(), Some(ast), "input {:?}", input);
+    }
+}
+
+#[test]
+fn test_empty_repetition() {
+    //  it stops when nothing is consumed, instead of repeating forever
+    let rules = rules! {
+        "main"  =>  and!(rep!(or!(lit!("a"), not!(not!(lit!("b")))), 0), lit!("b"))
+    };
+    assert!(crate::parse("aab", &rules).is_ok());
+    let rules = crate::rules_from_peg("main = eof*").unwrap();
+    assert!(crate::parse("", &rules).is_ok());
+}
+
+#[test]
+fn test_ast_builder_same_tree() {
+    use crate::ast::builder::AstBuilder;
+
+    let rules = crate::rules_from_peg(
+        r#"
+        main    =   (stmt  _)*  eof
+        stmt    =   call  ';'  /  sum  ';'  /  name  '='  (sum  #Value)  ';'
+        @raw
+        call    =   name  _  '('  _  ')'
+        @passthrough
+        sum     =   num  ('+'  num)*
+        @passthrough
+        num     =   hex  /  dec
+        dec     =   [0-9]+
+        hex     =   '0x'  [0-9a-f]+
+        name    =   [a-z]+
+        _       =   ' '*
+        "#,
+    )
+    .unwrap();
+
+    let build = |input: &str| {
+        let mut builder = AstBuilder::default();
+        crate::parse_with_builder(input, &rules, &crate::ParseOptions::default(), &mut builder)
+            .map(|_| builder.finish())
+    };
+    for input in &["", "f ( );", "1;", "1+0x2+3; a=4;", "a=0x1f+2; g(); 7;"] {
+        let ast = crate::parse(input, &rules).unwrap();
+        assert_eq!(build(input).unwrap(), Some(ast), "input {:?}", input);
+    }
+    assert!(build("a=;").is_err());
+}
+
+#[test]
+fn test_error_render_context_limits() {
+    let rules = rules! {"main" => rep!(and!(lit!("ab"), lit!("\n")), 0)};
+    let input = "ab\r\naX\r\nab";
+
+    let error = crate::parse(input, &rules).unwrap_err();
+    //  no lines before the first one, nor after the last one
+    assert_eq!(
+        error.render(input, 5),
+        "expected literal: <\\n> (row 0, col 0)\n0 | ab\n  |   ^\n1 | aX\n2 | ab"
+    );
+    assert_eq!(
+        error.render(input, 0),
+        "expected literal: <\\n> (row 0, col 0)\n0 | ab\n  |   ^"
+    );
+}
+
+#[test]
+fn test_expected_tokens() {
+    use crate::codes::Code;
+    let error = |peg: &str, input: &str| {
+        let rules = crate::rules_from_peg(peg).unwrap();
+        crate::parse(input, &rules).unwrap_err()
+    };
+
+    let e = error("main = 'a' / 'b' / [_0-9]", "c");
+    assert_eq!(e.descr, "expected one of: <a>, <b>, [_0-9]");
+    assert_eq!(e.expected, vec!["<a>", "<b>", "[_0-9]"]);
+    assert_eq!(e.code, Code::ExpectedOneOf);
+    assert_eq!(e.pos.n, 1);
+
+    //  the repetition could continue
+    let e = error("main = 'a'* ('b' / 'a'*  'c')", "aax");
+    assert_eq!(e.descr, "expected one of: <a>, <b>, <c>");
+
+    //  the deepest failure wins
+    let e = error("main = 'abc' / 'x'", "abd");
+    assert_eq!(e.descr, "expected literal: <abc>");
+    assert_eq!(e.expected, vec!["<abc>"]);
+    assert_eq!(e.code, Code::ExpectedLiteral);
+
+    let e = error("main = 'a'? 'b'i / [x-z]", "c");
+    assert_eq!(e.expected, vec!["<a>", "<b> (no case)", "[x-z]"]);
+    let e = error("main = 'a' / .", "");
+    assert_eq!(e.descr, "expected one of: <a>, any char");
+
+    let e = error("main = !'if' [a-z]+", "if");
+    assert_eq!(e.descr, "unexpected <if>");
+    assert!(e.expected.is_empty());
+}
+
+#[test]
+fn test_decisions_on_accepted_path() {
+    let rules = crate::rules_from_peg(
+        r#"
+        main    =   first  /  second
+        first   =   ('a' / 'b')  'x'
+        second  =   ('a' / 'c')  'y'
+        "#,
+    )
+    .unwrap();
+
+    let (_, decisions) = crate::parse_with_decisions("ay", &rules).unwrap();
+    let matched: Vec<(&str, usize, usize, usize)> = decisions
+        .iter()
+        .map(|d| (d.rule.as_str(), d.index, d.alternative, d.span.end.n))
+        .collect();
+    //  the 'a' matched on first was discarded
+    assert_eq!(matched, vec![("main", 0, 1, 2), ("second", 0, 0, 1)]);
+    assert_eq!(decisions[0].alternatives, 2);
+
+    assert!(crate::parse_with_decisions("az", &rules).is_err());
+}
+
+#[test]
+fn test_scratch_buffers() {
+    use crate::ast::Node;
+
+    fn exact_children(node: &Node) -> bool {
+        match node {
+            Node::Rule((_, nodes)) => {
+                nodes.len() == nodes.capacity() && nodes.iter().all(exact_children)
+            }
+            _ => true,
+        }
+    }
+
+    let rules = crate::rules_from_peg(
+        r#"
+        main    =   (item / other)+
+        item    =   [0-9]+  ','   #Item
+        other   =   [0-9]+  ';'  /  ' '
+        "#,
+    )
+    .unwrap();
+
+    let input = "12, 3;45, 6;";
+    let ast = crate::parse(input, &rules).unwrap();
+    assert!(exact_children(&ast));
+    //  reused buffers give the same trees
+    assert_eq!(crate::parse(input, &rules).unwrap(), ast);
+    crate::parser::release_scratch_buffers();
+    assert_eq!(crate::parse(input, &rules).unwrap(), ast);
+}
+
+#[test]
+fn test_determinism() {
+    use crate::parser::expression::RuleAccess;
+    use std::thread;
+
+    //  compiled on every thread, with new hash seeds each time
+    let results = || {
+        let rules = crate::rules_from_peg(
+            r#"
+            main    =   (expr  (';' / eol))+
+            expr    =   expr  ('+' / '-')  num      #Op
+                    /   num
+            num     =   [0-9]+  /  [a-z]+
+            eol     =   "\n"
+            "#,
+        )
+        .unwrap()
+        .left_recursion(true);
+        let memoized = crate::ParseOptions {
+            memoize: true,
+            ..crate::ParseOptions::default()
+        };
+
+        let mut results = vec![];
+        for input in &["1+2-a;3\n", "1+;2", "1-2-3;x", ";", "12+\n"] {
+            for options in &[crate::ParseOptions::default(), memoized.clone()] {
+                let result = crate::parse_with(input, &rules, options);
+                results.push(format!("{:?}", result));
+            }
+        }
+        //  several access violations, always the same one reported
+        let host = crate::rules_from_peg("main = a b \n a = 'a' \n b = 'b'")
+            .unwrap()
+            .set_access("a", RuleAccess::Sealed)
+            .set_access("b", RuleAccess::Internal);
+        let plugin = crate::rules_from_peg("z = b \n b = 'x' \n a = 'y'").unwrap();
+        results.push(format!("{:?}", host.merge_plugin(plugin)));
+        results
+    };
+
+    let expected = results();
+    let threads: Vec<_> = (0..8).map(|_| thread::spawn(results)).collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), expected);
+    }
+    assert_eq!(results(), expected);
+}
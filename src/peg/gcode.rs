@@ -52,8 +52,68 @@ pub fn rust_from_rules(rules: &expression::SetOfRules) -> String {
         crules + "\n       " + begin + rule
     };
 
-    rules.0.iter().fold("".to_string(), |acc, (name, expr)| {
-        add_rule(acc, &rule2code(name, expr))
+    rules.names().into_iter().fold("".to_string(), |acc, name| {
+        add_rule(acc, &rule2code(name, &rules.exprs[name]))
+    })
+}
+
+/// Generate a string with the rust code to add the annotations and the
+/// access policy of a ```expression::SetOfRules```
+///
+/// They are method calls to chain after the rules generated by
+/// ```rust_from_rules``` (```@raw```, ```@passthrough```, ```@sync```,
+/// ```%recover```... are lost without them). ```RuleAccess``` has to be
+/// imported
+///
+/// ```rust
+/// use dynparser::{peg, rules_from_peg};
+///
+/// let rules = rules_from_peg("main = a \n @raw \n a = 'a'").unwrap();
+/// assert_eq!(
+///     peg::gcode::rust_meta_from_rules(&rules),
+///     "\n        .set_meta(r#\"a\"#, \"raw\", None)"
+/// );
+/// ```
+pub fn rust_meta_from_rules(rules: &expression::SetOfRules) -> String {
+    let add_call = |calls: String, call: &str| -> String { calls + "\n        ." + call };
+
+    let calls = rules.names().into_iter().fold("".to_string(), |acc, name| {
+        let acc = match rules.rule_meta(name) {
+            Some(meta) => meta.iter().fold(acc, |acc, (key, value)| {
+                add_call(acc, &meta2code(name, key, value))
+            }),
+            None => acc,
+        };
+        match rules.access(name) {
+            expression::RuleAccess::Extensible => acc,
+            access => add_call(
+                acc,
+                &format!(r##"set_access(r#"{}"#, RuleAccess::{:?})"##, name, access),
+            ),
+        }
+    });
+    if rules.allows_left_recursion() {
+        add_call(calls, "left_recursion(true)")
+    } else {
+        calls
+    }
+}
+
+fn meta2code(name: &str, key: &str, value: Option<&str>) -> String {
+    let value = value.map_or("None".to_string(), |v| {
+        format!(r#"Some("{}")"#, replace_esc(v))
+    });
+    format!(
+        r##"set_meta(r#"{}"#, "{}", {})"##,
+        name,
+        replace_esc(key),
+        value
+    )
+}
+
+fn keywords2code(keywords: &[String]) -> String {
+    keywords.iter().fold(String::new(), |acc, k| {
+        format!(r#"{}, "{}""#, acc, replace_esc(k))
     })
 }
 
@@ -61,7 +121,7 @@ fn rule2code(name: &str, expr: &Expression) -> String {
     format!(r##"r#"{}"# => {}"##, name, expr2code(expr))
 }
 
-fn expr2code(expr: &Expression) -> String {
+pub(crate) fn expr2code(expr: &Expression) -> String {
     match expr {
         Expression::Simple(atom) => atom2code(atom),
         Expression::And(mexpr) => format!("and!({})", mexpr2code(mexpr)),
@@ -69,6 +129,20 @@ fn expr2code(expr: &Expression) -> String {
         Expression::Not(e) => format!("not!({})", expr2code(e)),
         Expression::Repeat(rep) => repeat2code(rep),
         Expression::RuleName(rname) => format!(r##"ref_rule!(r#"{}"#)"##, rname),
+        Expression::Labeled(label, e) => {
+            format!(r##"label!(r#"{}"#, {})"##, label, expr2code(e))
+        }
+        Expression::NotKeyword(keywords, e) => {
+            format!("not_keyword!({}{})", expr2code(e), keywords2code(keywords))
+        }
+        Expression::Keywords(keywords, e) => {
+            format!("keywords!({}{})", expr2code(e), keywords2code(keywords))
+        }
+        Expression::Deprecated(message, e) => format!(
+            r#"deprecated!("{}", {})"#,
+            replace_esc(message),
+            expr2code(e)
+        ),
     }
 }
 
@@ -82,20 +156,45 @@ fn mexpr2code(mexpr: &expression::MultiExpr) -> String {
         })
 }
 
-fn atom2code(atom: &Atom) -> String {
-    let replace_esc = |s: String| {
-        s.replace("\n", r#"\n"#)
-            .replace("\r", r#"\r"#)
-            .replace("\t", r#"\t"#)
-            .replace(r#"""#, r#"\""#)
-    };
+fn replace_esc(s: &str) -> String {
+    s.replace(r#"\"#, r#"\\"#)
+        .replace("\n", r#"\n"#)
+        .replace("\r", r#"\r"#)
+        .replace("\t", r#"\t"#)
+        .replace(r#"""#, r#"\""#)
+}
 
+fn atom2code(atom: &Atom) -> String {
     match atom {
-        Atom::Literal(s) => format!(r#"lit!("{}")"#, replace_esc(s.to_string())),
-        Atom::Error(s) => format!(r#"error!("{}")"#, replace_esc(s.to_string())),
+        Atom::Literal(s) => format!(r#"lit!("{}")"#, replace_esc(s)),
+        Atom::Char(ch) => format!("lit_char!({:?})", ch),
+        Atom::LiteralNoCase(s) => format!(r#"no_case!(lit!("{}"))"#, replace_esc(s)),
+        Atom::Error(s) => format!(r#"error!("{}")"#, replace_esc(s)),
+        Atom::Warning(s) => format!(r#"warning!("{}")"#, replace_esc(s)),
+        Atom::Pos(name) => format!(r#"pos!("{}")"#, name),
         Atom::Match(mrules) => match_rules2code(mrules),
+        Atom::MatchNoCase(mrules) => format!("no_case!({})", match_rules2code(mrules)),
         Atom::Dot => "dot!()".to_string(),
+        Atom::DotNoNewline => "dot_no_newline!()".to_string(),
         Atom::EOF => "eof!()".to_string(),
+        Atom::BOF => "bof!()".to_string(),
+        Atom::BOL => "bol!()".to_string(),
+        Atom::EOL => "eol!()".to_string(),
+        Atom::Until(d, None) => format!(r#"until!("{}")"#, replace_esc(d)),
+        Atom::Until(d, Some(e)) => {
+            format!(r#"until!("{}", "{}")"#, replace_esc(d), replace_esc(e))
+        }
+        Atom::Balanced(o, c) => {
+            format!(r#"balanced!("{}", "{}")"#, replace_esc(o), replace_esc(c))
+        }
+        //  the function has to be in scope with the same name
+        Atom::Custom(c) => format!(r#"custom!("{0}", {0})"#, c.name()),
+        #[cfg(feature = "unicode")]
+        Atom::XidStart => "xid_start!()".to_string(),
+        #[cfg(feature = "unicode")]
+        Atom::XidContinue => "xid_continue!()".to_string(),
+        #[cfg(feature = "graphemes")]
+        Atom::Grapheme => "grapheme!()".to_string(),
     }
 }
 
@@ -103,17 +202,26 @@ fn match_rules2code(mrules: &atom::MatchRules) -> String {
     fn bounds2code(acc: String, bounds: &[(char, char)]) -> String {
         match bounds.split_first() {
             Some(((f, t), rest)) => {
-                format!(", from '{}', to '{}' {}", f, t, bounds2code(acc, rest))
+                format!(", from {:?}, to {:?} {}", f, t, bounds2code(acc, rest))
             }
             None => acc,
         }
     }
 
-    format!(
-        r##"ematch!(chlist r#"{}"#  {})"##,
-        &mrules.0,
-        bounds2code(String::new(), &mrules.1)
-    )
+    let not = if mrules.is_negated() { "not " } else { "" };
+    if mrules.1.is_empty() {
+        format!(
+            r##"ematch!({}chlist r#"{}"#, from2 vec![])"##,
+            not, &mrules.0
+        )
+    } else {
+        format!(
+            r##"ematch!({}chlist r#"{}"#  {})"##,
+            not,
+            &mrules.0,
+            bounds2code(String::new(), &mrules.1)
+        )
+    }
 }
 
 fn repeat2code(rep: &expression::RepInfo) -> String {
@@ -0,0 +1,289 @@
+#![warn(missing_docs)]
+//! Generate markdown documentation from a set of rules
+//!
+//! A section per rule (on declaration order) with...
+//!
+//! * The text of the ```@doc("...")``` annotation
+//! * The rule in peg notation
+//! * Other annotations and the access policy, if any
+//! * The rules it references and the rules referencing it, as links
+//! * Optionally, a link to a railroad diagram
+//!
+//! example
+//! ```rust
+//! extern crate dynparser;
+//! use dynparser::rules_from_peg;
+//!
+//! fn main() {
+//!     let rules = rules_from_peg(
+//!         r#"
+//!
+//!             main    =   num  (_ '+' _ num)*
+//!
+//!             @doc("An integer number")
+//!             num     =   [0-9]+
+//!
+//!             _       =   ' '*
+//!
+//! "#,
+//!     ).unwrap();
+//!
+//!     let doc = rules.to_markdown();
+//!     assert!(doc.contains("main = num  (_  '+'  _  num)*"));
+//!     assert!(doc.contains("An integer number"));
+//!     assert!(doc.contains("Referenced by: [main](#main)"));
+//! }
+//! ```
+//!
+//! Expressions built from peg sugar (i.e. ```*?``` or ```assert```) are
+//! written expanded
+
+use crate::parser::{
+    atom::{self, Atom},
+    expression::{self, Expression, RuleAccess},
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Generate the markdown documentation of a ```expression::SetOfRules```
+pub fn markdown_from_rules(rules: &expression::SetOfRules) -> String {
+    markdown(rules, None)
+}
+
+/// Same as ```markdown_from_rules```, with a link to a railroad diagram
+/// per rule
+///
+/// ```{rule}``` on the url will be replaced by the rule name
+pub fn markdown_with_diagrams(rules: &expression::SetOfRules, url: &str) -> String {
+    markdown(rules, Some(url))
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  I N T E R N A L
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+fn markdown(rules: &expression::SetOfRules, diagrams: Option<&str>) -> String {
+    let references: BTreeMap<&str, BTreeSet<&str>> = rules
+        .exprs
+        .iter()
+        .map(|(name, expr)| {
+            let refs = expression::rule_references(expr).into_iter().collect();
+            (name.as_str(), refs)
+        })
+        .collect();
+    let mut referenced_by: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (name, refs) in &references {
+        for r in refs {
+            referenced_by.entry(r).or_default().insert(name);
+        }
+    }
+
+    let names = rules.names();
+    let index = names.iter().fold(String::new(), |acc, name| {
+        acc + &format!("- {}\n", link(name))
+    });
+    let md = names
+        .iter()
+        .fold(format!("# Grammar\n\n{}\n", index), |acc, name| {
+            let section = RuleSection {
+                name,
+                expr: &rules.exprs[*name],
+                meta: rules.rule_meta(name),
+                access: rules.access(name),
+                refs: &references[name],
+                referenced_by: referenced_by.get(name),
+                diagrams,
+            };
+            acc + &section.to_markdown()
+        });
+    md.trim_end().to_string() + "\n"
+}
+
+struct RuleSection<'a> {
+    name: &'a str,
+    expr: &'a Expression,
+    meta: Option<&'a expression::RuleMeta>,
+    access: RuleAccess,
+    refs: &'a BTreeSet<&'a str>,
+    referenced_by: Option<&'a BTreeSet<&'a str>>,
+    diagrams: Option<&'a str>,
+}
+
+impl<'a> RuleSection<'a> {
+    fn to_markdown(&self) -> String {
+        let mut md = format!("<a name=\"{0}\"></a>\n## {0}\n\n", self.name);
+        if let Some(doc) = self.meta.and_then(|m| m.value("doc")) {
+            md += &format!("{}\n\n", doc);
+        }
+        md += &format!("```peg\n{} = {}\n```\n\n", self.name, expr2peg(self.expr));
+
+        let annotations: Vec<String> = self
+            .meta
+            .iter()
+            .flat_map(|m| m.iter())
+            .filter(|(key, _)| *key != "doc")
+            .map(|(key, value)| match value {
+                Some(v) => format!("`@{}(\"{}\")`", key, replace_esc(v)),
+                None => format!("`@{}`", key),
+            })
+            .collect();
+        if !annotations.is_empty() {
+            md += &format!("Annotations: {}\n\n", annotations.join(", "));
+        }
+        match self.access {
+            RuleAccess::Extensible => (),
+            RuleAccess::Sealed => md += "Access: sealed\n\n",
+            RuleAccess::Internal => md += "Access: internal\n\n",
+        }
+        if !self.refs.is_empty() {
+            md += &format!("References: {}\n\n", links(self.refs.iter()));
+        }
+        if let Some(by) = self.referenced_by {
+            md += &format!("Referenced by: {}\n\n", links(by.iter()));
+        }
+        if let Some(url) = self.diagrams {
+            md += &format!(
+                "[Railroad diagram]({})\n\n",
+                url.replace("{rule}", self.name)
+            );
+        }
+        md
+    }
+}
+
+fn link(name: &str) -> String {
+    format!("[{0}](#{0})", name)
+}
+
+fn links<'a>(names: impl Iterator<Item = &'a &'a str>) -> String {
+    names.map(|n| link(n)).collect::<Vec<_>>().join(", ")
+}
+
+//  precedence levels, to add parenthesis when needed
+const OR: u8 = 0;
+const AND: u8 = 1;
+const UNARY: u8 = 2;
+const ATOM: u8 = 3;
+
+pub(crate) fn expr2peg(expr: &Expression) -> String {
+    expr2peg_level(expr).0
+}
+
+fn expr2peg_level(expr: &Expression) -> (String, u8) {
+    match expr {
+        Expression::Simple(atom) => (atom2peg(atom), ATOM),
+        Expression::RuleName(name) => (name.clone(), ATOM),
+        Expression::And(mexpr) => {
+            let items: Vec<String> = mexpr.0.iter().map(|e| wrap(e, UNARY)).collect();
+            (items.join("  "), AND)
+        }
+        Expression::Or(mexpr) => {
+            //  labels are at the end of an alternative
+            let items: Vec<String> = mexpr.0.iter().map(|e| wrap(e, OR)).collect();
+            (items.join(" / "), OR)
+        }
+        Expression::Not(e) => (format!("!{}", wrap(e, ATOM)), UNARY),
+        Expression::Repeat(rep) => {
+            let e = wrap(&rep.expression, ATOM);
+            let rep = match (rep.min.0, rep.max.as_ref().map(|m| m.0)) {
+                (0, None) => format!("{}*", e),
+                (1, None) => format!("{}+", e),
+                (0, Some(1)) => format!("{}?", e),
+                (min, None) => format!("{}{{{},}}", e, min),
+                (min, Some(max)) if min == max => format!("{}{{{}}}", e, min),
+                (min, Some(max)) => format!("{}{{{},{}}}", e, min, max),
+            };
+            (rep, UNARY)
+        }
+        Expression::Labeled(label, e) => (format!("{}  #{}", wrap(e, AND), label), OR),
+        //  written as annotations
+        Expression::Deprecated(_, e)
+        | Expression::NotKeyword(_, e)
+        | Expression::Keywords(_, e) => expr2peg_level(e),
+    }
+}
+
+fn wrap(expr: &Expression, level: u8) -> String {
+    match expr2peg_level(expr) {
+        (peg, l) if l < level => format!("({})", peg),
+        (peg, _) => peg,
+    }
+}
+
+fn atom2peg(atom: &Atom) -> String {
+    match atom {
+        Atom::Literal(s) => lit2peg(s),
+        Atom::Char(ch) => lit2peg(&ch.to_string()),
+        Atom::LiteralNoCase(s) => format!("{}i", lit2peg(s)),
+        Atom::Match(mrules) => match2peg(mrules),
+        Atom::MatchNoCase(mrules) => format!("{}i", match2peg(mrules)),
+        Atom::Error(s) => format!("error(\"{}\")", replace_esc(s)),
+        Atom::Warning(s) => format!("warning(\"{}\")", replace_esc(s)),
+        Atom::Pos(name) => format!("@pos({})", name),
+        Atom::Dot => ".".to_string(),
+        Atom::DotNoNewline => "any".to_string(),
+        Atom::EOF => "eof".to_string(),
+        Atom::BOF => "bof".to_string(),
+        Atom::BOL => "bol".to_string(),
+        Atom::EOL => "eol".to_string(),
+        Atom::Until(d, None) => format!("until(\"{}\")", replace_esc(d)),
+        Atom::Until(d, Some(e)) => {
+            format!("until(\"{}\", \"{}\")", replace_esc(d), replace_esc(e))
+        }
+        Atom::Balanced(o, c) => {
+            format!("balanced(\"{}\", \"{}\")", replace_esc(o), replace_esc(c))
+        }
+        //  no peg syntax for them
+        Atom::Custom(c) => format!("<{}>", c.name()),
+        #[cfg(feature = "unicode")]
+        Atom::XidStart => "xid_start".to_string(),
+        #[cfg(feature = "unicode")]
+        Atom::XidContinue => "xid_continue".to_string(),
+        #[cfg(feature = "graphemes")]
+        Atom::Grapheme => "grapheme".to_string(),
+    }
+}
+
+fn lit2peg(s: &str) -> String {
+    if s.contains(|ch: char| ch == '\'' || ch == '\\' || ch.is_control()) {
+        format!("\"{}\"", replace_esc(s))
+    } else {
+        format!("'{}'", s)
+    }
+}
+
+fn match2peg(mrules: &atom::MatchRules) -> String {
+    let char2peg = |ch: char| match ch {
+        ']' => r"\x5D".to_string(),
+        '\\' => r"\\".to_string(),
+        '\n' => r"\n".to_string(),
+        '\r' => r"\r".to_string(),
+        '\t' => r"\t".to_string(),
+        ch => ch.to_string(),
+    };
+    let ranges = mrules.1.iter().fold(String::new(), |acc, (from, to)| {
+        acc + &char2peg(*from) + "-" + &char2peg(*to)
+    });
+    //  a '-' before ']' is a char
+    let (dash, chars): (Vec<char>, Vec<char>) = mrules.0.chars().partition(|ch| *ch == '-');
+    let chars: String = chars.into_iter().map(char2peg).collect();
+    let dash = if dash.is_empty() { "" } else { "-" };
+    let class = format!("{}{}{}", ranges, chars, dash);
+    match (mrules.is_negated(), class.strip_prefix('^')) {
+        (true, _) => format!("[^{}]", class),
+        //  a '^' after the '[' is a negation
+        (false, Some(rest)) => format!(r"[\x5E{}]", rest),
+        (false, None) => format!("[{}]", class),
+    }
+}
+
+fn replace_esc(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('\n', r"\n")
+        .replace('\r', r"\r")
+        .replace('\t', r"\t")
+        .replace('"', "\\\"")
+}
@@ -0,0 +1,520 @@
+#![warn(missing_docs)]
+//! Generate rust types from a set of rules
+//!
+//! Once a grammar is stable, it's more comfortable to work with typed
+//! data than with the generic ```ast::Node```
+//!
+//! This module generates rust source code with a type for each rule
+//! and the conversion from the AST
+//!
+//! example
+//! ```rust
+//! extern crate dynparser;
+//! use dynparser::{peg, rules_from_peg};
+//!
+//! fn main() {
+//!     let rules = rules_from_peg(
+//!         r#"
+//!
+//!             main            =   expr
+//!
+//!             expr            =   num  (_ op _ num)*
+//!
+//!             op              =   '+' / '-'
+//!
+//!             num             =   [0-9]+
+//!
+//!             _               =   ' '*
+//!
+//! "#,
+//!     ).unwrap();
+//!
+//!     println!("{}", peg::gtypes::rust_types_from_rules(&rules, &["_"]))
+//! }
+//! ```
+//!
+//! The shape of the types is...
+//!
+//! * Rules without references to other rules will be a ```String```
+//!   with the text consumed
+//! * Sequences (and) will be a tuple struct with a field for each
+//!   element with references to rules
+//! * Alternatives (or) will be an enum. The variants will be named as
+//!   the referenced rule, the label, or ```AltN```
+//! * Repetitions will be a ```Vec``` and optionals an ```Option```
+//!
+//! ```ignore
+//!     #[derive(Debug, Clone, PartialEq)]
+//!     pub struct Expr(pub Box<Num>, pub Vec<(Op, Num)>);
+//! ```
+//!
+//! Every type will have a ```from_ast(&ast::Node)``` function to build it
+//! from the AST returned by ```parse```
+//!
+//! Rules on ```skip``` list (i.e. spaces) will not generate types, and
+//! they will be ignored on the conversion
+
+use crate::parser::expression::{self, Expression};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Generate a string with rust types from a ```expression::SetOfRules```
+///
+/// Rules on ```skip``` will be ignored
+pub fn rust_types_from_rules(rules: &expression::SetOfRules, skip: &[&str]) -> String {
+    let sorted_rules: BTreeMap<&String, &Expression> = rules
+        .exprs
+        .iter()
+        .filter(|(name, _)| !skip.contains(&name.as_str()))
+        .collect();
+    let type_names = type_names(sorted_rules.keys().map(|n| n.as_str()));
+
+    let gen = Gen {
+        type_names,
+        skip: skip.iter().map(|s| s.to_string()).collect(),
+    };
+
+    //  names are chosen on sorted order, the code is on declaration order
+    let code = rules
+        .names()
+        .into_iter()
+        .filter(|name| !skip.contains(name))
+        .fold(String::new(), |acc, name| {
+            acc + &gen.rule2code(name, &rules.exprs[name])
+        });
+
+    prelude(skip) + &code
+}
+
+//-----------------------------------------------------------------------
+//  SUPPORT
+
+//  simplified view of an expression, looking just the rules references
+#[derive(Debug)]
+enum Shape {
+    Text,
+    Ref(String),
+    Seq(Vec<Shape>),
+    Choice(Vec<Shape>),
+    Many(Box<Shape>),
+    Opt(Box<Shape>),
+    Labeled(String, Box<Shape>),
+}
+
+fn shape(expr: &Expression, skip: &BTreeSet<String>) -> Shape {
+    let not_text = |s: &Shape| !matches!(s, Shape::Text);
+
+    match expr {
+        Expression::Simple(_) | Expression::Not(_) => Shape::Text,
+        Expression::RuleName(name) => {
+            if skip.contains(name) {
+                Shape::Text
+            } else {
+                Shape::Ref(name.clone())
+            }
+        }
+        Expression::And(mexpr) => {
+            let mut shapes: Vec<Shape> = mexpr
+                .0
+                .iter()
+                .map(|e| shape(e, skip))
+                .filter(not_text)
+                .collect();
+            match shapes.len() {
+                0 => Shape::Text,
+                1 => shapes.remove(0),
+                _ => Shape::Seq(shapes),
+            }
+        }
+        Expression::Or(mexpr) => {
+            let shapes: Vec<Shape> = mexpr.0.iter().map(|e| shape(e, skip)).collect();
+            if shapes.iter().any(not_text) {
+                Shape::Choice(shapes)
+            } else {
+                Shape::Text
+            }
+        }
+        Expression::Repeat(rep) => match (shape(&rep.expression, skip), &rep.max) {
+            (Shape::Text, _) => Shape::Text,
+            (s, Some(max)) if max.0 == 1 => Shape::Opt(Box::new(s)),
+            (s, _) => Shape::Many(Box::new(s)),
+        },
+        Expression::Labeled(label, e) => Shape::Labeled(label.clone(), Box::new(shape(e, skip))),
+        Expression::Deprecated(_, e)
+        | Expression::NotKeyword(_, e)
+        | Expression::Keywords(_, e) => shape(e, skip),
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let camel: String = name
+        .split(|ch: char| !ch.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect();
+
+    match camel.chars().next() {
+        None => "R".to_string(),
+        Some(ch) if ch.is_ascii_digit() => format!("R{}", camel),
+        _ => camel,
+    }
+}
+
+//  unique type name for each rule name
+fn type_names<'a>(names: impl Iterator<Item = &'a str>) -> BTreeMap<String, String> {
+    names.fold(BTreeMap::new(), |mut acc, name| {
+        let base = camel_case(name);
+        let mut tname = base.clone();
+        let mut n = 1;
+        while acc.values().any(|t| t == &tname) {
+            n += 1;
+            tname = format!("{}{}", base, n);
+        }
+        acc.insert(name.to_string(), tname);
+        acc
+    })
+}
+
+fn variant_names(alts: &[Shape]) -> Vec<String> {
+    alts.iter()
+        .enumerate()
+        .fold(vec![], |mut acc: Vec<String>, (i, s)| {
+            let name = match s {
+                Shape::Ref(r) => camel_case(r),
+                Shape::Labeled(l, _) => camel_case(l),
+                _ => format!("Alt{}", i),
+            };
+            let name = if acc.contains(&name) {
+                format!("{}{}", name, i)
+            } else {
+                name
+            };
+            acc.push(name);
+            acc
+        })
+}
+
+struct Gen {
+    type_names: BTreeMap<String, String>,
+    skip: BTreeSet<String>,
+}
+
+//  code generated for a rule
+//  auxiliary enums and functions will be prefixed with the rule type name
+struct RuleCode {
+    tname: String,
+    fn_prefix: String,
+    counter: usize,
+    items: String,
+}
+
+impl Gen {
+    fn type_name(&self, rule: &str) -> String {
+        self.type_names
+            .get(rule)
+            .cloned()
+            .unwrap_or_else(|| camel_case(rule))
+    }
+
+    fn rule2code(&self, name: &str, expr: &Expression) -> String {
+        let tname = self.type_name(name);
+        let mut rc = RuleCode {
+            tname: tname.clone(),
+            fn_prefix: format!("consume_{}", tname.to_lowercase()),
+            counter: 0,
+            items: String::new(),
+        };
+
+        let (type_def, from_nodes) = match shape(expr, &self.skip) {
+            Shape::Text => (
+                format!("pub struct {}(pub String);\n", tname),
+                format!("        Ok({}(consume_text(nodes).0))\n", tname),
+            ),
+            Shape::Seq(shapes) => {
+                let fields: Vec<(String, String)> = shapes
+                    .iter()
+                    .map(|s| self.shape2code(&mut rc, s, false))
+                    .collect();
+                let consumes = fields
+                    .iter()
+                    .enumerate()
+                    .fold(String::new(), |acc, (i, (_, f))| {
+                        acc + &format!("        let (f{}, nodes) = {}(nodes)?;\n", i, f)
+                    });
+                let types: Vec<String> = fields.iter().map(|(t, _)| format!("pub {}", t)).collect();
+                let values: Vec<String> = (0..fields.len()).map(|i| format!("f{}", i)).collect();
+                (
+                    format!("pub struct {}({});\n", tname, types.join(", ")),
+                    format!(
+                        "{}        check_end(nodes)?;\n        Ok({}({}))\n",
+                        consumes,
+                        tname,
+                        values.join(", ")
+                    ),
+                )
+            }
+            Shape::Choice(alts) => {
+                let (variants, alt_fns) = self.variants(&mut rc, &alts);
+                let tries = variants
+                    .iter()
+                    .zip(alt_fns.iter())
+                    .fold(String::new(), |acc, ((vname, _), f)| {
+                        acc + &format!(
+                            "        if let Ok((v, rest)) = {}(nodes) {{\n            if check_end(rest).is_ok() {{\n                return Ok({}::{}(v));\n            }}\n        }}\n",
+                            f, tname, vname
+                        )
+                    });
+                (
+                    format!("pub enum {} {{\n{}}}\n", tname, variants2code(&variants)),
+                    format!(
+                        "{}        Err(ast::error(\"no alternative matched for {}\", None))\n",
+                        tries, name
+                    ),
+                )
+            }
+            s => {
+                let (t, f) = self.shape2code(&mut rc, &s, false);
+                (
+                    format!("pub struct {}(pub {});\n", tname, t),
+                    format!(
+                        "        let (f0, nodes) = {}(nodes)?;\n        check_end(nodes)?;\n        Ok({}(f0))\n",
+                        f, tname
+                    ),
+                )
+            }
+        };
+
+        format!(
+            r##"
+//  {name}
+#[derive(Debug, Clone, PartialEq)]
+{type_def}
+impl {tname} {{
+    /// Build it from an ```ast::Node::Rule``` named ```{name}```
+    pub fn from_ast(node: &ast::Node) -> Result<Self, ast::Error> {{
+        match node {{
+            ast::Node::Rule((n, nodes)) if n == r#"{name}"# => Self::from_nodes(nodes),
+            _ => Err(ast::error("expected rule {name}", None)),
+        }}
+    }}
+
+    fn from_nodes(nodes: &[ast::Node]) -> Result<Self, ast::Error> {{
+{from_nodes}    }}
+}}
+{items}"##,
+            name = name,
+            type_def = type_def,
+            tname = tname,
+            from_nodes = from_nodes,
+            items = rc.items
+        )
+    }
+
+    fn variants(&self, rc: &mut RuleCode, alts: &[Shape]) -> (Vec<(String, String)>, Vec<String>) {
+        let codes: Vec<(String, String)> = alts
+            .iter()
+            .map(|s| self.shape2code(rc, s, false))
+            .collect();
+        let variants = variant_names(alts)
+            .into_iter()
+            .zip(codes.iter().map(|(t, _)| t.clone()))
+            .collect();
+        (variants, codes.into_iter().map(|(_, f)| f).collect())
+    }
+
+    //  it returns the type for the shape and the name of the function
+    //  to consume it
+    fn shape2code(&self, rc: &mut RuleCode, s: &Shape, in_vec: bool) -> (String, String) {
+        rc.counter += 1;
+        let fn_name = format!("{}_{}", rc.fn_prefix, rc.counter);
+        let fn_code = |t: &str, body: &str| {
+            format!(
+                "\nfn {}<'a>(nodes: &'a [ast::Node]) -> Result<({}, &'a [ast::Node]), ast::Error> {{\n{}}}\n",
+                fn_name, t, body
+            )
+        };
+
+        let (t, body) = match s {
+            Shape::Text => (
+                "String".to_string(),
+                "    Ok(consume_text(nodes))\n".to_string(),
+            ),
+            Shape::Ref(r) => {
+                let tname = self.type_name(r);
+                if in_vec {
+                    (
+                        tname.clone(),
+                        format!(
+                            "    consume_rule(nodes, r#\"{}\"#, {}::from_nodes)\n",
+                            r, tname
+                        ),
+                    )
+                } else {
+                    (
+                        format!("Box<{}>", tname),
+                        format!(
+                            "    consume_rule(nodes, r#\"{}\"#, {}::from_nodes)\n        .map(|(v, nodes)| (Box::new(v), nodes))\n",
+                            r, tname
+                        ),
+                    )
+                }
+            }
+            Shape::Labeled(label, inner) => {
+                let (t, f) = self.shape2code(rc, inner, in_vec);
+                (
+                    t,
+                    format!(
+                        "    consume_rule(nodes, r#\"{}\"#, |nodes| {{\n        let (v, nodes) = {}(nodes)?;\n        check_end(nodes)?;\n        Ok(v)\n    }})\n",
+                        label, f
+                    ),
+                )
+            }
+            Shape::Seq(shapes) => {
+                let fields: Vec<(String, String)> = shapes
+                    .iter()
+                    .map(|s| self.shape2code(rc, s, in_vec))
+                    .collect();
+                let consumes = fields
+                    .iter()
+                    .enumerate()
+                    .fold(String::new(), |acc, (i, (_, f))| {
+                        acc + &format!("    let (f{}, nodes) = {}(nodes)?;\n", i, f)
+                    });
+                let types: Vec<String> = fields.iter().map(|(t, _)| t.clone()).collect();
+                let values: Vec<String> = (0..fields.len()).map(|i| format!("f{}", i)).collect();
+                (
+                    format!("({})", types.join(", ")),
+                    format!("{}    Ok((({}), nodes))\n", consumes, values.join(", ")),
+                )
+            }
+            Shape::Choice(alts) => {
+                let ename = format!("{}Choice{}", rc.tname, rc.counter);
+                let (variants, alt_fns) = self.variants(rc, alts);
+                let tries = variants
+                    .iter()
+                    .zip(alt_fns.iter())
+                    .fold(String::new(), |acc, ((vname, _), f)| {
+                        acc + &format!(
+                            "    if let Ok((v, rest)) = {}(nodes) {{\n        if rest.len() < nodes.len() {{\n            return Ok(({}::{}(v), rest));\n        }} else if fallback.is_none() {{\n            fallback = Some(({}::{}(v), rest));\n        }}\n    }}\n",
+                            f, ename, vname, ename, vname
+                        )
+                    });
+                rc.items += &format!(
+                    "\n#[derive(Debug, Clone, PartialEq)]\npub enum {} {{\n{}}}\n",
+                    ename,
+                    variants2code(&variants)
+                );
+                (
+                    ename.clone(),
+                    format!(
+                        "    let mut fallback = None;\n{}    fallback.ok_or_else(|| ast::error(\"no alternative matched for {}\", None))\n",
+                        tries, ename
+                    ),
+                )
+            }
+            Shape::Many(inner) => {
+                let (t, f) = self.shape2code(rc, inner, true);
+                (
+                    format!("Vec<{}>", t),
+                    format!("    Ok(consume_many(nodes, {}))\n", f),
+                )
+            }
+            Shape::Opt(inner) => {
+                let (t, f) = self.shape2code(rc, inner, in_vec);
+                (
+                    format!("Option<{}>", t),
+                    format!("    Ok(consume_opt(nodes, {}))\n", f),
+                )
+            }
+        };
+
+        rc.items += &fn_code(&t, &body);
+        (t, fn_name)
+    }
+}
+
+fn variants2code(variants: &[(String, String)]) -> String {
+    variants
+        .iter()
+        .fold(String::new(), |acc, (vname, t)| {
+            acc + &format!("    {}({}),\n", vname, t)
+        })
+}
+
+fn prelude(skip: &[&str]) -> String {
+    let skip_list: Vec<String> = skip.iter().map(|s| format!("r#\"{}\"#", s)).collect();
+
+    format!(
+        r#"//  Code generated by dynparser::peg::gtypes
+use dynparser::ast;
+
+const SKIP_RULES: &[&str] = &[{skip}];
+
+fn is_text(node: &ast::Node) -> bool {{
+    match node {{
+        ast::Node::Rule((name, _)) => SKIP_RULES.contains(&name.as_str()),
+        _ => true,
+    }}
+}}
+
+fn check_end(nodes: &[ast::Node]) -> Result<(), ast::Error> {{
+    if nodes.iter().all(is_text) {{
+        Ok(())
+    }} else {{
+        Err(ast::error("not consumed full nodes", None))
+    }}
+}}
+
+fn consume_text(nodes: &[ast::Node]) -> (String, &[ast::Node]) {{
+    let n = nodes.iter().take_while(|n| is_text(n)).count();
+    let text = nodes[..n].iter().fold(String::new(), |acc, n| match n {{
+        ast::Node::Val(v) => acc + v,
+        _ => acc,
+    }});
+    (text, &nodes[n..])
+}}
+
+fn consume_rule<'a, T>(
+    nodes: &'a [ast::Node],
+    name: &str,
+    from_nodes: fn(&[ast::Node]) -> Result<T, ast::Error>,
+) -> Result<(T, &'a [ast::Node]), ast::Error> {{
+    let (_, nodes) = consume_text(nodes);
+    let (nodes, sub_nodes) = ast::consume_node_get_subnodes_for_rule_name_is(name, nodes)?;
+    Ok((from_nodes(sub_nodes)?, nodes))
+}}
+
+fn consume_many<'a, T>(
+    nodes: &'a [ast::Node],
+    consume: fn(&'a [ast::Node]) -> Result<(T, &'a [ast::Node]), ast::Error>,
+) -> (Vec<T>, &'a [ast::Node]) {{
+    let mut result = vec![];
+    let mut nodes = nodes;
+    while let Ok((v, rest)) = consume(nodes) {{
+        if rest.len() == nodes.len() {{
+            break;
+        }}
+        result.push(v);
+        nodes = rest;
+    }}
+    (result, nodes)
+}}
+
+fn consume_opt<'a, T>(
+    nodes: &'a [ast::Node],
+    consume: fn(&'a [ast::Node]) -> Result<(T, &'a [ast::Node]), ast::Error>,
+) -> (Option<T>, &'a [ast::Node]) {{
+    match consume(nodes) {{
+        Ok((v, rest)) if rest.len() < nodes.len() => (Some(v), rest),
+        _ => (None, nodes),
+    }}
+}}
+"#,
+        skip = skip_list.join(", ")
+    )
+}
@@ -3,16 +3,22 @@
 //!
 
 pub mod gcode;
+pub mod gdoc;
+pub mod gtypes;
 pub mod peg2code;
 mod rules;
+mod rules_v1;
 
 use crate::ast::{self, flat};
+use crate::codes::Code;
 use crate::parse;
 use crate::parser::{
     self,
-    expression::{self, Expression},
+    atom::Atom,
+    expression::{self, Expression, RepInfo},
 };
 use idata::{self, cont::IVec};
+use std::collections::{HashMap, HashSet};
 use std::{self, result};
 
 #[cfg(test)]
@@ -22,17 +28,42 @@ struct Context {
     //  stack with the module paths we are inside
     //  i.e.   mod_a, mod_a.mod_b, mod_a.mod_b, mod_c
     inside_mods: Vec<String>,
+
+    //  reserved words declared with %keywords
+    keywords: Vec<String>,
+    //  rules annotated with @identifier, they will reject the keywords
+    identifiers: Vec<String>,
+
+    //  declared with %test
+    tests: Vec<InlineTest>,
+
+    //  annotations (rule, key, value)
+    meta: Vec<(String, String, Option<String>)>,
+
+    //  module path of the rules declared inside a module
+    rule_mods: HashMap<String, String>,
+    //  rules annotated with @internal (rule, module path)
+    internal: Vec<(String, String)>,
 }
 
 impl Context {
     fn new() -> Self {
         Context {
             inside_mods: vec![],
+            keywords: vec![],
+            identifiers: vec![],
+            tests: vec![],
+            meta: vec![],
+            rule_mods: HashMap::new(),
+            internal: vec![],
         }
     }
+    fn module_path(&self) -> &str {
+        self.inside_mods.last().map_or("", |m| m.as_str())
+    }
     fn add_module(mut self, mod_name: &str) -> Self {
         match self.inside_mods.last().cloned() {
-            Some(mod_path) => self.inside_mods.push(format!("{}{}", mod_path, mod_name)),
+            Some(mod_path) => self.inside_mods.push(format!("{}.{}", mod_path, mod_name)),
             None => self.inside_mods.push(mod_name.to_string()),
         };
         self
@@ -44,10 +75,10 @@ impl Context {
                 self.inside_mods.pop();
                 Ok(self)
             }
-            None => Err(error_peg_s(&format!(
-                "remove module on empty path statck: <{}>",
-                mod_name
-            ))),
+            None => Err(error_node_shape(
+                "open module",
+                &format!("end of module <{}>", mod_name),
+            )),
         }
     }
 }
@@ -65,10 +96,97 @@ pub enum Error {
     Parser(parser::Error),
     /// When error is on ast side
     Ast(ast::Error),
+    /// Invalid grammar, detected compiling the rules
+    Compile(ErrorKind),
+}
+
+/// Kind of the errors on a peg grammar
+///
+/// Look at ```Error::kind```. Tests and tools can check them without
+/// looking at the messages
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    /// The text is not a valid peg grammar
+    Syntax,
+    /// An open parenthesis without the closing one
+    UnbalancedParen,
+    /// Syntax version not supported
+    UnsupportedVersion(u32),
+    /// Wrong ```%syntax``` directive
+    InvalidDirective(String),
+    /// Repetition symbol not supported
+    UnknownRepetition(String),
+    /// Count of a repetition (```{n}```, ```{n,}```, ```{n,m}```) not
+    /// valid
+    InvalidRepetitionCount(String),
+    /// A lazy repetition (```*?```, ```+?```) at the end of a sequence
+    LazyRepetitionWithoutStop,
+    /// Escape or hex char not valid on a literal
+    InvalidEscape(String),
+    /// A match (```[...]```) without chars nor ranges
+    EmptyMatch,
+    /// An annotation with a wrong value
+    InvalidAnnotation {
+        /// Name of the annotation
+        annotation: String,
+        /// What is wrong
+        descr: String,
+    },
+    /// ```@identifier``` rules without keywords
+    IdentifierWithoutKeywords,
+    /// ```@identifier``` on a rule not found
+    MissingIdentifierRule(String),
+    /// ```%test``` on a rule not defined
+    UnknownTestRule(String),
+    /// Reference to an ```@internal``` rule from out of its module
+    InternalRuleReference {
+        /// The internal rule
+        rule: String,
+        /// Module of the internal rule
+        module: String,
+        /// Rule with the reference
+        from: String,
+    },
+    /// The AST of the grammar is not the expected one. It's a bug on
+    /// the compiler, or on the meta grammar
+    UnexpectedNodeShape {
+        /// What the compiler was looking for
+        expected: String,
+        /// What it found
+        got: String,
+    },
+    /// Errors created out of the compiler
+    Other(String),
+}
+
+//  accepted by the %recover directive (look at parse_recovering)
+const RECOVERY_STRATEGIES: &[&str] = &["lines", "sync"];
+
+//  accepted by the %dot directive
+const DOT_POLICIES: &[&str] = &["any", "no_newline"];
+
+//  error on the meta grammars (v1 and v2)
+const UNBALANCED_PAREN: &str = "unbalanced parethesis: missing ')'";
+
+//  error on the meta grammar v2
+const INVALID_COUNT: &str = "invalid repetition count, expected {n}, {n,} or {n,m}";
+
+fn error_kind(kind: ErrorKind) -> Error {
+    Error::Compile(kind)
 }
 
-fn error_peg_s(s: &str) -> Error {
-    Error::Peg((s.to_string(), None))
+fn error_node_shape(expected: &str, got: &str) -> Error {
+    Error::Compile(ErrorKind::UnexpectedNodeShape {
+        expected: expected.to_string(),
+        got: got.to_string(),
+    })
+}
+
+fn error_annotation(annotation: &str, descr: &str) -> Error {
+    Error::Compile(ErrorKind::InvalidAnnotation {
+        annotation: annotation.to_string(),
+        descr: descr.to_string(),
+    })
 }
 
 impl Error {
@@ -76,6 +194,114 @@ impl Error {
     pub fn ipush(self, desc: &str) -> Self {
         Error::Peg((desc.to_string(), Some(Box::new(self))))
     }
+
+    /// Kind of the error
+    ///
+    /// The descriptions added with ```ipush``` are skipped
+    ///
+    /// ```
+    /// use dynparser::peg::{self, ErrorKind};
+    ///
+    /// let error = peg::rules_from_peg("main = ('a' 'b'").unwrap_err();
+    /// assert_eq!(error.kind(), ErrorKind::UnbalancedParen);
+    ///
+    /// let error = peg::rules_from_peg("main = 'a'*?").unwrap_err();
+    /// assert_eq!(error.kind(), ErrorKind::LazyRepetitionWithoutStop);
+    /// ```
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Compile(kind) => kind.clone(),
+            Error::Peg((_, Some(e))) => e.kind(),
+            Error::Peg((s, None)) => ErrorKind::Other(s.clone()),
+            Error::Parser(e) if e.descr == UNBALANCED_PAREN => ErrorKind::UnbalancedParen,
+            Error::Parser(e) if e.descr == INVALID_COUNT => ErrorKind::InvalidRepetitionCount(
+                e.line_after.chars().take_while(|ch| *ch != '}').collect(),
+            ),
+            Error::Parser(_) => ErrorKind::Syntax,
+            //  from the flat AST of the grammar
+            Error::Ast(e) => ErrorKind::UnexpectedNodeShape {
+                expected: e.0.clone(),
+                got: e.1.clone().unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Stable code of the error (look at ```codes```)
+    ///
+    /// ```
+    /// use dynparser::codes::Code;
+    /// use dynparser::peg;
+    ///
+    /// let error = peg::rules_from_peg("main = ('a' 'b'").unwrap_err();
+    /// assert_eq!(error.code(), Code::UnbalancedParen);
+    /// ```
+    pub fn code(&self) -> Code {
+        self.kind().code()
+    }
+}
+
+impl ErrorKind {
+    /// Stable code of the kind (look at ```codes```)
+    pub fn code(&self) -> Code {
+        match self {
+            ErrorKind::Syntax => Code::Syntax,
+            ErrorKind::UnbalancedParen => Code::UnbalancedParen,
+            ErrorKind::UnsupportedVersion(_) => Code::UnsupportedVersion,
+            ErrorKind::InvalidDirective(_) => Code::InvalidDirective,
+            ErrorKind::UnknownRepetition(_) => Code::UnknownRepetition,
+            ErrorKind::InvalidRepetitionCount(_) => Code::InvalidRepetitionCount,
+            ErrorKind::LazyRepetitionWithoutStop => Code::LazyRepetitionWithoutStop,
+            ErrorKind::InvalidEscape(_) => Code::InvalidEscape,
+            ErrorKind::EmptyMatch => Code::EmptyMatch,
+            ErrorKind::InvalidAnnotation { .. } => Code::InvalidAnnotation,
+            ErrorKind::IdentifierWithoutKeywords => Code::IdentifierWithoutKeywords,
+            ErrorKind::MissingIdentifierRule(_) => Code::MissingIdentifierRule,
+            ErrorKind::UnknownTestRule(_) => Code::UnknownTestRule,
+            ErrorKind::InternalRuleReference { .. } => Code::InternalRuleReference,
+            ErrorKind::UnexpectedNodeShape { .. } => Code::UnexpectedNodeShape,
+            ErrorKind::Other(_) => Code::CompileOther,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ErrorKind::Syntax => write!(f, "invalid peg syntax"),
+            ErrorKind::UnbalancedParen => write!(f, "{}", UNBALANCED_PAREN),
+            ErrorKind::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported syntax version {}, supported versions {:?}",
+                version,
+                supported_syntax_versions()
+            ),
+            ErrorKind::InvalidDirective(d) => write!(f, "invalid directive <{}>", d),
+            ErrorKind::UnknownRepetition(r) => write!(f, "repetition symbol unknown {}", r),
+            ErrorKind::InvalidRepetitionCount(c) => write!(f, "invalid repetition count {{{}}}", c),
+            ErrorKind::LazyRepetitionWithoutStop => {
+                write!(f, "lazy repetition has to be followed by an expression")
+            }
+            ErrorKind::InvalidEscape(e) => write!(f, "invalid escape {}", e),
+            ErrorKind::EmptyMatch => write!(f, "match without chars nor ranges"),
+            ErrorKind::InvalidAnnotation { annotation, descr } => {
+                write!(f, "{} on @{}", descr, annotation)
+            }
+            ErrorKind::IdentifierWithoutKeywords => {
+                write!(f, "@identifier rules without %keywords")
+            }
+            ErrorKind::MissingIdentifierRule(r) => write!(f, "missing @identifier rule {}", r),
+            ErrorKind::UnknownTestRule(r) => write!(f, "%test on unknown rule {}", r),
+            ErrorKind::InternalRuleReference { rule, module, from } => write!(
+                f,
+                "rule {} is internal to module {}, it can't be referenced from rule {}",
+                rule, module, from
+            ),
+            ErrorKind::UnexpectedNodeShape { expected, got } => {
+                write!(f, "unexpected node. expected {}, got {}", expected, got)
+            }
+            ErrorKind::Other(s) => write!(f, "{}", s),
+        }
+    }
 }
 
 impl From<parser::Error> for Error {
@@ -97,6 +323,7 @@ impl std::fmt::Display for Error {
             Error::Peg((s, Some(b))) => write!(f, "{} > {}", s, b),
             Error::Parser(p) => write!(f, "Parser({:?})", p),
             Error::Ast(a) => write!(f, "AST({:?})", a),
+            Error::Compile(k) => write!(f, "{}", k),
         }
     }
 }
@@ -105,12 +332,199 @@ impl std::fmt::Display for Error {
 /// or an error
 pub type Result = result::Result<expression::SetOfRules, Error>;
 
+/// Test case declared on the grammar
+///
+/// ```peg
+///     %test  rule_name  accepts  "input"
+///     %test  rule_name  rejects  "input"
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineTest {
+    /// Rule to start parsing
+    pub rule: String,
+    /// If true, the full input has to be accepted by the rule
+    /// If false, it has to be rejected
+    pub accepts: bool,
+    /// Text to parse
+    pub input: String,
+}
+
+impl InlineTest {
+    /// Check the test with these rules
+    pub fn passes(&self, rules: &expression::SetOfRules) -> bool {
+        crate::matches_rule(&self.input, rules, &self.rule) == self.accepts
+    }
+}
+
+/// Rules generated from a peg grammar, with the tests declared on it
+///
+/// Created with ```compile```
+#[derive(Debug)]
+pub struct CompiledGrammar {
+    /// Rules to use on the parser
+    pub rules: expression::SetOfRules,
+    /// Tests declared with ```%test```, in order
+    pub tests: Vec<InlineTest>,
+
+    //  to find the changes on update_from_peg
+    version: u32,
+    declarations: Vec<Declaration>,
+    features: Vec<String>,
+    dot_no_newline: bool,
+}
+
+//  text of a top level declaration
+//  rule is informed only for rules without annotations
+#[derive(Debug, Clone, PartialEq)]
+struct Declaration {
+    rule: Option<String>,
+    text: String,
+}
+
+impl CompiledGrammar {
+    /// Run the tests declared on the grammar
+    ///
+    /// It returns the tests not passed
+    pub fn run_inline_tests(&self) -> Vec<&InlineTest> {
+        self.tests
+            .iter()
+            .filter(|t| !t.passes(&self.rules))
+            .collect()
+    }
+
+    /// Compile a new version of the grammar, reusing the rules not
+    /// modified
+    ///
+    /// The grammar is compared declaration by declaration. Only the
+    /// rules with a different text (or the new ones) are compiled
+    /// again. If something else changed (modules, annotated rules,
+    /// ```%keywords```, ```%test```...), or the grammar has ```@internal```
+    /// rules, the full grammar is compiled
+    ///
+    /// It returns the names of the compiled rules. On error, the
+    /// grammar is not modified
+    ///
+    /// ```
+    /// extern crate dynparser;
+    /// use dynparser::{parse, peg};
+    ///
+    /// fn main() {
+    ///     let mut grammar = peg::compile(
+    ///         r#"
+    ///         main    =   num  (',' num)*
+    ///         num     =   [0-9]+
+    ///         "#,
+    ///     ).unwrap();
+    ///
+    ///     let compiled = grammar.update_from_peg(
+    ///         r#"
+    ///         main    =   num  (';' num)*
+    ///         num     =   [0-9]+
+    ///         "#,
+    ///     ).unwrap();
+    ///
+    ///     assert_eq!(compiled, vec!["main"]);
+    ///     assert!(parse("1;2", &grammar.rules).is_ok());
+    /// }
+    /// ```
+    pub fn update_from_peg(&mut self, peg: &str) -> result::Result<Vec<String>, Error> {
+        let features: Vec<&str> = self.features.iter().map(|f| f.as_str()).collect();
+        let peg = &select_features(peg, &features)?;
+        match self.updated_rules(peg) {
+            Some((rules, compiled)) => {
+                self.rules = rules;
+                self.declarations = split_declarations(peg);
+                Ok(compiled)
+            }
+            None => {
+                *self = compile_with_features(peg, &features)?;
+                let mut compiled: Vec<String> = self.rules.exprs.keys().cloned().collect();
+                compiled.sort();
+                Ok(compiled)
+            }
+        }
+    }
+
+    //  None if the changes require a full compilation
+    fn updated_rules(&self, peg: &str) -> Option<(expression::SetOfRules, Vec<String>)> {
+        let declarations = split_declarations(peg);
+        let others = |decls: &[Declaration]| -> Vec<Declaration> {
+            decls.iter().filter(|d| d.rule.is_none()).cloned().collect()
+        };
+        if others(&self.declarations) != others(&declarations) {
+            return None;
+        }
+        //  references to @internal rules are checked on a full compilation
+        if self
+            .rules
+            .names()
+            .iter()
+            .any(|r| self.rules.access(r) == expression::RuleAccess::Internal)
+        {
+            return None;
+        }
+
+        let rule_texts = |decls: &[Declaration]| -> HashMap<String, String> {
+            decls
+                .iter()
+                .filter_map(|d| d.rule.clone().map(|r| (r, d.text.clone())))
+                .collect()
+        };
+        let old = rule_texts(&self.declarations);
+        let new = rule_texts(&declarations);
+        let n_new_rules = declarations.iter().filter(|d| d.rule.is_some()).count();
+
+        let mut rules = self.rules.exprs.clone();
+        for removed in old.keys().filter(|r| !new.contains_key(*r)) {
+            rules.remove(removed);
+        }
+        let changed_names = old
+            .keys()
+            .chain(new.keys())
+            .filter(|r| !old.contains_key(*r) || !new.contains_key(*r));
+        let from_others = |r: &String| rules.contains_key(r) && !old.contains_key(r);
+        if n_new_rules != new.len()
+            || changed_names
+                .clone()
+                .any(|r| builtin_atom(r).is_some() || from_others(r))
+            || self
+                .tests
+                .iter()
+                .any(|t| !rules.contains_key(&t.rule) && !new.contains_key(&t.rule))
+        {
+            return None;
+        }
+
+        let defined: HashSet<String> = rules.keys().chain(new.keys()).cloned().collect();
+        let mut compiled: Vec<(&String, &String)> = new
+            .iter()
+            .filter(|(name, text)| old.get(*name) != Some(text))
+            .collect();
+        compiled.sort();
+        let inline = inline_bodies(&rules, &self.rules);
+        let exprs = parser::map_per_rule(&compiled, |(name, text)| {
+            compile_declaration(self.version, text, name, &defined, self.dot_no_newline)
+                .map(|expr| inline_expr(expr, &inline, &mut vec![name.to_string()]))
+        });
+        for ((name, _), expr) in compiled.iter().zip(exprs) {
+            rules.insert(name.to_string(), expr?);
+        }
+        let compiled = compiled.into_iter().map(|(name, _)| name.clone()).collect();
+        let order = declaration_order(&self.rules.names(), &old, &declarations);
+        Some((self.rules.with_rules(rules).with_order(order), compiled))
+    }
+}
+
 // -------------------------------------------------------------------------------------
 //  A P I
 
 /// Given a ```peg``` set of rules on an string, it will generate
 /// the set of rules to use in the parser
 ///
+/// The grammar can start with a ```%syntax <version>``` directive
+/// (comments and spaces before it are allowed). Without it, the
+/// version 1 syntax will be used. Look at ```supported_syntax_versions```
+///
 /// Next, is a full example showing the error messages, if so
 /// ```
 /// extern crate dynparser;
@@ -189,21 +603,820 @@ pub type Result = result::Result<expression::SetOfRules, Error>;
 /// ```
 
 pub fn rules_from_peg(peg: &str) -> Result {
-    let ast = parse(peg, &rules::parse_peg())?;
+    compile(peg).map(|grammar| grammar.rules)
+}
+
+/// Same as ```rules_from_peg```, with the ```%if``` sections of the
+/// features enabled
+///
+/// A section starts with ```%if feature``` (or ```%if !feature```), it
+/// can have an ```%else```, and it finishes with ```%endif```. Each one
+/// on its own line. Sections can be nested
+///
+/// ```
+/// extern crate dynparser;
+/// use dynparser::{parse, peg};
+///
+/// fn main() {
+///     let grammar = r#"
+///         main    =   num  (op  num)*
+///         num     =   [0-9]+
+///     %if power
+///         op      =   '+'  /  '*'  /  '^'
+///     %else
+///         op      =   '+'  /  '*'
+///     %endif
+///     "#;
+///
+///     let rules = peg::rules_from_peg(grammar).unwrap();
+///     assert!(parse("2^3", &rules).is_err());
+///
+///     let rules = peg::rules_from_peg_with_features(grammar, &["power"]).unwrap();
+///     assert!(parse("2^3", &rules).is_ok());
+/// }
+/// ```
+pub fn rules_from_peg_with_features(peg: &str, features: &[&str]) -> Result {
+    compile_with_features(peg, features).map(|grammar| grammar.rules)
+}
+
+/// Same as ```rules_from_peg```, keeping also the tests declared on
+/// the grammar (available from syntax version 2)
+///
+/// ```
+/// extern crate dynparser;
+/// use dynparser::peg;
+///
+/// fn main() {
+///     let grammar = peg::compile(
+///         r#"
+///         %syntax 2
+///
+///         main    =   num  (',' num)*
+///         num     =   [0-9]+
+///
+///         %test num   accepts "123"
+///         %test num   rejects "12a"
+///         %test main  accepts "1,2,3"
+///         %test main  accepts "1,"
+///         "#,
+///     ).unwrap();
+///
+///     let failed = grammar.run_inline_tests();
+///     assert_eq!(failed.len(), 1);
+///     assert_eq!(failed[0].input, "1,");
+/// }
+/// ```
+pub fn compile(peg: &str) -> result::Result<CompiledGrammar, Error> {
+    compile_with_features(peg, &[])
+}
+
+/// Same as ```compile```, with the ```%if``` sections of the features
+/// enabled (look at ```rules_from_peg_with_features```)
+///
+/// ```update_from_peg``` will keep the features
+pub fn compile_with_features(
+    peg: &str,
+    features: &[&str],
+) -> result::Result<CompiledGrammar, Error> {
+    let peg = &select_features(peg, features)?;
+    let (recover, text) = split_line_directive(peg, "%recover", RECOVERY_STRATEGIES)?;
+    let (dot, text) = split_line_directive(&text, "%dot", DOT_POLICIES)?;
+    let (version, text) = split_syntax_version(&text)?;
+    let ast = parse(&text, &meta_rules(version)?)?;
     let nodes = ast.compact().prune(&["_", "_1", "_eol"]).flatten();
 
-    rules_from_flat_ast(&nodes)
+    let dot_no_newline = dot.as_deref() == Some("no_newline");
+    let grammar = rules_from_flat_ast(&nodes, dot_no_newline)?;
+    let rules = match recover {
+        Some(strategy) => grammar.rules.set_meta("main", "recover", Some(&strategy)),
+        None => grammar.rules,
+    };
+    Ok(CompiledGrammar {
+        rules,
+        version,
+        declarations: split_declarations(peg),
+        features: features.iter().map(|f| f.to_string()).collect(),
+        dot_no_newline,
+        ..grammar
+    })
+}
+
+/// Same as ```rules_from_peg```, but on error it reports the errors
+/// of every declaration, not only the first one
+///
+/// Look at ```compile_all_errors```
+pub fn rules_from_peg_all_errors(peg: &str) -> result::Result<expression::SetOfRules, Vec<Error>> {
+    compile_all_errors(peg).map(|grammar| grammar.rules)
+}
+
+/// Same as ```compile```, but on error it reports the errors of every
+/// declaration (rule, module, directive...), not only the first one
+///
+/// Every declaration is checked alone. Errors relating several
+/// declarations (i.e. a ```%test``` on an unknown rule) are reported
+/// when the declarations have no errors of their own. Positions are on
+/// the full grammar, and errors without position have the line of the
+/// declaration
+///
+/// ```
+/// extern crate dynparser;
+/// use dynparser::peg::{self, ErrorKind};
+///
+/// fn main() {
+///     let errors = peg::compile_all_errors(
+///         r#"
+///         main    =   num  (',' num)*
+///         num     =   [0-9+
+///         @passthrough("x")
+///         op      =   '+'
+///         "#,
+///     ).unwrap_err();
+///
+///     assert_eq!(errors.len(), 2);
+///     assert_eq!(errors[0].kind(), ErrorKind::Syntax);
+///     assert!(errors[1].to_string().contains("declaration on line 4"));
+/// }
+/// ```
+pub fn compile_all_errors(peg: &str) -> result::Result<CompiledGrammar, Vec<Error>> {
+    let first = match compile(peg) {
+        Ok(grammar) => return Ok(grammar),
+        Err(e) => e,
+    };
+    let peg = match split_line_directive(peg, "%recover", RECOVERY_STRATEGIES)
+        .and_then(|(_, text)| split_line_directive(&text, "%dot", DOT_POLICIES))
+    {
+        Ok((_, text)) => text,
+        Err(_) => return Err(vec![first]),
+    };
+    let (version, text) = match split_syntax_version(&peg) {
+        Ok((version, text)) if supported_syntax_versions().contains(&version) => (version, text),
+        _ => return Err(vec![first]),
+    };
+
+    let declarations = split_declarations(&text);
+    let mut errors = vec![];
+    let mut line = 1;
+    for (i, declaration) in declarations.iter().enumerate() {
+        if !skip_trivia(&declaration.text).is_empty() {
+            //  the rest of the grammar as spaces, to keep the positions
+            let alone: String = declarations
+                .iter()
+                .enumerate()
+                .map(|(j, d)| {
+                    if i == j {
+                        d.text.clone()
+                    } else {
+                        blank(&d.text)
+                    }
+                })
+                .collect();
+            if let Err(e) = check_declaration(version, &alone) {
+                errors.push(match e {
+                    Error::Parser(_) => e,
+                    e => e.ipush(&format!("declaration on line {}", line)),
+                });
+            }
+        }
+        line += declaration.text.matches('\n').count();
+    }
+
+    if errors.is_empty() {
+        Err(vec![first])
+    } else {
+        Err(errors)
+    }
+}
+
+/// Versions of the peg syntax accepted by ```rules_from_peg```
+///
+/// Once released, a syntax version will not change. New features
+/// will go to a new version
+///
+/// - 1 The original syntax. Grammars without ```%syntax``` directive
+/// - 2 Ranges and chars on matches can be in any order, and a ```-```
+///   before the ```]``` is a char (i.e. ```[a-z_-]```). To finish a
+///   range with ```]```, write it as ```\x5D```.
+///   Tests on the grammar with ```%test``` (look at ```compile```).
+///   ```until("delimiter", "escape")``` (look at ```until!```).
+///   ```balanced("open", "close")``` (look at ```balanced!```).
+///   Separated lists ```expr % sep``` and ```expr %, sep``` (look
+///   at ```sep_by!```).
+///   Named items ```name:expr``` (look at ```label!```).
+///   Negated matches ```[^...]``` (look at ```ematch!```)
+///   Unicode escapes ```\u{1F600}``` on literals with double quotes
+///   and on matches
+///   Positions on the captures ```@pos(name)``` (look at ```pos!```)
+///   Repetition counts ```expr{n}```, ```expr{n,}``` and
+///   ```expr{n,m}``` (look at ```rep!```)
+///
+/// ```
+/// extern crate dynparser;
+/// use dynparser::{parse, peg, rules_from_peg};
+///
+/// fn main() {
+///     assert_eq!(peg::supported_syntax_versions(), &[1, 2]);
+///
+///     assert!(rules_from_peg("main = [a-z_-]+").is_err());
+///
+///     let rules = rules_from_peg(
+///         r#"
+///         %syntax 2
+///         main = [a-z_-]+
+///         "#,
+///     ).unwrap();
+///     assert!(parse("snake_and-kebab", &rules).is_ok());
+/// }
+/// ```
+pub fn supported_syntax_versions() -> &'static [u32] {
+    &[1, 2]
 }
 
 //  A P I
 // -------------------------------------------------------------------------------------
 
-fn rules_from_flat_ast(nodes: &[flat::Node]) -> Result {
-    let (rules, nodes, _context) = consume_main(&nodes, Context::new())?;
+fn meta_rules(version: u32) -> result::Result<expression::SetOfRules, Error> {
+    match version {
+        1 => Ok(rules_v1::parse_peg()),
+        2 => Ok(rules::parse_peg()),
+        _ => Err(error_kind(ErrorKind::UnsupportedVersion(version))),
+    }
+}
+
+//  %syntax directive has to be before any rule
+//  It will be replaced by spaces, to keep the positions on errors
+//  spaces and comments
+fn skip_trivia(s: &str) -> &str {
+    let s = s.trim_start();
+    if let Some(rest) = s.strip_prefix("//") {
+        skip_trivia(rest.find('\n').map_or("", |nl| &rest[nl..]))
+    } else if let Some(rest) = s.strip_prefix("/*") {
+        rest.find("*/")
+            .map_or(s, |end| skip_trivia(&rest[end + 2..]))
+    } else {
+        s
+    }
+}
+
+//  same chars count and lines, only spaces
+fn blank(text: &str) -> String {
+    text.chars()
+        .map(|ch| match ch {
+            '\n' | '\r' => ch,
+            _ => ' ',
+        })
+        .collect()
+}
+
+fn split_syntax_version(peg: &str) -> result::Result<(u32, String), Error> {
+    let rest = skip_trivia(peg);
+    if !rest.starts_with("%syntax") {
+        return Ok((1, peg.to_string()));
+    }
+    let start = peg.len() - rest.len();
+    let end = start + rest.find(['\n', '\r']).unwrap_or(rest.len());
+    let directive = &peg[start..end];
+    let version = directive["%syntax".len()..]
+        .split("//")
+        .next()
+        .unwrap_or("")
+        .trim();
+    let version = version
+        .parse::<u32>()
+        .map_err(|_| error_kind(ErrorKind::InvalidDirective(directive.to_string())))?;
+
+    Ok((
+        version,
+        format!(
+            "{}{}{}",
+            &peg[..start],
+            " ".repeat(directive.chars().count()),
+            &peg[end..]
+        ),
+    ))
+}
+
+//  %if feature / %else / %endif, on their own lines
+//  Lines out of the enabled sections and the directives will be replaced
+//  by spaces, to keep the positions on errors
+fn select_features(peg: &str, features: &[&str]) -> result::Result<String, Error> {
+    if !peg.contains("%if") {
+        return Ok(peg.to_string());
+    }
+    let invalid = |d: &str| error_kind(ErrorKind::InvalidDirective(d.to_string()));
+
+    //  condition of the open sections
+    let mut sections: Vec<bool> = vec![];
+    let mut selected = String::with_capacity(peg.len());
+    for line in peg.split_inclusive('\n') {
+        let directive = line.split("//").next().unwrap_or("").trim();
+        let enabled = sections.iter().all(|cond| *cond);
+        let keep = if let Some(feature) = directive.strip_prefix("%if ") {
+            let feature = feature.trim();
+            sections.push(match feature.strip_prefix('!') {
+                Some(feature) => !features.contains(&feature),
+                None => features.contains(&feature),
+            });
+            false
+        } else if directive == "%else" {
+            let cond = sections.pop().ok_or_else(|| invalid(directive))?;
+            sections.push(!cond);
+            false
+        } else if directive == "%endif" {
+            sections.pop().ok_or_else(|| invalid(directive))?;
+            false
+        } else {
+            enabled
+        };
+
+        if keep {
+            selected.push_str(line);
+        } else {
+            selected.push_str(&blank(line));
+        }
+    }
+    if sections.is_empty() {
+        Ok(selected)
+    } else {
+        Err(invalid("%if without %endif"))
+    }
+}
+
+//  %recover strategy, %dot policy... on its own line, with one of the
+//  accepted values
+//  It will be replaced by spaces, to keep the positions on errors
+fn split_line_directive(
+    peg: &str,
+    name: &str,
+    accepted: &[&str],
+) -> result::Result<(Option<String>, String), Error> {
+    if !peg.contains(name) {
+        return Ok((None, peg.to_string()));
+    }
+
+    let mut value = None;
+    let mut text = String::with_capacity(peg.len());
+    for line in peg.split_inclusive('\n') {
+        let directive = line.split("//").next().unwrap_or("").trim();
+        match directive.strip_prefix(name) {
+            Some(v) => {
+                let v = v.trim();
+                if !accepted.contains(&v) || value.is_some() {
+                    return Err(error_kind(ErrorKind::InvalidDirective(
+                        directive.to_string(),
+                    )));
+                }
+                value = Some(v.to_string());
+                text.push_str(&blank(line));
+            }
+            None => text.push_str(line),
+        }
+    }
+    Ok((value, text))
+}
+
+//  a declaration starts on a new line, out of modules and comments
+//  annotations are on the same declaration as their rule
+fn split_declarations(peg: &str) -> Vec<Declaration> {
+    fn symbol_len(s: &str) -> usize {
+        s.char_indices()
+            .find(|&(i, ch)| {
+                !(ch.is_ascii_alphanumeric() || ch == '_' || (i > 0 && (ch == '\'' || ch == '"')))
+            })
+            .map_or(s.len(), |(i, _)| i)
+    }
+    //  rule_name  _  '='
+    fn rule_start(line: &str) -> Option<String> {
+        let mut len = usize::from(line.starts_with('.'));
+        loop {
+            let n = symbol_len(&line[len..]);
+            if n == 0 {
+                return None;
+            }
+            len += n;
+            if !line[len..].starts_with('.') {
+                break;
+            }
+            len += 1;
+        }
+        let rest = line[len..].trim_start_matches([' ', '\t']);
+        if rest.starts_with('=') {
+            Some(line[..len].to_string())
+        } else {
+            None
+        }
+    }
+    fn module_start(line: &str) -> bool {
+        let n = symbol_len(line);
+        n > 0 && line[n..].trim_start_matches([' ', '\t']).starts_with('{')
+    }
+    //  annotation  =   '@'  symbol  ( '('  _  literal  _  ')' )?
+    fn skip_annotations(mut line: &str) -> &str {
+        while let Some(rest) = line.strip_prefix('@') {
+            let rest = &rest[symbol_len(rest)..];
+            line = match rest.strip_prefix('(') {
+                Some(param) => param.find(')').map_or("", |end| &param[end + 1..]),
+                None => rest,
+            }
+            .trim_start();
+        }
+        line
+    }
+
+    let mut declarations = vec![Declaration {
+        rule: None,
+        text: String::new(),
+    }];
+    let (mut depth, mut in_comment, mut annotations) = (0usize, false, false);
+    for line in peg.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if depth == 0 && !in_comment {
+            //  annotations can be on the lines before their rule
+            let rest = skip_annotations(trimmed);
+            let only_annotations = rest.is_empty() || rest.starts_with("//");
+            let starts = if annotations {
+                annotations = only_annotations;
+                None
+            } else if rest.len() != trimmed.len() {
+                annotations = only_annotations;
+                Some(None)
+            } else if let Some(rule) = rule_start(trimmed) {
+                Some(Some(rule))
+            } else if trimmed.starts_with('%') || module_start(trimmed) {
+                Some(None)
+            } else {
+                None
+            };
+            if let Some(rule) = starts {
+                declarations.push(Declaration {
+                    rule,
+                    text: String::new(),
+                });
+            }
+        }
+        if !in_comment && module_start(trimmed) {
+            depth += 1;
+        } else if !in_comment && trimmed.starts_with('}') {
+            depth = depth.saturating_sub(1);
+        }
+        if let Some(start) = line.rfind("/*") {
+            in_comment = !line[start..].contains("*/");
+        } else if line.contains("*/") {
+            in_comment = false;
+        }
+        if let Some(last) = declarations.last_mut() {
+            last.text.push_str(line);
+        }
+    }
+    declarations
+}
+
+//  names on the order of the new declarations
+//  the rules from other declarations (annotated, modules...) don't
+//  change, they keep their place after the previous rule declaration
+fn declaration_order<'a>(
+    old_names: &[&'a str],
+    old_rules: &HashMap<String, String>,
+    declarations: &'a [Declaration],
+) -> Vec<String> {
+    let mut after: HashMap<Option<&str>, Vec<&str>> = HashMap::new();
+    let mut anchor = None;
+    for name in old_names {
+        if old_rules.contains_key(*name) {
+            anchor = Some(*name);
+        } else {
+            after.entry(anchor).or_default().push(name);
+        }
+    }
+
+    let mut order = after.remove(&None).unwrap_or_default();
+    for name in declarations.iter().filter_map(|d| d.rule.as_deref()) {
+        order.push(name);
+        order.extend(after.remove(&Some(name)).unwrap_or_default());
+    }
+    //  the ones after a removed rule, at the end
+    let rest: HashSet<&str> = after.into_values().flatten().collect();
+    order.extend(old_names.iter().filter(|name| rest.contains(*name)));
+    order.into_iter().map(|name| name.to_string()).collect()
+}
+
+//  a rule declaration compiled alone
+//  it has to produce just the expected rule
+fn compile_declaration(
+    version: u32,
+    text: &str,
+    name: &str,
+    defined: &HashSet<String>,
+    dot_no_newline: bool,
+) -> Option<Expression> {
+    let ast = parse(text, &meta_rules(version).ok()?).ok()?;
+    let nodes = ast.compact().prune(&["_", "_1", "_eol"]).flatten();
+    let (mut rules, nodes, context) = consume_main(&nodes, Context::new()).ok()?;
+    match (
+        rules.exprs.remove(name),
+        rules.exprs.is_empty(),
+        nodes.is_empty(),
+    ) {
+        (Some(expr), true, true)
+            if context.identifiers.is_empty()
+                && context.meta.is_empty()
+                && context.keywords.is_empty()
+                && context.tests.is_empty() =>
+        {
+            let expr = if dot_no_newline {
+                expression::no_newline_dots(expr)
+            } else {
+                expr
+            };
+            Some(resolve_builtins_expr(expr, defined))
+        }
+        _ => None,
+    }
+}
+
+//  errors of a declaration, without the rest of the grammar
+fn check_declaration(version: u32, text: &str) -> result::Result<(), Error> {
+    let ast = parse(text, &meta_rules(version)?)?;
+    let nodes = ast.compact().prune(&["_", "_1", "_eol"]).flatten();
+    consume_main(&nodes, Context::new()).map(|_| ())
+}
+
+fn rules_from_flat_ast(
+    nodes: &[flat::Node],
+    dot_no_newline: bool,
+) -> result::Result<CompiledGrammar, Error> {
+    let (rules, nodes, context) = consume_main(&nodes, Context::new())?;
     if !nodes.is_empty() {
-        Err(error_peg_s("expected empty nodes after processing main"))
+        Err(error_node_shape(
+            "end of nodes after main",
+            &format!("{} nodes", nodes.len()),
+        ))
     } else {
-        Ok(rules)
+        check_internal_refs(&rules, &context)?;
+        //  before the built-ins, any_nl is a dot for any char
+        let rules = if dot_no_newline {
+            map_rules(rules, |_, expr| expression::no_newline_dots(expr.clone()))
+        } else {
+            rules
+        };
+        let rules = resolve_builtins(apply_keywords(rules, &context)?);
+        let rules = context
+            .meta
+            .iter()
+            .fold(rules, |rules, (rule, key, value)| {
+                rules.set_meta(rule, key, value.as_deref())
+            });
+        let rules = apply_inline(rules);
+        let rules = context.internal.iter().fold(rules, |rules, (rule, _)| {
+            rules.set_access(rule, expression::RuleAccess::Internal)
+        });
+        match context
+            .tests
+            .iter()
+            .find(|t| !rules.exprs.contains_key(&t.rule))
+        {
+            Some(t) => Err(error_kind(ErrorKind::UnknownTestRule(t.rule.clone()))),
+            None => Ok(CompiledGrammar {
+                rules,
+                tests: context.tests,
+                version: 1,
+                declarations: vec![],
+                features: vec![],
+                dot_no_newline,
+            }),
+        }
+    }
+}
+
+//  an @internal rule can be referenced only from its module (and the
+//  nested ones). Internal rules out of modules are internal for plugins
+fn check_internal_refs(
+    rules: &expression::SetOfRules,
+    context: &Context,
+) -> result::Result<(), Error> {
+    let inside = |from: &str, module: &str| {
+        let from_mod = context.rule_mods.get(from).map_or("", |m| m.as_str());
+        module.is_empty() || from_mod == module || from_mod.starts_with(&format!("{}.", module))
+    };
+
+    let check_rule = |from: &&str| {
+        for rule in expression::rule_references(&rules.exprs[*from]) {
+            match context
+                .internal
+                .iter()
+                .find(|(internal, _)| internal == rule)
+            {
+                Some((_, module)) if !inside(from, module) => {
+                    return Err(error_kind(ErrorKind::InternalRuleReference {
+                        rule: rule.to_string(),
+                        module: module.clone(),
+                        from: from.to_string(),
+                    }))
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    };
+    //  the first error on declaration order
+    parser::map_per_rule(&rules.names(), check_rule)
+        .into_iter()
+        .collect()
+}
+
+//  run a pass on every rule expression
+//  with the rayon feature, the rules are processed in parallel
+fn map_rules<F>(mut rules: expression::SetOfRules, f: F) -> expression::SetOfRules
+where
+    F: Fn(&str, &Expression) -> Expression + Sync + Send,
+{
+    let items: Vec<(&String, &Expression)> = rules.exprs.iter().collect();
+    let exprs: Vec<(String, Expression)> =
+        parser::map_per_rule(&items, |(name, expr)| (name.to_string(), f(name, expr)));
+    rules.exprs.extend(exprs);
+    rules
+}
+
+//  keywords can be declared after the identifiers
+fn apply_keywords(
+    rules: expression::SetOfRules,
+    context: &Context,
+) -> result::Result<expression::SetOfRules, Error> {
+    let scoped = context.meta.iter().any(|(_, key, _)| key == "keywords");
+    if !context.identifiers.is_empty() && context.keywords.is_empty() && !scoped {
+        return Err(error_kind(ErrorKind::IdentifierWithoutKeywords));
+    }
+    let keywords: Vec<&str> = context.keywords.iter().map(|k| k.as_str()).collect();
+
+    let mut rules = rules;
+    for name in &context.identifiers {
+        let expr = rules
+            .exprs
+            .remove(name)
+            .ok_or_else(|| error_kind(ErrorKind::MissingIdentifierRule(name.clone())))?;
+        rules = rules.add(name, expression::not_keyword(&keywords, expr));
+    }
+    Ok(rules)
+}
+
+//  references to rules annotated with @inline are replaced by the
+//  rule expression (no call, and no node on the AST)
+//  Recursive references, and expansions bigger than INLINE_MAX_SIZE
+//  expressions, are still called
+const INLINE_MAX_SIZE: usize = 32;
+
+//  bodies of the rules annotated with @inline
+fn inline_bodies(
+    exprs: &HashMap<String, Expression>,
+    rules: &expression::SetOfRules,
+) -> HashMap<String, Expression> {
+    exprs
+        .iter()
+        .filter(|(name, _)| rules.rule_meta(name).is_some_and(|m| m.has("inline")))
+        .map(|(name, expr)| (name.clone(), expr.clone()))
+        .collect()
+}
+
+fn apply_inline(rules: expression::SetOfRules) -> expression::SetOfRules {
+    let inline = inline_bodies(&rules.exprs, &rules);
+    if inline.is_empty() {
+        return rules;
+    }
+
+    map_rules(rules, |name, expr| {
+        inline_expr(expr.clone(), &inline, &mut vec![name.to_string()])
+    })
+}
+
+fn inline_expr(
+    expr: Expression,
+    inline: &HashMap<String, Expression>,
+    expanding: &mut Vec<String>,
+) -> Expression {
+    let mut inline_mexpr = |mexpr: expression::MultiExpr| {
+        expression::MultiExpr(
+            mexpr
+                .0
+                .into_iter()
+                .map(|e| inline_expr(e, inline, expanding))
+                .collect(),
+        )
+    };
+
+    match expr {
+        Expression::RuleName(name) => match inline.get(&name) {
+            Some(body) if !expanding.contains(&name) => {
+                expanding.push(name.clone());
+                let expanded = inline_expr(body.clone(), inline, expanding);
+                expanding.pop();
+                if expr_size(&expanded) <= INLINE_MAX_SIZE {
+                    expanded
+                } else {
+                    Expression::RuleName(name)
+                }
+            }
+            _ => Expression::RuleName(name),
+        },
+        Expression::And(mexpr) => Expression::And(inline_mexpr(mexpr)),
+        Expression::Or(mexpr) => Expression::Or(inline_mexpr(mexpr)),
+        Expression::Not(e) => Expression::Not(Box::new(inline_expr(*e, inline, expanding))),
+        Expression::Repeat(rep) => Expression::Repeat(expression::RepInfo {
+            expression: Box::new(inline_expr(*rep.expression, inline, expanding)),
+            ..rep
+        }),
+        Expression::Labeled(label, e) => {
+            Expression::Labeled(label, Box::new(inline_expr(*e, inline, expanding)))
+        }
+        Expression::Deprecated(message, e) => {
+            Expression::Deprecated(message, Box::new(inline_expr(*e, inline, expanding)))
+        }
+        Expression::NotKeyword(keywords, e) => {
+            Expression::NotKeyword(keywords, Box::new(inline_expr(*e, inline, expanding)))
+        }
+        Expression::Keywords(keywords, e) => {
+            Expression::Keywords(keywords, Box::new(inline_expr(*e, inline, expanding)))
+        }
+        Expression::Simple(_) => expr,
+    }
+}
+
+fn expr_size(expr: &Expression) -> usize {
+    match expr {
+        Expression::Simple(_) | Expression::RuleName(_) => 1,
+        Expression::And(mexpr) | Expression::Or(mexpr) => {
+            1 + mexpr.0.iter().map(expr_size).sum::<usize>()
+        }
+        Expression::Not(e)
+        | Expression::Labeled(_, e)
+        | Expression::Deprecated(_, e)
+        | Expression::NotKeyword(_, e)
+        | Expression::Keywords(_, e) => 1 + expr_size(e),
+        Expression::Repeat(rep) => 1 + expr_size(&rep.expression),
+    }
+}
+
+//  built-in zero width assertions (and unicode classes, explicit dots)
+//  available on peg grammars if they are not defined as rules
+fn builtin_atom(name: &str) -> Option<Atom> {
+    match name {
+        "eof" => Some(Atom::EOF),
+        "bof" => Some(Atom::BOF),
+        "eol" => Some(Atom::EOL),
+        "bol" => Some(Atom::BOL),
+        "any" => Some(Atom::DotNoNewline),
+        "any_nl" => Some(Atom::Dot),
+        #[cfg(feature = "unicode")]
+        "xid_start" => Some(Atom::XidStart),
+        #[cfg(feature = "unicode")]
+        "xid_continue" => Some(Atom::XidContinue),
+        #[cfg(feature = "graphemes")]
+        "grapheme" => Some(Atom::Grapheme),
+        _ => None,
+    }
+}
+
+fn resolve_builtins(rules: expression::SetOfRules) -> expression::SetOfRules {
+    let defined: HashSet<String> = rules.exprs.keys().cloned().collect();
+    map_rules(rules, |_, expr| {
+        resolve_builtins_expr(expr.clone(), &defined)
+    })
+}
+
+fn resolve_builtins_expr(expr: Expression, defined: &HashSet<String>) -> Expression {
+    let resolve_mexpr = |mexpr: expression::MultiExpr| {
+        expression::MultiExpr(
+            mexpr
+                .0
+                .into_iter()
+                .map(|e| resolve_builtins_expr(e, defined))
+                .collect(),
+        )
+    };
+
+    match expr {
+        Expression::RuleName(name) => match (defined.contains(&name), builtin_atom(&name)) {
+            (false, Some(atom)) => Expression::Simple(atom),
+            _ => Expression::RuleName(name),
+        },
+        Expression::And(mexpr) => Expression::And(resolve_mexpr(mexpr)),
+        Expression::Or(mexpr) => Expression::Or(resolve_mexpr(mexpr)),
+        Expression::Not(e) => Expression::Not(Box::new(resolve_builtins_expr(*e, defined))),
+        Expression::Repeat(rep) => Expression::Repeat(expression::RepInfo {
+            expression: Box::new(resolve_builtins_expr(*rep.expression, defined)),
+            ..rep
+        }),
+        Expression::Labeled(label, e) => {
+            Expression::Labeled(label, Box::new(resolve_builtins_expr(*e, defined)))
+        }
+        Expression::Deprecated(message, e) => {
+            Expression::Deprecated(message, Box::new(resolve_builtins_expr(*e, defined)))
+        }
+        Expression::NotKeyword(keywords, e) => {
+            Expression::NotKeyword(keywords, Box::new(resolve_builtins_expr(*e, defined)))
+        }
+        Expression::Keywords(keywords, e) => {
+            Expression::Keywords(keywords, Box::new(resolve_builtins_expr(*e, defined)))
+        }
+        Expression::Simple(_) => expr,
     }
 }
 
@@ -247,7 +1460,7 @@ fn consume_grammar(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(expression::SetOfRules, &[flat::Node], Context), Error> {
-    // grammar         =   (rule  /  module)+
+    // grammar         =   (rule  /  module  /  keywords  /  test)+
 
     fn consume_rule_and_add_set_of_rules(
         rules: expression::SetOfRules,
@@ -277,10 +1490,15 @@ fn consume_grammar(
                 let (rules, nodes, context) = match rule_or_module.as_ref() {
                     "rule" => consume_rule_and_add_set_of_rules(rules, nodes, context),
                     "module" => consume_module_and_add_set_of_rules(rules, nodes, context),
-                    unknown => Err(error_peg_s(&format!(
-                        "expected rule or module, received: {}",
-                        unknown
-                    ))),
+                    "keywords" => {
+                        let (_, nodes, context) = consume_keywords(nodes, context)?;
+                        Ok((rules, nodes, context))
+                    }
+                    "test" => {
+                        let (_, nodes, context) = consume_test(nodes, context)?;
+                        Ok((rules, nodes, context))
+                    }
+                    unknown => Err(error_node_shape("rule or module", unknown)),
                 }?;
                 rec_consume_rules_or_modules(rules, nodes, context)
             }
@@ -294,6 +1512,60 @@ fn consume_grammar(
     })
 }
 
+fn consume_keywords(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<((), &[flat::Node], Context), Error> {
+    // keywords        =   _  '%keywords'  ( _1 _ !(rule_name _ ('=' / '{')) symbol )+  _eol _
+
+    fn rec_consume_symbols(
+        nodes: &[flat::Node],
+        mut context: Context,
+    ) -> result::Result<((), &[flat::Node], Context), Error> {
+        match flat::get_nodename(flat::peek_first_node(nodes)?) {
+            Ok("symbol") => {
+                let (symbol, nodes, mut context) = consume_symbol(nodes, context)?;
+                context.keywords.push(symbol.to_string());
+                rec_consume_symbols(nodes, context)
+            }
+            _ => {
+                context.keywords.sort();
+                context.keywords.dedup();
+                Ok(((), nodes, context))
+            }
+        }
+    }
+
+    consuming_rule("keywords", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("%keywords", nodes)?;
+        rec_consume_symbols(nodes, context)
+    })
+}
+
+fn consume_test(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<((), &[flat::Node], Context), Error> {
+    // test            =   _  '%test'  _1 _  rule_name  _1 _  ('accepts' / 'rejects')  _1 _  literal  _eol _
+
+    consuming_rule("test", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("%test", nodes)?;
+        let (rule, nodes, context) = consume_rule_name(nodes, context)?;
+        let (accepts, nodes) = match flat::consume_val(nodes)? {
+            ("accepts", nodes) => Ok((true, nodes)),
+            ("rejects", nodes) => Ok((false, nodes)),
+            (unknown, _) => Err(error_node_shape("accepts or rejects", unknown)),
+        }?;
+        let (input, nodes, mut context) = consume_literal_string(nodes, context)?;
+        context.tests.push(InlineTest {
+            rule,
+            accepts,
+            input,
+        });
+        Ok(((), nodes, context))
+    })
+}
+
 fn consume_module(
     nodes: &[flat::Node],
     context: Context,
@@ -323,21 +1595,107 @@ fn consume_mod_name(
 }
 
 type StringExpression = (String, expression::Expression);
+//  @name("param")
+type Annotation = (String, Option<String>);
 fn consume_rule(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(StringExpression, &[flat::Node], Context), Error> {
-    // rule            =   _  rule_name  _  '='  _  expr  _eol _
+    // rule            =   _  (annotation  _)*  rule_name  _  '='  _  expr  _eol _
+
+    fn rec_consume_annotations(
+        acc: Vec<Annotation>,
+        nodes: &[flat::Node],
+        context: Context,
+    ) -> result::Result<(Vec<Annotation>, &[flat::Node], Context), Error> {
+        match flat::get_nodename(flat::peek_first_node(nodes)?) {
+            Ok("annotation") => {
+                let (annotation, nodes, context) = consume_annotation(nodes, context)?;
+                rec_consume_annotations(acc.ipush(annotation), nodes, context)
+            }
+            _ => Ok((acc, nodes, context)),
+        }
+    }
+
+    let annotate = |rule_name: &str,
+                    (expr, mut context): (Expression, Context),
+                    (name, param): Annotation| {
+        context
+            .meta
+            .push((rule_name.to_string(), name.clone(), param.clone()));
+        match (name.as_ref(), param) {
+            ("deprecated", Some(message)) => {
+                Ok((Expression::Deprecated(message, Box::new(expr)), context))
+            }
+            ("deprecated", None) => Err(error_annotation("deprecated", "expected message")),
+            ("identifier", None) => {
+                context.identifiers.push(rule_name.to_string());
+                Ok((expr, context))
+            }
+            ("identifier", Some(_)) => Err(error_annotation("identifier", "unexpected value")),
+            ("passthrough", Some(_)) => Err(error_annotation("passthrough", "unexpected value")),
+            ("inline", Some(_)) => Err(error_annotation("inline", "unexpected value")),
+            ("raw", Some(_)) => Err(error_annotation("raw", "unexpected value")),
+            ("internal", None) => {
+                let module = context.module_path().to_string();
+                context.internal.push((rule_name.to_string(), module));
+                Ok((expr, context))
+            }
+            ("internal", Some(_)) => Err(error_annotation("internal", "unexpected value")),
+            ("sync", Some(token)) if !token.is_empty() => Ok((expr, context)),
+            ("sync", _) => Err(error_annotation("sync", "expected token")),
+            ("keywords", Some(words)) => {
+                let words: Vec<&str> = words
+                    .split(|ch: char| ch == ',' || ch.is_whitespace())
+                    .filter(|w| !w.is_empty())
+                    .collect();
+                Ok((expression::keywords(&words, expr), context))
+            }
+            ("keywords", None) => Err(error_annotation("keywords", "expected words")),
+            //  metadata for the application
+            (_, _) => Ok((expr, context)),
+        }
+    };
 
     consuming_rule("rule", nodes, context, |nodes, context| {
+        let (annotations, nodes, context) = rec_consume_annotations(vec![], nodes, context)?;
         let (rule_name, nodes, context) = consume_rule_name(nodes, context)?;
         let nodes = flat::consume_this_value("=", nodes)?;
         let (expr, nodes, context) = consume_peg_expr(nodes, context)?;
+        let (expr, context) = annotations
+            .into_iter()
+            .try_fold((expr, context), |acc, a| annotate(&rule_name, acc, a))?;
+        let mut context = context;
+        if !context.inside_mods.is_empty() {
+            let module = context.module_path().to_string();
+            context.rule_mods.insert(rule_name.clone(), module);
+        }
 
         Ok(((rule_name, expr), nodes, context))
     })
 }
 
+fn consume_annotation(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Annotation, &[flat::Node], Context), Error> {
+    // annotation      =   '@'  symbol  ( '('  _  literal  _  ')' )?
+
+    consuming_rule("annotation", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("@", nodes)?;
+        let (name, nodes, context) = consume_symbol(nodes, context)?;
+        match flat::peek_first_node(nodes)? {
+            flat::Node::Val(v) if v == "(" => {
+                let nodes = flat::consume_this_value("(", nodes)?;
+                let (param, nodes, context) = consume_literal_string(nodes, context)?;
+                let nodes = flat::consume_this_value(")", nodes)?;
+                Ok(((name.to_string(), Some(param)), nodes, context))
+            }
+            _ => Ok(((name.to_string(), None), nodes, context)),
+        }
+    })
+}
+
 fn consume_rule_name(
     nodes: &[flat::Node],
     context: Context,
@@ -433,7 +1791,7 @@ fn consume_or(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(Expression, &[flat::Node], Context), Error> {
-    // or              =   and         ( _  '/'  _  or )?
+    // or              =   labeled     ( _  '/'  _  or )?
 
     fn rec_consume_or(
         eov: ExprOrVecExpr,
@@ -441,7 +1799,7 @@ fn consume_or(
         context: Context,
     ) -> result::Result<(ExprOrVecExpr, &[flat::Node], Context), Error> {
         consuming_rule("or", nodes, context, |nodes, context| {
-            let (expr, nodes, context) = consume_and(nodes, context)?;
+            let (expr, nodes, context) = consume_labeled(nodes, context)?;
             let eov = eov.ipush(expr);
             let next_node = flat::peek_first_node(nodes)?;
 
@@ -462,13 +1820,44 @@ fn consume_or(
         let (eov, nodes, context) = rec_consume_or(ExprOrVecExpr::None, nodes, context)?;
 
         match eov {
-            ExprOrVecExpr::None => Err(error_peg_s("logic error, empty or parsing???")),
+            ExprOrVecExpr::None => Err(error_node_shape("expression", "empty")),
             ExprOrVecExpr::Expr(e) => Ok((e, nodes, context)),
             ExprOrVecExpr::VExpr(v) => Ok((build_or_expr(v), nodes, context)),
         }
     })
 }
 
+fn consume_labeled(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // labeled         =   and  ( _  label )?
+
+    consuming_rule("labeled", nodes, context, |nodes, context| {
+        let (expr, nodes, context) = consume_and(nodes, context)?;
+
+        match flat::peek_first_node(nodes)? {
+            flat::Node::BeginRule(_) => {
+                let (label, nodes, context) = consume_label(nodes, context)?;
+                Ok((label!(label, expr), nodes, context))
+            }
+            _ => Ok((expr, nodes, context)),
+        }
+    })
+}
+
+fn consume_label(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(&str, &[flat::Node], Context), Error> {
+    // label           =   '#'  symbol
+
+    consuming_rule("label", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("#", nodes)?;
+        consume_symbol(nodes, context)
+    })
+}
+
 fn consume_error(
     nodes: &[flat::Node],
     context: Context,
@@ -485,6 +1874,86 @@ fn consume_error(
     Ok((error!(val), nodes, context))
 }
 
+fn consume_warning(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // warning         =   'warning' _ '('  _  literal  _  ')'
+    let (val, nodes, context) = consuming_rule("warning", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("warning", nodes)?;
+        let nodes = flat::consume_this_value("(", nodes)?;
+        let (text, nodes, context) = consume_literal_string(nodes, context)?;
+        let nodes = flat::consume_this_value(")", nodes)?;
+        Ok((text, nodes, context))
+    })?;
+
+    Ok((warning!(val), nodes, context))
+}
+
+fn consume_until(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // until           =   'until' _  '('  _  literal  _  ( ','  _  literal  _ )?  ')'
+    consuming_rule("until", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("until", nodes)?;
+        let nodes = flat::consume_this_value("(", nodes)?;
+        let (delimiter, nodes, context) = consume_literal_string(nodes, context)?;
+        let (escape, nodes, context) = match flat::peek_first_node(nodes)? {
+            flat::Node::Val(v) if v == "," => {
+                let nodes = flat::consume_this_value(",", nodes)?;
+                let (escape, nodes, context) = consume_literal_string(nodes, context)?;
+                (Some(escape), nodes, context)
+            }
+            _ => (None, nodes, context),
+        };
+        let nodes = flat::consume_this_value(")", nodes)?;
+        Ok((
+            Expression::Simple(Atom::Until(delimiter, escape)),
+            nodes,
+            context,
+        ))
+    })
+}
+
+fn consume_balanced(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // balanced        =   'balanced' _  '('  _  literal  _  ','  _  literal  _  ')'
+    consuming_rule("balanced", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("balanced", nodes)?;
+        let nodes = flat::consume_this_value("(", nodes)?;
+        let (open, nodes, context) = consume_literal_string(nodes, context)?;
+        let nodes = flat::consume_this_value(",", nodes)?;
+        let (close, nodes, context) = consume_literal_string(nodes, context)?;
+        let nodes = flat::consume_this_value(")", nodes)?;
+        Ok((balanced!(open, close), nodes, context))
+    })
+}
+
+fn consume_pos(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // pos             =   '@pos' _  '('  _  symbol  _  ')'
+    consuming_rule("pos", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("@pos", nodes)?;
+        let nodes = flat::consume_this_value("(", nodes)?;
+        let (name, nodes, context) = consume_symbol(nodes, context)?;
+        let nodes = flat::consume_this_value(")", nodes)?;
+        Ok((pos!(name), nodes, context))
+    })
+}
+
+//  A lazy repetition needs the next expression on the sequence
+//  in order to know where to stop (and the labels on it, to not
+//  include the stop)
+enum SeqItem {
+    Expr(Expression),
+    LazyRep(Expression, usize, Vec<String>),
+}
+
 fn consume_and(
     nodes: &[flat::Node],
     context: Context,
@@ -493,34 +1962,53 @@ fn consume_and(
     //                 /   rep_or_neg  ( _1 _ !(rule_name _ ('=' / '{')) and )*
 
     fn rec_consume_and(
-        eov: ExprOrVecExpr,
+        items: Vec<SeqItem>,
         nodes: &[flat::Node],
         context: Context,
-    ) -> result::Result<(ExprOrVecExpr, &[flat::Node], Context), Error> {
+    ) -> result::Result<(Vec<SeqItem>, &[flat::Node], Context), Error> {
         consuming_rule("and", nodes, context, |nodes, context| {
             if "error" == flat::get_nodename(flat::peek_first_node(nodes)?)? {
                 let (expr, nodes, context) = consume_error(nodes, context)?;
-                let eov = eov.ipush(expr);
-                Ok((eov, nodes, context))
+                Ok((items.ipush(SeqItem::Expr(expr)), nodes, context))
             } else {
-                let (expr, nodes, context) = consume_rep_or_neg(nodes, context)?;
-                let eov = eov.ipush(expr);
+                let (item, nodes, context) = consume_rep_or_neg(nodes, context)?;
+                let items = items.ipush(item);
                 let next_node = flat::peek_first_node(nodes)?;
 
                 match (next_node, flat::get_nodename(next_node)) {
-                    (flat::Node::BeginRule(_), Ok("and")) => rec_consume_and(eov, nodes, context),
-                    _ => Ok((eov, nodes, context)),
+                    (flat::Node::BeginRule(_), Ok("and")) => rec_consume_and(items, nodes, context),
+                    _ => Ok((items, nodes, context)),
                 }
             }
         })
     }
 
+    //  processing from the end, the stop expression for a lazy
+    //  repetition is the last one pushed
+    fn resolve_lazy_reps(items: Vec<SeqItem>) -> result::Result<Vec<Expression>, Error> {
+        let rev_exprs =
+            items
+                .into_iter()
+                .rev()
+                .try_fold(vec![], |acc: Vec<Expression>, item| match item {
+                    SeqItem::Expr(e) => Ok(acc.ipush(e)),
+                    SeqItem::LazyRep(e, min, labels) => match acc.ipop() {
+                        (Some(stop), acc) => Ok(acc.ipush(label_lazy_repeat(e, min, stop, labels))),
+                        (None, _) => Err(error_kind(ErrorKind::LazyRepetitionWithoutStop)),
+                    },
+                })?;
+        Ok(rev_exprs.into_iter().rev().collect())
+    }
+
     let build_and_expr = |vexpr| Expression::And(expression::MultiExpr(vexpr));
     //  --------------------------
 
-    let (eov, nodes, context) = rec_consume_and(ExprOrVecExpr::None, nodes, context)?;
+    let (items, nodes, context) = rec_consume_and(vec![], nodes, context)?;
+    let eov = resolve_lazy_reps(items)?
+        .into_iter()
+        .fold(ExprOrVecExpr::None, |eov, e| eov.ipush(e));
     match eov {
-        ExprOrVecExpr::None => Err(error_peg_s("logic error, empty or parsing???")),
+        ExprOrVecExpr::None => Err(error_node_shape("expression", "empty")),
         ExprOrVecExpr::Expr(e) => Ok((e, nodes, context)),
         ExprOrVecExpr::VExpr(v) => Ok((build_and_expr(v), nodes, context)),
     }
@@ -529,21 +2017,26 @@ fn consume_and(
 fn consume_rep_or_neg(
     nodes: &[flat::Node],
     context: Context,
-) -> result::Result<(Expression, &[flat::Node], Context), Error> {
-    // rep_or_neg      =   atom_or_par ("*" / "+" / "?")?
-    //                 /   "!" atom_or_par
+) -> result::Result<(SeqItem, &[flat::Node], Context), Error> {
+    // rep_or_neg      =   named
+    //                 /   atom_or_par (   _  ('%,' / '%' !symbol)  _  atom_or_par
+    //                                 /   '*?' / '+?' / '*' / '+' / '?'
+    //                                 /   rep_count
+    //                                 )?
+    //                 /   '!' atom_or_par
 
     fn process_repetition_indicator(
         expr: Expression,
         rsymbol: &str,
-    ) -> result::Result<Expression, Error> {
+    ) -> result::Result<SeqItem, Error> {
         match rsymbol {
-            "+" => Ok(rep!(expr, 1)),
-            "*" => Ok(rep!(expr, 0)),
-            "?" => Ok(rep!(expr, 0, 1)),
-            unknown => Err(error_peg_s(&format!(
-                "repetition symbol unknown {}",
-                unknown
+            "+" => Ok(SeqItem::Expr(rep!(expr, 1))),
+            "*" => Ok(SeqItem::Expr(rep!(expr, 0))),
+            "?" => Ok(SeqItem::Expr(rep!(expr, 0, 1))),
+            "+?" => Ok(SeqItem::LazyRep(expr, 1, vec![])),
+            "*?" => Ok(SeqItem::LazyRep(expr, 0, vec![])),
+            unknown => Err(error_kind(ErrorKind::UnknownRepetition(
+                unknown.to_string(),
             ))),
         }
     }
@@ -553,36 +2046,129 @@ fn consume_rep_or_neg(
         let next_node = flat::peek_first_node(nodes)?;
 
         match next_node {
+            flat::Node::Val(op) if op == "%" || op == "%," => {
+                let (_, nodes) = flat::consume_val(nodes)?;
+                let (separator, nodes, context) = consume_atom_or_par(nodes, context)?;
+                let trailing = op == "%,";
+                let expr = expression::separated(expr, separator, trailing);
+                Ok((SeqItem::Expr(expr), nodes, context))
+            }
             flat::Node::Val(_) => {
                 let (sep, nodes) = flat::consume_val(nodes)?;
                 Ok((process_repetition_indicator(expr, sep)?, nodes, context))
             }
-            _ => Ok((expr, nodes, context)),
+            flat::Node::BeginRule(name) if name == "rep_count" => {
+                let (expr, nodes, context) = consume_rep_count(expr, nodes, context)?;
+                Ok((SeqItem::Expr(expr), nodes, context))
+            }
+            _ => Ok((SeqItem::Expr(expr), nodes, context)),
         }
     };
     let neg_and_atom =
-        |nodes, context| -> result::Result<(Expression, &[flat::Node], Context), Error> {
+        |nodes, context| -> result::Result<(SeqItem, &[flat::Node], Context), Error> {
             let nodes = flat::consume_this_value(r#"!"#, nodes)?;
             let (expr, nodes, context) = consume_atom_or_par(nodes, context)?;
-            Ok((not!(expr), nodes, context))
+            Ok((SeqItem::Expr(not!(expr)), nodes, context))
         };
     //  --------------------------
 
-    consuming_rule(
-        "rep_or_neg",
-        nodes,
-        context,
-        |nodes, context| match flat::peek_first_node(nodes)? {
-            flat::Node::Val(v) => {
+    consuming_rule("rep_or_neg", nodes, context, |nodes, context| {
+        let next_node = flat::peek_first_node(nodes)?;
+        match (next_node, flat::get_nodename(next_node)) {
+            (flat::Node::Val(v), _) => {
                 if v == "!" {
                     neg_and_atom(nodes, context)
                 } else {
-                    Err(error_peg_s(&format!("expected '!', received {}", v)))
+                    Err(error_node_shape("!", v))
                 }
             }
+            (flat::Node::BeginRule(_), Ok("named")) => consume_named(nodes, context),
             _ => atom_and_rep(nodes, context),
-        },
-    )
+        }
+    })
+}
+
+fn consume_rep_count(
+    expr: Expression,
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // rep_count       =   '{'  (  count  '}'
+    //                          /  error("invalid repetition count, expected {n}, {n,} or {n,m}")
+    //                          )
+    // count           =   [0-9]+  (','  [0-9]*)?
+
+    consuming_rule("rep_count", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("{", nodes)?;
+        let (count, nodes, context) = consuming_rule("count", nodes, context, |nodes, context| {
+            let (val, nodes) = flat::consume_val(nodes)?;
+            Ok((val, nodes, context))
+        })?;
+        let nodes = flat::consume_this_value("}", nodes)?;
+
+        let invalid = |descr: &str| {
+            error_kind(ErrorKind::InvalidRepetitionCount(count.to_string())).ipush(descr)
+        };
+        let number = |n: &str| n.parse::<usize>().map_err(|_| invalid("number too big"));
+        let (min, max) = match count.split_once(',') {
+            None => (number(count)?, Some(number(count)?)),
+            Some((min, "")) => (number(min)?, None),
+            Some((min, max)) => (number(min)?, Some(number(max)?)),
+        };
+        match max {
+            Some(0) => Err(invalid("the maximum has to be greater than 0")),
+            Some(max) if min > max => Err(invalid("the minimum is greater than the maximum")),
+            _ => Ok((
+                Expression::Repeat(RepInfo::new(Box::new(expr), min, max)),
+                nodes,
+                context,
+            )),
+        }
+    })
+}
+
+fn consume_named(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(SeqItem, &[flat::Node], Context), Error> {
+    // named           =   symbol  ':'  rep_or_neg
+
+    consuming_rule("named", nodes, context, |nodes, context| {
+        let (name, nodes, context) = consume_symbol(nodes, context)?;
+        let nodes = flat::consume_this_value(":", nodes)?;
+        let (item, nodes, context) = consume_rep_or_neg(nodes, context)?;
+        let item = match item {
+            SeqItem::Expr(expr) => SeqItem::Expr(label!(name, expr)),
+            SeqItem::LazyRep(expr, min, labels) => {
+                SeqItem::LazyRep(expr, min, labels.ipush(name.to_string()))
+            }
+        };
+        Ok((item, nodes, context))
+    })
+}
+
+//  the labels are for the repetition, the stop is the next item
+fn label_lazy_repeat(
+    expr: Expression,
+    min: usize,
+    stop: Expression,
+    labels: Vec<String>,
+) -> Expression {
+    let lazy = expression::lazy_repeat(expr, min, stop);
+    match (lazy, labels.is_empty()) {
+        (Expression::And(mexpr), false) => {
+            let mut items = mexpr.0;
+            let stop = items.pop();
+            let lazy = Expression::And(expression::MultiExpr::new(items));
+            let labeled = labels
+                .into_iter()
+                .fold(lazy, |expr, label| label!(label, expr));
+            Expression::And(expression::MultiExpr::new(
+                std::iter::once(labeled).chain(stop).collect(),
+            ))
+        }
+        (lazy, _) => lazy,
+    }
 }
 
 fn consume_atom_or_par(
@@ -599,7 +2185,7 @@ fn consume_atom_or_par(
             match &node_name as &str {
                 "atom" => consume_atom(nodes, context),
                 "parenth" => consume_parenth(nodes, context),
-                unknown => Err(error_peg_s(&format!("unknown {}", unknown))),
+                unknown => Err(error_node_shape("atom or parenth", unknown)),
             }
         })?;
 
@@ -611,10 +2197,15 @@ fn consume_atom(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(Expression, &[flat::Node], Context), Error> {
-    // atom            =   literal
-    //                 /   match
-    //                 /   dot
+    // atom            =   literal  no_case?
+    //                 /   match  no_case?
+    //                 /   assert
+    //                 /   warning
+    //                 /   until
+    //                 /   balanced
+    //                 /   pos
     //                 /   rule_name
+    //                 /   dot
 
     consuming_rule("atom", nodes, context, |nodes, context| {
         let next_node = flat::peek_first_node(nodes)?;
@@ -622,11 +2213,16 @@ fn consume_atom(
 
         let (expr, nodes, context) = push_err!(&format!("n:{}", node_name), {
             match &node_name as &str {
-                "literal" => consume_literal_expr(nodes, context),
+                "literal" => consume_literal_expr(nodes, context).and_then(consume_no_case),
+                "match" => consume_match(nodes, context).and_then(consume_no_case),
                 "rule_name" => consume_rule_ref(nodes, context),
                 "dot" => consume_dot(nodes, context),
-                "match" => consume_match(nodes, context),
-                unknown => Err(error_peg_s(&format!("unknown {}", unknown))),
+                "assert" => consume_assert(nodes, context),
+                "warning" => consume_warning(nodes, context),
+                "until" => consume_until(nodes, context),
+                "balanced" => consume_balanced(nodes, context),
+                "pos" => consume_pos(nodes, context),
+                unknown => Err(error_node_shape("atom", unknown)),
             }
         })?;
 
@@ -634,6 +2230,20 @@ fn consume_atom(
     })
 }
 
+//  optional suffix to ignore the case on literals and matches
+fn consume_no_case(
+    (expr, nodes, context): (Expression, &[flat::Node], Context),
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // no_case         =   'i'
+    match flat::get_nodename(flat::peek_first_node(nodes)?) {
+        Ok("no_case") => consuming_rule("no_case", nodes, context, |nodes, context| {
+            let nodes = flat::consume_this_value("i", nodes)?;
+            Ok((expression::no_case(expr), nodes, context))
+        }),
+        _ => Ok((expr, nodes, context)),
+    }
+}
+
 fn consume_parenth(
     nodes: &[flat::Node],
     context: Context,
@@ -648,6 +2258,23 @@ fn consume_parenth(
     })
 }
 
+fn consume_assert(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // assert          =   'assert' _  '('  _  expr  _  ','  _  literal  _  ')'
+
+    consuming_rule("assert", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("assert", nodes)?;
+        let nodes = flat::consume_this_value("(", nodes)?;
+        let (expr, nodes, context) = consume_peg_expr(nodes, context)?;
+        let nodes = flat::consume_this_value(",", nodes)?;
+        let (message, nodes, context) = consume_literal_string(nodes, context)?;
+        let nodes = flat::consume_this_value(")", nodes)?;
+        Ok((assert_expr!(expr, &message), nodes, context))
+    })
+}
+
 fn consume_literal_string(
     nodes: &[flat::Node],
     context: Context,
@@ -659,7 +2286,7 @@ fn consume_literal_string(
         match next_node_name {
             "lit_noesc" => consume_literal_no_esc(nodes, context),
             "lit_esc" => consume_literal_esc(nodes, context),
-            _ => Err(error_peg_s(&format!("unexpected node {}", next_node_name))),
+            _ => Err(error_node_shape("lit_noesc or lit_esc", next_node_name)),
         }
     })
 }
@@ -669,7 +2296,11 @@ fn consume_literal_expr(
     context: Context,
 ) -> result::Result<(Expression, &[flat::Node], Context), Error> {
     let (val, nodes, context) = consume_literal_string(nodes, context)?;
-    Ok((lit!(val), nodes, context))
+    let mut chars = val.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok((lit_char!(ch), nodes, context)),
+        _ => Ok((lit!(val), nodes, context)),
+    }
 }
 
 fn consume_literal_esc(
@@ -689,7 +2320,7 @@ fn consume_literal_esc(
         let crule_name = |rule_name, nodes, context| match rule_name {
             "esc_char" => consume_esc_char(nodes, context),
             "hex_char" => consume_hex_char(nodes, context),
-            _ => Err(error_peg_s(&format!("unknown rule_name: {}", rule_name))),
+            _ => Err(error_node_shape("esc_char or hex_char", rule_name)),
         };
 
         let next_n = flat::peek_first_node(nodes)?;
@@ -700,7 +2331,7 @@ fn consume_literal_esc(
                 let (val, nodes) = flat::consume_val(nodes).map(|(v, n)| (v.to_string(), n))?;
                 Ok((val, nodes, context))
             }
-            _ => Err(error_peg_s(&format!("looking for element {:#?}", next_n))),
+            _ => Err(error_node_shape("rule or value", &format!("{:?}", next_n))),
         }?;
 
         Ok((val.to_string(), nodes, context))
@@ -744,6 +2375,7 @@ fn consume_esc_char(
     //                 /   '\t'
     //                 /   '\\'
     //                 /   '\"'
+    //                 /   '\0' !'x'
 
     consuming_rule("esc_char", nodes, context, |nodes, context| {
         let (val, nodes) = flat::consume_val(nodes)?;
@@ -753,7 +2385,8 @@ fn consume_esc_char(
             r#"\t"# => Ok("\t"),
             r#"\\"# => Ok(r#"\"#),
             r#"\""# => Ok(r#"""#),
-            _ => Err(error_peg_s(&format!("unknow esc char: {}", val))),
+            r#"\0"# => Ok("\0"),
+            _ => Err(error_kind(ErrorKind::InvalidEscape(val.to_string()))),
         }?;
         Ok((val.to_string(), nodes, context))
     })
@@ -764,17 +2397,23 @@ fn consume_hex_char(
     context: Context,
 ) -> result::Result<(String, &[flat::Node], Context), Error> {
     // hex_char        =   '\0x' [0-9A-F] [0-9A-F]
+    //                 /   '\x' [0-9a-fA-F] [0-9a-fA-F]
+    //                 /   '\u{' [0-9a-fA-F]+ '}'
 
     use std::u8;
 
     consuming_rule("hex_char", nodes, context, |nodes, context| {
         let (val, nodes) = flat::consume_val(nodes)?;
-        let val = &val[3..];
-
-        let ch = match u8::from_str_radix(val, 16) {
-            Ok(v) => Ok(v as char),
-            _ => Err(error_peg_s(&format!("error parsing hex {}", &val[2..]))),
-        }?;
+        let ch = match val.strip_prefix(r#"\u{"#) {
+            Some(unicode) => u32::from_str_radix(unicode.trim_end_matches('}'), 16)
+                .ok()
+                .and_then(std::char::from_u32),
+            None => {
+                let hex = val.trim_start_matches(r#"\0x"#).trim_start_matches(r#"\x"#);
+                u8::from_str_radix(hex, 16).ok().map(|v| v as char)
+            }
+        }
+        .ok_or_else(|| error_kind(ErrorKind::InvalidEscape(val.to_string())))?;
         Ok((ch.to_string(), nodes, context))
     })
 }
@@ -790,7 +2429,7 @@ fn consume_literal_no_esc(
         let (nodes, context) = consume_single_quote(nodes, context)?;
         let (val, nodes) = flat::consume_val(nodes)?;
 
-        let val = val.replace(r#"\"#, r#"\\"#);
+        let val = val.to_string();
         let vclone = val.clone();
         push_err!(&format!("l:({})", vclone), {
             let (nodes, context) = consume_single_quote(nodes, context)?;
@@ -850,49 +2489,53 @@ fn consume_match(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // v1
     // match           =   "["
     //                         (
     //                             (mchars  mbetween*)
     //                             / mbetween+
     //                         )
     //                     "]"
+    // v2
+    // match           =   '['  ('^' !']')?  ( mbetween  /  mchars )+  ']'
 
-    type VecChCh = Vec<(char, char)>;
+    type CharsBetween = (String, Vec<(char, char)>);
     consuming_rule("match", nodes, context, |nodes, context| {
-        fn rec_consume_mbetween(
-            acc: Vec<(char, char)>,
+        fn rec_consume_melements(
+            (chars, between): CharsBetween,
             nodes: &[flat::Node],
             context: Context,
-        ) -> result::Result<(VecChCh, &[flat::Node], Context), Error> {
+        ) -> result::Result<(CharsBetween, &[flat::Node], Context), Error> {
             let next_node = flat::peek_first_node(nodes)?;
             let node_name = flat::get_nodename(next_node);
             match node_name {
                 Ok("mbetween") => {
                     let ((from, to), nodes, context) = consume_mbetween(nodes, context)?;
-                    rec_consume_mbetween(acc.ipush((from, to)), nodes, context)
+                    rec_consume_melements((chars, between.ipush((from, to))), nodes, context)
                 }
-                _ => Ok((acc, nodes, context)),
+                Ok("mchars") => {
+                    let (mchars, nodes, context) = consume_mchars(nodes, context)?;
+                    rec_consume_melements((chars + &mchars, between), nodes, context)
+                }
+                _ => Ok(((chars, between), nodes, context)),
             }
         }
         //  --------------------------
 
-        let nodes = flat::consume_this_value("[", nodes)?;
-
-        let (omchars, nodes, context) = match flat::get_nodename(flat::peek_first_node(nodes)?)? {
-            "mchars" => {
-                let (mchars, nodes, context) = consume_mchars(nodes, context)?;
-                (Some(mchars), nodes, context)
-            }
-            _ => (None, nodes, context),
-        };
+        //  compacted with the '^'
+        let (negated, nodes) = match flat::consume_val(nodes)? {
+            ("[", nodes) => Ok((false, nodes)),
+            ("[^", nodes) => Ok((true, nodes)),
+            (unknown, _) => Err(error_node_shape("[ or [^", unknown)),
+        }?;
 
-        let (vchars, nodes, context) = rec_consume_mbetween(vec![], nodes, context)?;
+        let ((chars, between), nodes, context) =
+            rec_consume_melements((String::new(), vec![]), nodes, context)?;
 
-        let (expr, nodes) = match (omchars, vchars.is_empty()) {
-            (Some(chars), true) => Ok((ematch!(chlist chars, from2 vec![]), nodes)),
-            (Some(chars), false) => Ok((ematch!(chlist chars, from2 vchars), nodes)),
-            (None, false) => Ok((ematch!(chlist "", from2 vchars), nodes)),
-            _ => Err(error_peg_s("Invalid match combination")),
+        let expr = match (chars.is_empty() && between.is_empty(), negated) {
+            (true, _) => Err(error_kind(ErrorKind::EmptyMatch)),
+            (false, false) => Ok(ematch!(chlist &chars, from2 between)),
+            (false, true) => Ok(ematch!(not chlist &chars, from2 between)),
         }?;
 
         let nodes = flat::consume_this_value("]", nodes)?;
@@ -904,12 +2547,50 @@ fn consume_match(
 fn consume_mchars(
     nodes: &[flat::Node],
     context: Context,
-) -> result::Result<(&str, &[flat::Node], Context), Error> {
-    // mchars          =   (!"]" !(. "-") .)+
+) -> result::Result<(String, &[flat::Node], Context), Error> {
+    // mchars          =   (!']' !(mchar '-') mchar)+
+
+    fn rec_consume_mchar(
+        acc: String,
+        nodes: &[flat::Node],
+        context: Context,
+    ) -> result::Result<(String, &[flat::Node], Context), Error> {
+        match flat::get_nodename(flat::peek_first_node(nodes)?) {
+            Ok("mchar") => {
+                let (ch, nodes, context) = consume_mchar(nodes, context)?;
+                rec_consume_mchar(format!("{}{}", acc, ch), nodes, context)
+            }
+            _ => Ok((acc, nodes, context)),
+        }
+    }
 
     consuming_rule("mchars", nodes, context, |nodes, context| {
-        let (val, nodes) = flat::consume_val(nodes)?;
-        Ok((val, nodes, context))
+        rec_consume_mchar(String::new(), nodes, context)
+    })
+}
+
+fn consume_mchar(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(char, &[flat::Node], Context), Error> {
+    // mchar           =   esc_char  /  hex_char  /  .
+
+    consuming_rule("mchar", nodes, context, |nodes, context| {
+        let (val, nodes, context) = match flat::peek_first_node(nodes)? {
+            flat::Node::BeginRule(r_name) if r_name == "esc_char" => {
+                consume_esc_char(nodes, context)?
+            }
+            flat::Node::BeginRule(r_name) if r_name == "hex_char" => {
+                consume_hex_char(nodes, context)?
+            }
+            _ => {
+                let (val, nodes) = flat::consume_val(nodes)?;
+                (val.to_string(), nodes, context)
+            }
+        };
+        let (ch, _) =
+            idata::consume_char(val.chars()).ok_or_else(|| error_node_shape("char", "empty"))?;
+        Ok((ch, nodes, context))
     })
 }
 
@@ -918,16 +2599,12 @@ fn consume_mbetween(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(CharChar, &[flat::Node], Context), Error> {
-    // mbetween        =   (.  "-"  .)
+    // mbetween        =   (mchar  '-'  !']'  mchar)
 
     consuming_rule("mbetween", nodes, context, |nodes, context| {
-        let (from_to, nodes) = flat::consume_val(nodes)?;
-
-        let (from, chars) = idata::consume_char(from_to.chars())
-            .ok_or_else(|| error_peg_s("expected from char"))?;
-        let (_, chars) =
-            idata::consume_char(chars).ok_or_else(|| error_peg_s("expected '-' char"))?;
-        let (to, _) = idata::consume_char(chars).ok_or_else(|| error_peg_s("expected to char"))?;
+        let (from, nodes, context) = consume_mchar(nodes, context)?;
+        let nodes = flat::consume_this_value("-", nodes)?;
+        let (to, nodes, context) = consume_mchar(nodes, context)?;
         Ok(((from, to), nodes, context))
     })
 }
@@ -19,6 +19,8 @@
 //!
 //! And the result, has to be pasted in peg::rules.rs
 //!
+//! peg::rules_v1.rs is frozen, it will not be generated again
+//!
 
 use {crate::peg, crate::rules_from_peg};
 
@@ -26,43 +28,75 @@ fn text_peg2code() -> &'static str {
     r#"
     /*      A peg grammar to parse peg grammars
      *
+     *      Version 1 is frozen on peg::rules_v1
      */
+    %syntax 2
 
     main            =   grammar
 
-    grammar         =   (rule  /  module)+
+    grammar         =   (rule  /  module  /  keywords  /  test)+
     
     module          =   _  mod_name _ '{'  _ grammar  _ '}' _eol _
     mod_name        =   symbol
     symbol          =   [_a-zA-Z0-9] [_'"a-zA-Z0-9]*
 
-    rule            =   _  rule_name  _  '='  _  expr  _eol _
+    keywords        =   _  '%keywords'  ( _1 _ !(rule_name _ ('=' / '{')) symbol )+  _eol _
+
+    test            =   _  '%test'  _1 _  rule_name  _1 _  ('accepts' / 'rejects')  _1 _  literal  _eol _
+
+    rule            =   _  (annotation  _)*  rule_name  _  '='  _  expr  _eol _
+    annotation      =   '@'  symbol  ( '('  _  literal  _  ')' )?
     rule_name       =   '.'?  symbol  ('.' symbol)*
 
     expr            =   or
 
-    or              =   and         ( _  '/'  _  or )?
+    or              =   labeled     ( _  '/'  _  or )?
+    labeled         =   and  ( _  label )?
+    label           =   '#'  symbol
     error           =   'error' _  '('  _  literal  _  ')'
 
     and             =   error 
-                    /   rep_or_neg  ( _1 _ !(rule_name _ ('=' / '{')) and )*
-    _1              =   (' ' / eol)     //  this is the and separator
-
-    rep_or_neg      =   atom_or_par ('*' / '+' / '?')?
+                    /   rep_or_neg  ( _1 _ !(rule_name _ ('=' / '{' !(count '}'))) and )*
+    _1              =   (' ' / "\t" / eol / comment)     //  this is the and separator
+
+    rep_or_neg      =   named
+                    /   atom_or_par (   _  ('%,' / '%' !symbol)  _  atom_or_par
+                                    /   '*?' / '+?' / '*' / '+' / '?'
+                                    /   rep_count
+                                    )?
                     /   '!' atom_or_par
 
+    rep_count       =   '{'  (  count  '}'
+                             /  error("invalid repetition count, expected {n}, {n,} or {n,m}")
+                             )
+    count           =   [0-9]+  (','  [0-9]*)?
+
+    named           =   symbol  ':'  rep_or_neg
+
     atom_or_par     =   (atom / parenth)
 
     parenth         =   '('  _  expr  _  (  ')'
                                          /  error("unbalanced parethesis: missing ')'") 
                                          )
 
-    atom            =   literal
-                    /   match
+    atom            =   literal  no_case?
+                    /   match  no_case?
+                    /   assert          //  has to be before rule_name
+                    /   warning         //  has to be before rule_name
+                    /   until           //  has to be before rule_name
+                    /   balanced        //  has to be before rule_name
+                    /   pos
                     /   rule_name
                     /   dot             //  as rule_name can start with a '.', dot has to be after rule_name
 
+    assert          =   'assert' _  '('  _  expr  _  ','  _  literal  _  ')'
+    warning         =   'warning' _  '('  _  literal  _  ')'
+    until           =   'until' _  '('  _  literal  _  ( ','  _  literal  _ )?  ')'
+    balanced        =   'balanced' _  '('  _  literal  _  ','  _  literal  _  ')'
+    pos             =   '@pos' _  '('  _  symbol  _  ')'
+
     literal         =  lit_noesc  /  lit_esc
+    no_case         =   'i'
 
     lit_noesc       =   _'   (  !_' .  )*   _'
     _'              =   "'"
@@ -80,25 +114,25 @@ fn text_peg2code() -> &'static str {
                     /   '\t'
                     /   '\\'
                     /   '\"'
+                    /   '\0' !'x'
 
     hex_char        =   '\0x' [0-9A-F] [0-9A-F]
+                    /   '\x' [0-9a-fA-F] [0-9a-fA-F]
+                    /   '\u{' [0-9a-fA-F]+ '}'
 
     eol             =   ("\r\n"  /  "\n"  /  "\r")
-    _eol            =   (' ' / comment)*  eol
+    _eol            =   (' ' / "\t" / comment)*  (eol / eof)
 
-    match           =   '['
-                            (
-                                (mchars  mbetween*)
-                                / mbetween+
-                            )
-                        ']'
+    match           =   '['  ('^' !']')?  ( mbetween  /  mchars )+  ']'
 
-    mchars          =   (!']' !(. '-') .)+
-    mbetween        =   (.  '-'  .)
+    mchars          =   (!']' !(mchar '-' !']') mchar)+
+    mbetween        =   (mchar  '-'  !']'  mchar)
+    mchar           =   esc_char  /  hex_char  /  .
 
     dot             =   '.'
 
     _               =   (  ' '
+                        /   "\t"
                         /   eol
                         /   comment
                         )*
@@ -106,7 +140,7 @@ fn text_peg2code() -> &'static str {
     comment         =   line_comment
                     /   mline_comment
 
-    line_comment    =   '//' (!eol .)*  eol
+    line_comment    =   '//' (!eol .)*  (eol / eof)
     mline_comment   =   '/*' (!'*/' .)* '*/'
     "#
 }
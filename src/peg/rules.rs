@@ -2,39 +2,54 @@ use crate::parser;
 
 pub(crate) fn parse_peg() -> parser::expression::SetOfRules {
   rules!(
-         r#"dot"# => lit!(".")
-       , r#"module"# => and!(ref_rule!(r#"_"#), ref_rule!(r#"mod_name"#), ref_rule!(r#"_"#), lit!("{"), ref_rule!(r#"_"#), ref_rule!(r#"grammar"#), ref_rule!(r#"_"#), lit!("}"), ref_rule!(r#"_eol"#), ref_rule!(r#"_"#))
-       , r#"mline_comment"# => and!(lit!("/*"), rep!(and!(not!(lit!("*/")), dot!()), 0), lit!("*/"))
-       , r#"_1"# => or!(lit!(" "), ref_rule!(r#"eol"#))
+         r#"_""# => lit_char!('"')
+       , r#"_"# => rep!(or!(lit_char!(' '), lit_char!('\t'), ref_rule!(r#"eol"#), ref_rule!(r#"comment"#)), 0)
+       , r#"_'"# => lit_char!('\'')
+       , r#"_1"# => or!(lit_char!(' '), lit_char!('\t'), ref_rule!(r#"eol"#), ref_rule!(r#"comment"#))
+       , r#"_eol"# => and!(rep!(or!(lit_char!(' '), lit_char!('\t'), ref_rule!(r#"comment"#)), 0), or!(ref_rule!(r#"eol"#), eof!()))
+       , r#"and"# => or!(ref_rule!(r#"error"#), and!(ref_rule!(r#"rep_or_neg"#), rep!(and!(ref_rule!(r#"_1"#), ref_rule!(r#"_"#), not!(and!(ref_rule!(r#"rule_name"#), ref_rule!(r#"_"#), or!(lit_char!('='), and!(lit_char!('{'), not!(and!(ref_rule!(r#"count"#), lit_char!('}'))))))), ref_rule!(r#"and"#)), 0)))
+       , r#"annotation"# => and!(lit_char!('@'), ref_rule!(r#"symbol"#), rep!(and!(lit_char!('('), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#), lit_char!(')')), 0, 1))
+       , r#"assert"# => and!(lit!("assert"), ref_rule!(r#"_"#), lit_char!('('), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_"#), lit_char!(','), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#), lit_char!(')'))
+       , r#"atom"# => or!(and!(ref_rule!(r#"literal"#), rep!(ref_rule!(r#"no_case"#), 0, 1)), and!(ref_rule!(r#"match"#), rep!(ref_rule!(r#"no_case"#), 0, 1)), ref_rule!(r#"assert"#), ref_rule!(r#"warning"#), ref_rule!(r#"until"#), ref_rule!(r#"balanced"#), ref_rule!(r#"pos"#), ref_rule!(r#"rule_name"#), ref_rule!(r#"dot"#))
+       , r#"atom_or_par"# => or!(ref_rule!(r#"atom"#), ref_rule!(r#"parenth"#))
+       , r#"balanced"# => and!(lit!("balanced"), ref_rule!(r#"_"#), lit_char!('('), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#), lit_char!(','), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#), lit_char!(')'))
        , r#"comment"# => or!(ref_rule!(r#"line_comment"#), ref_rule!(r#"mline_comment"#))
-       , r#"or"# => and!(ref_rule!(r#"and"#), rep!(and!(ref_rule!(r#"_"#), lit!("/"), ref_rule!(r#"_"#), ref_rule!(r#"or"#)), 0, 1))
-       , r#"literal"# => or!(ref_rule!(r#"lit_noesc"#), ref_rule!(r#"lit_esc"#))
-       , r#"mod_name"# => ref_rule!(r#"symbol"#)
-       , r#"esc_char"# => or!(lit!("\\r"), lit!("\\n"), lit!("\\t"), lit!("\\\\"), lit!("\\\""))
-       , r#"atom"# => or!(ref_rule!(r#"literal"#), ref_rule!(r#"match"#), ref_rule!(r#"rule_name"#), ref_rule!(r#"dot"#))
-       , r#"hex_char"# => and!(lit!("\\0x"), ematch!(chlist r#""#  , from '0', to '9' , from 'A', to 'F' ), ematch!(chlist r#""#  , from '0', to '9' , from 'A', to 'F' ))
-       , r#"parenth"# => and!(lit!("("), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_"#), or!(lit!(")"), error!("unbalanced parethesis: missing ')'")))
-       , r#"and"# => or!(ref_rule!(r#"error"#), and!(ref_rule!(r#"rep_or_neg"#), rep!(and!(ref_rule!(r#"_1"#), ref_rule!(r#"_"#), not!(and!(ref_rule!(r#"rule_name"#), ref_rule!(r#"_"#), or!(lit!("="), lit!("{")))), ref_rule!(r#"and"#)), 0)))
-       , r#"rule"# => and!(ref_rule!(r#"_"#), ref_rule!(r#"rule_name"#), ref_rule!(r#"_"#), lit!("="), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_eol"#), ref_rule!(r#"_"#))
-       , r#"grammar"# => rep!(or!(ref_rule!(r#"rule"#), ref_rule!(r#"module"#)), 1)
-       , r#"match"# => and!(lit!("["), or!(and!(ref_rule!(r#"mchars"#), rep!(ref_rule!(r#"mbetween"#), 0)), rep!(ref_rule!(r#"mbetween"#), 1)), lit!("]"))
-       , r#"rep_or_neg"# => or!(and!(ref_rule!(r#"atom_or_par"#), rep!(or!(lit!("*"), lit!("+"), lit!("?")), 0, 1)), and!(lit!("!"), ref_rule!(r#"atom_or_par"#)))
-       , r#"_eol"# => and!(rep!(or!(lit!(" "), ref_rule!(r#"comment"#)), 0), ref_rule!(r#"eol"#))
-       , r#"line_comment"# => and!(lit!("//"), rep!(and!(not!(ref_rule!(r#"eol"#)), dot!()), 0), ref_rule!(r#"eol"#))
-       , r#"lit_noesc"# => and!(ref_rule!(r#"_'"#), rep!(and!(not!(ref_rule!(r#"_'"#)), dot!()), 0), ref_rule!(r#"_'"#))
-       , r#"rule_name"# => and!(rep!(lit!("."), 0, 1), ref_rule!(r#"symbol"#), rep!(and!(lit!("."), ref_rule!(r#"symbol"#)), 0))
-       , r#"_""# => lit!("\"")
-       , r#"mchars"# => rep!(and!(not!(lit!("]")), not!(and!(dot!(), lit!("-"))), dot!()), 1)
+       , r#"count"# => and!(rep!(ematch!(chlist r#""#  , from '0', to '9' ), 1), rep!(and!(lit_char!(','), rep!(ematch!(chlist r#""#  , from '0', to '9' ), 0)), 0, 1))
+       , r#"dot"# => lit_char!('.')
+       , r#"eol"# => or!(lit!("\r\n"), lit_char!('\n'), lit_char!('\r'))
+       , r#"error"# => and!(lit!("error"), ref_rule!(r#"_"#), lit_char!('('), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#), lit_char!(')'))
+       , r#"esc_char"# => or!(lit!("\\r"), lit!("\\n"), lit!("\\t"), lit!("\\\\"), lit!("\\\""), and!(lit!("\\0"), not!(lit_char!('x'))))
        , r#"expr"# => ref_rule!(r#"or"#)
-       , r#"symbol"# => and!(ematch!(chlist r#"_"#  , from 'a', to 'z' , from 'A', to 'Z' , from '0', to '9' ), rep!(ematch!(chlist r#"_'""#  , from 'a', to 'z' , from 'A', to 'Z' , from '0', to '9' ), 0))
-       , r#"error"# => and!(lit!("error"), ref_rule!(r#"_"#), lit!("("), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#), lit!(")"))
-       , r#"_'"# => lit!("'")
-       , r#"eol"# => or!(lit!("\r\n"), lit!("\n"), lit!("\r"))
+       , r#"grammar"# => rep!(or!(ref_rule!(r#"rule"#), ref_rule!(r#"module"#), ref_rule!(r#"keywords"#), ref_rule!(r#"test"#)), 1)
+       , r#"hex_char"# => or!(and!(lit!("\\0x"), ematch!(chlist r#""#  , from '0', to '9' , from 'A', to 'F' ), ematch!(chlist r#""#  , from '0', to '9' , from 'A', to 'F' )), and!(lit!("\\x"), ematch!(chlist r#""#  , from '0', to '9' , from 'a', to 'f' , from 'A', to 'F' ), ematch!(chlist r#""#  , from '0', to '9' , from 'a', to 'f' , from 'A', to 'F' )), and!(lit!("\\u{"), rep!(ematch!(chlist r#""#  , from '0', to '9' , from 'a', to 'f' , from 'A', to 'F' ), 1), lit_char!('}')))
+       , r#"keywords"# => and!(ref_rule!(r#"_"#), lit!("%keywords"), rep!(and!(ref_rule!(r#"_1"#), ref_rule!(r#"_"#), not!(and!(ref_rule!(r#"rule_name"#), ref_rule!(r#"_"#), or!(lit_char!('='), lit_char!('{')))), ref_rule!(r#"symbol"#)), 1), ref_rule!(r#"_eol"#), ref_rule!(r#"_"#))
+       , r#"label"# => and!(lit_char!('#'), ref_rule!(r#"symbol"#))
+       , r#"labeled"# => and!(ref_rule!(r#"and"#), rep!(and!(ref_rule!(r#"_"#), ref_rule!(r#"label"#)), 0, 1))
+       , r#"line_comment"# => and!(lit!("//"), rep!(and!(not!(ref_rule!(r#"eol"#)), dot!()), 0), or!(ref_rule!(r#"eol"#), eof!()))
        , r#"lit_esc"# => and!(ref_rule!(r#"_""#), rep!(or!(ref_rule!(r#"esc_char"#), ref_rule!(r#"hex_char"#), and!(not!(ref_rule!(r#"_""#)), dot!())), 0), ref_rule!(r#"_""#))
-       , r#"mbetween"# => and!(dot!(), lit!("-"), dot!())
-       , r#"_"# => rep!(or!(lit!(" "), ref_rule!(r#"eol"#), ref_rule!(r#"comment"#)), 0)
+       , r#"lit_noesc"# => and!(ref_rule!(r#"_'"#), rep!(and!(not!(ref_rule!(r#"_'"#)), dot!()), 0), ref_rule!(r#"_'"#))
+       , r#"literal"# => or!(ref_rule!(r#"lit_noesc"#), ref_rule!(r#"lit_esc"#))
        , r#"main"# => ref_rule!(r#"grammar"#)
-       , r#"atom_or_par"# => or!(ref_rule!(r#"atom"#), ref_rule!(r#"parenth"#))
+       , r#"match"# => and!(lit_char!('['), rep!(and!(lit_char!('^'), not!(lit_char!(']'))), 0, 1), rep!(or!(ref_rule!(r#"mbetween"#), ref_rule!(r#"mchars"#)), 1), lit_char!(']'))
+       , r#"mbetween"# => and!(ref_rule!(r#"mchar"#), lit_char!('-'), not!(lit_char!(']')), ref_rule!(r#"mchar"#))
+       , r#"mchar"# => or!(ref_rule!(r#"esc_char"#), ref_rule!(r#"hex_char"#), dot!())
+       , r#"mchars"# => rep!(and!(not!(lit_char!(']')), not!(and!(ref_rule!(r#"mchar"#), lit_char!('-'), not!(lit_char!(']')))), ref_rule!(r#"mchar"#)), 1)
+       , r#"mline_comment"# => and!(lit!("/*"), rep!(and!(not!(lit!("*/")), dot!()), 0), lit!("*/"))
+       , r#"mod_name"# => ref_rule!(r#"symbol"#)
+       , r#"module"# => and!(ref_rule!(r#"_"#), ref_rule!(r#"mod_name"#), ref_rule!(r#"_"#), lit_char!('{'), ref_rule!(r#"_"#), ref_rule!(r#"grammar"#), ref_rule!(r#"_"#), lit_char!('}'), ref_rule!(r#"_eol"#), ref_rule!(r#"_"#))
+       , r#"named"# => and!(ref_rule!(r#"symbol"#), lit_char!(':'), ref_rule!(r#"rep_or_neg"#))
+       , r#"no_case"# => lit_char!('i')
+       , r#"or"# => and!(ref_rule!(r#"labeled"#), rep!(and!(ref_rule!(r#"_"#), lit_char!('/'), ref_rule!(r#"_"#), ref_rule!(r#"or"#)), 0, 1))
+       , r#"parenth"# => and!(lit_char!('('), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_"#), or!(lit_char!(')'), error!("unbalanced parethesis: missing ')'")))
+       , r#"pos"# => and!(lit!("@pos"), ref_rule!(r#"_"#), lit_char!('('), ref_rule!(r#"_"#), ref_rule!(r#"symbol"#), ref_rule!(r#"_"#), lit_char!(')'))
+       , r#"rep_count"# => and!(lit_char!('{'), or!(and!(ref_rule!(r#"count"#), lit_char!('}')), error!("invalid repetition count, expected {n}, {n,} or {n,m}")))
+       , r#"rep_or_neg"# => or!(ref_rule!(r#"named"#), and!(ref_rule!(r#"atom_or_par"#), rep!(or!(and!(ref_rule!(r#"_"#), or!(lit!("%,"), and!(lit_char!('%'), not!(ref_rule!(r#"symbol"#)))), ref_rule!(r#"_"#), ref_rule!(r#"atom_or_par"#)), lit!("*?"), lit!("+?"), lit_char!('*'), lit_char!('+'), lit_char!('?'), ref_rule!(r#"rep_count"#)), 0, 1)), and!(lit_char!('!'), ref_rule!(r#"atom_or_par"#)))
+       , r#"rule"# => and!(ref_rule!(r#"_"#), rep!(and!(ref_rule!(r#"annotation"#), ref_rule!(r#"_"#)), 0), ref_rule!(r#"rule_name"#), ref_rule!(r#"_"#), lit_char!('='), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_eol"#), ref_rule!(r#"_"#))
+       , r#"rule_name"# => and!(rep!(lit_char!('.'), 0, 1), ref_rule!(r#"symbol"#), rep!(and!(lit_char!('.'), ref_rule!(r#"symbol"#)), 0))
+       , r#"symbol"# => and!(ematch!(chlist r#"_"#  , from 'a', to 'z' , from 'A', to 'Z' , from '0', to '9' ), rep!(ematch!(chlist r#"_'""#  , from 'a', to 'z' , from 'A', to 'Z' , from '0', to '9' ), 0))
+       , r#"test"# => and!(ref_rule!(r#"_"#), lit!("%test"), ref_rule!(r#"_1"#), ref_rule!(r#"_"#), ref_rule!(r#"rule_name"#), ref_rule!(r#"_1"#), ref_rule!(r#"_"#), or!(lit!("accepts"), lit!("rejects")), ref_rule!(r#"_1"#), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_eol"#), ref_rule!(r#"_"#))
+       , r#"until"# => and!(lit!("until"), ref_rule!(r#"_"#), lit_char!('('), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#), rep!(and!(lit_char!(','), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#)), 0, 1), lit_char!(')'))
+       , r#"warning"# => and!(lit!("warning"), ref_rule!(r#"_"#), lit_char!('('), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#), lit_char!(')'))
 
   )
 }
@@ -3,7 +3,9 @@
 //  mod peg  TEST
 //
 //-----------------------------------------------------------------------
+use crate::ast;
 use crate::parse;
+use crate::parser::expression::RuleAccess;
 use crate::peg;
 
 #[test]
@@ -347,3 +349,1290 @@ fn test_match() {
     assert!(parse("Z", &rules).is_err());
     assert!(parse("ABZ", &rules).is_err());
 }
+
+#[test]
+fn parse_negated_match() {
+    let peg = r#"
+    %syntax 2
+
+    main    =   '"'  [^"\\\n]*  '"'  [^]  [^a-c]i  [\x5E-]
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse(r#""a b"^x-"#, &rules).is_ok());
+    assert!(parse(r#""ñ€"^D^"#, &rules).is_ok());
+    assert!(parse(r#""a"b"^x-"#, &rules).is_err());
+    assert!(parse("\"a\nb\"^x-", &rules).is_err());
+    assert!(parse(r#""a"^B-"#, &rules).is_err());
+    assert!(parse(r#""a"xx-"#, &rules).is_err());
+
+    //  the docs and the code keep the negation
+    let doc = peg::gdoc::markdown_from_rules(&rules);
+    assert!(doc.contains(r#"main = '"'  [^"\\\n]*  '"'  [\x5E]  [^a-c]i  [\x5E-]"#));
+    assert!(peg::gcode::rust_from_rules(&rules).contains(r##"ematch!(not chlist r#""\"##));
+}
+
+#[test]
+fn parse_lazy_klean() {
+    let peg = r#"
+
+    main    =   "<" .*? ">"
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("<>", &rules).is_ok());
+    assert!(parse("<hello>", &rules).is_ok());
+    assert!(parse("<hello> <world>", &rules).is_err());
+    assert!(parse("<hello", &rules).is_err());
+}
+
+#[test]
+fn parse_lazy_one_or_more() {
+    let peg = r#"
+
+    main    =   .+? "end"  'x'*
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("aend", &rules).is_ok());
+    assert!(parse("endend", &rules).is_ok());
+    assert!(parse("aendxx", &rules).is_ok());
+    assert!(parse("end", &rules).is_err());
+    assert!(parse("aendend", &rules).is_err());
+}
+
+#[test]
+fn parse_lazy_without_stop() {
+    let peg = r#"
+
+    main    =   (.*?)  "end"
+
+    "#;
+
+    assert!(peg::rules_from_peg(peg).is_err());
+}
+
+#[test]
+fn parse_builtin_assertions() {
+    let peg = r#"
+
+    main    =   bof  line*  eof
+    line    =   bol  [a-z]+  eol  nl?
+    nl      =   "\n"
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("", &rules).is_ok());
+    assert!(parse("abc", &rules).is_ok());
+    assert!(parse("abc\ndef\n", &rules).is_ok());
+    assert!(parse("abc\nd3f", &rules).is_err());
+}
+
+#[test]
+fn parse_builtin_overwritten() {
+    let peg = r#"
+
+    main    =   'a'  eol  'b'
+    eol     =   "\n"
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("a\nb", &rules).is_ok());
+    assert!(parse("ab", &rules).is_err());
+}
+
+#[test]
+fn parse_labeled_alternatives() {
+    let peg = r#"
+
+    main    =   'if' _ name   #If
+            /   'while' _ name #While
+            /   name
+    name    =   [a-z]+
+    _       =   ' '+
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    let label_name = |text| match parse(text, &rules).unwrap() {
+        ast::Node::Rule((_, nodes)) => match &nodes[0] {
+            ast::Node::Rule((name, _)) => name.clone(),
+            _ => panic!("expected rule node"),
+        },
+        _ => panic!("expected rule node"),
+    };
+
+    assert_eq!(label_name("if abc"), "If");
+    assert_eq!(label_name("while abc"), "While");
+    assert_eq!(label_name("abc"), "name");
+}
+
+#[test]
+fn parse_assert() {
+    let peg = r#"
+
+    main    =   'a'  assert(digit, "expected a digit")  digit+
+            /   'b'
+    digit   =   [0-9]
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("a12", &rules).is_ok());
+    assert!(parse("b", &rules).is_ok());
+    match parse("ax", &rules) {
+        Err(e) => assert_eq!(e.descr, "expected a digit"),
+        Ok(_) => panic!("it should fail"),
+    }
+}
+
+#[test]
+fn multiline_alternatives_leading_slash() {
+    let peg = "
+    main    =   'a'
+        /   'b'
+                        /   'c'
+    ";
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("a", &rules).is_ok());
+    assert!(parse("b", &rules).is_ok());
+    assert!(parse("c", &rules).is_ok());
+}
+
+#[test]
+fn multiline_alternatives_trailing_slash() {
+    let peg = "
+    main    =   'a' /
+                'b' /
+        'c'
+    ";
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("a", &rules).is_ok());
+    assert!(parse("b", &rules).is_ok());
+    assert!(parse("c", &rules).is_ok());
+}
+
+#[test]
+fn multiline_sequence() {
+    let peg = "
+    main    =   'a'
+                'b'
+            'c'  d
+    d       =   'd'
+    ";
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("abcd", &rules).is_ok());
+    assert!(parse("abc", &rules).is_err());
+}
+
+#[test]
+fn multiline_with_tabs() {
+    let peg = "
+\tmain\t=\t'a'\t'b'
+\t\t/\t'c'
+\td\t=\t'd'
+";
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("ab", &rules).is_ok());
+    assert!(parse("c", &rules).is_ok());
+}
+
+#[test]
+fn multiline_with_comments() {
+    let peg = "
+    main    =   'a'     //  first
+                'b'/*second*/'c'
+            /   'd'     /*  other
+                            option  */
+    ";
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("abc", &rules).is_ok());
+    assert!(parse("d", &rules).is_ok());
+}
+
+#[test]
+fn multiline_crlf() {
+    let peg = "main    =   'a'\r\n            /   'b'\r\n            'c'\r\nc = 'c'\r\n";
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("a", &rules).is_ok());
+    assert!(parse("bc", &rules).is_ok());
+}
+
+#[test]
+fn rule_without_final_eol() {
+    let rules = peg::rules_from_peg("main = 'a' b\nb = 'b'").unwrap();
+
+    assert!(parse("ab", &rules).is_ok());
+}
+
+#[test]
+fn new_rule_ends_continuation() {
+    let peg = "
+    main    =   'a'
+    b       =   'b'
+    ";
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("a", &rules).is_ok());
+    assert!(parse("ab", &rules).is_err());
+}
+
+#[test]
+fn gtypes_shapes() {
+    let peg = r#"
+
+    main    =   expr
+    expr    =   num  (_ op _ num)*  (_ '?' name)?
+    stmt    =   'if' _ expr     #If
+            /   'skip'
+    op      =   '+' / '-'
+    num     =   [0-9]+
+    name    =   [a-z]+
+    _       =   ' '*
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+    let code = peg::gtypes::rust_types_from_rules(&rules, &["_"]);
+
+    assert!(code.contains("pub struct Main(pub Box<Expr>);"));
+    assert!(
+        code.contains("pub struct Expr(pub Box<Num>, pub Vec<(Op, Num)>, pub Option<Box<Name>>);")
+    );
+    assert!(code.contains("pub enum Stmt {\n    If(Box<Expr>),\n    Alt1(String),\n}"));
+    assert!(code.contains("pub struct Op(pub String);"));
+    assert!(!code.contains("pub struct R("));
+}
+
+#[test]
+fn parse_noesc_backslash() {
+    let rules = peg::rules_from_peg(r#"main = 'a\b'"#).unwrap();
+    assert!(parse(r#"a\b"#, &rules).is_ok());
+}
+
+#[test]
+fn parse_nul_and_control_literals() {
+    let rules = peg::rules_from_peg(r#"main = "a\0b" "\x01" '\x02' "\0x41""#).unwrap();
+    assert!(parse("a\0b\x01\\x02A", &rules).is_ok());
+    assert!(parse("a\0b\x02\\x02A", &rules).is_err());
+}
+
+#[test]
+fn parse_char_literals() {
+    let rules = peg::rules_from_peg(
+        r#"
+        %syntax 2
+        main = 'a' "b"i '😀' "\u{e9}" [\u{e0}-\u{ff}]
+        "#,
+    )
+    .unwrap();
+    let code = peg::gcode::rust_from_rules(&rules);
+    assert!(code.contains("lit_char!('a')"));
+    assert!(code.contains("lit_char!('😀')"));
+    assert!(parse("aB😀éü", &rules).is_ok());
+    assert!(parse("ab😀eü", &rules).is_err());
+
+    let error = peg::rules_from_peg("%syntax 2\nmain = \"\\u{110000}\"").unwrap_err();
+    assert_eq!(error.code(), crate::codes::Code::InvalidEscape);
+}
+
+#[test]
+fn parse_control_chars_on_match() {
+    let rules = peg::rules_from_peg(r#"main = [\x1F\x00-\x08]+ [\t\n]"#).unwrap();
+    assert!(parse("\0\x01\x1f\t", &rules).is_ok());
+    assert!(parse("\0\x01\x1f\n", &rules).is_ok());
+    assert!(parse("\0 \t", &rules).is_err());
+}
+
+#[test]
+fn error_display_escapes_control_chars() {
+    let rules = peg::rules_from_peg(r#"main = "\0\x01" "b""#).unwrap();
+    let err = parse("\0\x01c", &rules).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected literal: <b> (row 0, col 3)\n\\0\\x01c\n       ^"
+    );
+}
+
+#[test]
+fn parse_warnings() {
+    let rules = peg::rules_from_peg(
+        r#"
+        main    =   item  (',' item)*
+        item    =   'ñ'  /  warning("expected ñ") [a-z]
+        "#,
+    )
+    .unwrap();
+
+    let parsed = crate::parse_with_warnings("ñ,a,ñ,b", &rules).unwrap();
+    let warnings: Vec<_> = parsed
+        .warnings
+        .iter()
+        .map(|w| (w.descr.as_str(), w.pos.col))
+        .collect();
+    assert_eq!(warnings, vec![("expected ñ", 2), ("expected ñ", 6)]);
+    assert!(parse("ñ,1", &rules).is_err());
+}
+
+#[test]
+fn parse_deprecated_rules() {
+    let rules = peg::rules_from_peg(
+        r#"
+        main        =   stmt+
+
+        stmt        =   new_stmt  /  old_stmt
+        new_stmt    =   'let '  [a-z]  ';'
+
+        @deprecated("use let instead")
+        old_stmt    =   'var '  [a-z]  (';' / warning("missing ;"))
+        "#,
+    )
+    .unwrap();
+
+    let parsed = crate::parse_with_warnings("let a;var b;var c", &rules).unwrap();
+    let warnings: Vec<_> = parsed
+        .warnings
+        .iter()
+        .map(|w| (w.descr.as_str(), w.pos.n, w.end.n))
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![
+            ("use let instead", 6, 12),
+            ("use let instead", 12, 17),
+            ("missing ;", 17, 17)
+        ]
+    );
+
+    assert!(peg::rules_from_peg(r#"@deprecated main = 'a'"#).is_err());
+    //  other annotations are metadata for the application
+    let rules = peg::rules_from_peg(r#"@unknown("a") main = 'a'"#).unwrap();
+    assert_eq!(rules.rule_meta("main").unwrap().value("unknown"), Some("a"));
+}
+
+#[test]
+fn rule_meta() {
+    let peg = r#"
+        main    =   (kw / id / ' ')*
+
+        @token  @highlight("keyword")
+        kw      =   'if'
+
+        @deprecated("use kw")
+        id      =   [a-z]+
+        "#;
+    let mut grammar = peg::compile(peg).unwrap();
+
+    let meta = grammar.rules.rule_meta("kw").unwrap();
+    assert_eq!(
+        meta.iter().collect::<Vec<_>>(),
+        vec![("highlight", Some("keyword")), ("token", None)]
+    );
+    assert_eq!(
+        grammar.rules.rule_meta("id").unwrap().value("deprecated"),
+        Some("use kw")
+    );
+    assert!(grammar.rules.rule_meta("main").is_none());
+
+    //  kept when other rules are compiled again
+    let updated = grammar
+        .update_from_peg(&peg.replace("(kw / id / ' ')*", "(kw / id / '  ')*"))
+        .unwrap();
+    assert_eq!(updated, vec!["main"]);
+    assert!(grammar.rules.rule_meta("kw").unwrap().has("token"));
+}
+
+#[test]
+fn passthrough_rules() {
+    let rules = peg::rules_from_peg(
+        r#"
+        main    =   sum
+
+        @passthrough
+        sum     =   product  ('+'  product)*
+        @passthrough
+        product =   num  ('*'  num)*
+        num     =   [0-9]+
+        "#,
+    )
+    .unwrap();
+
+    let num = |n: &str| ast::Node::Rule(("num".to_string(), vec![ast::Node::Val(n.to_string())]));
+    let main = |nodes| ast::Node::Rule(("main".to_string(), nodes));
+
+    assert_eq!(parse("2", &rules).unwrap(), main(vec![num("2")]));
+    assert_eq!(
+        parse("2*3", &rules).unwrap(),
+        main(vec![ast::Node::Rule((
+            "product".to_string(),
+            vec![num("2"), ast::Node::Val("*".to_string()), num("3")]
+        ))])
+    );
+
+    assert!(peg::rules_from_peg("@passthrough(\"a\")\nmain = 'a'").is_err());
+}
+
+#[test]
+fn raw_rules() {
+    use crate::ast::kind::NodeKind;
+
+    let rules = peg::rules_from_peg(
+        r#"
+        main    =   (call  ';'  _)*
+        @raw
+        call    =   name  _  '('  _  (name  _)*  ')'
+        name    =   [a-z]+
+        _       =   ' '*
+        "#,
+    )
+    .unwrap();
+
+    let input = "f ( a  b ); g();";
+    let call =
+        |src: &str| ast::Node::Rule(("call".to_string(), vec![ast::Node::Val(src.to_string())]));
+    let ast = parse(input, &rules).unwrap();
+    let (_, nodes) = ast.rule_parts();
+    assert_eq!(nodes[0], call("f ( a  b )"));
+    assert_eq!(nodes[3], call("g()"));
+
+    let ast = crate::parse_with_kinds(input, &rules, &crate::ParseOptions::default()).unwrap();
+    let (_, nodes) = ast.rule_parts();
+    let kinds: Vec<NodeKind> = nodes.iter().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds[..2],
+        [NodeKind::Rule("call".to_string()), NodeKind::Literal]
+    );
+    assert_eq!(nodes[0].rule_parts().1[0].kind(), NodeKind::Raw);
+    assert_eq!(ast.without_kinds(), parse(input, &rules).unwrap());
+
+    assert!(peg::rules_from_peg("@raw(\"a\")\nmain = 'a'").is_err());
+}
+
+#[test]
+fn inline_rules() {
+    let rules = peg::rules_from_peg(
+        r#"
+        main    =   num  (_  ','  _  num)*
+
+        num     =   digit+
+        @inline
+        digit   =   [0-9]
+        @inline
+        _       =   ' '*
+
+        @inline
+        list    =   '['  (num  / list)*  ']'
+        "#,
+    )
+    .unwrap();
+
+    let num = |n: &str| ast::Node::Rule(("num".to_string(), vec![ast::Node::Val(n.to_string())]));
+    assert_eq!(
+        parse("1 ,23", &rules).unwrap().compact(),
+        ast::Node::Rule((
+            "main".to_string(),
+            vec![num("1"), ast::Node::Val(" ,".to_string()), num("23")]
+        ))
+    );
+
+    //  the rule is kept, and recursive references are still called
+    assert!(rules.exprs.contains_key("digit"));
+    assert_eq!(
+        crate::parser::expression::rule_references(&rules.exprs["list"]),
+        vec!["num", "list"]
+    );
+
+    assert!(peg::rules_from_peg("@inline(\"a\")\nmain = 'a'").is_err());
+}
+
+#[test]
+fn markdown_doc() {
+    let rules = peg::rules_from_peg(
+        r#"
+        %syntax 2
+
+        main    =   (item  /  'x'i)*  eof
+
+        @doc("An item of the list")  @token
+        item    =   [a-z_-]+  "it's\n"  #Item
+                /   !'a'  .
+        "#,
+    )
+    .unwrap()
+    .set_access("item", RuleAccess::Sealed);
+
+    let doc = rules.to_markdown();
+    assert!(doc.starts_with("# Grammar\n\n- [main](#main)\n- [item](#item)\n"));
+    assert!(doc.contains("main = (item / 'x'i)*  eof\n"));
+    assert!(doc.contains(r#"item = [a-z_-]+  "it's\n"  #Item / !'a'  ."#));
+    assert!(doc.contains("## item\n\nAn item of the list\n\n"));
+    assert!(doc.contains("Annotations: `@token`\n\nAccess: sealed\n"));
+    assert!(doc.contains("References: [item](#item)\n"));
+    assert!(doc.contains("Referenced by: [main](#main)\n"));
+    assert!(!doc.contains("Railroad"));
+
+    let doc = peg::gdoc::markdown_with_diagrams(&rules, "diagrams/{rule}.svg");
+    assert!(doc.contains("[Railroad diagram](diagrams/main.svg)"));
+}
+
+#[test]
+fn parse_no_case() {
+    let rules = peg::rules_from_peg(
+        r#"
+        main    =   'select'i  ' '  "straße"i  ' '  [ña-f]i+  ' '  'x' i
+        i       =   'y'
+        "#,
+    )
+    .unwrap();
+
+    assert!(parse("SeLeCt STRAẞE aFñÑ xy", &rules).is_ok());
+    assert!(parse("select straße ABC xy", &rules).is_ok());
+    assert!(parse("select STRASSE abc xy", &rules).is_err());
+    assert!(parse("select straße abg xy", &rules).is_err());
+    assert!(parse("select straße abc Xy", &rules).is_err());
+
+    let ast = parse("SELECT straße a xy", &rules).unwrap();
+    assert_eq!(
+        ast.to_sexpr(),
+        r#"(main "SELECT" " " "straße" " " "a" " " "x" (i "y"))"#
+    );
+}
+
+#[test]
+fn no_case_greek_final_sigma() {
+    let rules = peg::rules_from_peg(r#"main = 'ΟΔΟΣ'i"#).unwrap();
+    assert!(parse("οδος", &rules).is_ok());
+    assert!(parse("οδοσ", &rules).is_ok());
+}
+
+#[test]
+fn parse_keywords() {
+    let rules = peg::rules_from_peg(
+        r#"
+        main        =   stmt+
+
+        stmt        =   'if '  ident  ' then '  ident  ';'
+                    /   ident  ' = '  ident  ';'
+
+        @identifier
+        ident       =   [a-z]+
+
+        %keywords   if  then
+                    else
+        "#,
+    )
+    .unwrap();
+
+    assert!(parse("a = b;if a then iff;", &rules).is_ok());
+    assert!(parse("a = then;", &rules).is_err());
+    assert!(parse("else = b;", &rules).is_err());
+    assert!(parse("elsea = b;", &rules).is_ok());
+
+    assert!(peg::rules_from_peg("@identifier\nmain = [a-z]+").is_err());
+}
+
+#[test]
+fn parse_contextual_keywords() {
+    let rules = peg::rules_from_peg(
+        r#"
+        main        =   (query / assign)+
+
+        @keywords("select, from")
+        query       =   'select '  list  ' from '  ident  ';'
+        list        =   ident  (', '  ident)*
+        assign      =   ident  ' = '  ident  ';'
+
+        @identifier
+        ident       =   [a-z]+
+        "#,
+    )
+    .unwrap();
+
+    assert!(parse("from = select;select a, b from c;", &rules).is_ok());
+    assert!(parse("select from from a;", &rules).is_err());
+    assert!(parse("select a, select from b;", &rules).is_err());
+    assert!(parse("select a from b;select = from;", &rules).is_ok());
+
+    assert!(peg::rules_from_peg("@keywords\nmain = [a-z]+").is_err());
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn parse_xid_builtins() {
+    let rules = peg::rules_from_peg(
+        r#"
+        main    =   ident  (' '  ident)*
+        ident   =   (xid_start / '_')  xid_continue*
+        "#,
+    )
+    .unwrap();
+
+    assert!(parse("año _x1 δέλτα 変数", &rules).is_ok());
+    assert!(parse("1a", &rules).is_err());
+    assert!(parse("a-b", &rules).is_err());
+
+    //  a rule with the same name is used
+    let rules = peg::rules_from_peg("main = xid_start\nxid_start = 'x'").unwrap();
+    assert!(parse("x", &rules).is_ok());
+    assert!(parse("a", &rules).is_err());
+}
+
+#[cfg(feature = "graphemes")]
+#[test]
+fn parse_grapheme_builtin() {
+    let rules = peg::rules_from_peg("main = grapheme grapheme").unwrap();
+    //  a letter with a combining accent, and a family emoji
+    assert!(parse("e\u{301}👨‍👩‍👧", &rules).is_ok());
+    assert!(parse("e\u{301}", &rules).is_err());
+
+    let rules = peg::rules_from_peg("main = . .").unwrap();
+    assert!(parse("e\u{301}", &rules).is_ok());
+}
+
+#[test]
+fn parse_separated_lists() {
+    let rules = peg::rules_from_peg(
+        r#"
+        %syntax 2
+
+        main    =   call  /  obj
+        call    =   'f('  (num % ', ')?  ')'
+        obj     =   '{'  (field %, ',')?  '}'
+        field   =   [a-z]+  ':'  num
+        num     =   [0-9]+
+        //  a directive after a rule is not a separator
+        %keywords   f
+        "#,
+    )
+    .unwrap();
+
+    assert!(parse("f()", &rules).is_ok());
+    assert!(parse("f(1, 2, 3)", &rules).is_ok());
+    assert!(parse("f(1, 2, )", &rules).is_err());
+    assert!(parse("{a:1,b:2}", &rules).is_ok());
+    assert!(parse("{a:1,b:2,}", &rules).is_ok());
+    assert!(parse("{a:1,,}", &rules).is_err());
+
+    //  only on syntax version 2
+    assert!(peg::rules_from_peg("main = 'a' % ','").is_err());
+}
+
+#[test]
+fn parse_named_items() {
+    let rules = peg::rules_from_peg(
+        r#"
+        %syntax 2
+        main    =   key:[a-z]+  ' '*  ':'  ' '*  value:(num / text)  comment:'#'*?  eof
+        num     =   [0-9]+
+        text    =   '"'  [a-z]*  '"'
+        "#,
+    )
+    .unwrap();
+
+    let ast = parse("port: 80#", &rules).unwrap().compact();
+    assert_eq!(
+        ast.to_sexpr(),
+        r##"(main (key "port") ": " (value (num "80")) (comment "#") "EOF")"##
+    );
+    let ast = parse("name:\"x\"", &rules).unwrap().compact();
+    assert_eq!(
+        ast.to_sexpr(),
+        r#"(main (key "name") ":" (value (text "\"x\"")) (comment) "EOF")"#
+    );
+
+    //  only on syntax version 2
+    assert!(peg::rules_from_peg("main = key:[a-z]+").is_err());
+}
+
+#[test]
+fn parse_positions() {
+    let rules = peg::rules_from_peg(
+        r#"
+        %syntax 2
+        main    =   (@pos(tried) 'z'  /  section)+
+        section =   '#' [a-z]+ "\n"  @pos(body)  line*
+        line    =   !'#' [a-z]+ "\n"
+
+        @deprecated("not used")
+        old     =   'x'
+        "#,
+    )
+    .unwrap();
+    assert!(peg::gcode::rust_from_rules(&rules).contains(r#"pos!("body")"#));
+
+    let parsed = crate::parse_with_captures("#a\nx\ny\n#b\n", &rules).unwrap();
+    let body: Vec<usize> = parsed.captures["body"].iter().map(|s| s.start.n).collect();
+    assert_eq!(body, vec![3, 10]);
+    assert!(parsed.captures["body"].iter().all(|s| s.start == s.end));
+    //  discarded branch
+    assert!(!parsed.captures.contains_key("tried"));
+
+    //  only on syntax version 2
+    assert!(peg::rules_from_peg("main = @pos(a) 'a'").is_err());
+}
+
+#[test]
+fn parse_repetition_counts() {
+    use crate::peg::ErrorKind;
+
+    let rules = peg::rules_from_peg(
+        r#"
+        %syntax 2
+        main    =   code  '-'  digit{2,}  '-'  ('ab' / 'c'){1,2}
+        code    =   [A-Z]{3}
+        digit   =   [0-9]
+
+        m {
+            x   =   'x'{2,3}
+        }
+        "#,
+    )
+    .unwrap();
+    assert!(parse("ABC-12-abc", &rules).is_ok());
+    assert!(parse("ABC-12345-c", &rules).is_ok());
+    assert!(parse("AB-12-c", &rules).is_err());
+    assert!(parse("ABCD-12-c", &rules).is_err());
+    assert!(parse("ABC-1-c", &rules).is_err());
+    assert!(parse("ABC-12-ccc", &rules).is_err());
+    let doc = peg::gdoc::markdown_from_rules(&rules);
+    assert!(doc.contains("digit{2,}") && doc.contains("[A-Z]{3}"));
+
+    let kind = |peg: &str| {
+        peg::rules_from_peg(&format!("%syntax 2\n{}", peg))
+            .unwrap_err()
+            .kind()
+    };
+    let invalid = |count: &str| ErrorKind::InvalidRepetitionCount(count.to_string());
+    assert_eq!(kind("main = 'a'{x}"), invalid("x"));
+    assert_eq!(kind("main = 'a'{2,1}"), invalid("2,1"));
+    assert_eq!(kind("main = 'a'{0}"), invalid("0"));
+    assert_eq!(kind("main = 'a'{,3}"), invalid(",3"));
+    assert_eq!(
+        kind("main = 'a'{99999999999999999999999}"),
+        invalid("99999999999999999999999")
+    );
+    assert_eq!(
+        invalid("x").code(),
+        crate::codes::Code::InvalidRepetitionCount
+    );
+
+    //  only on syntax version 2
+    assert!(peg::rules_from_peg("main = 'a'{2}").is_err());
+}
+
+#[test]
+fn error_kinds() {
+    use crate::peg::ErrorKind;
+    let kind = |peg: &str| peg::rules_from_peg(peg).unwrap_err().kind();
+
+    assert_eq!(kind("main = 'a' $"), ErrorKind::Syntax);
+    assert_eq!(kind("main = ('a' / 'b'"), ErrorKind::UnbalancedParen);
+    assert_eq!(
+        kind("%syntax 9\nmain = 'a'"),
+        ErrorKind::UnsupportedVersion(9)
+    );
+    assert_eq!(
+        kind("%syntax x\nmain = 'a'"),
+        ErrorKind::InvalidDirective("%syntax x".to_string())
+    );
+    assert_eq!(
+        kind("main = 'a' 'b'+?"),
+        ErrorKind::LazyRepetitionWithoutStop
+    );
+    assert_eq!(
+        kind("@identifier\nmain = [a-z]+"),
+        ErrorKind::IdentifierWithoutKeywords
+    );
+    assert_eq!(
+        kind("@deprecated\nmain = 'a'"),
+        ErrorKind::InvalidAnnotation {
+            annotation: "deprecated".to_string(),
+            descr: "expected message".to_string()
+        }
+    );
+    assert_eq!(
+        kind("%syntax 2\nmain = 'a'\n%test other accepts \"a\""),
+        ErrorKind::UnknownTestRule("other".to_string())
+    );
+
+    let error = peg::rules_from_peg("@keywords\nmain = 'a'").unwrap_err();
+    assert!(error.to_string().ends_with("> expected words on @keywords"));
+}
+
+#[test]
+fn feature_sections() {
+    let grammar = r#"
+        %syntax 2
+        main    =   stmt+
+        stmt    =   'let ' [a-z]+ ';'
+        %if loops
+                /   'loop {' stmt* '}'
+            %if !labels
+                /   'break;'
+            %else
+                /   'break ' [a-z]+ ';'
+            %endif
+        %endif
+        "#;
+
+    let rules = peg::rules_from_peg(grammar).unwrap();
+    assert!(parse("let a;", &rules).is_ok());
+    assert!(parse("loop {break;}", &rules).is_err());
+
+    let rules = peg::rules_from_peg_with_features(grammar, &["loops"]).unwrap();
+    assert!(parse("loop {let a;break;}", &rules).is_ok());
+    assert!(parse("loop {break a;}", &rules).is_err());
+
+    let rules = peg::rules_from_peg_with_features(grammar, &["loops", "labels"]).unwrap();
+    assert!(parse("loop {break a;}", &rules).is_ok());
+
+    //  positions on errors are kept
+    let error = peg::rules_from_peg("%if a\nb = 'b'\n%endif\nmain = 'a' $").unwrap_err();
+    assert!(matches!(error, peg::Error::Parser(e) if e.pos.row == 3));
+
+    //  the features are kept on updates
+    let mut compiled = peg::compile_with_features(grammar, &["loops"]).unwrap();
+    compiled
+        .update_from_peg(&grammar.replace("let ", "var "))
+        .unwrap();
+    assert!(parse("loop {var a;break;}", &compiled.rules).is_ok());
+
+    assert!(peg::rules_from_peg("%if a\nmain = 'a'").is_err());
+    assert!(peg::rules_from_peg("main = 'a'\n%endif").is_err());
+}
+
+#[test]
+fn parse_syntax_versions() {
+    //  without directive, version 1
+    assert!(peg::rules_from_peg("main = [a-fñ]+").is_err());
+    assert!(peg::rules_from_peg("main = [+-]").is_err());
+    let rules = peg::rules_from_peg("main = [A-]]").unwrap();
+    assert!(parse("]", &rules).is_ok());
+
+    let rules = peg::rules_from_peg(
+        r#"
+        // comments before the directive are allowed
+        %syntax 2   // latest
+        main    =   [a-fñ]+  [+-]  [a-]
+        "#,
+    )
+    .unwrap();
+    assert!(parse("cñ-a", &rules).is_ok());
+    assert!(parse("cñ+-", &rules).is_ok());
+    assert!(parse("cñ+]", &rules).is_err());
+
+    let rules = peg::rules_from_peg("%syntax 1\nmain = [A-]]").unwrap();
+    assert!(parse("B", &rules).is_ok());
+
+    assert!(peg::rules_from_peg("%syntax 3\nmain = 'a'").is_err());
+    assert!(peg::rules_from_peg("%syntax two\nmain = 'a'").is_err());
+    assert!(peg::rules_from_peg("main = 'a'\n%syntax 2").is_err());
+
+    //  positions are kept
+    match peg::rules_from_peg("%syntax 2\nmain = 'a' /") {
+        Err(peg::Error::Parser(e)) => assert_eq!(e.pos.row, 1),
+        _ => panic!("expected parser error"),
+    }
+}
+
+#[test]
+fn parse_inline_tests() {
+    let grammar = peg::compile(
+        r#"
+        %syntax 2
+        main    =   word  (' ' word)*
+        %test main      accepts "hello world"
+        %test main      rejects "hello  world"
+
+        word    =   [a-z]+
+        %test word      accepts 'abc'
+        %test word      accepts "ab c"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(grammar.tests.len(), 4);
+    assert_eq!(
+        grammar.tests[1],
+        peg::InlineTest {
+            rule: "main".to_string(),
+            accepts: false,
+            input: "hello  world".to_string()
+        }
+    );
+    let failed = grammar.run_inline_tests();
+    assert_eq!(failed, vec![&grammar.tests[3]]);
+
+    assert!(peg::compile("%syntax 2\nmain = 'a'\n%test other accepts 'a'").is_err());
+    //  not available on version 1
+    assert!(peg::compile("main = 'a'\n%test main accepts 'a'").is_err());
+}
+
+#[test]
+fn update_from_peg() {
+    //  same rules as a full compilation
+    fn same_rules(grammar: &peg::CompiledGrammar, peg: &str) {
+        let sorted = |rules: &crate::parser::expression::SetOfRules| {
+            let mut rules: Vec<String> = rules.exprs.iter().map(|r| format!("{:?}", r)).collect();
+            rules.sort();
+            rules
+        };
+        assert_eq!(
+            sorted(&grammar.rules),
+            sorted(&peg::rules_from_peg(peg).unwrap())
+        );
+    }
+
+    let v1 = r#"
+        %syntax 2
+        %keywords if else
+
+        main    =   (stmt  ';')*
+        //  a statement
+        stmt    =   'if' ' ' id
+                /   id
+        @identifier
+        id      =   [a-z]+
+        "#;
+    let mut grammar = peg::compile(v1).unwrap();
+
+    let v2 = v1.replace("/   id", "/   id  eol");
+    assert_eq!(grammar.update_from_peg(&v2).unwrap(), vec!["stmt"]);
+    same_rules(&grammar, &v2);
+
+    //  new and removed rules
+    let v3 = v2
+        .replace("id  eol", "id  end")
+        .replace("main    =", "end = ';'\n        main =");
+    assert_eq!(
+        grammar.update_from_peg(&v3).unwrap(),
+        vec!["end", "main", "stmt"]
+    );
+    same_rules(&grammar, &v3);
+    assert_eq!(grammar.update_from_peg(&v2).unwrap(), vec!["main", "stmt"]);
+    same_rules(&grammar, &v2);
+
+    //  a rule replacing a builtin, full compilation
+    let v4 = format!("{}\n        eol = 'x'\n", v2);
+    assert_eq!(grammar.update_from_peg(&v4).unwrap().len(), 4);
+    same_rules(&grammar, &v4);
+
+    //  keywords changed, full compilation
+    let v5 = v4.replace("if else", "if else while");
+    assert_eq!(grammar.update_from_peg(&v5).unwrap().len(), 4);
+    assert!(crate::parse("while;", &grammar.rules).is_err());
+
+    //  on error, nothing changes
+    assert!(grammar
+        .update_from_peg(&v5.replace("[a-z]+", "[a-z]+ /"))
+        .is_err());
+    assert!(grammar
+        .update_from_peg(&v5.replace("'x'", "'x' /"))
+        .is_err());
+    same_rules(&grammar, &v5);
+}
+
+#[test]
+fn update_from_peg_inline() {
+    let v1 = r#"
+        main    =   num  ','  num
+        num     =   digit+
+        @inline
+        digit   =   [0-9]
+        "#;
+    let mut grammar = peg::compile(v1).unwrap();
+
+    let v2 = v1.replace("','  num", "(','  num)*");
+    assert_eq!(grammar.update_from_peg(&v2).unwrap(), vec!["main"]);
+    let v3 = v2.replace("digit+", "digit+  digit?");
+    assert_eq!(grammar.update_from_peg(&v3).unwrap(), vec!["num"]);
+
+    let full = peg::rules_from_peg(&v3).unwrap();
+    for name in &["main", "num"] {
+        assert_eq!(
+            format!("{:?}", grammar.rules.exprs[*name]),
+            format!("{:?}", full.exprs[*name])
+        );
+    }
+    let input = "1,23,4";
+    assert_eq!(
+        parse(input, &grammar.rules).unwrap(),
+        parse(input, &full).unwrap()
+    );
+}
+
+#[test]
+fn parse_until() {
+    let rules = peg::rules_from_peg(
+        r#"
+        %syntax 2
+        main    =   (string / comment)+
+        string  =   '"'  until('"', '\')  '"'
+        comment =   '/*'  until("*/")  '*/'
+        "#,
+    )
+    .unwrap();
+
+    assert!(parse(r#""a\"b"/* " */"#, &rules).is_ok());
+    assert!(parse(r#"/* a "#, &rules).is_err());
+
+    //  a rule called until
+    let rules = peg::rules_from_peg("%syntax 2\nmain = until\nuntil = 'a'").unwrap();
+    assert!(parse("a", &rules).is_ok());
+
+    assert!(peg::rules_from_peg("main = '\"' until('\"') '\"'").is_err());
+}
+
+#[test]
+fn parse_balanced() {
+    let rules = peg::rules_from_peg(
+        r#"
+        %syntax 2
+        main    =   ( text / code )*
+        text    =   (!'{{' .)+
+        code    =   balanced('{{', '}}')
+        "#,
+    )
+    .unwrap();
+
+    let ast = parse("Hi {{ user.name {{x}} }}!", &rules).unwrap();
+    let codes: Vec<_> = ast
+        .compact()
+        .flatten()
+        .into_iter()
+        .skip_while(|n| *n != ast::flat::Node::BeginRule("code".to_string()))
+        .nth(1)
+        .into_iter()
+        .collect();
+    assert_eq!(
+        codes,
+        vec![ast::flat::Node::Val(" user.name {{x}} ".to_string())]
+    );
+    assert!(parse("Hi {{ user.name {{x}}!", &rules).is_err());
+}
+
+#[test]
+fn all_errors() {
+    use crate::peg::ErrorKind;
+
+    let grammar = r#"
+        %syntax 2
+
+        main    =   a  b  c
+        // a comment
+        a       =   'a'*?
+        b       =   'b'
+        c       =   ('c'
+        "#;
+    let errors = peg::compile_all_errors(grammar).unwrap_err();
+    let kinds: Vec<ErrorKind> = errors.iter().map(|e| e.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![ErrorKind::LazyRepetitionWithoutStop, ErrorKind::UnbalancedParen]
+    );
+    //  same position as compiling the declaration on the grammar
+    let only_c = grammar.replace("'a'*?", "'a'  ");
+    match (&errors[1], peg::compile(&only_c).unwrap_err()) {
+        (peg::Error::Parser(e), peg::Error::Parser(expected)) => {
+            assert_eq!(e.pos, expected.pos)
+        }
+        (e, _) => panic!("expected parser error, got {}", e),
+    }
+
+    //  only errors relating several declarations
+    let errors = peg::compile_all_errors(
+        r#"
+        %syntax 2
+        main    =   'a'
+        %test other accepts "a"
+        "#,
+    )
+    .unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].kind(),
+        ErrorKind::UnknownTestRule("other".to_string())
+    );
+
+    assert!(peg::rules_from_peg_all_errors("main = 'a'").is_ok());
+}
+
+#[test]
+fn recover_directive() {
+    use crate::peg::ErrorKind;
+    let rules = peg::rules_from_peg(
+        r#"
+        main    =   ('a'  "\n")*
+        %recover lines      //  skip wrong lines
+        "#,
+    )
+    .unwrap();
+    let meta = rules.rule_meta("main").unwrap();
+    assert_eq!(meta.value("recover"), Some("lines"));
+
+    let kind = |peg: &str| peg::rules_from_peg(peg).unwrap_err().kind();
+    assert_eq!(
+        kind("%recover tokens\nmain = 'a'"),
+        ErrorKind::InvalidDirective("%recover tokens".to_string())
+    );
+    assert_eq!(
+        kind("%recover lines\n%recover lines\nmain = 'a'"),
+        ErrorKind::InvalidDirective("%recover lines".to_string())
+    );
+
+    //  positions on errors are not modified
+    let err = peg::rules_from_peg("%recover lines\nmain = 'a' $").unwrap_err();
+    let plain = peg::rules_from_peg(&format!("{}\nmain = 'a' $", " ".repeat(14))).unwrap_err();
+    assert_eq!(err.to_string(), plain.to_string());
+}
+
+#[test]
+fn dot_policy() {
+    use crate::peg::ErrorKind;
+    let text = "ab\ncd";
+    let dots = |peg: &str| {
+        let rules = peg::rules_from_peg(peg).unwrap();
+        (parse(text, &rules).is_ok(), parse("abcd", &rules).is_ok())
+    };
+
+    assert_eq!(dots("main = .*"), (true, true));
+    assert_eq!(dots("%dot any\nmain = .*"), (true, true));
+    assert_eq!(dots("%dot no_newline\nmain = .*"), (false, true));
+    assert_eq!(dots("%dot no_newline\nmain = (. / \"\\n\")*"), (true, true));
+
+    //  explicit, whatever the policy
+    assert_eq!(dots("main = any*"), (false, true));
+    assert_eq!(dots("%dot no_newline\nmain = any_nl*"), (true, true));
+    assert_eq!(dots("main = any*  any_nl*"), (true, true));
+
+    //  a rule with the same name wins
+    assert_eq!(dots("main = any*\nany = [a-z]"), (false, true));
+
+    //  the policy is kept on updates
+    let mut grammar = peg::compile("%dot no_newline\nmain = .*").unwrap();
+    grammar
+        .update_from_peg("%dot no_newline\nmain = .* '!'?")
+        .unwrap();
+    assert!(parse(text, &grammar.rules).is_err());
+
+    assert_eq!(
+        peg::rules_from_peg("%dot byte\nmain = .").unwrap_err().kind(),
+        ErrorKind::InvalidDirective("%dot byte".to_string())
+    );
+}
+
+#[test]
+fn declaration_order() {
+    let peg = r#"
+        %dot no_newline
+        main        =   stmt+
+        stmt        =   ident  ' = '  value  ';'
+        @identifier
+        ident       =   [a-z]+
+        value       =   .
+        %keywords   if
+        "#;
+
+    let mut grammar = peg::compile(peg).unwrap();
+    assert_eq!(
+        grammar.rules.names(),
+        vec!["main", "stmt", "ident", "value"]
+    );
+
+    let code = peg::gcode::rust_from_rules(&grammar.rules);
+    let at = |name: &str| code.find(&format!("r#\"{}\"#", name)).unwrap();
+    assert!(at("main") < at("stmt") && at("stmt") < at("ident") && at("ident") < at("value"));
+
+    //  incremental updates follow the new declarations
+    grammar
+        .update_from_peg(&peg.replace("main        =   stmt+", "num = [0-9]+\nmain = stmt+"))
+        .unwrap();
+    assert_eq!(
+        grammar.rules.names(),
+        vec!["num", "main", "stmt", "ident", "value"]
+    );
+}
+
+#[test]
+fn internal_rules_of_modules() {
+    use crate::peg::ErrorKind;
+
+    let peg = r#"
+        main    =   number (',' number)*
+
+        number {
+            number  =   sign? digits
+            @internal
+            sign    =   '-'
+            @internal
+            digits  =   [0-9]+ frac?
+
+            frac {
+                frac    =   '.' digits
+            }
+        }
+        "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+    assert!(parse("1,-23,4.5", &rules).is_ok());
+    assert_eq!(rules.access("digits"), RuleAccess::Internal);
+    assert_eq!(rules.access("number"), RuleAccess::Extensible);
+
+    let outside = format!("{}\n other = digits", peg);
+    assert_eq!(
+        peg::rules_from_peg(&outside).unwrap_err().kind(),
+        ErrorKind::InternalRuleReference {
+            rule: "digits".to_string(),
+            module: "number".to_string(),
+            from: "other".to_string(),
+        }
+    );
+
+    //  also on incremental updates
+    let mut grammar = peg::compile(&format!("{}\n other = number", peg)).unwrap();
+    assert_eq!(
+        grammar.update_from_peg(&outside).unwrap_err().kind(),
+        peg::rules_from_peg(&outside).unwrap_err().kind()
+    );
+
+    //  nested module paths
+    let frac = "frac = '.' [0-9]+ \n @internal tail = 'x'";
+    let sibling = peg.replace("frac    =   '.' digits", frac) + "\n other {\n other = tail \n}\n";
+    assert_eq!(
+        peg::rules_from_peg(&sibling).unwrap_err().kind(),
+        ErrorKind::InternalRuleReference {
+            rule: "tail".to_string(),
+            module: "number.frac".to_string(),
+            from: "other".to_string(),
+        }
+    );
+
+    //  out of modules, only for plugins
+    let rules = peg::rules_from_peg("main = id \n @internal \n id = [a-z]+").unwrap();
+    let plugin = peg::rules_from_peg("other = id").unwrap();
+    assert!(rules.merge_plugin(plugin).is_err());
+}
@@ -0,0 +1,203 @@
+#![warn(missing_docs)]
+//! Fields of the records of an input, as rows (CSV, JSONL)
+//!
+//! A grammar can work as an extractor over big text files. Each match
+//! of the record rule gives a row, with the text of the field rules
+//! (or labels) matched inside it. The AST is not built, as on
+//! ```extract```
+//!
+//! The rows can be written as CSV lines or JSON lines
+//!
+//! ```
+//! extern crate dynparser;
+//! use dynparser::records::{csv_line, extract_records, jsonl_line};
+//! use dynparser::rules_from_peg;
+//!
+//! fn main() {
+//!     let rules = rules_from_peg(
+//!         r#"
+//!     line        =   date ' ' level (' ' user)? ': ' msg "\n"
+//!     date        =   [0-9]+ '-' [0-9]+ '-' [0-9]+
+//!     level       =   'INFO' / 'ERROR'
+//!     user        =   '@' [a-z]+
+//!     msg         =   (!"\n" .)*
+//!         "#,
+//!     ).unwrap();
+//!
+//!     let text = "2024-01-02 INFO: started\n2024-01-02 ERROR @bob: disk, full\n";
+//!     let fields = ["date", "level", "user", "msg"];
+//!     let rows: Vec<_> = extract_records(text, &rules, "line", &fields)
+//!         .collect::<Result<_, _>>()
+//!         .unwrap();
+//!
+//!     assert_eq!(csv_line(&rows[0], ','), "2024-01-02,INFO,,started");
+//!     assert_eq!(csv_line(&rows[1], ','), "2024-01-02,ERROR,@bob,\"disk, full\"");
+//!     assert_eq!(
+//!         jsonl_line(&fields, &rows[0]),
+//!         r#"{"date":"2024-01-02","level":"INFO","user":null,"msg":"started"}"#
+//!     );
+//! }
+//! ```
+
+use crate::codes::Code;
+use crate::parser::{self, expression, Status};
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Iterator over the rows of an input
+///
+/// Created with ```extract_records```
+pub struct Records<'a> {
+    rule_name: String,
+    fields: &'a [&'a str],
+    status: Option<Status<'a>>,
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Parse the input as a sequence of the record rule, and get a row per
+/// record with the text matched by every field rule (or label)
+///
+/// A field not matched on the record is ```None```. If it's matched
+/// several times, the first one is kept
+///
+/// It will stop after the first error. As on ```extract```, the error
+/// will only report the position
+pub fn extract_records<'a>(
+    text: &'a str,
+    rules: &'a expression::SetOfRules,
+    record_rule: &str,
+    fields: &'a [&'a str],
+) -> Records<'a> {
+    Records {
+        rule_name: record_rule.to_string(),
+        fields,
+        status: Some(
+            Status::init(text, rules)
+                .set_fail_fast(true)
+                .set_extract(fields),
+        ),
+    }
+}
+
+/// A row as a CSV line (without new line)
+///
+/// Fields with the separator, quotes or new lines are quoted. A field
+/// not matched is empty
+///
+/// ```
+/// use dynparser::records::csv_line;
+///
+/// let row = vec![Some("a".to_string()), None, Some("say \"hi\"".to_string())];
+/// assert_eq!(csv_line(&row, ';'), r#"a;;"say ""hi""""#);
+/// ```
+pub fn csv_line(row: &[Option<String>], separator: char) -> String {
+    let field = |value: &Option<String>| match value {
+        Some(v) if v.contains(&[separator, '"', '\n', '\r'][..]) => {
+            format!("\"{}\"", v.replace('"', "\"\""))
+        }
+        Some(v) => v.clone(),
+        None => String::new(),
+    };
+    row.iter()
+        .map(field)
+        .collect::<Vec<_>>()
+        .join(&separator.to_string())
+}
+
+/// A row as a JSON object on one line, with the names of the fields
+///
+/// A field not matched is ```null```
+///
+/// ```
+/// use dynparser::records::jsonl_line;
+///
+/// let row = vec![Some("a\n\"b\"".to_string()), None];
+/// assert_eq!(jsonl_line(&["x", "y"], &row), r#"{"x":"a\n\"b\"","y":null}"#);
+/// ```
+pub fn jsonl_line(names: &[&str], row: &[Option<String>]) -> String {
+    let pairs: Vec<String> = names
+        .iter()
+        .zip(row)
+        .map(|(name, value)| match value {
+            Some(v) => format!("\"{}\":\"{}\"", escape_json(name), escape_json(v)),
+            None => format!("\"{}\":null", escape_json(name)),
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Vec<Option<String>>, parser::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut status = self.status.take()?;
+        if status.it_parsing.as_str().is_empty() {
+            return None;
+        }
+
+        status.extracted.clear();
+        let start = status.pos.n;
+        match expression::parse_rule(status, &self.rule_name) {
+            Ok((st, _)) if st.pos.n == start => Some(Err(parser::Error::from_status_normal(
+                &st,
+                Code::EmptyRecord,
+                &format!("rule {} matched empty input", self.rule_name),
+            ))),
+            Ok((mut st, _)) => {
+                let row = self
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        st.extracted
+                            .iter()
+                            .find(|(name, _)| name == field)
+                            .map(|(_, text)| text.clone())
+                    })
+                    .collect();
+                st.potential_error = None;
+                self.status = Some(st);
+                Some(Ok(row))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  I N T E R N A L
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+fn escape_json(value: &str) -> String {
+    value.chars().fold(String::new(), |mut acc, ch| {
+        match ch {
+            '"' => acc.push_str(r#"\""#),
+            '\\' => acc.push_str(r"\\"),
+            '\n' => acc.push_str(r"\n"),
+            '\r' => acc.push_str(r"\r"),
+            '\t' => acc.push_str(r"\t"),
+            ch if (ch as u32) < 0x20 => acc.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => acc.push(ch),
+        }
+        acc
+    })
+}
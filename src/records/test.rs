@@ -0,0 +1,75 @@
+//-----------------------------------------------------------------------
+//
+//  mod records  TEST
+//
+//-----------------------------------------------------------------------
+use super::{csv_line, extract_records, jsonl_line};
+use crate::codes::Code;
+use crate::rules_from_peg;
+
+fn rules() -> crate::parser::expression::SetOfRules {
+    rules_from_peg(
+        r#"
+        %syntax 2
+        line    =   name ('=' value)?  ("\n" / eof)
+        name    =   [a-z]+
+        value   =   quoted:('"' (!'"' .)* '"')  /  [0-9]+
+        "#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn extract_rows() {
+    let rules = rules();
+    let fields = ["name", "value", "quoted"];
+    let rows: Vec<Vec<Option<String>>> =
+        extract_records("a=1\nb\nc=\"x,y\"", &rules, "line", &fields)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+    let some = |s: &str| Some(s.to_string());
+    assert_eq!(
+        rows,
+        vec![
+            vec![some("a"), some("1"), None],
+            vec![some("b"), None, None],
+            vec![some("c"), some("\"x,y\""), some("\"x,y\"")],
+        ]
+    );
+
+    let csv: Vec<String> = rows.iter().map(|r| csv_line(r, ',')).collect();
+    assert_eq!(csv, vec!["a,1,", "b,,", r#"c,"""x,y""","""x,y""""#]);
+    assert_eq!(
+        jsonl_line(&fields, &rows[2]),
+        r#"{"name":"c","value":"\"x,y\"","quoted":"\"x,y\""}"#
+    );
+}
+
+#[test]
+fn extract_rows_errors() {
+    let rules = rules();
+    let fields = ["name"];
+
+    let mut rows = extract_records("a=1\nb=?\nc\n", &rules, "line", &fields);
+    assert_eq!(rows.next().unwrap().unwrap(), vec![Some("a".to_string())]);
+    let error = rows.next().unwrap().unwrap_err();
+    assert_eq!(error.pos.row, 1);
+    assert!(rows.next().is_none());
+
+    let rules = rules_from_peg("line = [a-z]*").unwrap();
+    let error = extract_records("1", &rules, "line", &fields)
+        .next()
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(error.code, Code::EmptyRecord);
+    assert!(extract_records("", &rules, "line", &fields)
+        .next()
+        .is_none());
+}
+
+#[test]
+fn json_escapes() {
+    let row = vec![Some("\t\\\u{1}ñ".to_string())];
+    assert_eq!(jsonl_line(&["k\""], &row), r#"{"k\"":"\t\\\u0001ñ"}"#);
+}
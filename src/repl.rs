@@ -0,0 +1,180 @@
+//! Interactive session to try a grammar
+//!
+//! ```ignore
+//!     dynparser repl grammar.peg
+//! ```
+//!
+//! Every line typed will be parsed with the grammar, showing the AST
+//! or the error. The grammar file is watched, and reloaded when it
+//! changes (only the modified rules are compiled again)
+//!
+//! Colors can be disabled with the ```NO_COLOR``` environment variable
+
+use dynparser::{ast, parse_with_warnings, peg};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use std::{env, fs, thread};
+
+type SharedRules = Arc<Mutex<Option<peg::CompiledGrammar>>>;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const RESET: &str = "\x1b[0m";
+
+const HELP: &str = "Type a line to parse it with the grammar
+  \\n \\t \\\\    escape chars on input
+  :reload     load the grammar again
+  :help       this help
+  :quit       exit (or Ctrl-D)";
+
+/// Run the session till end of input or ```:quit```
+pub fn run(grammar: &str) -> io::Result<()> {
+    let path = PathBuf::from(grammar);
+    let rules: SharedRules = Arc::new(Mutex::new(None));
+
+    load(&path, &rules);
+    watch(path.clone(), rules.clone());
+    println!("{}", HELP);
+
+    let stdin = io::stdin();
+    prompt()?;
+    for line in stdin.lock().lines() {
+        match line?.as_str() {
+            ":quit" | ":q" => break,
+            ":reload" | ":r" => load(&path, &rules),
+            ":help" | ":h" => println!("{}", HELP),
+            input => match *rules.lock().unwrap() {
+                Some(ref grammar) => show_parse(&unescape(input), &grammar.rules),
+                None => println!("{}", paint(RED, "no valid grammar loaded")),
+            },
+        }
+        prompt()?;
+    }
+    Ok(())
+}
+
+//-----------------------------------------------------------------------
+//  I N T E R N A L
+//-----------------------------------------------------------------------
+
+fn prompt() -> io::Result<()> {
+    print!("{}", paint(BLUE, "> "));
+    io::stdout().flush()
+}
+
+fn load(path: &Path, rules: &SharedRules) {
+    let mut current = rules.lock().unwrap();
+    let loaded = fs::read_to_string(path)
+        .map_err(|e| format!("reading {}: {}", path.display(), e))
+        .and_then(|text| {
+            let compiled = match current.as_mut() {
+                Some(grammar) => grammar.update_from_peg(&text).map(|names| names.len()),
+                None => peg::compile(&text).map(|grammar| {
+                    let n = grammar.rules.expressions().len();
+                    *current = Some(grammar);
+                    n
+                }),
+            };
+            compiled.map_err(|e| match e {
+                peg::Error::Parser(e) => format!("invalid grammar: {}", e),
+                e => format!("invalid grammar: {}", e),
+            })
+        });
+
+    match loaded {
+        Ok(n_compiled) => println!(
+            "{}",
+            paint(
+                GREEN,
+                &format!("loaded {} ({} rules compiled)", path.display(), n_compiled)
+            )
+        ),
+        Err(e) => {
+            println!("{}", paint(RED, &e));
+            *current = None;
+        }
+    }
+}
+
+//  polling, to avoid dependencies
+fn watch(path: PathBuf, rules: SharedRules) {
+    let modified = |path: &Path| -> Option<SystemTime> { fs::metadata(path).ok()?.modified().ok() };
+
+    thread::spawn(move || {
+        let mut last = modified(&path);
+        loop {
+            thread::sleep(Duration::from_millis(500));
+            let current = modified(&path);
+            if current != last {
+                last = current;
+                println!();
+                load(&path, &rules);
+                let _ = prompt();
+            }
+        }
+    });
+}
+
+fn show_parse(input: &str, rules: &dynparser::parser::expression::SetOfRules) {
+    match parse_with_warnings(input, rules) {
+        Ok(parsed) => {
+            print!("{}", tree(&parsed.ast.compact(), 0));
+            for w in parsed.warnings {
+                println!(
+                    "{}",
+                    paint(
+                        YELLOW,
+                        &format!(
+                            "warning: {} (row {}, col {})",
+                            w.descr, w.pos.row, w.pos.col
+                        )
+                    )
+                );
+            }
+        }
+        Err(e) => println!("{}", paint(RED, &e.to_string())),
+    }
+}
+
+//  one node per line, indented
+//  a rule with a single value, will be on the same line
+fn tree(node: &ast::Node, indent: usize) -> String {
+    let margin = "  ".repeat(indent);
+    match node {
+        ast::Node::EOF => format!("{}{}\n", margin, paint(BLUE, "EOF")),
+        ast::Node::Val(v) => format!("{}{}\n", margin, paint(GREEN, &format!("{:?}", v))),
+        ast::Node::Rule((name, nodes)) => match nodes.as_slice() {
+            [ast::Node::Val(v)] => format!(
+                "{}{} {}\n",
+                margin,
+                paint(BLUE, name),
+                paint(GREEN, &format!("{:?}", v))
+            ),
+            _ => nodes
+                .iter()
+                .fold(format!("{}{}\n", margin, paint(BLUE, name)), |acc, n| {
+                    acc + &tree(n, indent + 1)
+                }),
+        },
+    }
+}
+
+fn paint(color: &str, text: &str) -> String {
+    if env::var_os("NO_COLOR").is_some() {
+        text.to_string()
+    } else {
+        format!("{}{}{}", color, text, RESET)
+    }
+}
+
+fn unescape(input: &str) -> String {
+    input
+        .replace(r"\\", "\0")
+        .replace(r"\n", "\n")
+        .replace(r"\t", "\t")
+        .replace('\0', r"\")
+}
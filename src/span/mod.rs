@@ -0,0 +1,284 @@
+#![warn(missing_docs)]
+//! Map positions between an original text and a transformed one
+//!
+//! Preprocessors (templates, macros, removing indentation...) parse a
+//! text different from the one written by the user. A ```SpanMap```
+//! keeps the edits, to report the positions on the original text
+//!
+//! Positions are in chars, as ```Possition::n```
+//!
+//! ```
+//! #[macro_use]  extern crate dynparser;
+//! use dynparser::parse;
+//! use dynparser::span::SpanMap;
+//!
+//! fn main() {
+//!     let rules = rules!{
+//!        "main"   =>  rep!(ematch!(chlist "", from 'a', to 'z'), 1)
+//!     };
+//!
+//!     //  the preprocessor replaced  "{{x}}"  by  "value"
+//!     let original = "ab{{x}}c2";
+//!     let preprocessed = "abvaluec2";
+//!     let map = SpanMap::new(vec![(2..7, 2..7)]).unwrap();
+//!
+//!     let error = parse(preprocessed, &rules).unwrap_err();
+//!     let error = map.error_to_original(&error, original);
+//!     assert_eq!(error.line_before, "ab{{x}}c2");
+//! }
+//! ```
+
+use crate::parser::{self, Possition};
+use std::fmt;
+use std::ops::Range;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Edits from an original text to a new one
+///
+/// Every edit replaces a range of the original text by a range on
+/// the new text. The text between edits is not modified
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpanMap {
+    edits: Vec<Edit>,
+}
+
+/// Edits not consistent (overlapped, or unmodified text with different
+/// length on both texts)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error(pub String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid span map: {}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Edit {
+    orig: Range<usize>,
+    new: Range<usize>,
+}
+
+//  text not modified
+//  the last one has no end
+#[derive(Debug, Clone, Copy)]
+struct Kept {
+    orig: usize,
+    new: usize,
+    len: Option<usize>,
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl SpanMap {
+    /// Map from a list of edits ```(original range, new range)```
+    ///
+    /// The order of the edits is not relevant
+    pub fn new(edits: Vec<(Range<usize>, Range<usize>)>) -> Result<Self, Error> {
+        let mut edits: Vec<Edit> = edits
+            .into_iter()
+            .map(|(orig, new)| Edit { orig, new })
+            .collect();
+        edits.sort_by_key(|e| (e.orig.start, e.orig.end));
+
+        let (mut orig, mut new) = (0, 0);
+        for e in &edits {
+            if e.orig.start > e.orig.end || e.new.start > e.new.end {
+                return Err(Error(format!("reversed range on {:?}", e)));
+            }
+            if e.orig.start < orig || e.new.start < new {
+                return Err(Error(format!("overlapped edit {:?}", e)));
+            }
+            if e.orig.start - orig != e.new.start - new {
+                return Err(Error(format!(
+                    "different length of unmodified text before {:?}",
+                    e
+                )));
+            }
+            orig = e.orig.end;
+            new = e.new.end;
+        }
+        Ok(SpanMap { edits })
+    }
+
+    /// Map without edits
+    pub fn identity() -> Self {
+        SpanMap::default()
+    }
+
+    /// Position on the original text for a position on the new one
+    ///
+    /// Inside an edit, the offset from the start of the edit is kept,
+    /// limited to the last char of the original range
+    pub fn to_original(&self, pos: usize) -> usize {
+        translate(pos, self.edits.iter().map(|e| (&e.new, &e.orig)))
+    }
+
+    /// Position on the new text for a position on the original one
+    pub fn to_new(&self, pos: usize) -> usize {
+        translate(pos, self.edits.iter().map(|e| (&e.orig, &e.new)))
+    }
+
+    /// Range on the original text for a range on the new one
+    ///
+    /// A range ending inside an edit, will cover the full edit on the
+    /// original text
+    pub fn range_to_original(&self, range: Range<usize>) -> Range<usize> {
+        let end = match self.edits.iter().find(|e| e.new.contains(&range.end)) {
+            Some(e) if range.end > e.new.start => e.orig.end,
+            _ => self.to_original(range.end),
+        };
+        self.to_original(range.start)..end
+    }
+
+    /// Map equivalent to apply this one and next the parameter
+    ///
+    /// If ```self``` goes from text A to B, and ```next``` from B to C,
+    /// the result will go from A to C
+    ///
+    /// ```
+    /// use dynparser::span::SpanMap;
+    ///
+    /// //  "abcd" -> "aXYbcd" -> "aXYd"
+    /// let insert = SpanMap::new(vec![(1..1, 1..3)]).unwrap();
+    /// let remove = SpanMap::new(vec![(3..5, 3..3)]).unwrap();
+    ///
+    /// let map = insert.compose(&remove);
+    /// assert_eq!(map, SpanMap::new(vec![(1..3, 1..3)]).unwrap());
+    /// assert_eq!(map.to_original(3), 3);
+    /// ```
+    pub fn compose(&self, next: &SpanMap) -> SpanMap {
+        let end = |start: usize, len: Option<usize>| len.map(|l| start + l);
+
+        //  text not modified on both maps (positions on B)
+        let mut kept = vec![];
+        for k1 in self.kept() {
+            for k2 in next.kept() {
+                let start = k1.new.max(k2.orig);
+                let stop = match (end(k1.new, k1.len), end(k2.orig, k2.len)) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                if stop.is_none_or(|stop| start < stop) {
+                    kept.push(Kept {
+                        orig: k1.orig + (start - k1.new),
+                        new: k2.new + (start - k2.orig),
+                        len: stop.map(|stop| stop - start),
+                    });
+                }
+            }
+        }
+        SpanMap::from_kept(&kept)
+    }
+
+    /// Error with the position and the lines on the original text
+    ///
+    /// The text has to be the original one
+    pub fn error_to_original(&self, error: &parser::Error, original: &str) -> parser::Error {
+        let pos = self.to_original(error.pos.n);
+
+        let mut possition = Possition::init();
+        original
+            .chars()
+            .take(pos)
+            .for_each(|ch| possition.advance_char(ch, error.newlines));
+
+        parser::Error {
+            line_before: original
+                .chars()
+                .skip(possition.start_line)
+                .take(possition.n - possition.start_line)
+                .collect(),
+            line_after: original
+                .chars()
+                .skip(possition.n)
+                .take_while(|&ch| !error.newlines.is_newline(ch) && ch != '\r')
+                .collect(),
+            pos: possition,
+            ..error.clone()
+        }
+    }
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  I N T E R N A L
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl SpanMap {
+    fn kept(&self) -> Vec<Kept> {
+        let (mut orig, mut new) = (0, 0);
+        let mut kept = vec![];
+        for e in &self.edits {
+            kept.push(Kept {
+                orig,
+                new,
+                len: Some(e.orig.start - orig),
+            });
+            orig = e.orig.end;
+            new = e.new.end;
+        }
+        kept.push(Kept {
+            orig,
+            new,
+            len: None,
+        });
+        kept
+    }
+
+    //  the edits are the gaps between the text not modified
+    fn from_kept(kept: &[Kept]) -> Self {
+        let (mut orig, mut new) = (0, 0);
+        let mut edits = vec![];
+        for k in kept.iter().filter(|k| k.len != Some(0)) {
+            if k.orig != orig || k.new != new {
+                edits.push(Edit {
+                    orig: orig..k.orig,
+                    new: new..k.new,
+                });
+            }
+            if let Some(len) = k.len {
+                orig = k.orig + len;
+                new = k.new + len;
+            }
+        }
+        SpanMap { edits }
+    }
+}
+
+//  edits sorted, as (from, to)
+fn translate<'a, I>(pos: usize, edits: I) -> usize
+where
+    I: Iterator<Item = (&'a Range<usize>, &'a Range<usize>)>,
+{
+    let mut last = (0, 0);
+    for (from, to) in edits {
+        if pos < from.start {
+            break;
+        }
+        if pos < from.end {
+            return to.start + (pos - from.start).min((to.end - to.start).saturating_sub(1));
+        }
+        last = (from.end, to.end);
+    }
+    last.1 + (pos - last.0)
+}
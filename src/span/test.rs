@@ -0,0 +1,68 @@
+//-----------------------------------------------------------------------
+//
+//  mod span  TEST
+//
+//-----------------------------------------------------------------------
+use super::SpanMap;
+use crate::parse;
+
+#[test]
+fn test_span_map_translate() {
+    //  "a{{x}}b{{yy}}c"  ->  "a1b22222c"
+    let map = SpanMap::new(vec![(7..13, 3..8), (1..6, 1..2)]).unwrap();
+
+    assert_eq!(map.to_original(0), 0);
+    assert_eq!(map.to_original(1), 1);
+    assert_eq!(map.to_original(2), 6);
+    assert_eq!(map.to_original(4), 8);
+    assert_eq!(map.to_original(8), 13);
+    assert_eq!(map.to_original(9), 14);
+
+    assert_eq!(map.to_new(3), 1);
+    assert_eq!(map.to_new(6), 2);
+    assert_eq!(map.to_new(13), 8);
+
+    assert_eq!(map.range_to_original(0..2), 0..6);
+    assert_eq!(map.range_to_original(2..3), 6..7);
+
+    assert_eq!(SpanMap::identity().to_original(5), 5);
+}
+
+#[test]
+fn test_span_map_invalid() {
+    assert!(SpanMap::new(vec![(1..3, 2..4)]).is_err());
+    assert!(SpanMap::new(vec![(1..4, 1..2), (3..5, 3..3)]).is_err());
+    assert!(SpanMap::new(vec![(1..4, 1..2), (5..6, 2..3)]).is_err());
+}
+
+#[test]
+fn test_span_map_compose() {
+    //  "abcdef" -> "aXbcdef" -> "aXbc"
+    let first = SpanMap::new(vec![(1..1, 1..2)]).unwrap();
+    let second = SpanMap::new(vec![(4..7, 4..4)]).unwrap();
+    let map = first.compose(&second);
+
+    assert_eq!(map, SpanMap::new(vec![(1..1, 1..2), (3..6, 4..4)]).unwrap());
+    assert_eq!(map.to_original(2), 1);
+    assert_eq!(map.to_original(4), 6);
+    assert_eq!(map.to_new(5), 4);
+
+    assert_eq!(map.compose(&SpanMap::identity()), map);
+    assert_eq!(SpanMap::identity().compose(&map), map);
+}
+
+#[test]
+fn test_span_map_error_to_original() {
+    let rules = rules! {
+       "main"   =>  rep!(ematch!(chlist "\n", from 'a', to 'z'), 1)
+    };
+    let original = "ab\n{{x}}\ncd2";
+    let preprocessed = "ab\nvalue\ncd2";
+    let map = SpanMap::new(vec![(3..8, 3..8)]).unwrap();
+
+    let error = parse(preprocessed, &rules).unwrap_err();
+    let error = map.error_to_original(&error, original);
+    assert_eq!(error.pos.row, 2);
+    assert_eq!(error.line_before, "cd2");
+    assert_eq!(error.line_after, "");
+}
@@ -0,0 +1,303 @@
+#![warn(missing_docs)]
+//! Parse an input received in chunks (i.e. from a socket)
+//!
+//! ```StreamingParse``` parses consecutive matches of a rule (as
+//! ```parse_iter```), while the input is fed. The engine can't suspend
+//! in the middle of a rule, so this is a line-buffered version: every
+//! time a chunk completes some lines, the records on them are parsed
+//! and returned. The last line (without new line) waits for more input
+//! or for ```finish```
+//!
+//! Then, the rule can't look after the new line ending a record. A
+//! record can take several lines, but it has to end with a new line (or
+//! with the input). i.e. ```line+``` would stop at the end of a chunk
+//!
+//! An error before the end of the complete lines is reported by
+//! ```feed```. If it is on the end, the record could be completed with
+//! more input, and the parser waits. The records before an error are
+//! returned first, and the error on next call (and on every call after)
+//!
+//! ```
+//! #[macro_use]  extern crate dynparser;
+//! use dynparser::StreamingParse;
+//!
+//! fn main() {
+//!     let rules = rules!{
+//!        "line"   =>  and!(rep!(ematch!(chlist "", from 'a', to 'z'), 1), lit!("\n"))
+//!     };
+//!
+//!     let mut stream = StreamingParse::new(&rules, "line");
+//!     assert_eq!(stream.feed("abc\nde").unwrap().len(), 1);
+//!     assert_eq!(stream.feed("f\ngh").unwrap().len(), 1);
+//!     assert_eq!(stream.feed("\n").unwrap().len(), 1);
+//!     assert!(stream.finish().unwrap().is_empty());
+//! }
+//! ```
+//!
+//! With ```parse_reader```, the chunks are read from a ```std::io::Read```
+//! (i.e. a big file), and the records are returned by an iterator. Only
+//! the last line read (not complete) is kept in memory
+
+use crate::ast;
+use crate::codes::Code;
+use crate::parser::{self, expression, Newlines, Possition, Status};
+use std::collections::VecDeque;
+use std::io::Read;
+use std::str;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Parser fed by chunks, returning the records as they are complete
+///
+/// Look at the module documentation
+#[derive(Debug)]
+pub struct StreamingParse<'a> {
+    rules: &'a expression::SetOfRules,
+    rule_name: String,
+    //  text not parsed yet, starting on pos
+    pending: String,
+    pos: Possition,
+    //  end of a chunk of bytes in the middle of a char
+    incomplete: Vec<u8>,
+    error: Option<parser::Error>,
+}
+
+/// Iterator over the records read from a ```std::io::Read```
+///
+/// Created with ```parse_reader```
+pub struct ParseReader<'a, R: Read> {
+    reader: R,
+    //  None after the end of input or an error
+    stream: Option<StreamingParse<'a>>,
+    records: VecDeque<ast::Node>,
+    chunk: Vec<u8>,
+}
+
+/// Size of the chunks read by ```parse_reader```
+pub const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl<'a> StreamingParse<'a> {
+    /// Stream of records matching the rule
+    pub fn new(rules: &'a expression::SetOfRules, rule_name: &str) -> Self {
+        StreamingParse {
+            rules,
+            rule_name: rule_name.to_string(),
+            pending: String::new(),
+            pos: Possition::init(),
+            incomplete: vec![],
+            error: None,
+        }
+    }
+
+    /// Add a chunk of input, and get the records completed by it
+    pub fn feed(&mut self, chunk: &str) -> Result<Vec<ast::Node>, parser::Error> {
+        self.check_error()?;
+        self.pending.push_str(chunk);
+        self.parse_records(false)
+    }
+
+    /// Same as ```feed``` with a chunk of UTF-8 bytes
+    ///
+    /// A char can be split between chunks. Invalid UTF-8 is an error
+    pub fn feed_bytes(&mut self, chunk: &[u8]) -> Result<Vec<ast::Node>, parser::Error> {
+        self.check_error()?;
+        self.incomplete.extend_from_slice(chunk);
+        let valid = match str::from_utf8(&self.incomplete) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => {
+                let text = String::from_utf8_lossy(&self.incomplete[..e.valid_up_to()]);
+                self.pending.push_str(&text);
+                return Err(self.fail_at_end(Code::InvalidUtf8, "invalid UTF-8 on input"));
+            }
+        };
+        let text = String::from_utf8_lossy(&self.incomplete[..valid]).into_owned();
+        self.incomplete.drain(..valid);
+        self.pending.push_str(&text);
+        self.parse_records(false)
+    }
+
+    /// No more input. It returns the records pending
+    ///
+    /// The rest of the input has to be records (or nothing)
+    pub fn finish(mut self) -> Result<Vec<ast::Node>, parser::Error> {
+        self.check_error()?;
+        if !self.incomplete.is_empty() {
+            return Err(self.fail_at_end(
+                Code::InvalidUtf8,
+                "incomplete UTF-8 char at the end of input",
+            ));
+        }
+        self.parse_records(true)
+    }
+
+    /// Position (on the full input) where next record will start
+    pub fn position(&self) -> &Possition {
+        &self.pos
+    }
+}
+
+/// Parse the input read as a sequence of the rule provided
+///
+/// Same as ```parse_iter```, but reading the input by chunks. It stops
+/// after the first error (reading errors are also reported as
+/// ```parser::Error```, with the position where they happened)
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse_reader;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "line"   =>  and!(rep!(ematch!(chlist "", from 'a', to 'z'), 1), lit!("\n"))
+///     };
+///
+///     let input = "abc\nde\n".repeat(100_000);
+///     let lines = parse_reader(input.as_bytes(), &rules, "line")
+///         .collect::<Result<Vec<_>, _>>()
+///         .unwrap();
+///     assert_eq!(lines.len(), 200_000);
+///
+///     let mut it = parse_reader("abc\nd3\n".as_bytes(), &rules, "line");
+///     assert!(it.next().unwrap().is_ok());
+///     assert!(it.next().unwrap().is_err());
+///     assert!(it.next().is_none());
+/// }
+/// ```
+pub fn parse_reader<'a, R: Read>(
+    reader: R,
+    rules: &'a expression::SetOfRules,
+    rule_name: &str,
+) -> ParseReader<'a, R> {
+    ParseReader {
+        reader,
+        stream: Some(StreamingParse::new(rules, rule_name)),
+        records: VecDeque::new(),
+        chunk: vec![0; READ_CHUNK_SIZE],
+    }
+}
+
+impl<'a, R: Read> Iterator for ParseReader<'a, R> {
+    type Item = Result<ast::Node, parser::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.records.is_empty() {
+            let mut stream = self.stream.take()?;
+            let records = match self.reader.read(&mut self.chunk) {
+                Ok(0) => stream.finish(),
+                Ok(len) => {
+                    let records = stream.feed_bytes(&self.chunk[..len]);
+                    self.stream = Some(stream);
+                    records
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {
+                    self.stream = Some(stream);
+                    continue;
+                }
+                Err(e) => {
+                    Err(stream.fail_at_end(Code::ReadError, &format!("error reading input: {}", e)))
+                }
+            };
+            match records {
+                Ok(records) => self.records.extend(records),
+                Err(e) => {
+                    self.stream = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.records.pop_front().map(Ok)
+    }
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  I N T E R N A L
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl<'a> StreamingParse<'a> {
+    fn check_error(&self) -> Result<(), parser::Error> {
+        match &self.error {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    //  on the complete lines, or on all the text at the end of input
+    //  the records before an error are returned, the error on next call
+    fn parse_records(&mut self, end_of_input: bool) -> Result<Vec<ast::Node>, parser::Error> {
+        let available = if end_of_input {
+            self.pending.len()
+        } else {
+            self.pending.rfind('\n').map_or(0, |nl| nl + 1)
+        };
+
+        let mut records = vec![];
+        let mut consumed = 0;
+        let mut pos = self.pos.clone();
+        while consumed < available {
+            let text = &self.pending[consumed..available];
+            let mut status = Status::init(text, self.rules);
+            status.pos = pos.clone();
+            match expression::parse_rule(status, &self.rule_name) {
+                Ok((st, _)) if st.pos.n == pos.n => {
+                    self.error = Some(parser::Error::from_status_normal(
+                        &st,
+                        Code::EmptyRecord,
+                        &format!("rule {} matched empty input", self.rule_name),
+                    ));
+                    break;
+                }
+                Ok((st, node)) => {
+                    consumed += text.len() - st.it_parsing.as_str().len();
+                    pos = st.pos;
+                    records.push(node);
+                }
+                Err(e) => {
+                    let mut end = pos.clone();
+                    end.advance(text, Newlines::default());
+                    //  the record could continue on next chunk
+                    if end_of_input || e.pos.n < end.n {
+                        self.error = Some(e);
+                    }
+                    break;
+                }
+            }
+        }
+        self.pending.drain(..consumed);
+        self.pos = pos;
+        if records.is_empty() {
+            self.check_error()?;
+        }
+        Ok(records)
+    }
+
+    fn fail_at_end(&mut self, code: Code, descr: &str) -> parser::Error {
+        let mut status = Status::init("", self.rules);
+        status.pos = self.pos.clone();
+        status.pos.advance(&self.pending, Newlines::default());
+        let e = parser::Error::from_status_normal(&status, code, descr);
+        self.error = Some(e.clone());
+        e
+    }
+}
@@ -0,0 +1,137 @@
+//-----------------------------------------------------------------------
+//
+//  mod stream  TEST
+//
+//-----------------------------------------------------------------------
+use super::{parse_reader, StreamingParse};
+use crate::parser::expression::SetOfRules;
+use std::io::{self, Read};
+
+fn line_rules() -> SetOfRules {
+    rules! {
+       "line"  =>  and!(rep!(ematch!(chlist "", from 'a', to 'z'), 1), lit!("\n"))
+    }
+}
+
+#[test]
+fn test_records_split_between_chunks() {
+    let rules = line_rules();
+    let mut stream = StreamingParse::new(&rules, "line");
+
+    assert!(stream.feed("ab").unwrap().is_empty());
+    assert!(stream.feed("c").unwrap().is_empty());
+    assert_eq!(stream.feed("\nd\nef").unwrap().len(), 2);
+    assert_eq!(stream.position().row, 2);
+    assert_eq!(stream.position().n, 6);
+    assert_eq!(stream.feed("\n").unwrap().len(), 1);
+    assert!(stream.finish().unwrap().is_empty());
+}
+
+#[test]
+fn test_char_split_between_byte_chunks() {
+    let rules = rules! {
+       "line"  =>  and!(rep!(and!(not!(lit!("\n")), dot!()), 1), lit!("\n"))
+    };
+    let mut stream = StreamingParse::new(&rules, "line");
+    let input = "añ\nb\n".as_bytes();
+
+    assert!(stream.feed_bytes(&input[..2]).unwrap().is_empty());
+    assert_eq!(stream.feed_bytes(&input[2..4]).unwrap().len(), 1);
+    assert_eq!(stream.feed_bytes(&input[4..]).unwrap().len(), 1);
+    assert!(stream.finish().unwrap().is_empty());
+
+    let mut stream = StreamingParse::new(&rules, "line");
+    assert!(stream.feed_bytes(&[b'a', 0xff, b'\n']).is_err());
+    assert!(stream.feed("b\n").is_err());
+
+    let mut stream = StreamingParse::new(&rules, "line");
+    assert!(stream.feed_bytes(&input[..2]).unwrap().is_empty());
+    assert!(stream.finish().is_err());
+}
+
+#[test]
+fn test_error_on_feed_or_waiting() {
+    let rules = line_rules();
+
+    //  an error inside the complete lines, on full input position
+    let mut stream = StreamingParse::new(&rules, "line");
+    assert_eq!(stream.feed("ab\ncd").unwrap().len(), 1);
+    let records = stream.feed("\n1\nx\n").unwrap();
+    assert_eq!(records.len(), 1);
+    let err = stream.feed("y\n").unwrap_err();
+    assert_eq!(err.pos.row, 2);
+    assert!(err.pos.n > 5);
+    assert!(stream.finish().is_err());
+
+    //  an error on the last line waits for the end of the line
+    let mut stream = StreamingParse::new(&rules, "line");
+    assert!(stream.feed("ab").unwrap().is_empty());
+    assert!(stream.feed("1").unwrap().is_empty());
+    assert!(stream.feed("\n").is_err());
+}
+
+#[test]
+fn test_finish_with_last_record() {
+    let rules = rules! {
+       "line"  =>  and!(
+                        rep!(ematch!(chlist "", from 'a', to 'z'), 1),
+                        or!(lit!("\n"), not!(dot!()))
+                    )
+    };
+    let mut stream = StreamingParse::new(&rules, "line");
+
+    assert_eq!(stream.feed("ab\ncd").unwrap().len(), 1);
+    assert_eq!(stream.finish().unwrap().len(), 1);
+
+    let mut stream = StreamingParse::new(&rules, "line");
+    assert!(stream.feed("ab\ncd1").unwrap().len() == 1);
+    assert!(stream.finish().is_err());
+}
+
+//  reads a byte per call, and fails at the end if asked
+struct SlowReader<'a> {
+    input: &'a [u8],
+    fail: bool,
+}
+
+impl<'a> Read for SlowReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.input.split_first() {
+            Some((byte, rest)) => {
+                buf[0] = *byte;
+                self.input = rest;
+                Ok(1)
+            }
+            None if self.fail => Err(io::Error::other("broken pipe")),
+            None => Ok(0),
+        }
+    }
+}
+
+#[test]
+fn test_parse_reader() {
+    let rules = line_rules();
+    let read = |input: &'static str, fail| SlowReader {
+        input: input.as_bytes(),
+        fail,
+    };
+
+    let lines = parse_reader(read("ab\ncd\n", false), &rules, "line")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(lines.len(), 2);
+
+    //  the records before the error, and then the error
+    let mut it = parse_reader(read("ab\nc1\nde\n", false), &rules, "line");
+    assert!(it.next().unwrap().is_ok());
+    assert_eq!(it.next().unwrap().unwrap_err().pos.row, 1);
+    assert!(it.next().is_none());
+
+    //  reading errors
+    let mut it = parse_reader(read("ab\ncd", true), &rules, "line");
+    assert!(it.next().unwrap().is_ok());
+    let err = it.next().unwrap().unwrap_err();
+    assert!(err.descr.contains("broken pipe"));
+    assert_eq!((err.pos.row, err.pos.col), (1, 2));
+    assert!(it.next().is_none());
+}
@@ -0,0 +1,158 @@
+//! Assertions for grammar tests
+//!
+//! Instead of ```parse(...).unwrap()``` or ```assert!(parse(...).is_ok())```,
+//! the macros print a useful message when they fail. The error rendered
+//! on the input, or the trees (as indented s-expressions)
+//!
+//! ```
+//! #[macro_use]  extern crate dynparser;
+//!
+//! fn main() {
+//!     let rules = rules!{
+//!        "main"   =>  and!(ref_rule!("a"), lit!("b")),
+//!        "a"      =>  lit!("a")
+//!     };
+//!
+//!     let ast = assert_parses!(&rules, "ab");
+//!     assert_eq!(ast.to_sexpr(), r#"(main (a "a") "b")"#);
+//!
+//!     let err = assert_rejects!(&rules, "ac");
+//!     assert_eq!(err.pos.col, 2);
+//!
+//!     assert_ast_eq!(&rules, "ab", r#"(main (a "a") "b")"#);
+//! }
+//! ```
+//!
+//! A failing ```assert_parses!(&rules, "aX")``` panics with
+//!
+//! ```text
+//! input rejected
+//! expected literal: <b> (row 0, col 2)
+//! 0 | aX
+//!   |   ^
+//! ```
+
+use crate::ast;
+use crate::parse;
+use crate::parser::{self, expression};
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Lines of context around the error on the messages
+pub const CONTEXT_LINES: usize = 2;
+
+/// The input is accepted, and it returns the AST
+///
+/// If not, it panics with the error rendered on the input
+#[macro_export]
+macro_rules! assert_parses {
+    ($rules:expr, $input:expr) => {{
+        $crate::testing::check_parses($rules, $input)
+    }};
+}
+
+/// The input is rejected, and it returns the error
+///
+/// If not, it panics with the AST
+#[macro_export]
+macro_rules! assert_rejects {
+    ($rules:expr, $input:expr) => {{
+        $crate::testing::check_rejects($rules, $input)
+    }};
+}
+
+/// The input is accepted, with the AST written as s-expression
+///
+/// Spaces and new lines on the expected s-expression are ignored. If
+/// the trees are different, it panics with both of them
+#[macro_export]
+macro_rules! assert_ast_eq {
+    ($rules:expr, $input:expr, $expected:expr) => {{
+        $crate::testing::check_ast_eq($rules, $input, $expected)
+    }};
+}
+
+/// Near-miss inputs (look at ```expression::near_misses```) are
+/// rejected with an error on the mutation, or after it
+///
+/// It returns the near misses checked. If not, it panics with the
+/// mutation and the error rendered on the input
+#[macro_export]
+macro_rules! assert_rejects_near_misses {
+    ($rules:expr, $budget:expr) => {{
+        $crate::testing::check_near_misses($rules, $budget)
+    }};
+}
+
+/// Called by ```assert_parses!```
+#[track_caller]
+pub fn check_parses(rules: &expression::SetOfRules, input: &str) -> ast::Node {
+    match parse(input, rules) {
+        Ok(ast) => ast,
+        Err(e) => panic!("input rejected\n{}", e.render(input, CONTEXT_LINES)),
+    }
+}
+
+/// Called by ```assert_rejects!```
+#[track_caller]
+pub fn check_rejects(rules: &expression::SetOfRules, input: &str) -> parser::Error {
+    match parse(input, rules) {
+        Ok(ast) => panic!("input accepted, it should fail\n{}", ast.to_sexpr_pretty()),
+        Err(e) => e,
+    }
+}
+
+/// Called by ```assert_ast_eq!```
+#[track_caller]
+pub fn check_ast_eq(rules: &expression::SetOfRules, input: &str, expected: &str) {
+    let expected = match ast::Node::from_sexpr(expected) {
+        Ok(expected) => expected,
+        Err(e) => panic!("invalid expected s-expression: {}", e.0),
+    };
+    let ast = check_parses(rules, input);
+    if ast != expected {
+        panic!(
+            "different AST\n\
+             --- expected\n{}\n\
+             --- parsed\n{}",
+            expected.to_sexpr_pretty(),
+            ast.to_sexpr_pretty()
+        );
+    }
+}
+
+/// Called by ```assert_rejects_near_misses!```
+#[track_caller]
+pub fn check_near_misses(
+    rules: &expression::SetOfRules,
+    budget: usize,
+) -> Vec<expression::NearMiss> {
+    let near_misses = expression::near_misses(rules, budget);
+    for near_miss in &near_misses {
+        let e = check_rejects(rules, &near_miss.input);
+        if e.pos.n < near_miss.at {
+            panic!(
+                "error before the mutation ({:?} on char {})\n\
+                 --- valid input\n{}\n\
+                 --- error\n{}",
+                near_miss.mutation,
+                near_miss.at,
+                near_miss.valid,
+                e.render(&near_miss.input, CONTEXT_LINES)
+            );
+        }
+    }
+    near_misses
+}
+
+//-----------------------------------------------------------------------
+//  T E S T
+//-----------------------------------------------------------------------
+#[cfg(test)]
+mod test;
@@ -0,0 +1,81 @@
+//-----------------------------------------------------------------------
+//
+//  mod testing  TEST
+//
+//-----------------------------------------------------------------------
+use crate::parser::expression::SetOfRules;
+
+fn ab_rules() -> SetOfRules {
+    rules! {
+       "main"   =>  and!(ref_rule!("a"), lit!("b")),
+       "a"      =>  lit!("a")
+    }
+}
+
+#[test]
+fn test_assertions_ok() {
+    let rules = ab_rules();
+
+    assert_eq!(
+        assert_parses!(&rules, "ab").to_sexpr(),
+        r#"(main (a "a") "b")"#
+    );
+    assert_eq!(assert_rejects!(&rules, "abc").pos.n, 2);
+    assert_ast_eq!(
+        &rules,
+        "ab",
+        r#"
+        (main
+            (a "a")
+            "b")
+        "#
+    );
+}
+
+#[test]
+#[should_panic(expected = "input rejected\nexpected literal: <b>")]
+fn test_assert_parses_fails() {
+    assert_parses!(&ab_rules(), "aX");
+}
+
+#[test]
+#[should_panic(expected = "input accepted, it should fail\n(main (a \"a\") \"b\")")]
+fn test_assert_rejects_fails() {
+    assert_rejects!(&ab_rules(), "ab");
+}
+
+#[test]
+#[should_panic(
+    expected = "--- expected\n(main (a \"b\") \"b\")\n--- parsed\n(main (a \"a\") \"b\")"
+)]
+fn test_assert_ast_eq_fails() {
+    assert_ast_eq!(&ab_rules(), "ab", r#"(main (a "b") "b")"#);
+}
+
+#[test]
+#[should_panic(expected = "invalid expected s-expression")]
+fn test_assert_ast_eq_invalid_sexpr() {
+    assert_ast_eq!(&ab_rules(), "ab", "(main");
+}
+
+#[test]
+fn test_near_misses_rejected() {
+    let rules = crate::rules_from_peg(
+        r#"
+        main    =   stmt+
+        stmt    =   'let '  [a-z]+  ' = '  [0-9]+  ';'
+        "#,
+    )
+    .unwrap();
+
+    let near_misses = assert_rejects_near_misses!(&rules, 200);
+    assert!(near_misses.len() > 10);
+    assert_eq!(near_misses, crate::testing::check_near_misses(&rules, 200));
+}
+
+#[test]
+#[should_panic(expected = "error before the mutation")]
+fn test_assert_rejects_near_misses_fails() {
+    let rules = crate::rules_from_peg(r#"main = 'a' 'b' 'c'  /  error("not abc")"#).unwrap();
+    assert_rejects_near_misses!(&rules, 100);
+}